@@ -3,10 +3,12 @@ pub enum ExitCode {
     CouldNotReadAggregationPipeline,
     CouldNotReadConfiguration,
     CouldNotProcessAggregationPipeline,
+    ErrorRunningNativeQuery,
     ErrorWriting,
     InvalidArguments,
     RefusedToOverwrite,
     ResourceNotFound,
+    ResultTypeMismatch,
 }
 
 impl From<ExitCode> for i32 {
@@ -15,10 +17,12 @@ impl From<ExitCode> for i32 {
             ExitCode::CouldNotReadAggregationPipeline => 201,
             ExitCode::CouldNotReadConfiguration => 202,
             ExitCode::CouldNotProcessAggregationPipeline => 205,
+            ExitCode::ErrorRunningNativeQuery => 206,
             ExitCode::ErrorWriting => 204,
             ExitCode::InvalidArguments => 400,
             ExitCode::RefusedToOverwrite => 203,
             ExitCode::ResourceNotFound => 404,
+            ExitCode::ResultTypeMismatch => 207,
         }
     }
 }
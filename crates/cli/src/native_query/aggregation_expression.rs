@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::{borrow::Cow, collections::BTreeMap, iter::once};
 
 use itertools::Itertools as _;
 use mongodb::bson::{Bson, Document};
@@ -244,11 +244,816 @@ fn infer_type_from_operator_expression(
             )?;
             C::ArrayOf(Box::new(C::Scalar(BsonScalarType::String)))
         }
+
+        // Conditional operators
+        "$cond" => infer_type_from_cond(context, desired_object_type_name, type_hint, operand)?,
+        "$switch" => infer_type_from_switch(context, desired_object_type_name, type_hint, operand)?,
+        "$ifNull" => {
+            let mut types = infer_types_from_aggregation_expression_tuple(
+                context,
+                desired_object_type_name,
+                type_hint,
+                operand,
+            )?;
+            let replacement_type = types.pop().ok_or_else(|| {
+                Error::Other(format!("{operator} requires at least two arguments"))
+            })?;
+            // The result is the first non-null argument, or the replacement if every other
+            // argument is null
+            let non_null_types = types
+                .into_iter()
+                .map(|t| t.without_null().map(Cow::into_owned).unwrap_or(t));
+            unify_branch_types(context, non_null_types.chain([replacement_type]))
+        }
+
+        // String operators
+        "$concat" => {
+            infer_types_from_aggregation_expression_tuple(
+                context,
+                desired_object_type_name,
+                Some(&C::Scalar(BsonScalarType::String)),
+                operand,
+            )?;
+            C::Scalar(BsonScalarType::String)
+        }
+        "$toUpper" | "$toLower" => {
+            infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                Some(&C::Scalar(BsonScalarType::String)),
+                operand,
+            )?;
+            C::Scalar(BsonScalarType::String)
+        }
+        "$trim" | "$ltrim" | "$rtrim" => {
+            infer_types_from_named_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                &[("input", Some(C::Scalar(BsonScalarType::String)))],
+                &[("chars", Some(C::Scalar(BsonScalarType::String)))],
+            )?;
+            C::Scalar(BsonScalarType::String)
+        }
+        "$substr" | "$substrCP" | "$substrBytes" => {
+            infer_types_from_positional_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                3,
+                &[
+                    Some(C::Scalar(BsonScalarType::String)),
+                    Some(C::Scalar(BsonScalarType::Int)),
+                    Some(C::Scalar(BsonScalarType::Int)),
+                ],
+            )?;
+            C::Scalar(BsonScalarType::String)
+        }
+        "$strLenCP" | "$strLenBytes" => {
+            infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                Some(&C::Scalar(BsonScalarType::String)),
+                operand,
+            )?;
+            C::Scalar(BsonScalarType::Int)
+        }
+        "$indexOfCP" | "$indexOfBytes" => {
+            infer_types_from_positional_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                2,
+                &[
+                    Some(C::Scalar(BsonScalarType::String)),
+                    Some(C::Scalar(BsonScalarType::String)),
+                    Some(C::Scalar(BsonScalarType::Int)),
+                    Some(C::Scalar(BsonScalarType::Int)),
+                ],
+            )?;
+            C::Scalar(BsonScalarType::Int)
+        }
+        "$strcasecmp" => {
+            infer_types_from_positional_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                2,
+                &[
+                    Some(C::Scalar(BsonScalarType::String)),
+                    Some(C::Scalar(BsonScalarType::String)),
+                ],
+            )?;
+            C::Scalar(BsonScalarType::Int)
+        }
+        "$regexMatch" | "$regexFind" | "$regexFindAll" => {
+            infer_types_from_named_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                &[
+                    ("input", Some(C::Scalar(BsonScalarType::String))),
+                    // may be a regular expression or a string
+                    ("regex", None),
+                ],
+                &[("options", Some(C::Scalar(BsonScalarType::String)))],
+            )?;
+            match operator {
+                "$regexMatch" => C::Scalar(BsonScalarType::Bool),
+                "$regexFind" => regex_match_type(context, desired_object_type_name).make_nullable(),
+                _ => C::ArrayOf(Box::new(regex_match_type(
+                    context,
+                    desired_object_type_name,
+                ))),
+            }
+        }
+        "$replaceOne" | "$replaceAll" => {
+            infer_types_from_named_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                &[
+                    ("input", Some(C::Scalar(BsonScalarType::String))),
+                    ("find", Some(C::Scalar(BsonScalarType::String))),
+                    ("replacement", Some(C::Scalar(BsonScalarType::String))),
+                ],
+                &[],
+            )?;
+            C::Scalar(BsonScalarType::String)
+        }
+
+        // Date operators
+        "$dateToString" => {
+            infer_types_from_named_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                &[("date", Some(C::Scalar(BsonScalarType::Date)))],
+                &[
+                    ("format", Some(C::Scalar(BsonScalarType::String))),
+                    ("timezone", Some(C::Scalar(BsonScalarType::String))),
+                    ("onNull", None),
+                ],
+            )?;
+            C::Scalar(BsonScalarType::String)
+        }
+        "$dateFromString" => {
+            infer_types_from_named_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                &[("dateString", Some(C::Scalar(BsonScalarType::String)))],
+                &[
+                    ("format", Some(C::Scalar(BsonScalarType::String))),
+                    ("timezone", Some(C::Scalar(BsonScalarType::String))),
+                    ("onError", None),
+                    ("onNull", None),
+                ],
+            )?;
+            C::Scalar(BsonScalarType::Date)
+        }
+        "$dateTrunc" => {
+            infer_types_from_named_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                &[
+                    ("date", Some(C::Scalar(BsonScalarType::Date))),
+                    ("unit", Some(C::Scalar(BsonScalarType::String))),
+                ],
+                &[
+                    ("binSize", Some(C::numeric())),
+                    ("timezone", Some(C::Scalar(BsonScalarType::String))),
+                    ("startOfWeek", Some(C::Scalar(BsonScalarType::String))),
+                ],
+            )?;
+            C::Scalar(BsonScalarType::Date)
+        }
+        "$dateAdd" | "$dateSubtract" => {
+            infer_types_from_named_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                &[
+                    ("startDate", Some(C::Scalar(BsonScalarType::Date))),
+                    ("unit", Some(C::Scalar(BsonScalarType::String))),
+                    ("amount", Some(C::numeric())),
+                ],
+                &[("timezone", Some(C::Scalar(BsonScalarType::String)))],
+            )?;
+            C::Scalar(BsonScalarType::Date)
+        }
+        "$dateDiff" => {
+            infer_types_from_named_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                &[
+                    ("startDate", Some(C::Scalar(BsonScalarType::Date))),
+                    ("endDate", Some(C::Scalar(BsonScalarType::Date))),
+                    ("unit", Some(C::Scalar(BsonScalarType::String))),
+                ],
+                &[
+                    ("timezone", Some(C::Scalar(BsonScalarType::String))),
+                    ("startOfWeek", Some(C::Scalar(BsonScalarType::String))),
+                ],
+            )?;
+            C::Scalar(BsonScalarType::Long)
+        }
+        "$year" | "$month" | "$week" | "$dayOfYear" | "$dayOfMonth" | "$dayOfWeek" | "$hour"
+        | "$minute" | "$second" | "$millisecond" | "$isoWeek" | "$isoWeekYear"
+        | "$isoDayOfWeek" => {
+            match operand {
+                // The argument may be a document with a timezone, or an expression for the date
+                Bson::Document(doc) if doc.contains_key("date") => {
+                    infer_types_from_named_arguments(
+                        context,
+                        desired_object_type_name,
+                        operator,
+                        doc.into(),
+                        &[("date", Some(C::Scalar(BsonScalarType::Date)))],
+                        &[("timezone", Some(C::Scalar(BsonScalarType::String)))],
+                    )?;
+                }
+                operand => {
+                    infer_type_from_aggregation_expression(
+                        context,
+                        desired_object_type_name,
+                        Some(&C::Scalar(BsonScalarType::Date)),
+                        operand,
+                    )?;
+                }
+            }
+            C::Scalar(BsonScalarType::Int)
+        }
+
+        // Array operators
+        "$size" => {
+            infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                Some(&C::ArrayOf(Box::new(C::ExtendedJSON))),
+                operand,
+            )?;
+            C::Scalar(BsonScalarType::Int)
+        }
+        "$isArray" => {
+            infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                None,
+                operand,
+            )?;
+            C::Scalar(BsonScalarType::Bool)
+        }
+        "$in" => {
+            let (element, array) = two_parameter_operand(operator, operand)?;
+            let array_type = infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_in_array"),
+                None,
+                array,
+            )?;
+            infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_in_element"),
+                Some(&C::ElementOf(Box::new(array_type))),
+                element,
+            )?;
+            C::Scalar(BsonScalarType::Bool)
+        }
+        "$map" => infer_type_from_map(context, desired_object_type_name, type_hint, operand)?,
+        "$filter" => infer_type_from_filter(context, desired_object_type_name, type_hint, operand)?,
+        "$reduce" => infer_type_from_reduce(context, desired_object_type_name, type_hint, operand)?,
+        "$concatArrays" => {
+            let array_types = infer_types_from_aggregation_expression_tuple(
+                context,
+                desired_object_type_name,
+                type_hint,
+                operand,
+            )?;
+            unify_branch_types(context, array_types)
+        }
+        "$reverseArray" => infer_type_from_aggregation_expression(
+            context,
+            desired_object_type_name,
+            type_hint,
+            operand,
+        )?,
+        "$slice" => {
+            let mut types = infer_types_from_positional_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                2,
+                &[
+                    type_hint.cloned(),
+                    Some(C::Scalar(BsonScalarType::Int)),
+                    Some(C::Scalar(BsonScalarType::Int)),
+                ],
+            )?;
+            types.swap_remove(0)
+        }
+        "$first" | "$last" => {
+            let array_type = infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                type_hint.map(|t| C::ArrayOf(Box::new(t.clone()))).as_ref(),
+                operand,
+            )?;
+            C::ElementOf(Box::new(array_type)).make_nullable()
+        }
+        "$indexOfArray" => {
+            infer_types_from_aggregation_expression_tuple(
+                context,
+                desired_object_type_name,
+                None,
+                operand,
+            )?;
+            C::Scalar(BsonScalarType::Int)
+        }
+        "$range" => {
+            infer_types_from_positional_arguments(
+                context,
+                desired_object_type_name,
+                operator,
+                operand,
+                2,
+                &[
+                    Some(C::Scalar(BsonScalarType::Int)),
+                    Some(C::Scalar(BsonScalarType::Int)),
+                    Some(C::Scalar(BsonScalarType::Int)),
+                ],
+            )?;
+            C::ArrayOf(Box::new(C::Scalar(BsonScalarType::Int)))
+        }
+
+        // Type conversion operators
+        "$toBool" | "$toDate" | "$toDecimal" | "$toDouble" | "$toInt" | "$toLong"
+        | "$toObjectId" | "$toString" => {
+            infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                None,
+                operand,
+            )?;
+            let scalar_type = match operator {
+                "$toBool" => BsonScalarType::Bool,
+                "$toDate" => BsonScalarType::Date,
+                "$toDecimal" => BsonScalarType::Decimal,
+                "$toDouble" => BsonScalarType::Double,
+                "$toInt" => BsonScalarType::Int,
+                "$toLong" => BsonScalarType::Long,
+                "$toObjectId" => BsonScalarType::ObjectId,
+                _ => BsonScalarType::String,
+            };
+            // Conversion operators return null if the input is null or missing
+            C::Scalar(scalar_type).make_nullable()
+        }
+        "$convert" => infer_type_from_convert(context, desired_object_type_name, operand)?,
+        "$type" => {
+            infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                None,
+                operand,
+            )?;
+            C::Scalar(BsonScalarType::String)
+        }
+        "$literal" => match operand {
+            // Strings in $literal are not field references or variables
+            Bson::String(_) => C::Scalar(BsonScalarType::String),
+            Bson::Document(_) | Bson::Array(_) => C::ExtendedJSON,
+            operand => infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                None,
+                operand,
+            )?,
+        },
+
+        // Variable scoping
+        "$let" => infer_type_from_let(context, desired_object_type_name, type_hint, operand)?,
+
         op => Err(Error::UnknownAggregationOperator(op.to_string()))?,
     };
     Ok(t)
 }
 
+fn infer_type_from_cond(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    type_hint: Option<&TypeConstraint>,
+    operand: Bson,
+) -> Result<TypeConstraint> {
+    let (condition, then, otherwise) = match operand {
+        Bson::Array(operands) if operands.len() == 3 => {
+            let mut operands = operands.into_iter();
+            let condition = operands.next().unwrap();
+            let then = operands.next().unwrap();
+            let otherwise = operands.next().unwrap();
+            (condition, then, otherwise)
+        }
+        Bson::Document(mut arguments) => (
+            required_argument("$cond", &mut arguments, "if")?,
+            required_argument("$cond", &mut arguments, "then")?,
+            required_argument("$cond", &mut arguments, "else")?,
+        ),
+        other => Err(Error::Other(format!(
+            "argument to $cond must be a three-element array or a document, but got {other}"
+        )))?,
+    };
+    infer_type_from_aggregation_expression(
+        context,
+        desired_object_type_name,
+        Some(&C::Scalar(BsonScalarType::Bool)),
+        condition,
+    )?;
+    let then_type =
+        infer_type_from_aggregation_expression(context, desired_object_type_name, type_hint, then)?;
+    let else_type = infer_type_from_aggregation_expression(
+        context,
+        desired_object_type_name,
+        type_hint,
+        otherwise,
+    )?;
+    Ok(unify_branch_types(context, [then_type, else_type]))
+}
+
+fn infer_type_from_switch(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    type_hint: Option<&TypeConstraint>,
+    operand: Bson,
+) -> Result<TypeConstraint> {
+    let mut arguments = document_operand("$switch", operand)?;
+    let branches = match required_argument("$switch", &mut arguments, "branches")? {
+        Bson::Array(branches) => branches,
+        other => Err(Error::ExpectedArrayExpressionArgument {
+            actual_argument: Box::new(other),
+        })?,
+    };
+    let mut result_types = Vec::with_capacity(branches.len() + 1);
+    for branch in branches {
+        let mut branch = document_operand("$switch", branch)?;
+        let case = required_argument("$switch", &mut branch, "case")?;
+        let then = required_argument("$switch", &mut branch, "then")?;
+        infer_type_from_aggregation_expression(
+            context,
+            desired_object_type_name,
+            Some(&C::Scalar(BsonScalarType::Bool)),
+            case,
+        )?;
+        result_types.push(infer_type_from_aggregation_expression(
+            context,
+            desired_object_type_name,
+            type_hint,
+            then,
+        )?);
+    }
+    // Without a default $switch fails when no case matches, so there is no implicit null result
+    if let Some(default) = arguments.remove("default") {
+        result_types.push(infer_type_from_aggregation_expression(
+            context,
+            desired_object_type_name,
+            type_hint,
+            default,
+        )?);
+    }
+    Ok(unify_branch_types(context, result_types))
+}
+
+fn infer_type_from_map(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    type_hint: Option<&TypeConstraint>,
+    operand: Bson,
+) -> Result<TypeConstraint> {
+    let mut arguments = document_operand("$map", operand)?;
+    let input_type = infer_type_from_aggregation_expression(
+        context,
+        &format!("{desired_object_type_name}_input"),
+        None,
+        required_argument("$map", &mut arguments, "input")?,
+    )?;
+    let variable_name = variable_name_argument("$map", &mut arguments)?;
+    let element_type_hint = match type_hint {
+        Some(C::ArrayOf(t)) => Some(t.as_ref()),
+        _ => None,
+    };
+    let element_type = infer_type_with_variables(
+        context,
+        [(variable_name, C::ElementOf(Box::new(input_type)))],
+        desired_object_type_name,
+        element_type_hint,
+        required_argument("$map", &mut arguments, "in")?,
+    )?;
+    Ok(C::ArrayOf(Box::new(element_type)))
+}
+
+fn infer_type_from_filter(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    type_hint: Option<&TypeConstraint>,
+    operand: Bson,
+) -> Result<TypeConstraint> {
+    let mut arguments = document_operand("$filter", operand)?;
+    let input_type = infer_type_from_aggregation_expression(
+        context,
+        desired_object_type_name,
+        type_hint,
+        required_argument("$filter", &mut arguments, "input")?,
+    )?;
+    let variable_name = variable_name_argument("$filter", &mut arguments)?;
+    infer_type_with_variables(
+        context,
+        [(variable_name, C::ElementOf(Box::new(input_type.clone())))],
+        desired_object_type_name,
+        Some(&C::Scalar(BsonScalarType::Bool)),
+        required_argument("$filter", &mut arguments, "cond")?,
+    )?;
+    if let Some(limit) = arguments.remove("limit") {
+        infer_type_from_aggregation_expression(
+            context,
+            desired_object_type_name,
+            Some(&C::Scalar(BsonScalarType::Int)),
+            limit,
+        )?;
+    }
+    Ok(input_type)
+}
+
+fn infer_type_from_reduce(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    type_hint: Option<&TypeConstraint>,
+    operand: Bson,
+) -> Result<TypeConstraint> {
+    let mut arguments = document_operand("$reduce", operand)?;
+    let input_type = infer_type_from_aggregation_expression(
+        context,
+        &format!("{desired_object_type_name}_input"),
+        None,
+        required_argument("$reduce", &mut arguments, "input")?,
+    )?;
+    let initial_value_type = infer_type_from_aggregation_expression(
+        context,
+        desired_object_type_name,
+        type_hint,
+        required_argument("$reduce", &mut arguments, "initialValue")?,
+    )?;
+    let in_type = infer_type_with_variables(
+        context,
+        [
+            ("value".to_string(), initial_value_type.clone()),
+            ("this".to_string(), C::ElementOf(Box::new(input_type))),
+        ],
+        desired_object_type_name,
+        type_hint,
+        required_argument("$reduce", &mut arguments, "in")?,
+    )?;
+    Ok(unify_branch_types(context, [initial_value_type, in_type]))
+}
+
+fn infer_type_from_convert(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    operand: Bson,
+) -> Result<TypeConstraint> {
+    let mut arguments = document_operand("$convert", operand)?;
+    let target_type = match required_argument("$convert", &mut arguments, "to")? {
+        Bson::String(type_name) if !type_name.starts_with('$') => {
+            let scalar_type = BsonScalarType::from_bson_name(&type_name)
+                .map_err(|err| Error::Other(format!("invalid type for $convert: {err}")))?;
+            C::Scalar(scalar_type)
+        }
+        // The target type may be computed, or given as a numeric type code
+        to => {
+            infer_type_from_aggregation_expression(context, desired_object_type_name, None, to)?;
+            C::ExtendedJSON
+        }
+    };
+    infer_type_from_aggregation_expression(
+        context,
+        desired_object_type_name,
+        None,
+        required_argument("$convert", &mut arguments, "input")?,
+    )?;
+    let fallback_types = ["onError", "onNull"]
+        .into_iter()
+        .filter_map(|name| arguments.remove(name))
+        .map(|fallback| {
+            infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                Some(&target_type),
+                fallback,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if fallback_types.is_empty() {
+        Ok(target_type)
+    } else {
+        Ok(unify_branch_types(
+            context,
+            once(target_type).chain(fallback_types),
+        ))
+    }
+}
+
+fn infer_type_from_let(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    type_hint: Option<&TypeConstraint>,
+    operand: Bson,
+) -> Result<TypeConstraint> {
+    let mut arguments = document_operand("$let", operand)?;
+    let vars = match required_argument("$let", &mut arguments, "vars")? {
+        Bson::Document(vars) => vars,
+        other => Err(Error::Other(format!(
+            "vars argument to $let must be a document, but got {other}"
+        )))?,
+    };
+    // Variable expressions are evaluated in the outer scope so they cannot reference each other
+    let variables = vars
+        .into_iter()
+        .map(|(name, expression)| {
+            let variable_type = infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_{name}"),
+                None,
+                expression,
+            )?;
+            Ok((name, variable_type))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    infer_type_with_variables(
+        context,
+        variables,
+        desired_object_type_name,
+        type_hint,
+        required_argument("$let", &mut arguments, "in")?,
+    )
+}
+
+/// Infers the type of an expression with additional variables in scope.
+fn infer_type_with_variables(
+    context: &mut PipelineTypeContext<'_>,
+    variables: impl IntoIterator<Item = (String, TypeConstraint)>,
+    desired_object_type_name: &str,
+    type_hint: Option<&TypeConstraint>,
+    expression: Bson,
+) -> Result<TypeConstraint> {
+    let outer = context.add_pipeline_variables(variables);
+    let result = infer_type_from_aggregation_expression(
+        context,
+        desired_object_type_name,
+        type_hint,
+        expression,
+    );
+    context.restore_pipeline_variables(outer);
+    result
+}
+
+/// Branches of a conditional expression may produce different types. The result type must
+/// accommodate all of them.
+fn unify_branch_types(
+    context: &mut PipelineTypeContext<'_>,
+    branch_types: impl IntoIterator<Item = TypeConstraint>,
+) -> TypeConstraint {
+    C::Variable(context.new_type_variable(Variance::Covariant, branch_types))
+}
+
+/// Type of the documents produced by `$regexFind` and `$regexFindAll`
+fn regex_match_type(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+) -> TypeConstraint {
+    let object_type_name = context.unique_type_name(&format!("{desired_object_type_name}_match"));
+    let object_type = ObjectTypeConstraint {
+        fields: [
+            ("match".into(), C::Scalar(BsonScalarType::String)),
+            ("idx".into(), C::Scalar(BsonScalarType::Int)),
+            (
+                "captures".into(),
+                C::ArrayOf(Box::new(C::Scalar(BsonScalarType::String).make_nullable())),
+            ),
+        ]
+        .into(),
+    };
+    context.insert_object_type(object_type_name.clone(), object_type);
+    C::Object(object_type_name)
+}
+
+fn document_operand(operator: &str, operand: Bson) -> Result<Document> {
+    match operand {
+        Bson::Document(arguments) => Ok(arguments),
+        other => Err(Error::Other(format!(
+            "argument to {operator} must be a document, but got {other}"
+        ))),
+    }
+}
+
+fn required_argument(operator: &str, arguments: &mut Document, name: &str) -> Result<Bson> {
+    arguments
+        .remove(name)
+        .ok_or_else(|| Error::Other(format!("{operator} is missing required argument, {name}")))
+}
+
+/// The name of the variable bound to each array element by `$map` and `$filter`
+fn variable_name_argument(operator: &str, arguments: &mut Document) -> Result<String> {
+    match arguments.remove("as") {
+        Some(Bson::String(name)) => Ok(name),
+        Some(other) => Err(Error::Other(format!(
+            "as argument to {operator} must be a string, but got {other}"
+        ))),
+        None => Ok("this".to_string()),
+    }
+}
+
+/// Infers types for operators that take a document of named arguments. Arguments that are not
+/// listed are still checked for parameters.
+fn infer_types_from_named_arguments(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    operator: &str,
+    operand: Bson,
+    required: &[(&str, Option<TypeConstraint>)],
+    optional: &[(&str, Option<TypeConstraint>)],
+) -> Result<()> {
+    let mut arguments = document_operand(operator, operand)?;
+    for (name, _) in required {
+        if !arguments.contains_key(name) {
+            return Err(Error::Other(format!(
+                "{operator} is missing required argument, {name}"
+            )));
+        }
+    }
+    arguments.into_iter().try_for_each(|(name, argument)| {
+        let type_hint = required
+            .iter()
+            .chain(optional)
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, t)| t.as_ref());
+        infer_type_from_aggregation_expression(
+            context,
+            &format!("{desired_object_type_name}_{name}"),
+            type_hint,
+            argument,
+        )?;
+        Ok(())
+    })
+}
+
+/// Infers types for operators that take an array of arguments with different types. Arguments
+/// after the first `required_count` may be omitted.
+fn infer_types_from_positional_arguments(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    operator: &str,
+    operand: Bson,
+    required_count: usize,
+    argument_types: &[Option<TypeConstraint>],
+) -> Result<Vec<TypeConstraint>> {
+    let arguments = match operand {
+        Bson::Array(arguments) => arguments,
+        other => Err(Error::ExpectedArrayExpressionArgument {
+            actual_argument: Box::new(other),
+        })?,
+    };
+    if arguments.len() < required_count || arguments.len() > argument_types.len() {
+        return Err(Error::Other(format!(
+            "{operator} expects between {required_count} and {} arguments, but got {}",
+            argument_types.len(),
+            arguments.len()
+        )));
+    }
+    arguments
+        .into_iter()
+        .zip(argument_types)
+        .map(|(argument, argument_type)| {
+            infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                argument_type.as_ref(),
+                argument,
+            )
+        })
+        .collect()
+}
+
 fn two_parameter_operand(operator: &str, operand: Bson) -> Result<(Bson, Bson)> {
     match operand {
         Bson::Array(operands) => {
@@ -347,7 +1152,27 @@ pub fn infer_type_from_reference_shorthand(
                 .chain(type_annotation.map(TypeConstraint::from));
             context.register_parameter(name.into(), constraints)
         }
-        Reference::PipelineVariable { name, .. } => Err(Error::Other(format!("Encountered a pipeline variable, $${name}. Pipeline variables are currently not supported.")))?,
+        Reference::PipelineVariable { name, nested_path } => {
+            let variable_type = match name.as_str() {
+                "CURRENT" | "ROOT" => context.get_input_document_type()?,
+                "NOW" => C::Scalar(BsonScalarType::Date),
+                "CLUSTER_TIME" => C::Scalar(BsonScalarType::Timestamp),
+                "REMOVE" => C::Scalar(BsonScalarType::Null),
+                // Results of `$redact` expressions
+                "DESCEND" | "PRUNE" | "KEEP" => C::Scalar(BsonScalarType::String),
+                _ => match context.get_pipeline_variable(&name) {
+                    Some(t) => t.clone(),
+                    None => Err(Error::Other(format!("Encountered a pipeline variable, $${name}. Only system variables and variables defined with let in a $lookup stage are currently supported.")))?,
+                },
+            };
+            match NonEmpty::from_vec(nested_path) {
+                Some(path) => C::FieldOf {
+                    target_type: Box::new(variable_type),
+                    path,
+                },
+                None => variable_type,
+            }
+        }
         Reference::InputDocumentField { name, nested_path } => {
             let doc_type = context.get_input_document_type()?;
             let path = NonEmpty {
@@ -0,0 +1,726 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use configuration::{
+    schema::{ObjectField, ObjectType, Type},
+    serialized::NativeQuery,
+};
+use itertools::Itertools as _;
+use ndc_models::{FieldName, ObjectTypeName};
+
+type ObjectTypes = BTreeMap<ObjectTypeName, ObjectType>;
+
+/// Reconciles a native query with freshly-inferred types against the existing configuration for
+/// that query. This follows the same rules that introspection uses to keep backward-compatible
+/// changes to collection schemas: existing descriptions, argument types, field types, and object
+/// type names win, while new arguments, fields, and object types are added. Arguments that the
+/// pipeline no longer references are removed.
+///
+/// Object types are matched by their position in the result document type, or in argument types,
+/// so inferred types are mapped onto existing types even if those have been renamed.
+pub fn keep_manual_edits(existing: NativeQuery, inferred: NativeQuery) -> NativeQuery {
+    reconcile(existing, inferred).0
+}
+
+/// Lists inferred changes that were not applied by [keep_manual_edits], or returns `None` if every
+/// inferred change was applied. Those are inferred argument and field types that conflict with
+/// existing types, and existing fields that the pipeline no longer outputs. Differences in
+/// descriptions and object type names are not listed.
+pub fn ignored_changes(existing: NativeQuery, inferred: NativeQuery) -> Option<String> {
+    let (_, ignored_changes) = reconcile(existing, inferred);
+    if ignored_changes.is_empty() {
+        return None;
+    }
+    Some(ignored_changes.iter().map(ToString::to_string).join("\n"))
+}
+
+fn reconcile(existing: NativeQuery, inferred: NativeQuery) -> (NativeQuery, Vec<IgnoredChange>) {
+    let mut reconciler = Reconciler {
+        existing_object_types: existing.object_types,
+        inferred_object_types: inferred.object_types,
+        accumulated_object_types: Default::default(),
+        renamed_types: Default::default(),
+        ignored_changes: Default::default(),
+    };
+
+    let result_document_type = reconciler.reconcile_object_type(
+        Some(existing.result_document_type),
+        inferred.result_document_type,
+    );
+
+    let mut existing_arguments = existing.arguments;
+    let arguments = inferred
+        .arguments
+        .into_iter()
+        .map(|(name, inferred_argument)| {
+            let argument = match existing_arguments.remove(&name) {
+                Some(existing_argument) => ObjectField {
+                    r#type: reconciler.reconcile_types(
+                        || format!("argument {name}"),
+                        existing_argument.r#type,
+                        inferred_argument.r#type,
+                    ),
+                    description: existing_argument
+                        .description
+                        .or(inferred_argument.description),
+                },
+                None => ObjectField {
+                    r#type: reconciler.inferred_type(inferred_argument.r#type),
+                    description: inferred_argument.description,
+                },
+            };
+            (name, argument)
+        })
+        .collect();
+
+    let native_query = NativeQuery {
+        representation: existing.representation,
+        input_collection: inferred.input_collection,
+        arguments,
+        result_document_type,
+        object_types: reconciler.accumulated_object_types,
+        pipeline: inferred.pipeline,
+        description: existing.description.or(inferred.description),
+    };
+    (native_query, reconciler.ignored_changes)
+}
+
+/// An inferred change that [keep_manual_edits] did not apply
+#[derive(Debug)]
+enum IgnoredChange {
+    /// An inferred argument or field type conflicts with the existing type
+    TypeConflict {
+        location: String,
+        existing: Type,
+        /// Inferred type with references to inferred object types replaced by the names of the
+        /// corresponding reconciled types
+        inferred: Type,
+    },
+    /// An existing field is kept even though the pipeline no longer outputs it
+    RemovedField {
+        object_type_name: ObjectTypeName,
+        field_name: FieldName,
+        existing: Type,
+    },
+}
+
+impl Display for IgnoredChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IgnoredChange::TypeConflict {
+                location,
+                existing,
+                inferred,
+            } => write!(
+                f,
+                "{location}: kept existing type {existing}, inferred type is {inferred}"
+            ),
+            IgnoredChange::RemovedField {
+                object_type_name,
+                field_name,
+                existing,
+            } => write!(
+                f,
+                "field {field_name} of object type {object_type_name}: kept existing field with type {existing}, the pipeline no longer outputs this field"
+            ),
+        }
+    }
+}
+
+struct Reconciler {
+    existing_object_types: ObjectTypes,
+    inferred_object_types: ObjectTypes,
+    accumulated_object_types: ObjectTypes,
+    /// Names of object types in the reconciled configuration for inferred type names that have
+    /// been visited
+    renamed_types: BTreeMap<ObjectTypeName, ObjectTypeName>,
+    ignored_changes: Vec<IgnoredChange>,
+}
+
+impl Reconciler {
+    /// Returns the name of the object type in the reconciled configuration that corresponds to
+    /// the given inferred type.
+    fn reconcile_object_type(
+        &mut self,
+        existing_name: Option<ObjectTypeName>,
+        inferred_name: ObjectTypeName,
+    ) -> ObjectTypeName {
+        if let Some(name) = self.renamed_types.get(&inferred_name) {
+            return name.clone();
+        }
+        let existing = existing_name
+            .as_ref()
+            .filter(|name| !self.accumulated_object_types.contains_key(*name))
+            .and_then(|name| self.existing_object_types.remove(name));
+        let inferred = self.inferred_object_types.remove(&inferred_name);
+        match (existing_name, existing, inferred) {
+            (Some(existing_name), Some(existing), Some(inferred)) => {
+                self.renamed_types
+                    .insert(inferred_name, existing_name.clone());
+                // Reserve the name before visiting fields in case of recursive types
+                self.accumulated_object_types
+                    .insert(existing_name.clone(), existing.clone());
+                let object_type = self.reconcile_object_fields(&existing_name, existing, inferred);
+                self.accumulated_object_types
+                    .insert(existing_name.clone(), object_type);
+                existing_name
+            }
+            (Some(existing_name), Some(existing), None) => {
+                // The inferred type is defined in the connector schema, but the existing native
+                // query uses its own type.
+                self.keep_existing_object_type(existing_name.clone(), existing);
+                existing_name
+            }
+            (existing_name, existing, Some(inferred)) => {
+                // Put back an unmatched existing type so that other references can still use it
+                if let (Some(name), Some(existing)) = (existing_name, existing) {
+                    self.existing_object_types.insert(name, existing);
+                }
+                let name = self.unique_type_name(&inferred_name);
+                self.renamed_types.insert(inferred_name, name.clone());
+                self.accumulated_object_types
+                    .insert(name.clone(), inferred.clone());
+                let object_type = ObjectType {
+                    fields: inferred
+                        .fields
+                        .into_iter()
+                        .map(|(field_name, field)| {
+                            let field = ObjectField {
+                                r#type: self.inferred_type(field.r#type),
+                                description: field.description,
+                            };
+                            (field_name, field)
+                        })
+                        .collect(),
+                    description: inferred.description,
+                };
+                self.accumulated_object_types
+                    .insert(name.clone(), object_type);
+                name
+            }
+            // Both names refer to types from the connector schema, or to a type that has already
+            // been reconciled
+            (existing_name, _, None) => match existing_name {
+                Some(existing_name)
+                    if self.accumulated_object_types.contains_key(&existing_name) =>
+                {
+                    existing_name
+                }
+                _ => inferred_name,
+            },
+        }
+    }
+
+    fn reconcile_object_fields(
+        &mut self,
+        object_type_name: &ObjectTypeName,
+        existing: ObjectType,
+        mut inferred: ObjectType,
+    ) -> ObjectType {
+        let field_names = inferred
+            .fields
+            .keys()
+            .chain(existing.fields.keys())
+            .unique()
+            .cloned()
+            .collect_vec();
+        let fields = field_names
+            .into_iter()
+            .map(|name| {
+                let existing_field = existing.fields.get(&name).cloned();
+                let inferred_field = inferred.fields.remove(&name);
+                let field = match (existing_field, inferred_field) {
+                    (Some(existing_field), Some(inferred_field)) => ObjectField {
+                        r#type: self.reconcile_types(
+                            || format!("field {name} of object type {object_type_name}"),
+                            existing_field.r#type,
+                            inferred_field.r#type,
+                        ),
+                        description: existing_field.description.or(inferred_field.description),
+                    },
+                    (Some(existing_field), None) => {
+                        self.keep_existing_type(&existing_field.r#type);
+                        self.ignored_changes.push(IgnoredChange::RemovedField {
+                            object_type_name: object_type_name.clone(),
+                            field_name: name.clone(),
+                            existing: existing_field.r#type.clone(),
+                        });
+                        existing_field
+                    }
+                    (None, Some(inferred_field)) => ObjectField {
+                        r#type: self.inferred_type(inferred_field.r#type),
+                        description: inferred_field.description,
+                    },
+                    (None, None) => unreachable!(),
+                };
+                (name, field)
+            })
+            .collect();
+        ObjectType {
+            description: existing.description.or(inferred.description),
+            fields,
+        }
+    }
+
+    /// Reconciles the type of an argument or field, and records a conflict if the inferred type
+    /// was not applied.
+    fn reconcile_types(
+        &mut self,
+        location: impl FnOnce() -> String,
+        existing_type: Type,
+        inferred_type: Type,
+    ) -> Type {
+        let reconciled = self.reconcile_type(existing_type, inferred_type.clone());
+        let inferred = self.renamed_type(inferred_type);
+        if reconciled != inferred {
+            self.ignored_changes.push(IgnoredChange::TypeConflict {
+                location: location(),
+                existing: reconciled.clone(),
+                inferred,
+            });
+        }
+        reconciled
+    }
+
+    fn reconcile_type(&mut self, existing_type: Type, inferred_type: Type) -> Type {
+        match (existing_type, inferred_type) {
+            (Type::Nullable(a), Type::Nullable(b)) => {
+                Type::Nullable(Box::new(self.reconcile_type(*a, *b)))
+            }
+            (Type::Nullable(a), b) => Type::Nullable(Box::new(self.reconcile_type(*a, b))),
+            (a, Type::Nullable(b)) => self.reconcile_type(a, *b),
+            (Type::ArrayOf(a), Type::ArrayOf(b)) => {
+                Type::ArrayOf(Box::new(self.reconcile_type(*a, *b)))
+            }
+            (Type::Object(a), Type::Object(b)) => Type::Object(
+                self.reconcile_object_type(Some(a.into()), b.into())
+                    .to_string(),
+            ),
+            (a, _) => {
+                self.keep_existing_type(&a);
+                a
+            }
+        }
+    }
+
+    /// Replaces references to inferred object types with the names of reconciled types
+    fn renamed_type(&self, inferred_type: Type) -> Type {
+        let rename = |name: ObjectTypeName| self.renamed_types.get(&name).cloned().unwrap_or(name);
+        match inferred_type {
+            Type::Object(name) => Type::Object(rename(name.into()).to_string()),
+            Type::ArrayOf(t) => Type::ArrayOf(Box::new(self.renamed_type(*t))),
+            Type::Nullable(t) => Type::Nullable(Box::new(self.renamed_type(*t))),
+            Type::Predicate { object_type_name } => Type::Predicate {
+                object_type_name: rename(object_type_name),
+            },
+            t @ (Type::ExtendedJSON | Type::Scalar(_)) => t,
+        }
+    }
+
+    /// Maps references to inferred object types in a type that has no existing counterpart
+    fn inferred_type(&mut self, inferred_type: Type) -> Type {
+        match inferred_type {
+            Type::Object(name) => {
+                Type::Object(self.reconcile_object_type(None, name.into()).to_string())
+            }
+            Type::ArrayOf(t) => Type::ArrayOf(Box::new(self.inferred_type(*t))),
+            Type::Nullable(t) => Type::Nullable(Box::new(self.inferred_type(*t))),
+            Type::Predicate { object_type_name } => Type::Predicate {
+                object_type_name: self.reconcile_object_type(None, object_type_name),
+            },
+            t @ (Type::ExtendedJSON | Type::Scalar(_)) => t,
+        }
+    }
+
+    /// Carries over existing object types that are referenced by a kept existing type
+    fn keep_existing_type(&mut self, existing_type: &Type) {
+        match existing_type {
+            Type::Object(name) => {
+                let name: ObjectTypeName = name.clone().into();
+                if let Some(object_type) = self.existing_object_types.remove(&name) {
+                    self.keep_existing_object_type(name, object_type);
+                }
+            }
+            Type::Predicate { object_type_name } => {
+                if let Some(object_type) = self.existing_object_types.remove(object_type_name) {
+                    self.keep_existing_object_type(object_type_name.clone(), object_type);
+                }
+            }
+            Type::ArrayOf(t) | Type::Nullable(t) => self.keep_existing_type(t),
+            Type::ExtendedJSON | Type::Scalar(_) => (),
+        }
+    }
+
+    fn keep_existing_object_type(&mut self, name: ObjectTypeName, object_type: ObjectType) {
+        self.accumulated_object_types
+            .insert(name, object_type.clone());
+        for field in object_type.fields.values() {
+            self.keep_existing_type(&field.r#type);
+        }
+    }
+
+    fn unique_type_name(&self, desired_name: &ObjectTypeName) -> ObjectTypeName {
+        let is_taken = |name: &ObjectTypeName| {
+            self.accumulated_object_types.contains_key(name)
+                || self.existing_object_types.contains_key(name)
+        };
+        if !is_taken(desired_name) {
+            return desired_name.clone();
+        }
+        let mut counter = 2;
+        loop {
+            let name: ObjectTypeName = format!("{desired_name}_{counter}").into();
+            if !is_taken(&name) {
+                return name;
+            }
+            counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::{
+        native_query::NativeQueryRepresentation::Collection,
+        schema::{ObjectField, ObjectType, Type},
+        serialized::NativeQuery,
+    };
+    use mongodb::bson::doc;
+    use mongodb_support::BsonScalarType;
+    use pretty_assertions::assert_eq;
+
+    use super::{ignored_changes, keep_manual_edits};
+
+    fn field(r#type: Type, description: Option<&str>) -> ObjectField {
+        ObjectField {
+            r#type,
+            description: description.map(ToString::to_string),
+        }
+    }
+
+    #[test]
+    fn keeps_renamed_types_descriptions_and_argument_types() -> anyhow::Result<()> {
+        let existing = NativeQuery {
+            representation: Collection,
+            input_collection: Some("movies".into()),
+            arguments: [(
+                "year".into(),
+                field(Type::Scalar(BsonScalarType::Long), Some("release year")),
+            )]
+            .into(),
+            result_document_type: "MovieSummary".into(),
+            object_types: [(
+                "MovieSummary".into(),
+                ObjectType {
+                    fields: [(
+                        "title".into(),
+                        field(Type::Scalar(BsonScalarType::String), Some("movie title")),
+                    )]
+                    .into(),
+                    description: Some("a movie".to_string()),
+                },
+            )]
+            .into(),
+            pipeline: vec![doc! { "$match": { "year": "{{ year }}" } }],
+            description: Some("movies by year".to_string()),
+        };
+        let inferred = NativeQuery {
+            representation: Collection,
+            input_collection: Some("movies".into()),
+            arguments: [
+                (
+                    "year".into(),
+                    field(Type::Scalar(BsonScalarType::Int), None),
+                ),
+                (
+                    "rated".into(),
+                    field(Type::Scalar(BsonScalarType::String), None),
+                ),
+            ]
+            .into(),
+            result_document_type: "movies_by_year_project".into(),
+            object_types: [(
+                "movies_by_year_project".into(),
+                ObjectType {
+                    fields: [
+                        (
+                            "title".into(),
+                            field(Type::Scalar(BsonScalarType::String), None),
+                        ),
+                        (
+                            "runtime".into(),
+                            field(Type::Scalar(BsonScalarType::Int), None),
+                        ),
+                    ]
+                    .into(),
+                    description: None,
+                },
+            )]
+            .into(),
+            pipeline: vec![doc! { "$match": { "year": "{{ year }}", "rated": "{{ rated }}" } }],
+            description: None,
+        };
+
+        let reconciled = keep_manual_edits(existing.clone(), inferred.clone());
+
+        let expected = NativeQuery {
+            representation: Collection,
+            input_collection: Some("movies".into()),
+            arguments: [
+                (
+                    "year".into(),
+                    field(Type::Scalar(BsonScalarType::Long), Some("release year")),
+                ),
+                (
+                    "rated".into(),
+                    field(Type::Scalar(BsonScalarType::String), None),
+                ),
+            ]
+            .into(),
+            result_document_type: "MovieSummary".into(),
+            object_types: [(
+                "MovieSummary".into(),
+                ObjectType {
+                    fields: [
+                        (
+                            "title".into(),
+                            field(Type::Scalar(BsonScalarType::String), Some("movie title")),
+                        ),
+                        (
+                            "runtime".into(),
+                            field(Type::Scalar(BsonScalarType::Int), None),
+                        ),
+                    ]
+                    .into(),
+                    description: Some("a movie".to_string()),
+                },
+            )]
+            .into(),
+            pipeline: vec![doc! { "$match": { "year": "{{ year }}", "rated": "{{ rated }}" } }],
+            description: Some("movies by year".to_string()),
+        };
+        assert_eq!(reconciled, expected);
+        assert_eq!(
+            ignored_changes(existing, inferred),
+            Some("argument year: kept existing type long!, inferred type is int!".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn adds_new_nested_object_types() -> anyhow::Result<()> {
+        let existing = NativeQuery {
+            representation: Collection,
+            input_collection: None,
+            arguments: Default::default(),
+            result_document_type: "Result".into(),
+            object_types: [(
+                "Result".into(),
+                ObjectType {
+                    fields: [(
+                        "count".into(),
+                        field(Type::Scalar(BsonScalarType::Int), None),
+                    )]
+                    .into(),
+                    description: None,
+                },
+            )]
+            .into(),
+            pipeline: vec![doc! { "$count": "count" }],
+            description: None,
+        };
+        let inferred = NativeQuery {
+            representation: Collection,
+            input_collection: None,
+            arguments: Default::default(),
+            result_document_type: "q_facet".into(),
+            object_types: [
+                (
+                    "q_facet".into(),
+                    ObjectType {
+                        fields: [
+                            (
+                                "count".into(),
+                                field(Type::Scalar(BsonScalarType::Int), None),
+                            ),
+                            (
+                                "stats".into(),
+                                field(Type::Object("q_facet_stats".to_string()), None),
+                            ),
+                        ]
+                        .into(),
+                        description: None,
+                    },
+                ),
+                (
+                    "q_facet_stats".into(),
+                    ObjectType {
+                        fields: [("max".into(), field(Type::Scalar(BsonScalarType::Int), None))]
+                            .into(),
+                        description: None,
+                    },
+                ),
+            ]
+            .into(),
+            pipeline: vec![doc! { "$count": "count" }],
+            description: None,
+        };
+
+        let reconciled = keep_manual_edits(existing, inferred.clone());
+
+        assert_eq!(reconciled.result_document_type, "Result".into());
+        assert_eq!(
+            reconciled.object_types["Result"].fields["stats"].r#type,
+            Type::Object("q_facet_stats".to_string())
+        );
+        assert_eq!(reconciled.object_types.len(), 2);
+        assert!(reconciled.object_types.contains_key("q_facet_stats"));
+        Ok(())
+    }
+
+    #[test]
+    fn reports_no_ignored_changes_for_descriptions_and_renamed_types() {
+        let existing = NativeQuery {
+            representation: Collection,
+            input_collection: Some("movies".into()),
+            arguments: [(
+                "title".into(),
+                field(Type::Scalar(BsonScalarType::String), Some("movie title")),
+            )]
+            .into(),
+            result_document_type: "MovieWithCredits".into(),
+            object_types: [
+                (
+                    "MovieWithCredits".into(),
+                    ObjectType {
+                        fields: [(
+                            "credits".into(),
+                            field(Type::Object("Credits".to_string()), Some("cast and crew")),
+                        )]
+                        .into(),
+                        description: Some("a movie".to_string()),
+                    },
+                ),
+                (
+                    "Credits".into(),
+                    ObjectType {
+                        fields: [(
+                            "director".into(),
+                            field(Type::Scalar(BsonScalarType::String), None),
+                        )]
+                        .into(),
+                        description: None,
+                    },
+                ),
+            ]
+            .into(),
+            pipeline: vec![doc! { "$match": { "title": "{{ title }}" } }],
+            description: Some("movie by title".to_string()),
+        };
+        let inferred = NativeQuery {
+            representation: Collection,
+            input_collection: Some("movies".into()),
+            arguments: [(
+                "title".into(),
+                field(Type::Scalar(BsonScalarType::String), None),
+            )]
+            .into(),
+            result_document_type: "movie_by_title_project".into(),
+            object_types: [
+                (
+                    "movie_by_title_project".into(),
+                    ObjectType {
+                        fields: [(
+                            "credits".into(),
+                            field(
+                                Type::Object("movie_by_title_project_credits".to_string()),
+                                None,
+                            ),
+                        )]
+                        .into(),
+                        description: None,
+                    },
+                ),
+                (
+                    "movie_by_title_project_credits".into(),
+                    ObjectType {
+                        fields: [(
+                            "director".into(),
+                            field(Type::Scalar(BsonScalarType::String), None),
+                        )]
+                        .into(),
+                        description: None,
+                    },
+                ),
+            ]
+            .into(),
+            pipeline: vec![doc! { "$match": { "title": "{{ title }}" } }],
+            description: None,
+        };
+
+        assert_eq!(ignored_changes(existing, inferred), None);
+    }
+
+    #[test]
+    fn reports_fields_the_pipeline_no_longer_outputs() {
+        let existing = NativeQuery {
+            representation: Collection,
+            input_collection: Some("movies".into()),
+            arguments: Default::default(),
+            result_document_type: "MovieSummary".into(),
+            object_types: [(
+                "MovieSummary".into(),
+                ObjectType {
+                    fields: [
+                        (
+                            "title".into(),
+                            field(Type::Scalar(BsonScalarType::String), None),
+                        ),
+                        (
+                            "runtime".into(),
+                            field(Type::Scalar(BsonScalarType::Int), Some("minutes")),
+                        ),
+                    ]
+                    .into(),
+                    description: None,
+                },
+            )]
+            .into(),
+            pipeline: vec![doc! { "$project": { "title": 1, "runtime": 1 } }],
+            description: None,
+        };
+        let inferred = NativeQuery {
+            representation: Collection,
+            input_collection: Some("movies".into()),
+            arguments: Default::default(),
+            result_document_type: "movie_summary_project".into(),
+            object_types: [(
+                "movie_summary_project".into(),
+                ObjectType {
+                    fields: [(
+                        "title".into(),
+                        field(Type::Scalar(BsonScalarType::String), None),
+                    )]
+                    .into(),
+                    description: None,
+                },
+            )]
+            .into(),
+            pipeline: vec![doc! { "$project": { "title": 1 } }],
+            description: None,
+        };
+
+        let reconciled = keep_manual_edits(existing.clone(), inferred.clone());
+
+        assert_eq!(
+            reconciled.object_types["MovieSummary"].fields["runtime"],
+            field(Type::Scalar(BsonScalarType::Int), Some("minutes"))
+        );
+        assert_eq!(
+            ignored_changes(existing, inferred),
+            Some(
+                "field runtime of object type MovieSummary: kept existing field with type int!, the pipeline no longer outputs this field"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -1,11 +1,13 @@
 mod aggregation_expression;
 pub mod error;
 mod helpers;
+mod keep_manual_edits;
 mod pipeline;
 mod pipeline_type_context;
 mod pretty_printing;
 mod prune_object_types;
 mod reference_shorthand;
+mod run_native_query;
 mod type_annotation;
 mod type_constraint;
 mod type_solver;
@@ -23,8 +25,10 @@ use configuration::{
     native_query::NativeQueryRepresentation::Collection, serialized::NativeQuery, Configuration,
 };
 use configuration::{read_directory_with_ignored_configs, read_native_query_directory, WithName};
-use mongodb_support::aggregate::Pipeline;
-use ndc_models::{CollectionName, FunctionName};
+use mongodb::bson::{self, Document};
+use mongodb_agent_common::state::try_init_state_from_uri;
+use mongodb_support::aggregate::{Pipeline, Stage};
+use ndc_models::{ArgumentName, CollectionName, FunctionName};
 use pretty::termcolor::{ColorChoice, StandardStream};
 use pretty_printing::pretty_print_native_query;
 use tokio::fs;
@@ -33,8 +37,13 @@ use crate::exit_codes::ExitCode;
 use crate::Context;
 
 use self::error::Result;
+use self::keep_manual_edits::{ignored_changes, keep_manual_edits};
 use self::pipeline::infer_pipeline_types;
 use self::pretty_printing::pretty_print_native_query_info;
+use self::run_native_query::{
+    arguments_to_bson, check_result_documents, parse_argument, pipeline_with_arguments,
+    pipeline_with_limit, run_pipeline, ArgumentValue, CheckedDocument,
+};
 
 /// [BETA] Create or manage native queries - custom MongoDB queries that integrate into your data graph
 #[derive(Clone, Debug, Subcommand)]
@@ -68,6 +77,38 @@ pub enum Command {
     /// Print details of a native query identified by name. Use the list subcommand to see native
     /// query names.
     Show { native_query_name: String },
+
+    /// Re-infer types for an existing native query, keeping manual edits to descriptions,
+    /// argument types, and object types. Prints inferred changes that were not applied.
+    Update {
+        /// Name of the native query to update. Use the list subcommand to see native query names.
+        native_query_name: String,
+
+        /// Name of the collection that acts as input for the pipeline (defaults to the input
+        /// collection of the existing native query)
+        #[arg(long, short = 'c')]
+        collection: Option<CollectionName>,
+
+        /// Path to a JSON file with a new aggregation pipeline for the native query (defaults to
+        /// the pipeline in the existing native query configuration)
+        pipeline_path: Option<PathBuf>,
+    },
+
+    /// Run a native query against the configured database, and check that results match the
+    /// native query's result document type. Prints results, and any type mismatches.
+    Test {
+        /// Name of the native query to run. Use the list subcommand to see native query names.
+        native_query_name: String,
+
+        /// Argument value for the native query in the form name=value. Values are parsed as JSON,
+        /// or are read as strings if they are not valid JSON. May be given multiple times.
+        #[arg(long = "arg", short = 'a', value_name = "NAME=VALUE", value_parser = parse_argument)]
+        arguments: Vec<(ArgumentName, ArgumentValue)>,
+
+        /// Maximum number of result documents to read. Use 0 to read every document.
+        #[arg(long, short = 'l', default_value_t = 20)]
+        limit: u32,
+    },
 }
 
 pub async fn run(context: &Context, command: Command) -> anyhow::Result<()> {
@@ -81,6 +122,32 @@ pub async fn run(context: &Context, command: Command) -> anyhow::Result<()> {
         Command::Delete { native_query_name } => delete(context, &native_query_name).await,
         Command::List => list(context).await,
         Command::Show { native_query_name } => show(context, &native_query_name).await,
+        Command::Update {
+            native_query_name,
+            collection,
+            pipeline_path,
+        } => {
+            update(
+                context,
+                &native_query_name,
+                collection,
+                pipeline_path.as_deref(),
+            )
+            .await
+        }
+        Command::Test {
+            native_query_name,
+            arguments,
+            limit,
+        } => {
+            test(
+                context,
+                &native_query_name,
+                arguments.into_iter().collect(),
+                limit,
+            )
+            .await
+        }
     }
 }
 
@@ -192,6 +259,146 @@ async fn create(
     Ok(())
 }
 
+async fn update(
+    context: &Context,
+    native_query_name: &str,
+    collection: Option<CollectionName>,
+    pipeline_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    let (existing, native_query_path) = find_native_query(context, native_query_name).await?;
+
+    let configuration =
+        read_configuration(context, std::slice::from_ref(&native_query_path)).await?;
+
+    let pipeline = match pipeline_path {
+        Some(pipeline_path) => read_pipeline(pipeline_path).await,
+        None => pipeline_from_documents(existing.pipeline.clone()),
+    };
+    let pipeline = match pipeline {
+        Ok(p) => p,
+        Err(err) => {
+            write_stderr(&format!("Could not read aggregation pipeline.\n\n{err}"));
+            exit(ExitCode::CouldNotReadAggregationPipeline.into())
+        }
+    };
+
+    let input_collection = collection.or_else(|| existing.input_collection.clone());
+    let inferred = match native_query_from_pipeline(
+        &configuration,
+        native_query_name,
+        input_collection,
+        pipeline,
+    ) {
+        Ok(q) => q,
+        Err(err) => {
+            eprintln!();
+            write_stderr(&err.to_string());
+            eprintln!();
+            write_stderr(&format!("If you are not able to resolve this error you can update the native query by editing the configuration file directly in {}.", native_query_path.to_string_lossy()));
+            exit(ExitCode::CouldNotProcessAggregationPipeline.into())
+        }
+    };
+
+    let ignored_changes = ignored_changes(existing.clone(), inferred.clone());
+    let native_query = keep_manual_edits(existing, inferred);
+    let native_query = WithName::named(native_query_name.to_string(), native_query);
+
+    if let Err(err) = fs::write(
+        &native_query_path,
+        serde_json::to_string_pretty(&native_query)?,
+    )
+    .await
+    {
+        write_stderr(&format!("Error writing native query configuration: {err}"));
+        exit(ExitCode::ErrorWriting.into())
+    };
+    eprintln!(
+        "\nUpdated native query configuration at {}",
+        native_query_path.to_string_lossy()
+    );
+
+    if let Some(changes) = ignored_changes {
+        eprintln!();
+        write_stderr("Warning: some inferred changes conflict with manual edits to the existing configuration, and were **not** applied. To apply them edit the configuration file directly, or re-create the native query with the create subcommand and --force.");
+        eprintln!();
+        eprintln!("These inferred changes were **not** applied:");
+        eprintln!("{changes}");
+    }
+
+    eprintln!();
+    pretty_print_native_query_info(&mut stdout(context), &native_query.value).await?;
+    println!(); // blank line to avoid unterminated output indicator
+    Ok(())
+}
+
+async fn test(
+    context: &Context,
+    native_query_name: &str,
+    arguments: BTreeMap<ArgumentName, ArgumentValue>,
+    limit: u32,
+) -> anyhow::Result<()> {
+    let (native_query, _) = find_native_query(context, native_query_name).await?;
+    let configuration = read_configuration(context, &[]).await?;
+    let object_types = &configuration.object_types;
+
+    let pipeline = match arguments_to_bson(object_types, &native_query, arguments)
+        .and_then(|arguments| pipeline_with_arguments(&native_query, &arguments))
+    {
+        Ok(pipeline) => pipeline_with_limit(pipeline, limit),
+        Err(err) => {
+            write_stderr(&format!("Invalid arguments for native query.\n\n{err:#}"));
+            exit(ExitCode::InvalidArguments.into())
+        }
+    };
+
+    let connector_state = try_init_state_from_uri(context.connection_uri.as_ref()).await?;
+    let documents = match run_pipeline(
+        &connector_state.database(),
+        native_query.input_collection.as_ref(),
+        pipeline,
+    )
+    .await
+    {
+        Ok(documents) => documents,
+        Err(err) => {
+            write_stderr(&format!("Error running native query.\n\n{err:#}"));
+            exit(ExitCode::ErrorRunningNativeQuery.into())
+        }
+    };
+
+    let document_count = documents.len();
+    let mode = configuration
+        .options
+        .serialization_options
+        .extended_json_mode;
+    let mut mismatch_count = 0;
+    for (index, checked) in check_result_documents(object_types, &native_query, mode, documents)?
+        .into_iter()
+        .enumerate()
+    {
+        match checked {
+            CheckedDocument::Matches(value) => {
+                println!("{}", serde_json::to_string_pretty(&value)?)
+            }
+            CheckedDocument::Mismatch { document, error } => {
+                mismatch_count += 1;
+                println!("{}", serde_json::to_string_pretty(&document)?);
+                write_stderr(&format!(
+                    "Type mismatch: result document {index} does not match result document type {}: {error}",
+                    native_query.result_document_type
+                ));
+            }
+        }
+    }
+
+    eprintln!();
+    eprintln!("{document_count} documents returned, {mismatch_count} type mismatches");
+    if mismatch_count > 0 {
+        exit(ExitCode::ResultTypeMismatch.into())
+    }
+    Ok(())
+}
+
 /// Reads configuration, or exits with specific error code on error
 async fn read_configuration(
     context: &Context,
@@ -248,6 +455,15 @@ async fn read_pipeline(pipeline_path: &Path) -> anyhow::Result<Pipeline> {
     Ok(pipeline)
 }
 
+/// Parses stages of a pipeline stored in native query configuration
+fn pipeline_from_documents(stages: Vec<Document>) -> anyhow::Result<Pipeline> {
+    let pipeline = stages
+        .into_iter()
+        .map(bson::from_document::<Stage>)
+        .collect::<Result<_, _>>()?;
+    Ok(pipeline)
+}
+
 fn get_native_query_path(context: &Context, name: &str) -> PathBuf {
     context
         .path
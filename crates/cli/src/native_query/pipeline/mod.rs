@@ -1,15 +1,19 @@
 mod match_stage;
 mod project_stage;
 
-use std::{collections::BTreeMap, iter::once};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    iter::once,
+};
 
 use configuration::Configuration;
-use mongodb::bson::{Bson, Document};
+use mongodb::bson::{self, Bson, Document};
 use mongodb_support::{
     aggregate::{Accumulator, Pipeline, Stage},
     BsonScalarType,
 };
 use ndc_models::{CollectionName, FieldName, ObjectTypeName};
+use nonempty::NonEmpty;
 
 use super::{
     aggregation_expression::{
@@ -69,10 +73,11 @@ fn infer_stage_output_type(
     stage: &Stage,
 ) -> Result<Option<TypeConstraint>> {
     let output_type = match stage {
-        Stage::AddFields(_) => Err(Error::UnknownAggregationStage {
-            stage_index,
-            stage_name: Some("$addFields"),
-        })?,
+        Stage::AddFields(fields) => Some(infer_type_from_add_fields_stage(
+            context,
+            &format!("{desired_object_type_name}_addFields"),
+            fields,
+        )?),
         Stage::Documents(docs) => {
             let doc_constraints = docs
                 .iter()
@@ -115,10 +120,20 @@ fn infer_stage_output_type(
             )?;
             None
         }
-        Stage::Lookup { .. } => Err(Error::UnknownAggregationStage {
-            stage_index,
-            stage_name: Some("$lookup"),
-        })?,
+        Stage::Lookup {
+            from,
+            r#let,
+            pipeline,
+            r#as,
+            ..
+        } => Some(infer_type_from_lookup_stage(
+            context,
+            &format!("{desired_object_type_name}_lookup"),
+            from.as_deref(),
+            r#let.as_ref(),
+            pipeline.as_ref(),
+            r#as,
+        )?),
         Stage::Group {
             key_expression,
             accumulators,
@@ -131,14 +146,77 @@ fn infer_stage_output_type(
             )?;
             Some(TypeConstraint::Object(object_type_name))
         }
-        Stage::Facet(_) => Err(Error::UnknownAggregationStage {
-            stage_index,
-            stage_name: Some("$facet"),
-        })?,
-        Stage::Count(_) => Err(Error::UnknownAggregationStage {
-            stage_index,
-            stage_name: Some("$count"),
-        })?,
+        Stage::Bucket {
+            group_by,
+            boundaries,
+            default,
+            output,
+        } => {
+            let object_type_name = infer_type_from_bucket_stage(
+                context,
+                &format!("{desired_object_type_name}_bucket"),
+                group_by,
+                boundaries,
+                default.as_ref(),
+                output.as_ref(),
+            )?;
+            Some(TypeConstraint::Object(object_type_name))
+        }
+        Stage::BucketAuto {
+            group_by,
+            buckets,
+            output,
+            granularity: _,
+        } => {
+            let object_type_name = infer_type_from_bucket_auto_stage(
+                context,
+                &format!("{desired_object_type_name}_bucketAuto"),
+                group_by,
+                buckets,
+                output.as_ref(),
+            )?;
+            Some(TypeConstraint::Object(object_type_name))
+        }
+        Stage::SortByCount(expression) => {
+            let desired_object_type_name = format!("{desired_object_type_name}_sortByCount");
+            let key_type = infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_id"),
+                None,
+                expression.clone(),
+            )?;
+            let object_type = ObjectTypeConstraint {
+                fields: [
+                    ("_id".into(), key_type),
+                    ("count".into(), TypeConstraint::Scalar(BsonScalarType::Int)),
+                ]
+                .into(),
+            };
+            let object_type_name = context.unique_type_name(&desired_object_type_name);
+            context.insert_object_type(object_type_name.clone(), object_type);
+            Some(TypeConstraint::Object(object_type_name))
+        }
+        Stage::Facet(facets) => {
+            let object_type_name = infer_type_from_facet_stage(
+                context,
+                &format!("{desired_object_type_name}_facet"),
+                facets,
+            )?;
+            Some(TypeConstraint::Object(object_type_name))
+        }
+        Stage::Count(field_name) => {
+            let object_type = ObjectTypeConstraint {
+                fields: [(
+                    field_name.as_str().into(),
+                    TypeConstraint::Scalar(BsonScalarType::Int),
+                )]
+                .into(),
+            };
+            let object_type_name =
+                context.unique_type_name(&format!("{desired_object_type_name}_count"));
+            context.insert_object_type(object_type_name.clone(), object_type);
+            Some(TypeConstraint::Object(object_type_name))
+        }
         Stage::Project(doc) => {
             let augmented_type = project_stage::infer_type_from_project_stage(
                 context,
@@ -172,18 +250,282 @@ fn infer_stage_output_type(
             include_array_index.as_deref(),
             *preserve_null_and_empty_arrays,
         )?),
-        Stage::UnionWith { .. } => Err(Error::UnknownAggregationStage {
-            stage_index,
-            stage_name: Some("$unionWith"),
-        })?,
-        Stage::Other(_) => Err(Error::UnknownAggregationStage {
+        Stage::UnionWith { coll, pipeline } => {
+            let input_doc_type = context.get_input_document_type()?;
+            let coll_doc_type = TypeConstraint::Object(find_collection_object_type(
+                context.configuration(),
+                &coll.as_str().into(),
+            )?);
+            let branch_doc_type = match pipeline {
+                Some(pipeline) => infer_sub_pipeline_type(
+                    context,
+                    &format!("{desired_object_type_name}_unionWith"),
+                    Some(coll_doc_type),
+                    [],
+                    pipeline,
+                )?,
+                None => coll_doc_type,
+            };
+            // Documents from both sides of the union flow into the next stage so the output type
+            // must accommodate both
+            let type_variable =
+                context.new_type_variable(Variance::Covariant, [input_doc_type, branch_doc_type]);
+            Some(TypeConstraint::Variable(type_variable))
+        }
+        Stage::SetWindowFields {
+            partition_by,
+            sort_by: _,
+            output,
+        } => Some(infer_type_from_set_window_fields_stage(
+            context,
+            &format!("{desired_object_type_name}_setWindowFields"),
+            partition_by.as_ref(),
+            output,
+        )?),
+        Stage::Densify {
+            field,
+            partition_by_fields,
+            range: _,
+        } => Some(infer_type_from_densify_stage(
+            context,
+            &format!("{desired_object_type_name}_densify"),
             stage_index,
-            stage_name: None,
-        })?,
+            field,
+            partition_by_fields.as_deref().unwrap_or_default(),
+        )?),
+        Stage::Fill {
+            partition_by,
+            partition_by_fields: _,
+            sort_by: _,
+            output,
+        } => {
+            if let Some(partition_by) = partition_by {
+                infer_type_from_aggregation_expression(
+                    context,
+                    &format!("{desired_object_type_name}_fill_partitionBy"),
+                    None,
+                    partition_by.clone(),
+                )?;
+            }
+            // Filling replaces null and missing values so field types are unchanged. We still
+            // need to check fill values for parameters.
+            for (field_name, fill_spec) in output {
+                if let Some(value) = fill_spec.get("value") {
+                    infer_type_from_aggregation_expression(
+                        context,
+                        &format!("{desired_object_type_name}_fill_{field_name}"),
+                        None,
+                        value.clone(),
+                    )?;
+                }
+            }
+            None
+        }
+        Stage::Unset(field_names) => {
+            let fields = field_names
+                .iter()
+                .map(|field_name| {
+                    if field_name.contains('.') {
+                        return Err(Error::Other(format!("Removing nested fields with dotted paths in $unset is not supported: {field_name}")));
+                    }
+                    Ok((field_name.as_str().into(), None))
+                })
+                .collect::<Result<_>>()?;
+            Some(TypeConstraint::WithFieldOverrides {
+                augmented_object_type_name: format!("{desired_object_type_name}_unset").into(),
+                target_type: Box::new(context.get_input_document_type()?),
+                fields,
+            })
+        }
+        Stage::Sample { size } => {
+            infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                Some(&TypeConstraint::Scalar(BsonScalarType::Int)),
+                size.clone(),
+            )?;
+            None
+        }
+        Stage::Redact(expression) => {
+            infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_redact"),
+                None,
+                expression.clone(),
+            )?;
+            None
+        }
+        // `$set` is an alias for `$addFields`
+        Stage::Other(doc) => match doc.get_document("$set") {
+            Ok(fields) if doc.len() == 1 => Some(infer_type_from_add_fields_stage(
+                context,
+                &format!("{desired_object_type_name}_set"),
+                fields,
+            )?),
+            _ => Err(Error::UnknownAggregationStage {
+                stage_index,
+                stage_name: None,
+            })?,
+        },
     };
     Ok(output_type)
 }
 
+/// Infers the type of documents output by a sub-pipeline, such as the pipeline in a `$lookup`,
+/// `$facet`, or `$unionWith` stage. The given variables are in scope in addition to any variables
+/// from enclosing pipelines.
+fn infer_sub_pipeline_type(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    input_doc_type: Option<TypeConstraint>,
+    variables: impl IntoIterator<Item = (String, TypeConstraint)>,
+    pipeline: &Pipeline,
+) -> Result<TypeConstraint> {
+    fn helper(
+        context: &mut PipelineTypeContext<'_>,
+        desired_object_type_name: &str,
+        pipeline: &Pipeline,
+    ) -> Result<TypeConstraint> {
+        for (stage_index, stage) in pipeline.iter().enumerate() {
+            if let Some(output_type) =
+                infer_stage_output_type(context, desired_object_type_name, stage_index, stage)?
+            {
+                context.set_stage_doc_type(output_type);
+            }
+        }
+        context.get_input_document_type()
+    }
+
+    let outer = context.begin_sub_pipeline(input_doc_type, variables);
+    let result = helper(context, desired_object_type_name, pipeline);
+    context.end_sub_pipeline(outer);
+    result
+}
+
+fn infer_type_from_add_fields_stage(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    fields: &Document,
+) -> Result<TypeConstraint> {
+    let fields = fields
+        .iter()
+        .map(|(field_name, expression)| {
+            if field_name.contains('.') {
+                return Err(Error::Other(format!("Setting nested fields with dotted paths in $addFields or $set is not supported: {field_name}")));
+            }
+            let field_type = match expression {
+                // Assigning $$REMOVE removes the field from output documents
+                Bson::String(s) if s == "$$REMOVE" => None,
+                _ => Some(infer_type_from_aggregation_expression(
+                    context,
+                    &format!("{desired_object_type_name}_{field_name}"),
+                    None,
+                    expression.clone(),
+                )?),
+            };
+            Ok((field_name.as_str().into(), field_type))
+        })
+        .collect::<Result<_>>()?;
+
+    // Added fields are merged with fields from the output of the previous stage.
+    Ok(TypeConstraint::WithFieldOverrides {
+        augmented_object_type_name: desired_object_type_name.into(),
+        target_type: Box::new(context.get_input_document_type()?),
+        fields,
+    })
+}
+
+fn infer_type_from_lookup_stage(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    from: Option<&str>,
+    r#let: Option<&Document>,
+    pipeline: Option<&Pipeline>,
+    r#as: &str,
+) -> Result<TypeConstraint> {
+    if r#as.contains('.') {
+        return Err(Error::Other(format!(
+            "Writing $lookup results to a nested field with a dotted path is not supported: {}",
+            r#as
+        )));
+    }
+
+    // `let` expressions are evaluated against the local document, so they are typed before
+    // switching to the foreign pipeline.
+    let variables = r#let
+        .into_iter()
+        .flatten()
+        .map(|(name, expression)| {
+            let variable_type = infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_{name}"),
+                None,
+                expression.clone(),
+            )?;
+            Ok((name.clone(), variable_type))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let foreign_doc_type = from
+        .map(|collection_name| {
+            find_collection_object_type(context.configuration(), &collection_name.into())
+        })
+        .transpose()?
+        .map(TypeConstraint::Object);
+
+    let joined_doc_type = match pipeline {
+        Some(pipeline) => infer_sub_pipeline_type(
+            context,
+            desired_object_type_name,
+            foreign_doc_type,
+            variables,
+            pipeline,
+        )?,
+        None => foreign_doc_type.ok_or_else(|| {
+            Error::Other("$lookup stage must specify either from or pipeline".to_string())
+        })?,
+    };
+
+    Ok(TypeConstraint::WithFieldOverrides {
+        augmented_object_type_name: desired_object_type_name.into(),
+        target_type: Box::new(context.get_input_document_type()?),
+        fields: [(
+            r#as.into(),
+            Some(TypeConstraint::ArrayOf(Box::new(joined_doc_type))),
+        )]
+        .into(),
+    })
+}
+
+/// Each facet is a sub-pipeline that receives the input documents of the `$facet` stage. The
+/// stage outputs a single document with an array field for the output of each facet.
+fn infer_type_from_facet_stage(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    facets: &BTreeMap<String, Pipeline>,
+) -> Result<ObjectTypeName> {
+    let input_doc_type = context.get_input_document_type()?;
+    let fields = facets
+        .iter()
+        .map(|(facet_name, pipeline)| {
+            let facet_doc_type = infer_sub_pipeline_type(
+                context,
+                &format!("{desired_object_type_name}_{facet_name}"),
+                Some(input_doc_type.clone()),
+                [],
+                pipeline,
+            )?;
+            Ok((
+                facet_name.as_str().into(),
+                TypeConstraint::ArrayOf(Box::new(facet_doc_type)),
+            ))
+        })
+        .collect::<Result<_>>()?;
+    let object_type_name = context.unique_type_name(desired_object_type_name);
+    context.insert_object_type(object_type_name.clone(), ObjectTypeConstraint { fields });
+    Ok(object_type_name)
+}
+
 fn infer_type_from_group_stage(
     context: &mut PipelineTypeContext<'_>,
     desired_object_type_name: &str,
@@ -201,76 +543,380 @@ fn infer_type_from_group_stage(
         ("_id".into(), group_key_expression_type.clone());
 
     let accumulator_fields = accumulators.iter().map(|(key, accumulator)| {
-        let accumulator_type = match accumulator {
-            Accumulator::Count => TypeConstraint::Scalar(BsonScalarType::Int),
-            Accumulator::Min(expr) => infer_type_from_aggregation_expression(
+        let accumulator_type =
+            infer_type_from_accumulator(context, desired_object_type_name, accumulator)?;
+        Ok::<_, Error>((key.clone().into(), accumulator_type))
+    });
+
+    let fields = once(Ok(group_expression_field))
+        .chain(accumulator_fields)
+        .collect::<Result<_>>()?;
+    let object_type = ObjectTypeConstraint { fields };
+    let object_type_name = context.unique_type_name(desired_object_type_name);
+    context.insert_object_type(object_type_name.clone(), object_type);
+    Ok(object_type_name)
+}
+
+/// Output documents from `$bucket` have an `_id` field with the lower boundary of the bucket, or
+/// the `default` value for documents outside of the boundaries.
+fn infer_type_from_bucket_stage(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    group_by: &Bson,
+    boundaries: &[Bson],
+    default: Option<&Bson>,
+    output: Option<&BTreeMap<String, Accumulator>>,
+) -> Result<ObjectTypeName> {
+    infer_type_from_aggregation_expression(
+        context,
+        &format!("{desired_object_type_name}_groupBy"),
+        None,
+        group_by.clone(),
+    )?;
+    let boundary_types = boundaries
+        .iter()
+        .chain(default)
+        .map(|value| {
+            infer_type_from_aggregation_expression(
                 context,
-                &format!("{desired_object_type_name}_min"),
+                &format!("{desired_object_type_name}_id"),
                 None,
-                expr.clone(),
-            )?,
-            Accumulator::Max(expr) => infer_type_from_aggregation_expression(
+                value.clone(),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let id_type =
+        TypeConstraint::Variable(context.new_type_variable(Variance::Covariant, boundary_types));
+    infer_type_from_bucket_output(context, desired_object_type_name, id_type, output)
+}
+
+/// Output documents from `$bucketAuto` have an `_id` document with `min` and `max` fields with
+/// the bounds of the bucket.
+fn infer_type_from_bucket_auto_stage(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    group_by: &Bson,
+    buckets: &Bson,
+    output: Option<&BTreeMap<String, Accumulator>>,
+) -> Result<ObjectTypeName> {
+    infer_type_from_aggregation_expression(
+        context,
+        &format!("{desired_object_type_name}_buckets"),
+        Some(&TypeConstraint::Scalar(BsonScalarType::Int)),
+        buckets.clone(),
+    )?;
+    let group_by_type = infer_type_from_aggregation_expression(
+        context,
+        &format!("{desired_object_type_name}_groupBy"),
+        None,
+        group_by.clone(),
+    )?;
+    let id_object_type = ObjectTypeConstraint {
+        fields: [
+            ("min".into(), group_by_type.clone()),
+            ("max".into(), group_by_type),
+        ]
+        .into(),
+    };
+    let id_object_type_name = context.unique_type_name(&format!("{desired_object_type_name}_id"));
+    context.insert_object_type(id_object_type_name.clone(), id_object_type);
+    infer_type_from_bucket_output(
+        context,
+        desired_object_type_name,
+        TypeConstraint::Object(id_object_type_name),
+        output,
+    )
+}
+
+fn infer_type_from_bucket_output(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    id_type: TypeConstraint,
+    output: Option<&BTreeMap<String, Accumulator>>,
+) -> Result<ObjectTypeName> {
+    let output_fields = match output {
+        Some(accumulators) => accumulators
+            .iter()
+            .map(|(key, accumulator)| {
+                let accumulator_type =
+                    infer_type_from_accumulator(context, desired_object_type_name, accumulator)?;
+                Ok((key.as_str().into(), accumulator_type))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => vec![("count".into(), TypeConstraint::Scalar(BsonScalarType::Int))],
+    };
+    let fields = once(("_id".into(), id_type)).chain(output_fields).collect();
+    let object_type_name = context.unique_type_name(desired_object_type_name);
+    context.insert_object_type(object_type_name.clone(), ObjectTypeConstraint { fields });
+    Ok(object_type_name)
+}
+
+fn infer_type_from_set_window_fields_stage(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    partition_by: Option<&Bson>,
+    output: &BTreeMap<String, Document>,
+) -> Result<TypeConstraint> {
+    if let Some(partition_by) = partition_by {
+        infer_type_from_aggregation_expression(
+            context,
+            &format!("{desired_object_type_name}_partitionBy"),
+            None,
+            partition_by.clone(),
+        )?;
+    }
+    let fields = output
+        .iter()
+        .map(|(field_name, window_spec)| {
+            if field_name.contains('.') {
+                return Err(Error::Other(format!("Writing window results to nested fields with dotted paths in $setWindowFields is not supported: {field_name}")));
+            }
+            let field_type = infer_type_from_window_operator(
                 context,
-                &format!("{desired_object_type_name}_min"),
-                None,
-                expr.clone(),
-            )?,
-            Accumulator::AddToSet(expr) | Accumulator::Push(expr) => {
-                let t = infer_type_from_aggregation_expression(
-                    context,
-                    &format!("{desired_object_type_name}_push"),
-                    None,
-                    expr.clone(),
-                )?;
-                TypeConstraint::ArrayOf(Box::new(t))
+                &format!("{desired_object_type_name}_{field_name}"),
+                window_spec,
+            )?;
+            Ok((field_name.as_str().into(), Some(field_type)))
+        })
+        .collect::<Result<_>>()?;
+
+    // Window results are added to the input documents
+    Ok(TypeConstraint::WithFieldOverrides {
+        augmented_object_type_name: desired_object_type_name.into(),
+        target_type: Box::new(context.get_input_document_type()?),
+        fields,
+    })
+}
+
+/// Documents created by `$densify` only have the densified field and partition fields, so every
+/// other field of the input document type becomes nullable. That requires knowing which fields the
+/// input documents have.
+fn infer_type_from_densify_stage(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    stage_index: usize,
+    field: &str,
+    partition_by_fields: &[String],
+) -> Result<TypeConstraint> {
+    let input_doc_type = context.get_input_document_type()?;
+    let Some(input_field_names) = known_field_names(context, &input_doc_type) else {
+        return Err(Error::Other(format!(
+            "Cannot infer the result type of the $densify stage at index {stage_index} because the fields of its input documents are not known. Documents created by $densify only include the densified field and partition fields, so other fields must be made nullable."
+        )));
+    };
+    let fields = input_field_names
+        .into_iter()
+        .filter(|name| {
+            name.as_str() != field && !partition_by_fields.iter().any(|p| p == name.as_str())
+        })
+        .map(|name| {
+            let field_type = TypeConstraint::FieldOf {
+                target_type: Box::new(input_doc_type.clone()),
+                path: NonEmpty::singleton(name.clone()),
+            };
+            (name, Some(field_type.make_nullable()))
+        })
+        .collect();
+    Ok(TypeConstraint::WithFieldOverrides {
+        augmented_object_type_name: desired_object_type_name.into(),
+        target_type: Box::new(input_doc_type),
+        fields,
+    })
+}
+
+/// Names of fields of a document type, or `None` if the fields cannot be determined before types
+/// are solved
+fn known_field_names(
+    context: &PipelineTypeContext<'_>,
+    doc_type: &TypeConstraint,
+) -> Option<BTreeSet<FieldName>> {
+    match doc_type {
+        TypeConstraint::Object(name) => {
+            let object_type = context.get_object_type(name)?;
+            Some(object_type.fields.keys().cloned().collect())
+        }
+        TypeConstraint::WithFieldOverrides {
+            target_type,
+            fields,
+            ..
+        } => {
+            let mut names = known_field_names(context, target_type)?;
+            for (name, field_type) in fields {
+                match field_type {
+                    Some(_) => names.insert(name.clone()),
+                    None => names.remove(name),
+                };
             }
-            Accumulator::Avg(expr) => {
-                let t = infer_type_from_aggregation_expression(
-                    context,
-                    &format!("{desired_object_type_name}_avg"),
-                    Some(&TypeConstraint::numeric()),
-                    expr.clone(),
-                )?;
-                type_for_trig_operator(t).make_nullable()
+            Some(names)
+        }
+        _ => None,
+    }
+}
+
+/// A window operator is either a `$group` accumulator, or an operator that is only available in
+/// `$setWindowFields`. Window results are null when the window is empty, so most window-only
+/// operators produce nullable types.
+fn infer_type_from_window_operator(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    window_spec: &Document,
+) -> Result<TypeConstraint> {
+    let mut operator_doc = window_spec.clone();
+    operator_doc.remove("window");
+    if operator_doc.len() != 1 {
+        return Err(Error::MultipleExpressionOperators(Box::new(operator_doc)));
+    }
+
+    if let Ok(accumulator) = bson::from_document::<Accumulator>(operator_doc.clone()) {
+        return infer_type_from_accumulator(context, desired_object_type_name, &accumulator);
+    }
+
+    let (operator, argument) = operator_doc.iter().next().expect("checked length above");
+    let window_type = match operator.as_str() {
+        "$rank" | "$denseRank" | "$documentNumber" => TypeConstraint::Scalar(BsonScalarType::Int),
+        "$shift" => {
+            let argument = argument
+                .as_document()
+                .ok_or_else(|| Error::Other(format!("{operator} expects a document argument")))?;
+            let output = argument
+                .get("output")
+                .ok_or_else(|| Error::Other(format!("{operator} requires an output expression")))?;
+            let output_type = infer_type_from_aggregation_expression(
+                context,
+                desired_object_type_name,
+                None,
+                output.clone(),
+            )?;
+            match argument.get("default") {
+                Some(default) => {
+                    let default_type = infer_type_from_aggregation_expression(
+                        context,
+                        desired_object_type_name,
+                        None,
+                        default.clone(),
+                    )?;
+                    TypeConstraint::Variable(
+                        context.new_type_variable(Variance::Covariant, [output_type, default_type]),
+                    )
+                }
+                None => output_type.make_nullable(),
             }
-            Accumulator::Sum(expr) => infer_type_from_aggregation_expression(
+        }
+        "$locf" => infer_type_from_aggregation_expression(
+            context,
+            desired_object_type_name,
+            None,
+            argument.clone(),
+        )?
+        .make_nullable(),
+        "$linearFill" => infer_type_from_aggregation_expression(
+            context,
+            desired_object_type_name,
+            Some(&TypeConstraint::numeric()),
+            argument.clone(),
+        )?
+        .make_nullable(),
+        "$derivative" | "$integral" | "$expMovingAvg" => {
+            let input = argument
+                .as_document()
+                .and_then(|argument| argument.get("input"))
+                .ok_or_else(|| Error::Other(format!("{operator} requires an input expression")))?;
+            infer_type_from_aggregation_expression(
                 context,
-                &format!("{desired_object_type_name}_push"),
+                desired_object_type_name,
                 Some(&TypeConstraint::numeric()),
-                expr.clone(),
-            )?,
-            Accumulator::First(expr) | Accumulator::Last(expr) => {
+                input.clone(),
+            )?;
+            TypeConstraint::Scalar(BsonScalarType::Double).make_nullable()
+        }
+        "$covariancePop" | "$covarianceSamp" => {
+            let arguments =
+                argument
+                    .as_array()
+                    .ok_or_else(|| Error::ExpectedArrayExpressionArgument {
+                        actual_argument: Box::new(argument.clone()),
+                    })?;
+            for argument in arguments {
                 infer_type_from_aggregation_expression(
                     context,
-                    &format!("{desired_object_type_name}_first_last"),
-                    None,
-                    expr.clone(),
-                )?
-            }
-            Accumulator::StdDevSamp(expr) | Accumulator::StdDevPop(expr) => {
-                let t = infer_type_from_aggregation_expression(
-                    context,
-                    &format!("{desired_object_type_name}_stddev"),
+                    desired_object_type_name,
                     Some(&TypeConstraint::numeric()),
-                    expr.clone(),
+                    argument.clone(),
                 )?;
-                type_for_trig_operator(t).make_nullable()
-            }
-            Accumulator::Median(_) | Accumulator::Percentile(_) => {
-                // These are complex document-based accumulators - return Double which is the result type
-                TypeConstraint::Scalar(BsonScalarType::Double).make_nullable()
             }
-        };
-        Ok::<_, Error>((key.clone().into(), accumulator_type))
-    });
+            TypeConstraint::Scalar(BsonScalarType::Double).make_nullable()
+        }
+        _ => Err(Error::UnknownAggregationOperator(operator.clone()))?,
+    };
+    Ok(window_type)
+}
 
-    let fields = once(Ok(group_expression_field))
-        .chain(accumulator_fields)
-        .collect::<Result<_>>()?;
-    let object_type = ObjectTypeConstraint { fields };
-    let object_type_name = context.unique_type_name(desired_object_type_name);
-    context.insert_object_type(object_type_name.clone(), object_type);
-    Ok(object_type_name)
+fn infer_type_from_accumulator(
+    context: &mut PipelineTypeContext<'_>,
+    desired_object_type_name: &str,
+    accumulator: &Accumulator,
+) -> Result<TypeConstraint> {
+    let accumulator_type = match accumulator {
+        Accumulator::Count => TypeConstraint::Scalar(BsonScalarType::Int),
+        Accumulator::Min(expr) => infer_type_from_aggregation_expression(
+            context,
+            &format!("{desired_object_type_name}_min"),
+            None,
+            expr.clone(),
+        )?,
+        Accumulator::Max(expr) => infer_type_from_aggregation_expression(
+            context,
+            &format!("{desired_object_type_name}_min"),
+            None,
+            expr.clone(),
+        )?,
+        Accumulator::AddToSet(expr) | Accumulator::Push(expr) => {
+            let t = infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_push"),
+                None,
+                expr.clone(),
+            )?;
+            TypeConstraint::ArrayOf(Box::new(t))
+        }
+        Accumulator::Avg(expr) => {
+            let t = infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_avg"),
+                Some(&TypeConstraint::numeric()),
+                expr.clone(),
+            )?;
+            type_for_trig_operator(t).make_nullable()
+        }
+        Accumulator::Sum(expr) => infer_type_from_aggregation_expression(
+            context,
+            &format!("{desired_object_type_name}_push"),
+            Some(&TypeConstraint::numeric()),
+            expr.clone(),
+        )?,
+        Accumulator::First(expr) | Accumulator::Last(expr) => {
+            infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_first_last"),
+                None,
+                expr.clone(),
+            )?
+        }
+        Accumulator::StdDevSamp(expr) | Accumulator::StdDevPop(expr) => {
+            let t = infer_type_from_aggregation_expression(
+                context,
+                &format!("{desired_object_type_name}_stddev"),
+                Some(&TypeConstraint::numeric()),
+                expr.clone(),
+            )?;
+            type_for_trig_operator(t).make_nullable()
+        }
+        Accumulator::Median(_) | Accumulator::Percentile(_) => {
+            // These are complex document-based accumulators - return Double which is the result type
+            TypeConstraint::Scalar(BsonScalarType::Double).make_nullable()
+        }
+    };
+    Ok(accumulator_type)
 }
 
 fn infer_type_from_unwind_stage(
@@ -363,9 +1009,10 @@ mod tests {
     use configuration::schema::{ObjectField, ObjectType, Type};
     use mongodb::bson::doc;
     use mongodb_support::{
-        aggregate::{Pipeline, Selection, Stage},
+        aggregate::{Accumulator, Pipeline, Selection, SortDocument, Stage},
         BsonScalarType,
     };
+    use ndc_models::ObjectTypeName;
     use nonempty::NonEmpty;
     use pretty_assertions::assert_eq;
     use test_helpers::configuration::mflix_config;
@@ -416,6 +1063,327 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn infers_type_from_add_fields_stage() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::AddFields(doc! {
+            "movie_title": "$title",
+            "rated": "$$REMOVE",
+        })]);
+        let config = mflix_config();
+        let pipeline_types =
+            infer_pipeline_types(&config, "movies", Some(&("movies".into())), &pipeline)?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        assert_eq!(
+            result_type.fields["movie_title"].r#type,
+            Type::Scalar(BsonScalarType::String)
+        );
+        assert_eq!(
+            result_type.fields["year"].r#type,
+            Type::Scalar(BsonScalarType::Int)
+        );
+        assert!(!result_type.fields.contains_key("rated"));
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_count_stage() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::Count("total".to_string())]);
+        let config = mflix_config();
+        let pipeline_types =
+            infer_pipeline_types(&config, "movies", Some(&("movies".into())), &pipeline)?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        assert_eq!(
+            result_type.fields,
+            [(
+                "total".into(),
+                ObjectField {
+                    r#type: Type::Scalar(BsonScalarType::Int),
+                    description: None,
+                }
+            )]
+            .into()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_lookup_stage_using_foreign_collection_schema() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::Lookup {
+            from: Some("comments".to_string()),
+            local_field: Some("_id".to_string()),
+            foreign_field: Some("movie_id".to_string()),
+            r#let: None,
+            pipeline: None,
+            r#as: "comments".to_string(),
+        }]);
+        let config = mflix_config();
+        let pipeline_types =
+            infer_pipeline_types(&config, "movies", Some(&("movies".into())), &pipeline)?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        assert_eq!(
+            result_type.fields["comments"].r#type,
+            Type::ArrayOf(Box::new(Type::Object("comments".to_string())))
+        );
+        assert_eq!(
+            result_type.fields["title"].r#type,
+            Type::Scalar(BsonScalarType::String)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_lookup_stage_with_let_variables() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::Lookup {
+            from: Some("comments".to_string()),
+            local_field: None,
+            foreign_field: None,
+            r#let: Some(doc! { "movie_title": "$title" }),
+            pipeline: Some(Pipeline::new(vec![Stage::AddFields(doc! {
+                "movie_title": "$$movie_title",
+            })])),
+            r#as: "comments".to_string(),
+        }]);
+        let config = mflix_config();
+        let pipeline_types =
+            infer_pipeline_types(&config, "movies", Some(&("movies".into())), &pipeline)?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        let Type::ArrayOf(comment_type) = &result_type.fields["comments"].r#type else {
+            panic!("expected comments to be an array");
+        };
+        let Type::Object(comment_type_name) = comment_type.as_ref() else {
+            panic!("expected comments to be an array of objects");
+        };
+        let comment_type =
+            &pipeline_types.object_types[&ObjectTypeName::from(comment_type_name.as_str())];
+        assert_eq!(
+            comment_type.fields["movie_title"].r#type,
+            Type::Scalar(BsonScalarType::String)
+        );
+        assert_eq!(
+            comment_type.fields["name"].r#type,
+            Type::Scalar(BsonScalarType::String)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_facet_stage() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::Facet(
+            [
+                (
+                    "count".to_string(),
+                    Pipeline::new(vec![Stage::Count("total".to_string())]),
+                ),
+                (
+                    "all".to_string(),
+                    Pipeline::new(vec![Stage::Limit(10.into())]),
+                ),
+            ]
+            .into(),
+        )]);
+        let config = mflix_config();
+        let pipeline_types =
+            infer_pipeline_types(&config, "movies", Some(&("movies".into())), &pipeline)?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        assert_eq!(
+            result_type.fields["all"].r#type,
+            Type::ArrayOf(Box::new(Type::Object("movies".to_string())))
+        );
+        let Type::ArrayOf(count_type) = &result_type.fields["count"].r#type else {
+            panic!("expected count facet to be an array");
+        };
+        let Type::Object(count_type_name) = count_type.as_ref() else {
+            panic!("expected count facet to be an array of objects");
+        };
+        assert_eq!(
+            pipeline_types.object_types[&ObjectTypeName::from(count_type_name.as_str())].fields
+                ["total"]
+                .r#type,
+            Type::Scalar(BsonScalarType::Int)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_union_with_stage() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::UnionWith {
+            coll: "comments".to_string(),
+            pipeline: None,
+        }]);
+        let config = mflix_config();
+        let pipeline_types = infer_pipeline_types(
+            &config,
+            "movies_and_comments",
+            Some(&("movies".into())),
+            &pipeline,
+        )?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        let nullable = |t: Type| Type::Nullable(Box::new(t));
+        assert_eq!(
+            result_type.fields["_id"].r#type,
+            Type::Scalar(BsonScalarType::ObjectId)
+        );
+        assert_eq!(
+            result_type.fields["movie_id"].r#type,
+            nullable(Type::Scalar(BsonScalarType::ObjectId))
+        );
+        assert_eq!(
+            result_type.fields["name"].r#type,
+            nullable(Type::Scalar(BsonScalarType::String))
+        );
+        assert_eq!(
+            result_type.fields["title"].r#type,
+            nullable(Type::Scalar(BsonScalarType::String))
+        );
+        assert_eq!(
+            result_type.fields["year"].r#type,
+            nullable(Type::Scalar(BsonScalarType::Int))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_union_with_stage_with_pipeline() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::UnionWith {
+            coll: "comments".to_string(),
+            pipeline: Some(Pipeline::new(vec![Stage::AddFields(doc! {
+                "archived": true,
+            })])),
+        }]);
+        let config = mflix_config();
+        let pipeline_types = infer_pipeline_types(
+            &config,
+            "current_and_archived_comments",
+            Some(&("comments".into())),
+            &pipeline,
+        )?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        assert_eq!(
+            result_type.fields["name"].r#type,
+            Type::Scalar(BsonScalarType::String)
+        );
+        assert_eq!(
+            result_type.fields["archived"].r#type,
+            Type::Nullable(Box::new(Type::Scalar(BsonScalarType::Bool)))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_sort_by_count_stage() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::SortByCount("$rated".into())]);
+        let config = mflix_config();
+        let pipeline_types =
+            infer_pipeline_types(&config, "ratings", Some(&("movies".into())), &pipeline)?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        assert_eq!(
+            result_type.fields,
+            [
+                (
+                    "_id".into(),
+                    ObjectField {
+                        r#type: Type::Scalar(BsonScalarType::String),
+                        description: None,
+                    }
+                ),
+                (
+                    "count".into(),
+                    ObjectField {
+                        r#type: Type::Scalar(BsonScalarType::Int),
+                        description: None,
+                    }
+                ),
+            ]
+            .into()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_bucket_stage() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::Bucket {
+            group_by: "$year".into(),
+            boundaries: vec![1900.into(), 1950.into(), 2000.into()],
+            default: None,
+            output: Some(
+                [
+                    ("count".to_string(), Accumulator::Count),
+                    ("titles".to_string(), Accumulator::Push("$title".into())),
+                ]
+                .into(),
+            ),
+        }]);
+        let config = mflix_config();
+        let pipeline_types =
+            infer_pipeline_types(&config, "buckets", Some(&("movies".into())), &pipeline)?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        assert_eq!(
+            result_type.fields["_id"].r#type,
+            Type::Scalar(BsonScalarType::Int)
+        );
+        assert_eq!(
+            result_type.fields["count"].r#type,
+            Type::Scalar(BsonScalarType::Int)
+        );
+        assert_eq!(
+            result_type.fields["titles"].r#type,
+            Type::ArrayOf(Box::new(Type::Scalar(BsonScalarType::String)))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_set_window_fields_stage() -> Result<()> {
+        let pipeline = Pipeline::new(vec![Stage::SetWindowFields {
+            partition_by: Some("$rated".into()),
+            sort_by: Some(SortDocument(doc! { "year": 1 })),
+            output: [
+                ("rank".to_string(), doc! { "$rank": {} }),
+                (
+                    "total_runtime".to_string(),
+                    doc! {
+                        "$sum": "$runtime",
+                        "window": { "documents": ["unbounded", "current"] },
+                    },
+                ),
+            ]
+            .into(),
+        }]);
+        let config = mflix_config();
+        let pipeline_types =
+            infer_pipeline_types(&config, "movies", Some(&("movies".into())), &pipeline)?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        assert_eq!(
+            result_type.fields["rank"].r#type,
+            Type::Scalar(BsonScalarType::Int)
+        );
+        assert_eq!(
+            result_type.fields["total_runtime"].r#type,
+            Type::Scalar(BsonScalarType::Int)
+        );
+        assert_eq!(
+            result_type.fields["title"].r#type,
+            Type::Scalar(BsonScalarType::String)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn infers_type_from_unset_stage() -> Result<()> {
+        let pipeline = Pipeline::new(vec![
+            Stage::Unset(vec!["credits".to_string(), "tomatoes".to_string()]),
+            Stage::Sample { size: 10.into() },
+        ]);
+        let config = mflix_config();
+        let pipeline_types =
+            infer_pipeline_types(&config, "movies", Some(&("movies".into())), &pipeline)?;
+        let result_type = &pipeline_types.object_types[&pipeline_types.result_document_type];
+        assert!(!result_type.fields.contains_key("credits"));
+        assert!(!result_type.fields.contains_key("tomatoes"));
+        assert!(result_type.fields.contains_key("title"));
+        Ok(())
+    }
+
     #[test]
     fn infers_type_from_replace_with_stage() -> Result<()> {
         let pipeline = Pipeline::new(vec![Stage::ReplaceWith(Selection::new(doc! {
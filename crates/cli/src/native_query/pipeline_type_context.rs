@@ -29,6 +29,13 @@ pub struct PipelineTypes {
     pub warnings: Vec<Error>,
 }
 
+/// State of an outer pipeline that is saved while types are inferred for a sub-pipeline.
+#[derive(Clone, Debug)]
+pub struct SubPipelineScope {
+    input_doc_type: Option<TypeVariable>,
+    pipeline_variables: BTreeMap<String, TypeConstraint>,
+}
+
 #[derive(Clone, Debug)]
 pub struct PipelineTypeContext<'a> {
     configuration: &'a Configuration,
@@ -39,6 +46,10 @@ pub struct PipelineTypeContext<'a> {
 
     parameter_types: BTreeMap<ArgumentName, TypeVariable>,
 
+    /// Types of variables defined with `let` in stages such as `$lookup` that are in scope for
+    /// the pipeline being evaluated.
+    pipeline_variables: BTreeMap<String, TypeConstraint>,
+
     /// Object types defined in the process of type inference. [self.input_doc_type] may refer to
     /// to a type here, or in [self.configuration.object_types]
     object_types: BTreeMap<ObjectTypeName, ObjectTypeConstraint>,
@@ -58,6 +69,7 @@ impl PipelineTypeContext<'_> {
             configuration,
             input_doc_type: None,
             parameter_types: Default::default(),
+            pipeline_variables: Default::default(),
             object_types: Default::default(),
             type_variables: Default::default(),
             next_type_variable: 0,
@@ -71,6 +83,10 @@ impl PipelineTypeContext<'_> {
         context
     }
 
+    pub fn configuration(&self) -> &'a Configuration {
+        self.configuration
+    }
+
     #[cfg(test)]
     pub fn object_types(&self) -> &BTreeMap<ObjectTypeName, ObjectTypeConstraint> {
         &self.object_types
@@ -307,6 +323,52 @@ impl PipelineTypeContext<'_> {
         None
     }
 
+    pub fn get_pipeline_variable(&self, name: &str) -> Option<&TypeConstraint> {
+        self.pipeline_variables.get(name)
+    }
+
+    /// Switches to a sub-pipeline, such as the pipeline in a `$lookup` or `$facet` stage, that
+    /// receives documents of the given type. Variables given here are added to the variables that
+    /// are already in scope. Returns the outer pipeline state which must be passed to
+    /// [Self::end_sub_pipeline] when the sub-pipeline is done.
+    pub fn begin_sub_pipeline(
+        &mut self,
+        input_doc_type: Option<TypeConstraint>,
+        variables: impl IntoIterator<Item = (String, TypeConstraint)>,
+    ) -> SubPipelineScope {
+        let mut pipeline_variables = self.pipeline_variables.clone();
+        pipeline_variables.extend(variables);
+        let outer = SubPipelineScope {
+            input_doc_type: self.input_doc_type.take(),
+            pipeline_variables: std::mem::replace(&mut self.pipeline_variables, pipeline_variables),
+        };
+        if let Some(doc_type) = input_doc_type {
+            self.set_stage_doc_type(doc_type);
+        }
+        outer
+    }
+
+    pub fn end_sub_pipeline(&mut self, outer: SubPipelineScope) {
+        self.input_doc_type = outer.input_doc_type;
+        self.pipeline_variables = outer.pipeline_variables;
+    }
+
+    /// Brings variables into scope for an expression, such as the variables bound by `$let` or
+    /// `$map`. Returns the variables that were previously in scope which must be passed to
+    /// [Self::restore_pipeline_variables] when the expression is done.
+    pub fn add_pipeline_variables(
+        &mut self,
+        variables: impl IntoIterator<Item = (String, TypeConstraint)>,
+    ) -> BTreeMap<String, TypeConstraint> {
+        let outer = self.pipeline_variables.clone();
+        self.pipeline_variables.extend(variables);
+        outer
+    }
+
+    pub fn restore_pipeline_variables(&mut self, outer: BTreeMap<String, TypeConstraint>) {
+        self.pipeline_variables = outer;
+    }
+
     pub fn get_input_document_type(&self) -> Result<TypeConstraint> {
         let variable = self
             .input_doc_type
@@ -0,0 +1,399 @@
+//! Runs a native query against the configured database for the `native-query test` subcommand.
+//! Arguments are converted, and results are checked using the same functions that the connector
+//! uses to serve native queries.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context as _};
+use configuration::{
+    schema::{ObjectField, Type},
+    serialized::NativeQuery,
+    MongoScalarType,
+};
+use futures_util::TryStreamExt as _;
+use mongodb::bson::{Bson, Document};
+use mongodb_agent_common::{
+    mongo_query_plan,
+    mongodb::{CollectionTrait as _, DatabaseTrait},
+    procedure::interpolated_command,
+    query::serialization::{bson_to_json, is_nullable, json_to_bson, BsonToJsonError},
+};
+use mongodb_support::{
+    aggregate::{Pipeline, Stage},
+    BsonScalarType, ExtendedJsonMode,
+};
+use ndc_models::{ArgumentName, CollectionName, ObjectTypeName};
+use ndc_query_plan::inline_object_types;
+
+type ObjectTypes = BTreeMap<ObjectTypeName, ndc_models::ObjectType>;
+
+/// A document returned by a native query after checking it against the result document type
+#[derive(Debug)]
+pub enum CheckedDocument {
+    /// The document matches the result document type. The value is the JSON serialization that
+    /// the connector would produce.
+    Matches(serde_json::Value),
+    /// The document does not match the result document type. The value is an Extended JSON
+    /// representation of the document as it was returned from the database.
+    Mismatch {
+        document: serde_json::Value,
+        error: BsonToJsonError,
+    },
+}
+
+/// An argument value given on the command line
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArgumentValue {
+    /// The value parsed as JSON, or the raw string if it is not valid JSON
+    pub json: serde_json::Value,
+    /// The value as it was given
+    pub raw: String,
+}
+
+/// Parses a `name=value` argument from the command line. The value is read as JSON, or as
+/// a string if it is not valid JSON. The raw string is kept so that a value such as `1999` can
+/// still be used for an argument with a string type.
+pub fn parse_argument(input: &str) -> Result<(ArgumentName, ArgumentValue), String> {
+    let (name, value) = input
+        .split_once('=')
+        .ok_or_else(|| format!("expected an argument of the form name=value, got \"{input}\""))?;
+    let json = serde_json::from_str(value)
+        .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+    Ok((
+        name.into(),
+        ArgumentValue {
+            json,
+            raw: value.to_string(),
+        },
+    ))
+}
+
+/// Converts argument values to BSON according to the argument types declared by the native
+/// query. Arguments that are not given are null if their declared type is nullable.
+pub fn arguments_to_bson(
+    object_types: &ObjectTypes,
+    native_query: &NativeQuery,
+    mut arguments: BTreeMap<ArgumentName, ArgumentValue>,
+) -> anyhow::Result<BTreeMap<ArgumentName, Bson>> {
+    if let Some(name) = arguments
+        .keys()
+        .find(|name| !native_query.arguments.contains_key(*name))
+    {
+        return Err(anyhow!("the native query has no argument named \"{name}\""));
+    }
+    native_query
+        .arguments
+        .iter()
+        .map(|(name, ObjectField { r#type, .. })| {
+            let argument_type = plan_type(object_types, r#type.clone())?;
+            let value = match arguments.remove(name) {
+                Some(value) => json_for_argument_type(&argument_type, value),
+                None if is_nullable(&argument_type) => serde_json::Value::Null,
+                None => return Err(anyhow!("missing a value for argument \"{name}\"")),
+            };
+            let bson = json_to_bson(&argument_type, value)
+                .with_context(|| format!("error parsing argument \"{name}\""))?;
+            Ok((name.clone(), bson))
+        })
+        .collect()
+}
+
+/// Replaces argument placeholders in the native query pipeline with argument values
+pub fn pipeline_with_arguments(
+    native_query: &NativeQuery,
+    arguments: &BTreeMap<ArgumentName, Bson>,
+) -> anyhow::Result<Pipeline> {
+    let stages = native_query
+        .pipeline
+        .iter()
+        .map(|stage| Ok(Stage::Other(interpolated_command(stage, arguments)?)))
+        .collect::<anyhow::Result<_>>()?;
+    Ok(Pipeline::new(stages))
+}
+
+/// Appends a `$limit` stage so that testing a native query does not read an entire collection.
+/// A limit of zero leaves the pipeline unchanged.
+pub fn pipeline_with_limit(mut pipeline: Pipeline, limit: u32) -> Pipeline {
+    if limit > 0 {
+        pipeline.push(Stage::Limit(Bson::Int64(limit.into())));
+    }
+    pipeline
+}
+
+/// Runs the pipeline with `db.<collection>.aggregate` if the native query has an input
+/// collection, or with `db.aggregate` otherwise.
+pub async fn run_pipeline(
+    database: &impl DatabaseTrait,
+    input_collection: Option<&CollectionName>,
+    pipeline: Pipeline,
+) -> anyhow::Result<Vec<Document>> {
+    let documents = match input_collection {
+        Some(collection_name) => {
+            database
+                .collection(collection_name.as_str())
+                .aggregate(pipeline, None)
+                .await?
+                .try_collect()
+                .await?
+        }
+        None => {
+            database
+                .aggregate(pipeline, None)
+                .await?
+                .try_collect()
+                .await?
+        }
+    };
+    Ok(documents)
+}
+
+/// Checks each document against the native query's result document type
+pub fn check_result_documents(
+    object_types: &ObjectTypes,
+    native_query: &NativeQuery,
+    mode: ExtendedJsonMode,
+    documents: Vec<Document>,
+) -> anyhow::Result<Vec<CheckedDocument>> {
+    let result_type = plan_type(
+        object_types,
+        Type::Object(native_query.result_document_type.to_string()),
+    )?;
+    let checked = documents
+        .into_iter()
+        .map(|document| {
+            let document = Bson::Document(document);
+            match bson_to_json(mode, &result_type, document.clone()) {
+                Ok(value) => CheckedDocument::Matches(value),
+                Err(error) => CheckedDocument::Mismatch {
+                    document: mode.into_extjson(document),
+                    error,
+                },
+            }
+        })
+        .collect();
+    Ok(checked)
+}
+
+/// Values given for string arguments are strings even if they happen to be valid JSON of another
+/// type, like `1999` or `true`. Null is kept for nullable arguments.
+fn json_for_argument_type(
+    argument_type: &mongo_query_plan::Type,
+    value: ArgumentValue,
+) -> serde_json::Value {
+    match (argument_type, value.json) {
+        (_, json @ serde_json::Value::String(_)) => json,
+        (mongo_query_plan::Type::Nullable(_), serde_json::Value::Null) => serde_json::Value::Null,
+        (t, _) if is_string_type(t) => serde_json::Value::String(value.raw),
+        (_, json) => json,
+    }
+}
+
+fn is_string_type(t: &mongo_query_plan::Type) -> bool {
+    match t {
+        mongo_query_plan::Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)) => true,
+        mongo_query_plan::Type::Nullable(t) => is_string_type(t),
+        _ => false,
+    }
+}
+
+fn plan_type(object_types: &ObjectTypes, t: Type) -> anyhow::Result<mongo_query_plan::Type> {
+    let plan_type =
+        inline_object_types(object_types, &t.into(), MongoScalarType::lookup_scalar_type)?;
+    Ok(plan_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::{native_query::NativeQueryRepresentation, schema::ObjectType};
+    use mongodb::bson::{bson, doc};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    fn native_query() -> (ObjectTypes, NativeQuery) {
+        let object_types: BTreeMap<ObjectTypeName, ObjectType> = [(
+            "TitleWordFrequency".into(),
+            ObjectType {
+                fields: [
+                    (
+                        "_id".into(),
+                        ObjectField {
+                            r#type: Type::Scalar(BsonScalarType::String),
+                            description: None,
+                        },
+                    ),
+                    (
+                        "count".into(),
+                        ObjectField {
+                            r#type: Type::Scalar(BsonScalarType::Int),
+                            description: None,
+                        },
+                    ),
+                ]
+                .into(),
+                description: None,
+            },
+        )]
+        .into();
+        let native_query = NativeQuery {
+            representation: NativeQueryRepresentation::Collection,
+            input_collection: Some("movies".into()),
+            arguments: [
+                (
+                    "year".into(),
+                    ObjectField {
+                        r#type: Type::Scalar(BsonScalarType::Int),
+                        description: None,
+                    },
+                ),
+                (
+                    "title".into(),
+                    ObjectField {
+                        r#type: Type::Nullable(Box::new(Type::Scalar(BsonScalarType::String))),
+                        description: None,
+                    },
+                ),
+            ]
+            .into(),
+            result_document_type: "TitleWordFrequency".into(),
+            object_types: object_types.clone(),
+            pipeline: vec![doc! { "$match": { "year": "{{ year }}", "title": "{{ title }}" } }],
+            description: None,
+        };
+        let object_types = object_types
+            .into_iter()
+            .map(|(name, object_type)| (name, object_type.into()))
+            .collect();
+        (object_types, native_query)
+    }
+
+    fn parse_arguments<const N: usize>(
+        inputs: [&str; N],
+    ) -> anyhow::Result<BTreeMap<ArgumentName, ArgumentValue>> {
+        inputs
+            .into_iter()
+            .map(|input| parse_argument(input).map_err(anyhow::Error::msg))
+            .collect()
+    }
+
+    #[test]
+    fn parses_argument_values_as_json_or_string() -> anyhow::Result<()> {
+        let json_value = |input| -> anyhow::Result<_> {
+            let (name, value) = parse_argument(input).map_err(anyhow::Error::msg)?;
+            Ok((name, value.json))
+        };
+        assert_eq!(json_value("year=1999")?, ("year".into(), json!(1999)));
+        assert_eq!(
+            json_value("title=The Matrix")?,
+            ("title".into(), json!("The Matrix"))
+        );
+        assert_eq!(
+            json_value("filter={\"a\":1}")?,
+            ("filter".into(), json!({ "a": 1 }))
+        );
+        assert_eq!(parse_argument("year=1999")?.1.raw, "1999");
+        assert!(parse_argument("year").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_json_values_of_other_types_as_strings_for_string_arguments() -> anyhow::Result<()> {
+        let (object_types, native_query) = native_query();
+        let arguments = arguments_to_bson(
+            &object_types,
+            &native_query,
+            parse_arguments(["year=1999", "title=1999"])?,
+        )?;
+        assert_eq!(
+            arguments,
+            BTreeMap::from([
+                ("year".into(), bson!(1999)),
+                ("title".into(), bson!("1999"))
+            ])
+        );
+
+        let arguments = arguments_to_bson(
+            &object_types,
+            &native_query,
+            parse_arguments(["year=1999", "title=null"])?,
+        )?;
+        assert_eq!(arguments["title"], Bson::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn interpolates_typed_arguments_into_pipeline() -> anyhow::Result<()> {
+        let (object_types, native_query) = native_query();
+        let arguments = arguments_to_bson(
+            &object_types,
+            &native_query,
+            parse_arguments(["year=1999"])?,
+        )?;
+        assert_eq!(
+            arguments,
+            BTreeMap::from([("year".into(), bson!(1999)), ("title".into(), Bson::Null)])
+        );
+
+        let pipeline = pipeline_with_arguments(&native_query, &arguments)?;
+        assert_eq!(
+            pipeline.stages,
+            vec![Stage::Other(
+                doc! { "$match": { "year": 1999, "title": null } }
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn appends_limit_stage_to_pipeline() {
+        let pipeline = Pipeline::new(vec![Stage::Other(doc! { "$match": { "year": 1999 } })]);
+        assert_eq!(
+            pipeline_with_limit(pipeline.clone(), 20).stages,
+            vec![
+                Stage::Other(doc! { "$match": { "year": 1999 } }),
+                Stage::Limit(Bson::Int64(20)),
+            ]
+        );
+        assert_eq!(pipeline_with_limit(pipeline.clone(), 0), pipeline);
+    }
+
+    #[test]
+    fn rejects_missing_and_unknown_arguments() -> anyhow::Result<()> {
+        let (object_types, native_query) = native_query();
+        assert!(arguments_to_bson(&object_types, &native_query, Default::default()).is_err());
+        assert!(arguments_to_bson(
+            &object_types,
+            &native_query,
+            parse_arguments(["year=1999", "limit=10"])?
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reports_documents_that_do_not_match_result_type() -> anyhow::Result<()> {
+        let (object_types, native_query) = native_query();
+        let checked = check_result_documents(
+            &object_types,
+            &native_query,
+            ExtendedJsonMode::Relaxed,
+            vec![
+                doc! { "_id": "matrix", "count": 3 },
+                doc! { "_id": "matrix", "count": "three" },
+            ],
+        )?;
+        assert!(matches!(
+            &checked[0],
+            CheckedDocument::Matches(value) if value == &json!({ "_id": "matrix", "count": 3 })
+        ));
+        assert!(matches!(
+            &checked[1],
+            CheckedDocument::Mismatch {
+                error: BsonToJsonError::TypeMismatch(_, _),
+                ..
+            }
+        ));
+        Ok(())
+    }
+}
@@ -488,6 +488,325 @@ fn supports_project_stage_in_inclusion_mode() -> Result<()> {
     Ok(())
 }
 
+#[googletest::test]
+fn infers_parameter_types_inside_conditional_string_and_date_operators() -> googletest::Result<()> {
+    let config = mflix_config();
+
+    let pipeline = Pipeline::new(vec![Stage::AddFields(doc! {
+        "label": {
+            "$cond": {
+                "if": { "$gt": ["$year", "{{ year }}"] },
+                "then": { "$toUpper": "$title" },
+                "else": "$rated",
+            }
+        },
+        "next_release": {
+            "$dateAdd": { "startDate": "$released", "unit": "{{ unit }}", "amount": 1 }
+        },
+    })]);
+
+    let native_query =
+        native_query_from_pipeline(&config, "labeled_movies", Some("movies".into()), pipeline)?;
+
+    expect_that!(
+        native_query.arguments,
+        unordered_elements_are![
+            (
+                displays_as(eq("year")),
+                field!(ObjectField.r#type, eq(&Type::Scalar(BsonScalarType::Int)))
+            ),
+            (
+                displays_as(eq("unit")),
+                field!(
+                    ObjectField.r#type,
+                    eq(&Type::Scalar(BsonScalarType::String))
+                )
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[googletest::test]
+fn infers_parameter_types_with_variables_bound_by_let_and_map() -> googletest::Result<()> {
+    let config = mflix_config();
+
+    let pipeline = Pipeline::new(vec![Stage::Match(doc! {
+        "$expr": {
+            "$and": [
+                {
+                    "$let": {
+                        "vars": { "movie_title": "$title" },
+                        "in": { "$eq": ["$$movie_title", "{{ title }}"] },
+                    }
+                },
+                {
+                    "$in": [
+                        "{{ genre }}",
+                        { "$map": { "input": "$genres", "as": "genre", "in": { "$toLower": "$$genre" } } },
+                    ]
+                },
+            ]
+        }
+    })]);
+
+    let native_query =
+        native_query_from_pipeline(&config, "movies_by_genre", Some("movies".into()), pipeline)?;
+
+    expect_that!(
+        native_query.arguments,
+        unordered_elements_are![
+            (
+                displays_as(eq("title")),
+                field!(
+                    ObjectField.r#type,
+                    eq(&Type::Scalar(BsonScalarType::String))
+                )
+            ),
+            (
+                displays_as(eq("genre")),
+                field!(
+                    ObjectField.r#type,
+                    eq(&Type::Scalar(BsonScalarType::String))
+                )
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[googletest::test]
+fn infers_types_from_switch_if_null_first_and_last() -> googletest::Result<()> {
+    let config = mflix_config();
+
+    let pipeline = Pipeline::new(vec![Stage::ReplaceWith(Selection::new(doc! {
+        "runtime_category": {
+            "$switch": {
+                "branches": [
+                    { "case": { "$lt": ["$runtime", "{{ short_runtime }}"] }, "then": "short" },
+                    { "case": { "$gt": ["$runtime", "{{ long_runtime }}"] }, "then": "long" },
+                ],
+                "default": "$rated",
+            }
+        },
+        "first_genre": { "$ifNull": [{ "$first": "$genres" }, "Unknown"] },
+        "last_writer": { "$last": "$writers" },
+    }))]);
+
+    let native_query =
+        native_query_from_pipeline(&config, "categorized", Some("movies".into()), pipeline)?;
+
+    expect_eq!(
+        native_query.arguments,
+        object_fields([
+            ("short_runtime", Type::Scalar(BsonScalarType::Int)),
+            ("long_runtime", Type::Scalar(BsonScalarType::Int)),
+        ])
+    );
+
+    let result_type = native_query.result_document_type;
+    expect_eq!(
+        native_query.object_types[&result_type],
+        ObjectType {
+            fields: object_fields([
+                ("runtime_category", Type::Scalar(BsonScalarType::String)),
+                // $first may produce null, but $ifNull replaces null values
+                ("first_genre", Type::Scalar(BsonScalarType::String)),
+                (
+                    "last_writer",
+                    Type::Nullable(Box::new(Type::Scalar(BsonScalarType::String)))
+                ),
+            ]),
+            description: None,
+        }
+    );
+    Ok(())
+}
+
+#[googletest::test]
+fn infers_types_from_filter_reduce_and_slice() -> googletest::Result<()> {
+    let config = mflix_config();
+
+    let pipeline = Pipeline::new(vec![Stage::ReplaceWith(Selection::new(doc! {
+        "long_named_writers": {
+            "$filter": {
+                "input": "$writers",
+                "as": "writer",
+                "cond": { "$gte": [{ "$strLenCP": "$$writer" }, "{{ min_length }}"] },
+                "limit": "{{ max_writers }}",
+            }
+        },
+        "genre_list": {
+            "$reduce": {
+                "input": "$genres",
+                "initialValue": "",
+                "in": { "$concat": ["$$value", "{{ separator }}", "$$this"] },
+            }
+        },
+        "top_genres": { "$slice": ["$genres", "{{ genre_count }}"] },
+    }))]);
+
+    let native_query =
+        native_query_from_pipeline(&config, "genre_summary", Some("movies".into()), pipeline)?;
+
+    expect_eq!(
+        native_query.arguments,
+        object_fields([
+            ("min_length", Type::Scalar(BsonScalarType::Int)),
+            ("max_writers", Type::Scalar(BsonScalarType::Int)),
+            ("separator", Type::Scalar(BsonScalarType::String)),
+            ("genre_count", Type::Scalar(BsonScalarType::Int)),
+        ])
+    );
+
+    let result_type = native_query.result_document_type;
+    expect_eq!(
+        native_query.object_types[&result_type],
+        ObjectType {
+            fields: object_fields([
+                (
+                    "long_named_writers",
+                    Type::ArrayOf(Box::new(Type::Scalar(BsonScalarType::String)))
+                ),
+                ("genre_list", Type::Scalar(BsonScalarType::String)),
+                (
+                    "top_genres",
+                    Type::ArrayOf(Box::new(Type::Scalar(BsonScalarType::String)))
+                ),
+            ]),
+            description: None,
+        }
+    );
+    Ok(())
+}
+
+#[googletest::test]
+fn infers_types_from_convert_date_to_string_and_regex_find() -> googletest::Result<()> {
+    let config = mflix_config();
+
+    let pipeline = Pipeline::new(vec![Stage::ReplaceWith(Selection::new(doc! {
+        "year_string": {
+            "$convert": { "input": "$year", "to": "string", "onError": "{{ fallback }}" }
+        },
+        "released_label": {
+            "$dateToString": {
+                "date": "$released",
+                "format": "{{ date_format }}",
+                "timezone": "{{ timezone }}",
+            }
+        },
+        "title_match": {
+            "$regexFind": { "input": "$title", "regex": "^The ", "options": "{{ regex_options }}" }
+        },
+    }))]);
+
+    let native_query =
+        native_query_from_pipeline(&config, "labeled_movies", Some("movies".into()), pipeline)?;
+
+    expect_eq!(
+        native_query.arguments,
+        object_fields([
+            ("fallback", Type::Scalar(BsonScalarType::String)),
+            ("date_format", Type::Scalar(BsonScalarType::String)),
+            ("timezone", Type::Scalar(BsonScalarType::String)),
+            ("regex_options", Type::Scalar(BsonScalarType::String)),
+        ])
+    );
+
+    let result_type = &native_query.object_types[&native_query.result_document_type];
+    expect_eq!(
+        result_type.fields["year_string"].r#type,
+        Type::Scalar(BsonScalarType::String)
+    );
+    expect_eq!(
+        result_type.fields["released_label"].r#type,
+        Type::Scalar(BsonScalarType::String)
+    );
+
+    let Type::Nullable(match_type) = &result_type.fields["title_match"].r#type else {
+        panic!("expected a nullable type for the result of $regexFind");
+    };
+    let Type::Object(match_type_name) = match_type.as_ref() else {
+        panic!("expected an object type for the result of $regexFind");
+    };
+    expect_eq!(
+        native_query.object_types[match_type_name.as_str()].fields,
+        object_fields([
+            ("match", Type::Scalar(BsonScalarType::String)),
+            ("idx", Type::Scalar(BsonScalarType::Int)),
+            (
+                "captures",
+                Type::ArrayOf(Box::new(Type::Nullable(Box::new(Type::Scalar(
+                    BsonScalarType::String
+                )))))
+            ),
+        ])
+    );
+    Ok(())
+}
+
+#[googletest::test]
+fn infers_nullable_conversion_results_and_array_operand_of_size() -> googletest::Result<()> {
+    let config = mflix_config();
+
+    let pipeline = Pipeline::new(vec![Stage::ReplaceWith(Selection::new(doc! {
+        "year_string": { "$toString": "$year" },
+        "tag_count": { "$size": "{{ tags }}" },
+    }))]);
+
+    let native_query =
+        native_query_from_pipeline(&config, "converted_movies", Some("movies".into()), pipeline)?;
+
+    expect_eq!(
+        native_query.arguments,
+        object_fields([("tags", Type::ArrayOf(Box::new(Type::ExtendedJSON)))])
+    );
+
+    let result_type = &native_query.object_types[&native_query.result_document_type];
+    expect_eq!(
+        result_type.fields["year_string"].r#type,
+        Type::Nullable(Box::new(Type::Scalar(BsonScalarType::String)))
+    );
+    expect_eq!(
+        result_type.fields["tag_count"].r#type,
+        Type::Scalar(BsonScalarType::Int)
+    );
+    Ok(())
+}
+
+#[googletest::test]
+fn makes_fields_nullable_except_densify_and_partition_fields() -> Result<()> {
+    let config = mflix_config();
+
+    let pipeline = Pipeline::new(vec![Stage::Densify {
+        field: "year".into(),
+        partition_by_fields: Some(vec!["rated".into()]),
+        range: doc! { "step": 1, "bounds": "full" },
+    }]);
+
+    let native_query =
+        native_query_from_pipeline(&config, "densify_test", Some("movies".into()), pipeline)?;
+
+    let result_type = &native_query.object_types[&native_query.result_document_type];
+    expect_eq!(
+        result_type.fields["year"].r#type,
+        Type::Scalar(BsonScalarType::Int)
+    );
+    expect_eq!(
+        result_type.fields["rated"].r#type,
+        Type::Scalar(BsonScalarType::String)
+    );
+    expect_eq!(
+        result_type.fields["title"].r#type,
+        Type::Nullable(Box::new(Type::Scalar(BsonScalarType::String)))
+    );
+    expect_eq!(
+        result_type.fields["runtime"].r#type,
+        Type::Nullable(Box::new(Type::Scalar(BsonScalarType::Int)))
+    );
+    Ok(())
+}
+
 fn object_fields<S, K>(types: impl IntoIterator<Item = (S, Type)>) -> BTreeMap<K, ObjectField>
 where
     S: Into<K>,
@@ -3,6 +3,7 @@ use std::env;
 const CONFIGURATION_MODE_ENV: &str = "HASURA_CONFIGURATION_MODE";
 const CONNECTOR_ID_ENV: &str = "HASURA_CONFIGURATION_CONNECTOR_ID";
 const CONFIGURATION_SCHEMA_ENV: &str = "HASURA_CONFIGURATION_SCHEMA";
+const CONFIGURATION_PAGE_SIZE_ENV: &str = "HASURA_CONFIGURATION_PAGE_SIZE";
 const DEFAULT_SCHEMA: &str = "connector_config";
 
 #[derive(Clone)]
@@ -14,6 +15,8 @@ pub enum ConfigurationMode {
         url: String,
         connector_id: String,
         schema: String,
+        /// Number of rows to read per query when loading the full configuration
+        page_size: Option<u32>,
     },
 }
 
@@ -24,12 +27,14 @@ impl std::fmt::Debug for ConfigurationMode {
             ConfigurationMode::Postgres {
                 connector_id,
                 schema,
+                page_size,
                 ..
             } => f
                 .debug_struct("Postgres")
                 .field("url", &"<redacted>")
                 .field("connector_id", connector_id)
                 .field("schema", schema)
+                .field("page_size", page_size)
                 .finish(),
         }
     }
@@ -41,11 +46,15 @@ impl std::fmt::Debug for ConfigurationMode {
 ///   If set to a postgres URL, uses postgres-based config.
 /// - `HASURA_CONFIGURATION_CONNECTOR_ID`: Required when using postgres mode.
 /// - `HASURA_CONFIGURATION_SCHEMA`: Postgres schema name (default: "connector_config").
+/// - `HASURA_CONFIGURATION_PAGE_SIZE`: Number of rows to read per query when loading the full
+///   configuration for a schema response (default: read all rows at once). This batches queries;
+///   every row is still held in memory until the configuration is built.
 pub fn resolve_configuration_mode() -> anyhow::Result<ConfigurationMode> {
     resolve_from_values(
         env::var(CONFIGURATION_MODE_ENV).ok().as_deref(),
         env::var(CONNECTOR_ID_ENV).ok().as_deref(),
         env::var(CONFIGURATION_SCHEMA_ENV).ok().as_deref(),
+        env::var(CONFIGURATION_PAGE_SIZE_ENV).ok().as_deref(),
     )
 }
 
@@ -53,6 +62,7 @@ fn resolve_from_values(
     mode: Option<&str>,
     connector_id: Option<&str>,
     schema: Option<&str>,
+    page_size: Option<&str>,
 ) -> anyhow::Result<ConfigurationMode> {
     let mode = mode.unwrap_or("");
 
@@ -77,10 +87,21 @@ fn resolve_from_values(
         .unwrap_or(DEFAULT_SCHEMA)
         .to_string();
 
+    let page_size = page_size
+        .filter(|s| !s.is_empty())
+        .map(|s| match s.parse::<u32>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(anyhow::anyhow!(
+                "{CONFIGURATION_PAGE_SIZE_ENV} must be a positive integer, got {s:?}"
+            )),
+        })
+        .transpose()?;
+
     Ok(ConfigurationMode::Postgres {
         url,
         connector_id,
         schema,
+        page_size,
     })
 }
 
@@ -90,37 +111,37 @@ mod tests {
 
     #[test]
     fn default_is_json_mode() {
-        let mode = resolve_from_values(None, None, None).unwrap();
+        let mode = resolve_from_values(None, None, None, None).unwrap();
         assert!(matches!(mode, ConfigurationMode::Json));
     }
 
     #[test]
     fn empty_mode_is_json() {
-        let mode = resolve_from_values(Some(""), None, None).unwrap();
+        let mode = resolve_from_values(Some(""), None, None, None).unwrap();
         assert!(matches!(mode, ConfigurationMode::Json));
     }
 
     #[test]
     fn explicit_json_mode() {
-        let mode = resolve_from_values(Some("json"), None, None).unwrap();
+        let mode = resolve_from_values(Some("json"), None, None, None).unwrap();
         assert!(matches!(mode, ConfigurationMode::Json));
     }
 
     #[test]
     fn json_mode_case_insensitive() {
-        let mode = resolve_from_values(Some("JSON"), None, None).unwrap();
+        let mode = resolve_from_values(Some("JSON"), None, None, None).unwrap();
         assert!(matches!(mode, ConfigurationMode::Json));
     }
 
     #[test]
     fn postgres_mode_requires_connector_id() {
-        let result = resolve_from_values(Some("postgres://localhost/config"), None, None);
+        let result = resolve_from_values(Some("postgres://localhost/config"), None, None, None);
         assert!(result.is_err());
     }
 
     #[test]
     fn postgres_mode_rejects_empty_connector_id() {
-        let result = resolve_from_values(Some("postgres://localhost/config"), Some(""), None);
+        let result = resolve_from_values(Some("postgres://localhost/config"), Some(""), None, None);
         assert!(result.is_err());
     }
 
@@ -130,6 +151,7 @@ mod tests {
             Some("postgres://localhost/config"),
             Some("my-connector"),
             None,
+            None,
         )
         .unwrap();
         match mode {
@@ -137,10 +159,12 @@ mod tests {
                 url,
                 connector_id,
                 schema,
+                page_size,
             } => {
                 assert_eq!(url, "postgres://localhost/config");
                 assert_eq!(connector_id, "my-connector");
                 assert_eq!(schema, "connector_config");
+                assert_eq!(page_size, None);
             }
             _ => panic!("expected Postgres mode"),
         }
@@ -152,6 +176,7 @@ mod tests {
             Some("postgres://localhost/config"),
             Some("my-connector"),
             Some("custom_schema"),
+            None,
         )
         .unwrap();
         match mode {
@@ -161,4 +186,37 @@ mod tests {
             _ => panic!("expected Postgres mode"),
         }
     }
+
+    #[test]
+    fn postgres_mode_with_page_size() {
+        let mode = resolve_from_values(
+            Some("postgres://localhost/config"),
+            Some("my-connector"),
+            None,
+            Some("500"),
+        )
+        .unwrap();
+        match mode {
+            ConfigurationMode::Postgres { page_size, .. } => {
+                assert_eq!(page_size, Some(500));
+            }
+            _ => panic!("expected Postgres mode"),
+        }
+    }
+
+    #[test]
+    fn postgres_mode_rejects_invalid_page_size() {
+        for page_size in ["0", "-1", "lots"] {
+            let result = resolve_from_values(
+                Some("postgres://localhost/config"),
+                Some("my-connector"),
+                None,
+                Some(page_size),
+            );
+            assert!(
+                result.is_err(),
+                "expected error for page size {page_size:?}"
+            );
+        }
+    }
 }
@@ -1,6 +1,14 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+
 use anyhow::Context as _;
-use configuration::{serialized::Schema, Configuration, ConfigurationOptions};
+use configuration::{
+    collection_procedure::collection_names_for_procedure_name,
+    serialized::{NativeMutation, NativeQuery, Schema},
+    Configuration, ConfigurationOptions,
+};
 use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use ndc_models::{FunctionName, ProcedureName};
 use postgres_native_tls::MakeTlsConnector;
 
 const SOURCE: &str = "MONGODB";
@@ -8,11 +16,14 @@ const SOURCE: &str = "MONGODB";
 /// Reads connector configuration from a PostgreSQL config store,
 /// using the shared config_tables schema with a `raw_schema` column
 /// that stores the connector's native schema JSON per collection.
+/// Native queries and native mutations are stored in config_native_queries and
+/// config_native_mutations with a `definition` column per query or procedure.
 #[derive(Clone)]
 pub struct PostgresConfigurationStore {
     pool: Pool,
     connector_id: String,
     schema: String,
+    page_size: Option<u32>,
 }
 
 impl std::fmt::Debug for PostgresConfigurationStore {
@@ -20,12 +31,18 @@ impl std::fmt::Debug for PostgresConfigurationStore {
         f.debug_struct("PostgresConfigurationStore")
             .field("connector_id", &self.connector_id)
             .field("schema", &self.schema)
+            .field("page_size", &self.page_size)
             .finish()
     }
 }
 
 impl PostgresConfigurationStore {
-    pub fn new(url: String, connector_id: String, schema: String) -> anyhow::Result<Self> {
+    pub fn new(
+        url: String,
+        connector_id: String,
+        schema: String,
+        page_size: Option<u32>,
+    ) -> anyhow::Result<Self> {
         let tls_connector = native_tls::TlsConnector::builder()
             .build()
             .context("failed to build TLS connector")?;
@@ -48,6 +65,7 @@ impl PostgresConfigurationStore {
             pool,
             connector_id,
             schema,
+            page_size,
         })
     }
 
@@ -69,53 +87,301 @@ impl PostgresConfigurationStore {
     }
 
     /// Read schemas for multiple collections by name and merge them into a single Configuration.
-    /// This is needed when a query involves relationships to other collections.
+    /// This is needed when a query involves relationships to other collections. Each name may
+    /// refer to a collection or to a native query.
     pub async fn read_collections_configuration(
         &self,
         collection_names: &[&str],
     ) -> anyhow::Result<Configuration> {
         let client = self.get_client().await?;
+        let (schema, native_queries) = self
+            .read_schema_and_native_queries(&client, collection_names)
+            .await?;
 
-        let mut merged_schema = Schema::default();
-
-        for &collection_name in collection_names {
-            let query = format!(
-                r#"SELECT name, raw_schema
-                   FROM "{}".config_tables
-                   WHERE UPPER(source) = UPPER($1)
-                     AND connector_id = $2
-                     AND name = $3
-                     AND is_deleted = false
-                   ORDER BY updated_at DESC
-                   LIMIT 1"#,
-                self.schema
-            );
-
-            let row = client
-                .query_opt(&query, &[&SOURCE, &self.connector_id, &collection_name])
-                .await
-                .with_context(|| {
-                    format!("failed to query config_tables for collection {collection_name}")
-                })?
-                .ok_or_else(|| {
-                    anyhow::anyhow!("collection {collection_name} not found in config store")
-                })?;
+        let options = self.read_configuration_options(&client).await?;
+
+        Configuration::validate(schema, Default::default(), native_queries, options)
+    }
+
+    /// Read configuration for a mutation request: the native mutations named by the request, and
+    /// schemas for the given collections which may be referenced by relationships in mutation
+    /// results. Procedure names that are not stored native mutations may name procedures that are
+    /// generated for a collection, so the schemas of those collections are read too. Names that
+    /// match neither are skipped so that the request fails with the same error that an unknown
+    /// procedure gets in file-based configuration.
+    pub async fn read_mutation_configuration(
+        &self,
+        procedure_names: &[&str],
+        collection_names: &[&str],
+    ) -> anyhow::Result<Configuration> {
+        let client = self.get_client().await?;
+        let (mut schema, native_queries) = self
+            .read_schema_and_native_queries(&client, collection_names)
+            .await?;
+        let native_mutations = self.read_native_mutations(&client, procedure_names).await?;
+
+        for name in generated_procedure_collections(procedure_names, &native_mutations, &schema) {
+            if let Some(collection_schema) = self.read_collection_schema(&client, name).await? {
+                merge_schema(&mut schema, collection_schema);
+            }
+        }
+
+        let options = self.read_configuration_options(&client).await?;
 
-            let name: String = row.get(0);
-            let raw_schema_json: serde_json::Value = row.get(1);
-            let schema: Schema = serde_json::from_value(raw_schema_json)
+        Configuration::validate(schema, native_mutations, native_queries, options)
+    }
+
+    /// Read every collection schema, native query, and native mutation stored for the connector,
+    /// and merge them into a single Configuration. This is used to produce the full schema
+    /// response. When `page_size` is set each table is queried in batches of that many rows.
+    pub async fn read_full_configuration(&self) -> anyhow::Result<Configuration> {
+        let client = self.get_client().await?;
+
+        let mut schema = Schema::default();
+        for (name, raw_schema_json) in self
+            .read_all_rows(&client, "config_tables", "raw_schema")
+            .await?
+        {
+            let collection_schema: Schema = serde_json::from_value(raw_schema_json)
                 .with_context(|| format!("failed to parse raw_schema for collection {name}"))?;
+            merge_schema(&mut schema, collection_schema);
+        }
+
+        let native_queries = self
+            .read_all_rows(&client, "config_native_queries", "definition")
+            .await?
+            .into_iter()
+            .map(|(name, definition_json)| {
+                let native_query: NativeQuery = serde_json::from_value(definition_json)
+                    .with_context(|| {
+                        format!("failed to parse definition for native query {name}")
+                    })?;
+                Ok((name.into(), native_query))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let native_mutations = self
+            .read_all_rows(&client, "config_native_mutations", "definition")
+            .await?
+            .into_iter()
+            .map(|(name, definition_json)| {
+                let native_mutation: NativeMutation = serde_json::from_value(definition_json)
+                    .with_context(|| {
+                        format!("failed to parse definition for native mutation {name}")
+                    })?;
+                Ok((name.into(), native_mutation))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let options = self.read_configuration_options(&client).await?;
+
+        Configuration::validate(schema, native_mutations, native_queries, options)
+    }
+
+    /// Read the latest non-deleted row for every name in the given table, in name order. When
+    /// a page size is configured rows are fetched with one query per page, using keyset pagination
+    /// on the name. Every row is still held in memory until the configuration is built, so the page
+    /// size bounds the size of each query result, not the memory used for the whole read.
+    async fn read_all_rows(
+        &self,
+        client: &deadpool_postgres::Client,
+        table: &str,
+        column: &str,
+    ) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+        let query = format!(
+            r#"SELECT DISTINCT ON (name) name, {column}
+               FROM "{}".{table}
+               WHERE UPPER(source) = UPPER($1)
+                 AND connector_id = $2
+                 AND name > $3
+                 AND is_deleted = false
+               ORDER BY name, updated_at DESC
+               LIMIT $4"#,
+            self.schema
+        );
+        let query = &query;
+        let page_size = self.page_size.map(i64::from).unwrap_or(i64::MAX);
+
+        read_pages(page_size, |last_name| async move {
+            let page = client
+                .query(
+                    query,
+                    &[&SOURCE, &self.connector_id, &last_name, &page_size],
+                )
+                .await
+                .with_context(|| format!("failed to query {table}"))?;
+            Ok(page
+                .into_iter()
+                .map(|row| (row.get(0), row.get(1)))
+                .collect())
+        })
+        .await
+    }
+
+    /// Read connector configuration options from config_metadata. Options are stored under the
+    /// `configuration_options` key in the same format as the `configuration.json` file. Falls
+    /// back to default options if not found.
+    async fn read_configuration_options(
+        &self,
+        client: &deadpool_postgres::Client,
+    ) -> anyhow::Result<ConfigurationOptions> {
+        let query = format!(
+            r#"SELECT value
+               FROM "{}".config_metadata
+               WHERE UPPER(source) = UPPER($1)
+                 AND connector_id = $2
+                 AND key = $3
+               ORDER BY updated_at DESC
+               LIMIT 1"#,
+            self.schema
+        );
+
+        let row = client
+            .query_opt(
+                &query,
+                &[&SOURCE, &self.connector_id, &"configuration_options"],
+            )
+            .await
+            .context("failed to query config_metadata for configuration_options")?;
 
-            merged_schema.collections.extend(schema.collections);
-            merged_schema.object_types.extend(schema.object_types);
+        match row {
+            Some(row) => {
+                let value: serde_json::Value = row.get(0);
+                serde_json::from_value(value).context("failed to parse configuration_options")
+            }
+            None => Ok(ConfigurationOptions::default()),
         }
+    }
+
+    /// Native queries are looked up first, and remaining names are read as collections. The
+    /// schema of the input collection of each native query is included when it is stored because
+    /// native query definitions may reference object types from that schema.
+    async fn read_schema_and_native_queries(
+        &self,
+        client: &deadpool_postgres::Client,
+        names: &[&str],
+    ) -> anyhow::Result<(Schema, BTreeMap<FunctionName, NativeQuery>)> {
+        let native_queries = self.read_native_queries(client, names).await?;
+        let schema = read_schemas_for_names(names, &native_queries, |name| async move {
+            self.read_collection_schema(client, &name).await
+        })
+        .await?;
+        Ok((schema, native_queries))
+    }
+
+    async fn read_collection_schema(
+        &self,
+        client: &deadpool_postgres::Client,
+        collection_name: &str,
+    ) -> anyhow::Result<Option<Schema>> {
+        let query = format!(
+            r#"SELECT name, raw_schema
+               FROM "{}".config_tables
+               WHERE UPPER(source) = UPPER($1)
+                 AND connector_id = $2
+                 AND name = $3
+                 AND is_deleted = false
+               ORDER BY updated_at DESC
+               LIMIT 1"#,
+            self.schema
+        );
+
+        let row = client
+            .query_opt(&query, &[&SOURCE, &self.connector_id, &collection_name])
+            .await
+            .with_context(|| {
+                format!("failed to query config_tables for collection {collection_name}")
+            })?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let name: String = row.get(0);
+        let raw_schema_json: serde_json::Value = row.get(1);
+        let schema: Schema = serde_json::from_value(raw_schema_json)
+            .with_context(|| format!("failed to parse raw_schema for collection {name}"))?;
+        Ok(Some(schema))
+    }
+
+    /// Read native query definitions from the config_native_queries table. Each row stores one
+    /// definition in the same format as a file in the `native_queries` configuration directory.
+    /// Names that do not match a stored native query are skipped.
+    async fn read_native_queries(
+        &self,
+        client: &deadpool_postgres::Client,
+        names: &[&str],
+    ) -> anyhow::Result<BTreeMap<FunctionName, NativeQuery>> {
+        self.read_rows_by_name(client, "config_native_queries", "definition", names)
+            .await?
+            .into_iter()
+            .map(|(name, definition_json)| {
+                let native_query: NativeQuery = serde_json::from_value(definition_json)
+                    .with_context(|| {
+                        format!("failed to parse definition for native query {name}")
+                    })?;
+                Ok((name.into(), native_query))
+            })
+            .collect::<anyhow::Result<_>>()
+    }
 
-        Configuration::validate(
-            merged_schema,
-            Default::default(),
-            Default::default(),
-            ConfigurationOptions::default(),
+    /// Read native mutation definitions from the config_native_mutations table. Each row stores
+    /// one definition in the same format as a file in the `native_mutations` configuration
+    /// directory. Object types that a definition references must be defined in that definition,
+    /// or belong to a collection whose schema is read for the same request.
+    async fn read_native_mutations(
+        &self,
+        client: &deadpool_postgres::Client,
+        procedure_names: &[&str],
+    ) -> anyhow::Result<BTreeMap<ProcedureName, NativeMutation>> {
+        self.read_rows_by_name(
+            client,
+            "config_native_mutations",
+            "definition",
+            procedure_names,
         )
+        .await?
+        .into_iter()
+        .map(|(name, definition_json)| {
+            let native_mutation: NativeMutation = serde_json::from_value(definition_json)
+                .with_context(|| {
+                    format!("failed to parse definition for native mutation {name}")
+                })?;
+            Ok((name.into(), native_mutation))
+        })
+        .collect::<anyhow::Result<_>>()
+    }
+
+    /// Read the latest non-deleted row for each of the given names in one query. Names without
+    /// a stored row are skipped.
+    async fn read_rows_by_name(
+        &self,
+        client: &deadpool_postgres::Client,
+        table: &str,
+        column: &str,
+        names: &[&str],
+    ) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+        if names.is_empty() {
+            return Ok(vec![]);
+        }
+        let query = format!(
+            r#"SELECT DISTINCT ON (name) name, {column}
+               FROM "{}".{table}
+               WHERE UPPER(source) = UPPER($1)
+                 AND connector_id = $2
+                 AND name = ANY($3)
+                 AND is_deleted = false
+               ORDER BY name, updated_at DESC"#,
+            self.schema
+        );
+
+        let rows = client
+            .query(&query, &[&SOURCE, &self.connector_id, &names])
+            .await
+            .with_context(|| format!("failed to query {table}"))?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
     }
 
     /// Read the connection URI from config_metadata.
@@ -154,6 +420,102 @@ impl PostgresConfigurationStore {
     }
 }
 
+/// Reads rows page by page with keyset pagination. `read_page` is given the name of the last row
+/// read so far, or an empty string for the first page, and returns up to `page_size` rows with
+/// greater names in name order. A page with fewer than `page_size` rows is the last one.
+async fn read_pages<F, Fut>(
+    page_size: i64,
+    mut read_page: F,
+) -> anyhow::Result<Vec<(String, serde_json::Value)>>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = anyhow::Result<Vec<(String, serde_json::Value)>>>,
+{
+    let mut rows = Vec::new();
+    let mut last_name = String::new();
+    loop {
+        let page = read_page(last_name.clone()).await?;
+        let page_len = page.len();
+        if let Some((name, _)) = page.last() {
+            last_name.clone_from(name);
+        }
+        rows.extend(page);
+        if (page_len as i64) < page_size {
+            break;
+        }
+    }
+    Ok(rows)
+}
+
+/// Reads the collection schema for each name that is not one of the given native queries, and then
+/// schemas of input collections of those native queries that have not been read yet.
+/// `read_collection_schema` returns `None` if there is no stored schema for a collection name.
+async fn read_schemas_for_names<F, Fut>(
+    names: &[&str],
+    native_queries: &BTreeMap<FunctionName, NativeQuery>,
+    mut read_collection_schema: F,
+) -> anyhow::Result<Schema>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = anyhow::Result<Option<Schema>>>,
+{
+    let mut merged_schema = Schema::default();
+    for &name in names {
+        if native_queries.contains_key(name) {
+            continue;
+        }
+        let schema = read_collection_schema(name.to_owned())
+            .await?
+            .ok_or_else(|| {
+                anyhow::anyhow!("collection or native query {name} not found in config store")
+            })?;
+        merge_schema(&mut merged_schema, schema);
+    }
+
+    for name in missing_input_collections(native_queries, &merged_schema) {
+        if let Some(schema) = read_collection_schema(name.to_owned()).await? {
+            merge_schema(&mut merged_schema, schema);
+        }
+    }
+
+    Ok(merged_schema)
+}
+
+/// Adds the collections and object types of a schema read from the store to a merged schema.
+fn merge_schema(merged_schema: &mut Schema, schema: Schema) {
+    merged_schema.collections.extend(schema.collections);
+    merged_schema.object_types.extend(schema.object_types);
+}
+
+/// Input collections of the given native queries whose schemas have not been read yet.
+fn missing_input_collections<'a>(
+    native_queries: &'a BTreeMap<FunctionName, NativeQuery>,
+    schema: &Schema,
+) -> BTreeSet<&'a str> {
+    native_queries
+        .values()
+        .filter_map(|native_query| native_query.input_collection.as_ref())
+        .map(|collection| collection.as_str())
+        .filter(|collection| !schema.collections.contains_key(*collection))
+        .collect()
+}
+
+/// Collections that may have generated the named procedures that are not native mutations, and
+/// whose schemas have not been read yet.
+fn generated_procedure_collections<'a>(
+    procedure_names: &[&'a str],
+    native_mutations: &BTreeMap<ProcedureName, NativeMutation>,
+    schema: &Schema,
+) -> BTreeSet<&'a str> {
+    procedure_names
+        .iter()
+        .copied()
+        .filter(|name| !native_mutations.contains_key(*name))
+        .flat_map(collection_names_for_procedure_name)
+        .filter(|collection| !schema.collections.contains_key(*collection))
+        .collect()
+}
+
 /// Connection URI as stored in config_metadata.
 #[derive(Debug, serde::Deserialize)]
 #[serde(untagged)]
@@ -176,3 +538,219 @@ impl ConnectionUri {
 }
 
 pub const DEFAULT_DATABASE_URI_ENV_VAR: &str = "MONGODB_DATABASE_URI";
+
+#[cfg(test)]
+mod tests {
+    use configuration::schema::{Collection, ObjectType};
+    use serde_json::json;
+
+    use super::*;
+
+    fn collection_schema(collection_name: &str, type_name: &str) -> Schema {
+        Schema {
+            collections: [(
+                collection_name.into(),
+                Collection {
+                    r#type: type_name.into(),
+                    description: None,
+                },
+            )]
+            .into(),
+            object_types: [(
+                type_name.into(),
+                ObjectType {
+                    fields: Default::default(),
+                    description: None,
+                },
+            )]
+            .into(),
+        }
+    }
+
+    fn native_query(input_collection: Option<&str>) -> anyhow::Result<NativeQuery> {
+        Ok(serde_json::from_value(json!({
+            "representation": "collection",
+            "inputCollection": input_collection,
+            "resultDocumentType": "Album",
+            "pipeline": [],
+        }))?)
+    }
+
+    fn native_mutation() -> anyhow::Result<NativeMutation> {
+        Ok(serde_json::from_value(json!({
+            "resultType": "extendedJSON",
+            "command": { "ping": 1 },
+        }))?)
+    }
+
+    #[test]
+    fn merges_collections_and_object_types() {
+        let mut schema = collection_schema("albums", "Album");
+        merge_schema(&mut schema, collection_schema("artists", "Artist"));
+        assert_eq!(
+            schema
+                .collections
+                .keys()
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>(),
+            ["albums", "artists"]
+        );
+        assert_eq!(
+            schema
+                .object_types
+                .keys()
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>(),
+            ["Album", "Artist"]
+        );
+    }
+
+    #[test]
+    fn finds_input_collections_that_have_not_been_read() -> anyhow::Result<()> {
+        let native_queries = [
+            ("albums_by_year".into(), native_query(Some("albums"))?),
+            ("top_artists".into(), native_query(Some("artists"))?),
+            ("hello".into(), native_query(None)?),
+        ]
+        .into();
+        let schema = collection_schema("albums", "Album");
+        assert_eq!(
+            missing_input_collections(&native_queries, &schema),
+            ["artists"].into()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn finds_collections_for_generated_procedures_that_are_not_native_mutations(
+    ) -> anyhow::Result<()> {
+        let native_mutations = [("insert_artists_one".into(), native_mutation()?)].into();
+        let schema = collection_schema("albums", "Album");
+        let collections = generated_procedure_collections(
+            &[
+                "insert_albums_one",
+                "insert_artists_one",
+                "update_tracks_by_id",
+                "upsert_genres_by_name",
+                "hello",
+            ],
+            &native_mutations,
+            &schema,
+        );
+        assert_eq!(collections, ["genres", "genres_by_name", "tracks"].into());
+        Ok(())
+    }
+
+    /// Looks up schemas for the collections "albums", "artists", and "albums_by_year", and records
+    /// the names that were requested
+    fn stored_collection_schema(
+        requested: &mut Vec<String>,
+        name: String,
+    ) -> impl Future<Output = anyhow::Result<Option<Schema>>> {
+        requested.push(name.clone());
+        let schema = match name.as_str() {
+            "albums" => Some(collection_schema("albums", "Album")),
+            "artists" => Some(collection_schema("artists", "Artist")),
+            "albums_by_year" => Some(collection_schema("albums_by_year", "AlbumsByYear")),
+            _ => None,
+        };
+        async move { Ok(schema) }
+    }
+
+    #[tokio::test]
+    async fn resolves_names_as_native_queries_before_collections() -> anyhow::Result<()> {
+        let native_queries = [("albums_by_year".into(), native_query(Some("albums"))?)].into();
+        let mut requested = vec![];
+        let schema =
+            read_schemas_for_names(&["albums_by_year", "artists"], &native_queries, |name| {
+                stored_collection_schema(&mut requested, name)
+            })
+            .await?;
+        assert_eq!(
+            schema
+                .collections
+                .keys()
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>(),
+            ["albums", "artists"]
+        );
+        assert_eq!(requested, ["artists", "albums"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fails_if_name_is_neither_a_collection_nor_a_native_query() -> anyhow::Result<()> {
+        let mut requested = vec![];
+        let result = read_schemas_for_names(&["artists", "genres"], &Default::default(), |name| {
+            stored_collection_schema(&mut requested, name)
+        })
+        .await;
+        let Err(error) = result else {
+            panic!("expected an error for an unknown name");
+        };
+        assert_eq!(
+            error.to_string(),
+            "collection or native query genres not found in config store"
+        );
+        Ok(())
+    }
+
+    /// Stored rows as (name, updated_at, value). Several rows may share a name, in which case
+    /// only the latest one is read.
+    const STORED_ROWS: [(&str, u32, &str); 5] = [
+        ("albums", 1, "old albums"),
+        ("artists", 1, "artists"),
+        ("albums", 2, "albums"),
+        ("tracks", 2, "tracks"),
+        ("tracks", 1, "old tracks"),
+    ];
+
+    /// Selects rows the same way as the query in `read_all_rows`.
+    fn latest_rows_after(last_name: &str, page_size: i64) -> Vec<(String, serde_json::Value)> {
+        let mut latest: BTreeMap<&str, (u32, &str)> = BTreeMap::new();
+        for (name, updated_at, value) in STORED_ROWS {
+            let is_latest = !matches!(latest.get(name), Some((t, _)) if *t >= updated_at);
+            if name > last_name && is_latest {
+                latest.insert(name, (updated_at, value));
+            }
+        }
+        latest
+            .into_iter()
+            .take(page_size as usize)
+            .map(|(name, (_, value))| (name.to_owned(), json!(value)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reads_latest_row_for_each_name_one_page_at_a_time() -> anyhow::Result<()> {
+        let mut queried_after = vec![];
+        let rows = read_pages(1, |last_name| {
+            queried_after.push(last_name.clone());
+            async move { Ok(latest_rows_after(&last_name, 1)) }
+        })
+        .await?;
+        assert_eq!(
+            rows,
+            [
+                ("albums".to_owned(), json!("albums")),
+                ("artists".to_owned(), json!("artists")),
+                ("tracks".to_owned(), json!("tracks")),
+            ]
+        );
+        assert_eq!(queried_after, ["", "albums", "artists", "tracks"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_all_rows_in_one_query_without_page_size() -> anyhow::Result<()> {
+        let mut queries = 0;
+        let rows = read_pages(i64::MAX, |last_name| {
+            queries += 1;
+            async move { Ok(latest_rows_after(&last_name, i64::MAX)) }
+        })
+        .await?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(queries, 1);
+        Ok(())
+    }
+}
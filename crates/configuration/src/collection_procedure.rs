@@ -0,0 +1,642 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail};
+use itertools::Itertools as _;
+use mongodb_support::BsonScalarType;
+use ndc_models as ndc;
+use ndc_query_plan as plan;
+use plan::inline_object_types;
+use ref_cast::RefCast as _;
+
+use crate::{GeneratedProceduresConfig, MongoScalarType};
+
+/// Procedures that are generated for each tracked collection when enabled via
+/// [GeneratedProceduresConfig]. Unlike native mutations these do not have a configured command -
+/// the command to run is determined by the operation.
+#[derive(Clone, Debug)]
+pub struct CollectionProcedure {
+    pub collection: ndc::CollectionName,
+    pub operation: CollectionProcedureOperation,
+    pub result_type: plan::Type<MongoScalarType>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CollectionProcedureOperation {
+    /// Inserts the document given in the `document` argument, and returns the inserted document.
+    InsertOne,
+    /// Inserts the documents given in the `documents` argument, and returns the inserted
+    /// documents.
+    InsertMany,
+    /// Applies the `update` argument to the document with the given `_id`, and returns the
+    /// updated document, or null if there is no such document.
+    UpdateById,
+    /// Applies the `update` argument to every document that matches the `predicate` argument,
+    /// and returns counts of matched and modified documents.
+    UpdateMany,
+    /// Deletes the document with the given `_id`, and returns the deleted document, or null if
+    /// there is no such document.
+    DeleteById,
+    /// Deletes every document that matches the `predicate` argument, and returns the count of
+    /// deleted documents.
+    DeleteMany {
+        /// When true an empty predicate, which would match every document, is rejected.
+        require_non_empty_predicate: bool,
+    },
+    /// Inserts the document given in the `document` argument, or if there is a document with the
+    /// same values for `unique_columns` sets the other fields of the argument on that document.
+    /// Returns the resulting document, and whether it was inserted.
+    Upsert {
+        /// Columns of a uniqueness constraint of the collection
+        unique_columns: Vec<ndc::FieldName>,
+    },
+}
+
+/// Update operators that may be given in the `update` argument of generated update procedures.
+/// Each operator is a field of the argument type that maps document fields to operands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateOperator {
+    Set,
+    Inc,
+    Unset,
+    Push,
+    Pull,
+}
+
+impl UpdateOperator {
+    pub const ALL: [UpdateOperator; 5] = [
+        UpdateOperator::Set,
+        UpdateOperator::Inc,
+        UpdateOperator::Unset,
+        UpdateOperator::Push,
+        UpdateOperator::Pull,
+    ];
+
+    /// Name of the field in the `update` argument for this operator
+    pub fn field_name(self) -> &'static str {
+        match self {
+            UpdateOperator::Set => "_set",
+            UpdateOperator::Inc => "_inc",
+            UpdateOperator::Unset => "_unset",
+            UpdateOperator::Push => "_push",
+            UpdateOperator::Pull => "_pull",
+        }
+    }
+
+    /// The MongoDB update operator that this operator translates to
+    pub fn mongodb_operator(self) -> &'static str {
+        match self {
+            UpdateOperator::Set => "$set",
+            UpdateOperator::Inc => "$inc",
+            UpdateOperator::Unset => "$unset",
+            UpdateOperator::Push => "$push",
+            UpdateOperator::Pull => "$pull",
+        }
+    }
+
+    /// Type of the operand for a field of the given type, or `None` if the operator does not apply
+    /// to fields of that type.
+    fn operand_type(self, field_type: &ndc::Type) -> Option<ndc::Type> {
+        let underlying_type = match field_type {
+            ndc::Type::Nullable { underlying_type } => underlying_type.as_ref(),
+            t => t,
+        };
+        match self {
+            UpdateOperator::Set => Some(field_type.clone()),
+            UpdateOperator::Inc => match underlying_type {
+                ndc::Type::Named { name } => {
+                    match MongoScalarType::lookup_scalar_type(ndc::ScalarTypeName::ref_cast(name)) {
+                        Some(MongoScalarType::Bson(scalar_type)) if scalar_type.is_numeric() => {
+                            Some(underlying_type.clone())
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            UpdateOperator::Unset => Some(ndc::Type::Named {
+                name: BsonScalarType::Bool.graphql_name().into(),
+            }),
+            UpdateOperator::Push | UpdateOperator::Pull => match underlying_type {
+                ndc::Type::Array { element_type } => Some(element_type.as_ref().clone()),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl CollectionProcedureOperation {
+    fn procedure_name(&self, collection: &ndc::CollectionName) -> ndc::ProcedureName {
+        match self {
+            CollectionProcedureOperation::InsertOne => format!("insert_{collection}_one"),
+            CollectionProcedureOperation::InsertMany => format!("insert_{collection}_many"),
+            CollectionProcedureOperation::UpdateById => format!("update_{collection}_by_id"),
+            CollectionProcedureOperation::UpdateMany => format!("update_{collection}_many"),
+            CollectionProcedureOperation::DeleteById => format!("delete_{collection}_by_id"),
+            CollectionProcedureOperation::DeleteMany { .. } => {
+                format!("delete_{collection}_many")
+            }
+            // Upserts keyed on the primary key get the short name.
+            CollectionProcedureOperation::Upsert { unique_columns } => {
+                if unique_columns.len() == 1 && unique_columns[0].as_str() == "_id" {
+                    format!("upsert_{collection}")
+                } else {
+                    format!(
+                        "upsert_{collection}_by_{}",
+                        unique_columns.iter().join("_and_")
+                    )
+                }
+            }
+        }
+        .into()
+    }
+
+    fn arguments(
+        &self,
+        collection: &ndc::CollectionInfo,
+        collection_object_type: &ndc::ObjectType,
+    ) -> anyhow::Result<BTreeMap<ndc::ArgumentName, ndc::ArgumentInfo>> {
+        let document_type = ndc::Type::Named {
+            name: collection.collection_type.to_string().into(),
+        };
+        let insert_input_type = ndc::Type::Named {
+            name: insert_input_type_name(&collection.name).to_string().into(),
+        };
+        let update_type = ndc::Type::Named {
+            name: update_type_name(&collection.name).to_string().into(),
+        };
+        let arguments = match self {
+            CollectionProcedureOperation::InsertOne => vec![(
+                "document",
+                insert_input_type,
+                "The document to insert".to_owned(),
+            )],
+            CollectionProcedureOperation::InsertMany => vec![(
+                "documents",
+                ndc::Type::Array {
+                    element_type: Box::new(insert_input_type),
+                },
+                "The documents to insert".to_owned(),
+            )],
+            CollectionProcedureOperation::UpdateById => vec![
+                (
+                    "_id",
+                    id_type(collection, collection_object_type)?,
+                    "The ID of the document to update".to_owned(),
+                ),
+                (
+                    "update",
+                    update_type,
+                    "Changes to apply to the document".to_owned(),
+                ),
+            ],
+            CollectionProcedureOperation::UpdateMany => vec![
+                (
+                    "predicate",
+                    ndc::Type::Predicate {
+                        object_type_name: collection.collection_type.clone(),
+                    },
+                    "Documents that match this predicate are updated".to_owned(),
+                ),
+                (
+                    "update",
+                    update_type,
+                    "Changes to apply to each matching document".to_owned(),
+                ),
+            ],
+            CollectionProcedureOperation::DeleteById => vec![(
+                "_id",
+                id_type(collection, collection_object_type)?,
+                "The ID of the document to delete".to_owned(),
+            )],
+            CollectionProcedureOperation::DeleteMany { .. } => vec![(
+                "predicate",
+                ndc::Type::Predicate {
+                    object_type_name: collection.collection_type.clone(),
+                },
+                "Documents that match this predicate are deleted".to_owned(),
+            )],
+            CollectionProcedureOperation::Upsert { unique_columns } => vec![(
+                "document",
+                document_type,
+                format!(
+                    "The document to insert, or to apply to the existing document with the same {}",
+                    unique_columns.iter().join(", ")
+                ),
+            )],
+        };
+        Ok(arguments
+            .into_iter()
+            .map(|(name, argument_type, description)| {
+                (
+                    name.into(),
+                    ndc::ArgumentInfo {
+                        argument_type,
+                        description: Some(description),
+                    },
+                )
+            })
+            .collect())
+    }
+
+    fn result_type(&self, collection: &ndc::CollectionInfo) -> ndc::Type {
+        let document_type = ndc::Type::Named {
+            name: collection.collection_type.to_string().into(),
+        };
+        match self {
+            CollectionProcedureOperation::InsertOne => document_type,
+            CollectionProcedureOperation::InsertMany => ndc::Type::Array {
+                element_type: Box::new(document_type),
+            },
+            CollectionProcedureOperation::UpdateById | CollectionProcedureOperation::DeleteById => {
+                ndc::Type::Nullable {
+                    underlying_type: Box::new(document_type),
+                }
+            }
+            CollectionProcedureOperation::UpdateMany => ndc::Type::Named {
+                name: update_many_result_type_name(&collection.name)
+                    .to_string()
+                    .into(),
+            },
+            CollectionProcedureOperation::DeleteMany { .. } => ndc::Type::Named {
+                name: delete_many_result_type_name(&collection.name)
+                    .to_string()
+                    .into(),
+            },
+            CollectionProcedureOperation::Upsert { .. } => ndc::Type::Named {
+                name: upsert_result_type_name(&collection.name).to_string().into(),
+            },
+        }
+    }
+
+    fn description(&self, collection: &ndc::CollectionName) -> String {
+        match self {
+            CollectionProcedureOperation::InsertOne => {
+                format!("Insert a document into the {collection} collection")
+            }
+            CollectionProcedureOperation::InsertMany => {
+                format!("Insert documents into the {collection} collection")
+            }
+            CollectionProcedureOperation::UpdateById => {
+                format!("Update a document in the {collection} collection by its ID")
+            }
+            CollectionProcedureOperation::UpdateMany => {
+                format!("Update documents in the {collection} collection that match a predicate")
+            }
+            CollectionProcedureOperation::DeleteById => {
+                format!("Delete a document from the {collection} collection by its ID")
+            }
+            CollectionProcedureOperation::DeleteMany { .. } => {
+                format!("Delete documents from the {collection} collection that match a predicate")
+            }
+            CollectionProcedureOperation::Upsert { unique_columns } => {
+                format!(
+                    "Insert a document into the {collection} collection, or update the document with the same {}",
+                    unique_columns.iter().join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Names of collections that may have generated a procedure with the given name, or an empty list
+/// if the name does not follow the pattern of any generated procedure. Collection names may
+/// contain underscores, so an upsert procedure name can match more than one collection.
+pub fn collection_names_for_procedure_name(procedure_name: &str) -> Vec<&str> {
+    const AFFIXES: [(&str, &str); 6] = [
+        ("insert_", "_one"),
+        ("insert_", "_many"),
+        ("update_", "_by_id"),
+        ("update_", "_many"),
+        ("delete_", "_by_id"),
+        ("delete_", "_many"),
+    ];
+    let mut names: Vec<&str> = AFFIXES
+        .iter()
+        .filter_map(|(prefix, suffix)| {
+            procedure_name
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(suffix))
+        })
+        .collect();
+    if let Some(rest) = procedure_name.strip_prefix("upsert_") {
+        names.push(rest);
+        names.extend(rest.match_indices("_by_").map(|(index, _)| &rest[..index]));
+    }
+    names.retain(|name| !name.is_empty());
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+/// Produces procedure info for the schema response, and the internal representation used for
+/// execution, for each operation enabled in `options` for the given collection. Object types for
+/// generated argument and result types are added to `object_types`.
+pub fn collection_procedures(
+    object_types: &mut BTreeMap<ndc::ObjectTypeName, ndc::ObjectType>,
+    options: &GeneratedProceduresConfig,
+    collection: &ndc::CollectionInfo,
+) -> anyhow::Result<Vec<(ndc::ProcedureInfo, CollectionProcedure)>> {
+    let collection_object_type = object_types
+        .get(&collection.collection_type)
+        .ok_or_else(|| {
+            anyhow!(
+                "the type of collection {}, {}, is not defined",
+                collection.name,
+                collection.collection_type
+            )
+        })?
+        .clone();
+
+    let mut operations = vec![];
+    if options.insert {
+        add_object_type(
+            object_types,
+            insert_input_type_name(&collection.name),
+            insert_input_object_type(&collection.name, &collection_object_type),
+        )?;
+        operations.extend([
+            CollectionProcedureOperation::InsertOne,
+            CollectionProcedureOperation::InsertMany,
+        ]);
+    }
+    if options.update {
+        for (name, object_type) in update_object_types(&collection.name, &collection_object_type) {
+            add_object_type(object_types, name, object_type)?;
+        }
+        if collection_object_type.fields.contains_key("_id") {
+            operations.push(CollectionProcedureOperation::UpdateById);
+        }
+        operations.push(CollectionProcedureOperation::UpdateMany);
+    }
+    if options.delete {
+        add_object_type(
+            object_types,
+            delete_many_result_type_name(&collection.name),
+            count_result_object_type(&[("deleted_count", "Number of deleted documents")]),
+        )?;
+        if collection_object_type.fields.contains_key("_id") {
+            operations.push(CollectionProcedureOperation::DeleteById);
+        }
+        operations.push(CollectionProcedureOperation::DeleteMany {
+            require_non_empty_predicate: options.require_non_empty_delete_predicate,
+        });
+    }
+    if options.upsert && !collection.uniqueness_constraints.is_empty() {
+        add_object_type(
+            object_types,
+            upsert_result_type_name(&collection.name),
+            upsert_result_object_type(collection),
+        )?;
+        for constraint in collection.uniqueness_constraints.values() {
+            for column in &constraint.unique_columns {
+                if !collection_object_type.fields.contains_key(column) {
+                    bail!("the uniqueness constraint column {column} is not a field of the collection type");
+                }
+            }
+            operations.push(CollectionProcedureOperation::Upsert {
+                unique_columns: constraint.unique_columns.clone(),
+            });
+        }
+    }
+
+    operations
+        .into_iter()
+        .map(|operation| {
+            let ndc_result_type = operation.result_type(collection);
+            let result_type = inline_object_types(
+                object_types,
+                &ndc_result_type,
+                MongoScalarType::lookup_scalar_type,
+            )?;
+            let procedure_info = ndc::ProcedureInfo {
+                name: operation.procedure_name(&collection.name),
+                description: Some(operation.description(&collection.name)),
+                arguments: operation.arguments(collection, &collection_object_type)?,
+                result_type: ndc_result_type,
+            };
+            let procedure = CollectionProcedure {
+                collection: collection.name.clone(),
+                operation,
+                result_type,
+            };
+            Ok((procedure_info, procedure))
+        })
+        .collect()
+}
+
+fn insert_input_type_name(collection: &ndc::CollectionName) -> ndc::ObjectTypeName {
+    format!("{collection}_insert_input").into()
+}
+
+fn update_type_name(collection: &ndc::CollectionName) -> ndc::ObjectTypeName {
+    format!("{collection}_update").into()
+}
+
+fn update_operator_type_name(
+    collection: &ndc::CollectionName,
+    operator: UpdateOperator,
+) -> ndc::ObjectTypeName {
+    format!("{collection}_update{}", operator.field_name()).into()
+}
+
+fn update_many_result_type_name(collection: &ndc::CollectionName) -> ndc::ObjectTypeName {
+    format!("{collection}_update_many_result").into()
+}
+
+fn delete_many_result_type_name(collection: &ndc::CollectionName) -> ndc::ObjectTypeName {
+    format!("{collection}_delete_many_result").into()
+}
+
+fn upsert_result_type_name(collection: &ndc::CollectionName) -> ndc::ObjectTypeName {
+    format!("{collection}_upsert_result").into()
+}
+
+/// Object type for documents given to generated insert procedures. This is the collection object
+/// type except that `_id` is optional - the connector generates an ID if one is not given.
+fn insert_input_object_type(
+    collection: &ndc::CollectionName,
+    collection_object_type: &ndc::ObjectType,
+) -> ndc::ObjectType {
+    let mut fields = collection_object_type.fields.clone();
+    if let Some(id_field) = fields.get_mut("_id") {
+        id_field.r#type = nullable(id_field.r#type.clone());
+    }
+    ndc::ObjectType {
+        description: Some(format!(
+            "A document to insert into the {collection} collection"
+        )),
+        fields,
+        foreign_keys: Default::default(),
+    }
+}
+
+/// Object types for the `update` argument of generated update procedures, and for the result of
+/// `update_<collection>_many`. The `_id` field cannot be updated, and fields with names that
+/// MongoDB would interpret as operators or paths cannot be referenced in update operators so all
+/// of those are left out.
+fn update_object_types(
+    collection: &ndc::CollectionName,
+    collection_object_type: &ndc::ObjectType,
+) -> Vec<(ndc::ObjectTypeName, ndc::ObjectType)> {
+    let updatable_fields = collection_object_type
+        .fields
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            name != "_id" && !name.starts_with('$') && !name.contains('.')
+        })
+        .collect::<Vec<_>>();
+
+    let mut update_fields = BTreeMap::new();
+    let mut object_types = vec![];
+    for operator in UpdateOperator::ALL {
+        let operand_fields: BTreeMap<ndc::FieldName, ndc::ObjectField> = updatable_fields
+            .iter()
+            .filter_map(|(name, field)| {
+                let operand_type = operator.operand_type(&field.r#type)?;
+                Some(((*name).clone(), object_field(nullable(operand_type), None)))
+            })
+            .collect();
+        if operand_fields.is_empty() {
+            continue;
+        }
+        let type_name = update_operator_type_name(collection, operator);
+        update_fields.insert(
+            operator.field_name().into(),
+            object_field(
+                nullable(ndc::Type::Named {
+                    name: type_name.to_string().into(),
+                }),
+                Some(format!(
+                    "Fields to update using the MongoDB {} operator",
+                    operator.mongodb_operator()
+                )),
+            ),
+        );
+        object_types.push((
+            type_name,
+            ndc::ObjectType {
+                description: None,
+                fields: operand_fields,
+                foreign_keys: Default::default(),
+            },
+        ));
+    }
+
+    object_types.push((
+        update_type_name(collection),
+        ndc::ObjectType {
+            description: Some(format!(
+                "Changes to apply to documents in the {collection} collection"
+            )),
+            fields: update_fields,
+            foreign_keys: Default::default(),
+        },
+    ));
+
+    object_types.push((
+        update_many_result_type_name(collection),
+        count_result_object_type(&[
+            ("matched_count", "Number of matching documents"),
+            ("modified_count", "Number of modified documents"),
+        ]),
+    ));
+
+    object_types
+}
+
+/// Result type for procedures that report numbers of affected documents. Takes field names paired
+/// with descriptions.
+fn count_result_object_type(fields: &[(&str, &str)]) -> ndc::ObjectType {
+    ndc::ObjectType {
+        description: None,
+        fields: fields
+            .iter()
+            .map(|(name, description)| {
+                (
+                    (*name).into(),
+                    object_field(
+                        ndc::Type::Named {
+                            name: BsonScalarType::Int.graphql_name().into(),
+                        },
+                        Some((*description).to_owned()),
+                    ),
+                )
+            })
+            .collect(),
+        foreign_keys: Default::default(),
+    }
+}
+
+fn upsert_result_object_type(collection: &ndc::CollectionInfo) -> ndc::ObjectType {
+    ndc::ObjectType {
+        description: None,
+        fields: [
+            (
+                "document".into(),
+                object_field(
+                    nullable(ndc::Type::Named {
+                        name: collection.collection_type.to_string().into(),
+                    }),
+                    Some(
+                        "The inserted or updated document, or null if it was removed before it could be read back"
+                            .to_owned(),
+                    ),
+                ),
+            ),
+            (
+                "inserted".into(),
+                object_field(
+                    ndc::Type::Named {
+                        name: BsonScalarType::Bool.graphql_name().into(),
+                    },
+                    Some("True if the document was inserted, false if it was updated".to_owned()),
+                ),
+            ),
+        ]
+        .into(),
+        foreign_keys: Default::default(),
+    }
+}
+
+fn id_type(
+    collection: &ndc::CollectionInfo,
+    collection_object_type: &ndc::ObjectType,
+) -> anyhow::Result<ndc::Type> {
+    match collection_object_type.fields.get("_id") {
+        Some(field) => Ok(field.r#type.clone()),
+        None => bail!(
+            "cannot generate procedures for collection {} that reference documents by ID because its type does not have an _id field",
+            collection.name
+        ),
+    }
+}
+
+fn add_object_type(
+    object_types: &mut BTreeMap<ndc::ObjectTypeName, ndc::ObjectType>,
+    name: ndc::ObjectTypeName,
+    object_type: ndc::ObjectType,
+) -> anyhow::Result<()> {
+    if object_types.contains_key(&name) {
+        bail!("the generated object type name {name} conflicts with an existing object type");
+    }
+    object_types.insert(name, object_type);
+    Ok(())
+}
+
+fn object_field(r#type: ndc::Type, description: Option<String>) -> ndc::ObjectField {
+    ndc::ObjectField {
+        description,
+        r#type,
+        arguments: Default::default(),
+    }
+}
+
+fn nullable(t: ndc::Type) -> ndc::Type {
+    match t {
+        t @ ndc::Type::Nullable { .. } => t,
+        t => ndc::Type::Nullable {
+            underlying_type: Box::new(t),
+        },
+    }
+}
@@ -7,6 +7,7 @@ use ndc_models as ndc;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    collection_procedure::{self, CollectionProcedure},
     native_mutation::NativeMutation,
     native_query::{NativeQuery, NativeQueryRepresentation},
     read_directory, schema, serialized,
@@ -35,6 +36,10 @@ pub struct Configuration {
     /// user configuration.
     pub native_mutations: BTreeMap<ndc::ProcedureName, NativeMutation>,
 
+    /// Procedures generated for tracked collections according to
+    /// [ConfigurationOptions::generated_procedures].
+    pub collection_procedures: BTreeMap<ndc::ProcedureName, CollectionProcedure>,
+
     /// Native queries allow arbitrary aggregation pipelines that can be included in a query plan.
     pub native_queries: BTreeMap<ndc::FunctionName, NativeQuery>,
 
@@ -88,13 +93,23 @@ impl Configuration {
             .map(|(name, ot)| (name.to_owned(), ot.clone()))
             .collect();
 
-        let collections = {
-            let regular_collections = schema.collections.into_iter().map(|(name, collection)| {
+        let regular_collections: BTreeMap<_, _> = schema
+            .collections
+            .into_iter()
+            .map(|(name, collection)| {
                 (
                     name.clone(),
-                    collection_to_collection_info(&object_types, name, collection),
+                    collection_to_collection_info(
+                        &object_types,
+                        name,
+                        collection,
+                        options.relational_mode.mutations,
+                    ),
                 )
-            });
+            })
+            .collect();
+
+        let collections = {
             let native_query_collections = native_queries.iter().filter_map(
                 |(name, native_query): (&ndc::FunctionName, &serialized::NativeQuery)| {
                     if native_query.representation == NativeQueryRepresentation::Collection {
@@ -108,6 +123,8 @@ impl Configuration {
                 },
             );
             regular_collections
+                .clone()
+                .into_iter()
                 .chain(native_query_collections)
                 .collect()
         };
@@ -131,7 +148,7 @@ impl Configuration {
             })
             .partition_result();
 
-        let procedures = native_mutations
+        let mut procedures: BTreeMap<_, _> = native_mutations
             .iter()
             .map(|(name, native_mutation)| {
                 (
@@ -141,11 +158,18 @@ impl Configuration {
             })
             .collect();
 
-        let ndc_object_types = object_types
+        let mut ndc_object_types = object_types
             .into_iter()
             .map(|(name, ot)| (name, ot.into()))
             .collect();
 
+        let (collection_procedures, collection_procedure_errors) = generate_collection_procedures(
+            &mut ndc_object_types,
+            &options.generated_procedures,
+            &regular_collections,
+            &mut procedures,
+        );
+
         let internal_native_queries: BTreeMap<_, _> = native_queries
             .into_iter()
             .map(|(name, nq)| {
@@ -167,6 +191,7 @@ impl Configuration {
         let errors: Vec<String> = object_type_errors
             .into_iter()
             .chain(function_errors)
+            .chain(collection_procedure_errors)
             .map(|e| e.to_string())
             .collect();
         ensure!(
@@ -180,6 +205,7 @@ impl Configuration {
             functions,
             procedures,
             native_mutations: internal_native_mutations,
+            collection_procedures,
             native_queries: internal_native_queries,
             object_types: ndc_object_types,
             options,
@@ -217,6 +243,10 @@ pub struct ConfigurationOptions {
     /// are represented as strings in the schema and serialized as JSON strings in responses.
     #[serde(default)]
     pub relational_mode: RelationalModeConfig,
+
+    /// Options to generate procedures for each tracked collection.
+    #[serde(default)]
+    pub generated_procedures: GeneratedProceduresConfig,
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
@@ -282,6 +312,84 @@ pub struct RelationalModeConfig {
     /// as JSON strings.
     #[serde(default)]
     pub enabled: bool,
+
+    /// When true, collections in the connector schema accept relational insert, update, and
+    /// delete requests. Collections defined by native queries are read-only.
+    #[serde(default)]
+    pub mutations: bool,
+}
+
+/// Options to generate procedures for each collection in the connector schema. Collections
+/// defined by native queries do not get generated procedures.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct GeneratedProceduresConfig {
+    /// When true, generates `insert_<collection>_one` and `insert_<collection>_many` procedures.
+    #[serde(default)]
+    pub insert: bool,
+
+    /// When true, generates `update_<collection>_by_id` and `update_<collection>_many`
+    /// procedures.
+    #[serde(default)]
+    pub update: bool,
+
+    /// When true, generates `delete_<collection>_by_id` and `delete_<collection>_many`
+    /// procedures.
+    #[serde(default)]
+    pub delete: bool,
+
+    /// When true, `delete_<collection>_many` procedures reject empty predicates which would
+    /// otherwise delete every document in the collection.
+    #[serde(default)]
+    pub require_non_empty_delete_predicate: bool,
+
+    /// When true, generates an `upsert_<collection>` procedure for each uniqueness constraint of
+    /// each collection. Constraints other than the primary key get procedures named
+    /// `upsert_<collection>_by_<columns>`.
+    #[serde(default)]
+    pub upsert: bool,
+}
+
+/// Adds procedure info for generated procedures to `procedures`, and object types for generated
+/// argument and result types to `object_types`. Returns the internal representations of generated
+/// procedures, and an error for each generated procedure name that conflicts with a native
+/// mutation.
+fn generate_collection_procedures(
+    object_types: &mut BTreeMap<ndc::ObjectTypeName, ndc::ObjectType>,
+    options: &GeneratedProceduresConfig,
+    collections: &BTreeMap<ndc::CollectionName, ndc::CollectionInfo>,
+    procedures: &mut BTreeMap<ndc::ProcedureName, ndc::ProcedureInfo>,
+) -> (
+    BTreeMap<ndc::ProcedureName, CollectionProcedure>,
+    Vec<anyhow::Error>,
+) {
+    let mut collection_procedures = BTreeMap::new();
+    let mut errors = vec![];
+    for collection in collections.values() {
+        let generated =
+            match collection_procedure::collection_procedures(object_types, options, collection) {
+                Ok(generated) => generated,
+                Err(err) => {
+                    errors.push(anyhow!(
+                        "error generating procedures for collection {}: {err}",
+                        collection.name
+                    ));
+                    continue;
+                }
+            };
+        for (procedure_info, procedure) in generated {
+            let name = procedure_info.name.clone();
+            if procedures.contains_key(&name) {
+                errors.push(anyhow!(
+                    "the generated procedure name {name} conflicts with a native mutation"
+                ));
+                continue;
+            }
+            procedures.insert(name.clone(), procedure_info);
+            collection_procedures.insert(name, procedure);
+        }
+    }
+    (collection_procedures, errors)
 }
 
 fn merge_object_types<'a>(
@@ -305,6 +413,7 @@ fn collection_to_collection_info(
     object_types: &BTreeMap<ndc::ObjectTypeName, schema::ObjectType>,
     name: ndc::CollectionName,
     collection: schema::Collection,
+    relational_mutations: bool,
 ) -> ndc::CollectionInfo {
     let pk_constraint =
         get_primary_key_uniqueness_constraint(object_types, &name, &collection.r#type);
@@ -315,7 +424,11 @@ fn collection_to_collection_info(
         description: collection.description,
         arguments: Default::default(),
         uniqueness_constraints: BTreeMap::from_iter(pk_constraint),
-        relational_mutations: None,
+        relational_mutations: relational_mutations.then_some(ndc::RelationalMutationInfo {
+            insertable: true,
+            updatable: true,
+            deletable: true,
+        }),
     }
 }
 
@@ -477,4 +590,157 @@ mod tests {
         assert!(error_msg.contains("multiple definitions"));
         assert!(error_msg.contains("Album"));
     }
+
+    /// An `albums` collection with an `Album` object type that has only an `_id` field
+    fn albums_schema() -> Schema {
+        Schema {
+            collections: [(
+                "albums".into(),
+                schema::Collection {
+                    r#type: "Album".into(),
+                    description: Default::default(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+            object_types: [(
+                "Album".to_owned().into(),
+                schema::ObjectType {
+                    fields: [(
+                        "_id".into(),
+                        schema::ObjectField {
+                            r#type: Type::Scalar(mongodb_support::BsonScalarType::ObjectId),
+                            description: Default::default(),
+                        },
+                    )]
+                    .into_iter()
+                    .collect(),
+                    description: Default::default(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn generates_insert_procedures_for_collections() -> anyhow::Result<()> {
+        let schema = albums_schema();
+        let options = ConfigurationOptions {
+            generated_procedures: GeneratedProceduresConfig {
+                insert: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config =
+            Configuration::validate(schema, Default::default(), Default::default(), options)?;
+        let insert_one: ndc::ProcedureName = "insert_albums_one".into();
+        let insert_many: ndc::ProcedureName = "insert_albums_many".into();
+        assert_eq!(
+            config.procedures.keys().collect_vec(),
+            vec![&insert_many, &insert_one]
+        );
+        assert_eq!(
+            config.collection_procedures[&insert_many].operation,
+            collection_procedure::CollectionProcedureOperation::InsertMany
+        );
+        assert_eq!(
+            config.procedures[&insert_one].result_type,
+            ndc::Type::Named {
+                name: "Album".into()
+            }
+        );
+        assert_eq!(
+            config.procedures[&insert_one].arguments["document"].argument_type,
+            ndc::Type::Named {
+                name: "albums_insert_input".into()
+            }
+        );
+        assert_eq!(
+            config.object_types["albums_insert_input"].fields["_id"].r#type,
+            ndc::Type::Nullable {
+                underlying_type: Box::new(ndc::Type::Named {
+                    name: "ObjectId".into()
+                })
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn generates_upsert_procedures_for_uniqueness_constraints() -> anyhow::Result<()> {
+        let schema = albums_schema();
+        let options = ConfigurationOptions {
+            generated_procedures: GeneratedProceduresConfig {
+                upsert: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config =
+            Configuration::validate(schema, Default::default(), Default::default(), options)?;
+        let upsert: ndc::ProcedureName = "upsert_albums".into();
+        assert_eq!(config.procedures.keys().collect_vec(), vec![&upsert]);
+        assert_eq!(
+            config.collection_procedures[&upsert].operation,
+            collection_procedure::CollectionProcedureOperation::Upsert {
+                unique_columns: vec!["_id".into()]
+            }
+        );
+        assert_eq!(
+            config.procedures[&upsert].result_type,
+            ndc::Type::Named {
+                name: "albums_upsert_result".into()
+            }
+        );
+        assert_eq!(
+            config.object_types["albums_upsert_result"].fields["document"].r#type,
+            ndc::Type::Nullable {
+                underlying_type: Box::new(ndc::Type::Named {
+                    name: "Album".into()
+                })
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn maps_generated_procedure_names_to_collections() -> anyhow::Result<()> {
+        let mut schema = albums_schema();
+        let albums = schema.collections.remove("albums").unwrap();
+        schema.collections.insert("music_albums".into(), albums);
+        let options = ConfigurationOptions {
+            generated_procedures: GeneratedProceduresConfig {
+                insert: true,
+                update: true,
+                delete: true,
+                upsert: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config =
+            Configuration::validate(schema, Default::default(), Default::default(), options)?;
+        assert!(!config.collection_procedures.is_empty());
+        for (name, procedure) in &config.collection_procedures {
+            assert!(
+                collection_procedure::collection_names_for_procedure_name(name.as_str())
+                    .contains(&procedure.collection.as_str()),
+                "{name} should map to {}",
+                procedure.collection
+            );
+        }
+        assert_eq!(
+            collection_procedure::collection_names_for_procedure_name(
+                "upsert_music_albums_by_title_and_year"
+            ),
+            vec!["music_albums", "music_albums_by_title_and_year"]
+        );
+        assert_eq!(
+            collection_procedure::collection_names_for_procedure_name("hello"),
+            Vec::<&str>::new()
+        );
+        Ok(())
+    }
 }
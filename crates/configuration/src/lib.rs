@@ -1,3 +1,4 @@
+pub mod collection_procedure;
 mod configuration;
 mod directory;
 mod mongo_scalar_type;
@@ -9,7 +10,8 @@ mod with_name;
 
 pub use crate::configuration::{
     Configuration, ConfigurationIntrospectionOptions, ConfigurationOptions,
-    ConfigurationSerializationOptions, OnResponseTypeMismatch, RelationalModeConfig,
+    ConfigurationSerializationOptions, GeneratedProceduresConfig, OnResponseTypeMismatch,
+    RelationalModeConfig,
 };
 pub use crate::directory::parse_configuration_options_file;
 pub use crate::directory::read_existing_schemas;
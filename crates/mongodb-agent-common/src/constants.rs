@@ -22,3 +22,7 @@ pub struct BsonRowSet {
 
 /// Value must match the field name in [ndc_models::Group]
 pub const GROUP_DIMENSIONS_KEY: &str = "dimensions";
+
+/// Name of the virtual field that exists predicates over arrays of scalars use to reference each
+/// array element. Value must match the field name used in [ndc_query_plan::ExistsInCollection]
+pub const SCALAR_ELEMENT_FIELD: &str = "__value";
@@ -2,8 +2,8 @@ use std::collections::BTreeMap;
 
 use configuration::ConfigurationSerializationOptions;
 use configuration::{
-    native_mutation::NativeMutation, native_query::NativeQuery, Configuration, MongoScalarType,
-    RelationalModeConfig,
+    collection_procedure::CollectionProcedure, native_mutation::NativeMutation,
+    native_query::NativeQuery, Configuration, MongoScalarType, RelationalModeConfig,
 };
 use mongodb_support::{BsonScalarType, EXTENDED_JSON_TYPE_NAME};
 use ndc_models as ndc;
@@ -32,6 +32,10 @@ impl MongoConfiguration {
     pub fn native_mutations(&self) -> &BTreeMap<ndc::ProcedureName, NativeMutation> {
         &self.0.native_mutations
     }
+
+    pub fn collection_procedures(&self) -> &BTreeMap<ndc::ProcedureName, CollectionProcedure> {
+        &self.0.collection_procedures
+    }
 }
 
 impl ConnectorTypes for MongoConfiguration {
@@ -124,6 +128,9 @@ pub type Expression = ndc_query_plan::Expression<MongoConfiguration>;
 pub type Field = ndc_query_plan::Field<MongoConfiguration>;
 pub type Dimension = ndc_query_plan::Dimension<MongoConfiguration>;
 pub type Grouping = ndc_query_plan::Grouping<MongoConfiguration>;
+pub type GroupComparisonTarget = ndc_query_plan::GroupComparisonTarget<MongoConfiguration>;
+pub type GroupComparisonValue = ndc_query_plan::GroupComparisonValue<MongoConfiguration>;
+pub type GroupExpression = ndc_query_plan::GroupExpression<MongoConfiguration>;
 pub type GroupOrderBy = ndc_query_plan::GroupOrderBy<MongoConfiguration>;
 pub type GroupOrderByTarget = ndc_query_plan::GroupOrderByTarget<MongoConfiguration>;
 pub type MutationOperation = ndc_query_plan::MutationOperation<MongoConfiguration>;
@@ -131,6 +138,7 @@ pub type MutationPlan = ndc_query_plan::MutationPlan<MongoConfiguration>;
 pub type MutationProcedureArgument = ndc_query_plan::MutationProcedureArgument<MongoConfiguration>;
 pub type NestedField = ndc_query_plan::NestedField<MongoConfiguration>;
 pub type NestedArray = ndc_query_plan::NestedArray<MongoConfiguration>;
+pub type NestedCollection = ndc_query_plan::NestedCollection<MongoConfiguration>;
 pub type NestedObject = ndc_query_plan::NestedObject<MongoConfiguration>;
 pub type ObjectField = ndc_query_plan::ObjectField<MongoScalarType>;
 pub type ObjectType = ndc_query_plan::ObjectType<MongoScalarType>;
@@ -19,6 +19,16 @@ pub fn is_name_safe(name: impl AsRef<str>) -> bool {
     !(name.as_ref().starts_with('$') || name.as_ref().contains('.'))
 }
 
+/// Returns the given name if it is safe to use as a key in pipeline documents, such as the output
+/// fields of a `$group` stage. Otherwise returns an escaped name that is safe.
+pub fn safe_name(name: &str) -> Cow<'_, str> {
+    if is_name_safe(name) {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(format!("__escaped_{}", escape_invalid_variable_chars(name)))
+    }
+}
+
 // The escape character must be a valid character in MongoDB variable names, but must not appear in
 // lower-case hex strings. A non-ASCII character works if we specifically map it to a two-character
 // hex escape sequence (see [ESCAPE_CHAR_ESCAPE_SEQUENCE]). Another option would be to use an
@@ -5,7 +5,10 @@ use mongodb::bson::Bson;
 use ndc_models as ndc;
 
 use crate::{
-    mongo_query_plan::MutationProcedureArgument,
+    mongo_query_plan::{
+        ArrayComparison, ComparisonTarget, ComparisonValue, ExistsInCollection, Expression,
+        MutationProcedureArgument,
+    },
     query::{make_selector, serialization::json_to_bson},
 };
 
@@ -37,12 +40,68 @@ fn argument_to_mongodb_expression(
                 error: Box::new(error),
             }
         })?,
-        MutationProcedureArgument::Predicate { expression } => make_selector(&expression)
-            .map_err(|error| ProcedureError::ErrorParsingPredicate {
-                argument_name: name.to_string(),
-                error: Box::new(error),
-            })?
-            .into(),
+        MutationProcedureArgument::Predicate { expression } => {
+            if references_other_collections(&expression) {
+                return Err(ProcedureError::RelationshipInPredicate(name.clone()));
+            }
+            make_selector(&expression)
+                .map_err(|error| ProcedureError::ErrorParsingPredicate {
+                    argument_name: name.to_string(),
+                    error: Box::new(error),
+                })?
+                .into()
+        }
     };
     Ok(bson)
 }
+
+/// Mutation predicates are used directly as filters for commands like `deleteMany`, and
+/// `updateMany` which have no way to join related documents. A reference to a relationship in
+/// such a filter would test a field that does not exist - for example a negated `exists` would
+/// match every document in the collection. So we reject those predicates up front.
+fn references_other_collections(expression: &Expression) -> bool {
+    match expression {
+        Expression::And { expressions } | Expression::Or { expressions } => {
+            expressions.iter().any(references_other_collections)
+        }
+        Expression::Not { expression } => references_other_collections(expression),
+        Expression::UnaryComparisonOperator { column, .. } => {
+            target_references_relationship(column)
+        }
+        Expression::BinaryComparisonOperator { column, value, .. } => {
+            target_references_relationship(column) || value_references_relationship(value)
+        }
+        Expression::ArrayComparison { column, comparison } => {
+            target_references_relationship(column)
+                || match comparison {
+                    ArrayComparison::Contains { value } => value_references_relationship(value),
+                    ArrayComparison::IsEmpty => false,
+                }
+        }
+        Expression::Exists {
+            in_collection,
+            predicate,
+            ..
+        } => match in_collection {
+            ExistsInCollection::Related { .. } | ExistsInCollection::Unrelated { .. } => true,
+            ExistsInCollection::NestedCollection { .. }
+            | ExistsInCollection::NestedScalarCollection { .. } => predicate
+                .as_deref()
+                .is_some_and(references_other_collections),
+        },
+    }
+}
+
+fn target_references_relationship(target: &ComparisonTarget) -> bool {
+    match target {
+        ComparisonTarget::Column { .. } => false,
+        ComparisonTarget::Aggregate { .. } => true,
+    }
+}
+
+fn value_references_relationship(value: &ComparisonValue) -> bool {
+    match value {
+        ComparisonValue::Column { path, .. } => !path.is_empty(),
+        ComparisonValue::Scalar { .. } | ComparisonValue::Variable { .. } => false,
+    }
+}
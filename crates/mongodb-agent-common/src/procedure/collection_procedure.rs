@@ -0,0 +1,636 @@
+use std::collections::BTreeMap;
+
+use configuration::collection_procedure::{
+    CollectionProcedure, CollectionProcedureOperation, UpdateOperator,
+};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Document},
+    options::ReturnDocument,
+    ClientSession, Database,
+};
+use ndc_models as ndc;
+
+use crate::mongo_query_plan::{Expression, MutationProcedureArgument};
+
+use super::{arguments_to_mongodb_expressions::arguments_to_mongodb_expressions, ProcedureError};
+
+/// Runs the operation for a generated collection procedure.
+pub async fn execute_collection_procedure(
+    database: Database,
+    session: &mut ClientSession,
+    procedure: &CollectionProcedure,
+    arguments: BTreeMap<ndc::ArgumentName, MutationProcedureArgument>,
+) -> Result<Bson, ProcedureError> {
+    let mut arguments = prepare_arguments(procedure, arguments)?;
+    let collection = database.collection::<Document>(procedure.collection.as_str());
+    match &procedure.operation {
+        CollectionProcedureOperation::InsertOne => {
+            let document = with_id(document_argument(&mut arguments, "document")?);
+            collection
+                .insert_one(&document)
+                .session(&mut *session)
+                .await?;
+            Ok(document.into())
+        }
+        CollectionProcedureOperation::InsertMany => {
+            let documents = documents_argument(&mut arguments, "documents")?
+                .into_iter()
+                .map(with_id)
+                .collect::<Vec<_>>();
+            // The driver rejects an empty batch
+            if !documents.is_empty() {
+                collection
+                    .insert_many(&documents)
+                    .session(&mut *session)
+                    .await?;
+            }
+            Ok(Bson::Array(documents.into_iter().map(Bson::from).collect()))
+        }
+        CollectionProcedureOperation::UpdateById => {
+            let id = required_argument(&mut arguments, "_id")?;
+            let update = update_argument(&mut arguments, "update")?;
+            let updated = collection
+                .find_one_and_update(doc! { "_id": id }, update)
+                .return_document(ReturnDocument::After)
+                .session(&mut *session)
+                .await?;
+            Ok(updated.map(Bson::from).unwrap_or(Bson::Null))
+        }
+        CollectionProcedureOperation::UpdateMany => {
+            let predicate = document_argument(&mut arguments, "predicate")?;
+            let update = update_argument(&mut arguments, "update")?;
+            let result = collection
+                .update_many(predicate, update)
+                .session(&mut *session)
+                .await?;
+            Ok(doc! {
+                "matched_count": saturating_i32(result.matched_count),
+                "modified_count": saturating_i32(result.modified_count),
+            }
+            .into())
+        }
+        CollectionProcedureOperation::DeleteById => {
+            let id = required_argument(&mut arguments, "_id")?;
+            let deleted = collection
+                .find_one_and_delete(doc! { "_id": id })
+                .session(&mut *session)
+                .await?;
+            Ok(deleted.map(Bson::from).unwrap_or(Bson::Null))
+        }
+        CollectionProcedureOperation::DeleteMany { .. } => {
+            let predicate = document_argument(&mut arguments, "predicate")?;
+            let result = collection
+                .delete_many(predicate)
+                .session(&mut *session)
+                .await?;
+            Ok(doc! { "deleted_count": saturating_i32(result.deleted_count) }.into())
+        }
+        CollectionProcedureOperation::Upsert { unique_columns } => {
+            let document = document_argument(&mut arguments, "document")?;
+            let (filter, update) = upsert_filter_and_update(unique_columns, document)?;
+            let result = collection
+                .update_one(filter.clone(), update)
+                .upsert(true)
+                .session(&mut *session)
+                .await?;
+            let inserted = result.upserted_id.is_some();
+            let lookup = match result.upserted_id {
+                Some(id) => doc! { "_id": id },
+                None => filter,
+            };
+            // Another write may remove the document before we read it back, so the `document`
+            // field of the result type is nullable.
+            let document = collection.find_one(lookup).session(&mut *session).await?;
+            Ok(doc! {
+                "document": document.map(Bson::from).unwrap_or(Bson::Null),
+                "inserted": inserted,
+            }
+            .into())
+        }
+    }
+}
+
+/// Produces the database commands that are equivalent to running the given collection procedure,
+/// in the order that they run.
+pub fn collection_procedure_commands(
+    procedure: &CollectionProcedure,
+    arguments: BTreeMap<ndc::ArgumentName, MutationProcedureArgument>,
+) -> Result<Vec<Document>, ProcedureError> {
+    let mut arguments = prepare_arguments(procedure, arguments)?;
+    let collection = procedure.collection.as_str();
+    let command = match &procedure.operation {
+        CollectionProcedureOperation::InsertOne => doc! {
+            "insert": collection,
+            "documents": [without_null_id(document_argument(&mut arguments, "document")?)],
+        },
+        CollectionProcedureOperation::InsertMany => doc! {
+            "insert": collection,
+            "documents": documents_argument(&mut arguments, "documents")?
+                .into_iter()
+                .map(without_null_id)
+                .collect::<Vec<_>>(),
+        },
+        CollectionProcedureOperation::UpdateById => doc! {
+            "findAndModify": collection,
+            "query": { "_id": required_argument(&mut arguments, "_id")? },
+            "update": update_argument(&mut arguments, "update")?,
+            "new": true,
+        },
+        CollectionProcedureOperation::UpdateMany => doc! {
+            "update": collection,
+            "updates": [{
+                "q": document_argument(&mut arguments, "predicate")?,
+                "u": update_argument(&mut arguments, "update")?,
+                "multi": true,
+            }],
+        },
+        CollectionProcedureOperation::DeleteById => doc! {
+            "findAndModify": collection,
+            "query": { "_id": required_argument(&mut arguments, "_id")? },
+            "remove": true,
+        },
+        CollectionProcedureOperation::DeleteMany { .. } => doc! {
+            "delete": collection,
+            "deletes": [{
+                "q": document_argument(&mut arguments, "predicate")?,
+                "limit": 0,
+            }],
+        },
+        CollectionProcedureOperation::Upsert { unique_columns } => {
+            let document = document_argument(&mut arguments, "document")?;
+            let (filter, update) = upsert_filter_and_update(unique_columns, document)?;
+            // The upserted document is read back after the update. An inserted document is
+            // looked up by its new ID at that point, which is not known in advance, but it also
+            // matches the filter.
+            return Ok(vec![
+                doc! {
+                    "update": collection,
+                    "updates": [{ "q": filter.clone(), "u": update, "upsert": true }],
+                },
+                doc! {
+                    "find": collection,
+                    "filter": filter,
+                    "limit": 1,
+                    "singleBatch": true,
+                },
+            ]);
+        }
+    };
+    Ok(vec![command])
+}
+
+/// Checks constraints on arguments that are configured for the procedure, and converts arguments
+/// to BSON. Predicates that do not filter anything are converted to empty query documents.
+fn prepare_arguments(
+    procedure: &CollectionProcedure,
+    arguments: BTreeMap<ndc::ArgumentName, MutationProcedureArgument>,
+) -> Result<BTreeMap<ndc::ArgumentName, Bson>, ProcedureError> {
+    let unfiltered_predicates = arguments
+        .iter()
+        .filter(|(_, argument)| match argument {
+            MutationProcedureArgument::Predicate { expression } => {
+                matches_every_document(expression)
+            }
+            _ => false,
+        })
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+
+    if let CollectionProcedureOperation::DeleteMany {
+        require_non_empty_predicate: true,
+    } = &procedure.operation
+    {
+        if let Some(name) = unfiltered_predicates.first() {
+            return Err(ProcedureError::EmptyPredicate(name.clone()));
+        }
+    }
+
+    let mut bson_arguments = arguments_to_mongodb_expressions(arguments)?;
+    for name in unfiltered_predicates {
+        bson_arguments.insert(name, Document::new().into());
+    }
+    Ok(bson_arguments)
+}
+
+/// An empty conjunction is how a predicate that does not filter anything is expressed.
+fn matches_every_document(expression: &Expression) -> bool {
+    match expression {
+        Expression::And { expressions } => expressions.iter().all(matches_every_document),
+        _ => false,
+    }
+}
+
+/// Translates the `update` argument of a generated update procedure to a MongoDB update document.
+/// Fields in the argument that are null, or that are set to false in `_unset`, are ignored.
+fn update_argument(
+    arguments: &mut BTreeMap<ndc::ArgumentName, Bson>,
+    name: &str,
+) -> Result<Document, ProcedureError> {
+    let mut update_object = document_argument(arguments, name)?;
+    let mut update = Document::new();
+    for operator in UpdateOperator::ALL {
+        let operands = match update_object.remove(operator.field_name()) {
+            Some(Bson::Document(operands)) => operands,
+            _ => continue,
+        };
+        let operands: Document = operands
+            .into_iter()
+            .filter_map(|(field, operand)| match (operator, operand) {
+                (UpdateOperator::Set, operand) => Some((field, operand)),
+                (_, Bson::Null) => None,
+                (UpdateOperator::Unset, Bson::Boolean(true)) => Some((field, Bson::from(""))),
+                (UpdateOperator::Unset, _) => None,
+                (_, operand) => Some((field, operand)),
+            })
+            .collect();
+        if !operands.is_empty() {
+            update.insert(operator.mongodb_operator(), operands);
+        }
+    }
+    if update.is_empty() {
+        return Err(ProcedureError::EmptyUpdate(name.into()));
+    }
+    Ok(update)
+}
+
+/// Splits the `document` argument of an upsert procedure into a filter that matches on the unique
+/// columns, and an update that sets the remaining fields. A document ID that is not one of the
+/// unique columns is only set when inserting because the ID of an existing document cannot change.
+fn upsert_filter_and_update(
+    unique_columns: &[ndc::FieldName],
+    mut document: Document,
+) -> Result<(Document, Document), ProcedureError> {
+    let mut filter = Document::new();
+    for column in unique_columns {
+        let value = document.remove(column.as_str()).ok_or_else(|| {
+            ProcedureError::MissingUniqueColumn {
+                argument_name: "document".into(),
+                column: column.clone(),
+            }
+        })?;
+        filter.insert(column.as_str(), value);
+    }
+
+    let mut update = Document::new();
+    if let Some(id) = document.remove("_id") {
+        update.insert("$setOnInsert", doc! { "_id": id });
+    }
+    if !document.is_empty() {
+        update.insert("$set", document);
+    }
+    // MongoDB requires at least one update operator. Values from the filter are set on inserted
+    // documents regardless, so this one does not change anything.
+    if update.is_empty() {
+        update.insert("$setOnInsert", filter.clone());
+    }
+    Ok((filter, update))
+}
+
+fn saturating_i32(n: u64) -> i32 {
+    i32::try_from(n).unwrap_or(i32::MAX)
+}
+
+/// Documents must have an `_id` field to be returned from an insert procedure as they will appear
+/// in the database. If the given document does not have one, or its `_id` is null, we generate one
+/// the same way the server would.
+fn with_id(document: Document) -> Document {
+    let document = without_null_id(document);
+    if document.contains_key("_id") {
+        document
+    } else {
+        let mut with_id = doc! { "_id": ObjectId::new() };
+        with_id.extend(document);
+        with_id
+    }
+}
+
+/// The `_id` field of the insert input type is nullable so that it may be omitted. A null value
+/// means that the server should generate an ID, so it must not be inserted as-is.
+fn without_null_id(mut document: Document) -> Document {
+    if let Some(Bson::Null) = document.get("_id") {
+        document.remove("_id");
+    }
+    document
+}
+
+fn required_argument(
+    arguments: &mut BTreeMap<ndc::ArgumentName, Bson>,
+    name: &str,
+) -> Result<Bson, ProcedureError> {
+    let argument_name: ndc::ArgumentName = name.into();
+    arguments
+        .remove(&argument_name)
+        .ok_or(ProcedureError::MissingArgument(argument_name))
+}
+
+fn document_argument(
+    arguments: &mut BTreeMap<ndc::ArgumentName, Bson>,
+    name: &str,
+) -> Result<Document, ProcedureError> {
+    let argument_name: ndc::ArgumentName = name.into();
+    match arguments.remove(&argument_name) {
+        Some(Bson::Document(document)) => Ok(document),
+        Some(_) => Err(ProcedureError::UnexpectedArgumentType {
+            argument_name,
+            expected: "an object",
+        }),
+        None => Err(ProcedureError::MissingArgument(argument_name)),
+    }
+}
+
+fn documents_argument(
+    arguments: &mut BTreeMap<ndc::ArgumentName, Bson>,
+    name: &str,
+) -> Result<Vec<Document>, ProcedureError> {
+    let argument_name: ndc::ArgumentName = name.into();
+    let unexpected_type = || ProcedureError::UnexpectedArgumentType {
+        argument_name: argument_name.clone(),
+        expected: "an array of objects",
+    };
+    match arguments.remove(&argument_name) {
+        Some(Bson::Array(values)) => values
+            .into_iter()
+            .map(|value| match value {
+                Bson::Document(document) => Ok(document),
+                _ => Err(unexpected_type()),
+            })
+            .collect(),
+        Some(_) => Err(unexpected_type()),
+        None => Err(ProcedureError::MissingArgument(argument_name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::MongoScalarType;
+    use mongodb::bson::{bson, doc};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::mongo_query_plan::{ExistsInCollection, Type};
+
+    use super::*;
+
+    #[test]
+    fn translates_update_argument_to_update_operators() -> anyhow::Result<()> {
+        let mut arguments = [(
+            "update".into(),
+            bson!({
+                "_set": { "title": "Dune", "subtitle": null },
+                "_inc": { "pages": 10, "edition": null },
+                "_unset": { "isbn": true, "publisher": false },
+                "_push": { "tags": "scifi" },
+            }),
+        )]
+        .into();
+        let update = update_argument(&mut arguments, "update")?;
+        assert_eq!(
+            update,
+            doc! {
+                "$set": { "title": "Dune", "subtitle": null },
+                "$inc": { "pages": 10 },
+                "$unset": { "isbn": "" },
+                "$push": { "tags": "scifi" },
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_empty_delete_predicate_when_configured() {
+        let procedure = CollectionProcedure {
+            collection: "books".into(),
+            operation: CollectionProcedureOperation::DeleteMany {
+                require_non_empty_predicate: true,
+            },
+            result_type: Type::Scalar(MongoScalarType::ExtendedJSON),
+        };
+        let arguments = [(
+            "predicate".into(),
+            MutationProcedureArgument::Predicate {
+                expression: Expression::And {
+                    expressions: vec![],
+                },
+            },
+        )]
+        .into();
+        let result = prepare_arguments(&procedure, arguments);
+        assert!(matches!(result, Err(ProcedureError::EmptyPredicate(_))));
+    }
+
+    #[test]
+    fn rejects_delete_predicate_that_references_a_relationship() {
+        let procedure = CollectionProcedure {
+            collection: "books".into(),
+            operation: CollectionProcedureOperation::DeleteMany {
+                require_non_empty_predicate: true,
+            },
+            result_type: Type::Scalar(MongoScalarType::ExtendedJSON),
+        };
+        let arguments = [(
+            "predicate".into(),
+            MutationProcedureArgument::Predicate {
+                expression: Expression::Not {
+                    expression: Box::new(Expression::Exists {
+                        in_collection: ExistsInCollection::Related {
+                            relationship: "author".into(),
+                        },
+                        predicate: None,
+                        scope: None,
+                    }),
+                },
+            },
+        )]
+        .into();
+        let result = prepare_arguments(&procedure, arguments);
+        assert!(matches!(
+            result,
+            Err(ProcedureError::RelationshipInPredicate(name)) if name.as_str() == "predicate"
+        ));
+    }
+
+    #[test]
+    fn converts_empty_predicate_to_empty_query_document() -> anyhow::Result<()> {
+        let procedure = CollectionProcedure {
+            collection: "books".into(),
+            operation: CollectionProcedureOperation::DeleteMany {
+                require_non_empty_predicate: false,
+            },
+            result_type: Type::Scalar(MongoScalarType::ExtendedJSON),
+        };
+        let arguments = [(
+            "predicate".into(),
+            MutationProcedureArgument::Predicate {
+                expression: Expression::And {
+                    expressions: vec![],
+                },
+            },
+        )]
+        .into();
+        let bson_arguments = prepare_arguments(&procedure, arguments)?;
+        assert_eq!(
+            bson_arguments,
+            [("predicate".into(), Bson::Document(doc! {}))].into()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn splits_upsert_document_into_filter_and_update() -> anyhow::Result<()> {
+        let (filter, update) = upsert_filter_and_update(
+            &["isbn".into()],
+            doc! { "_id": 1, "isbn": "978-0441013593", "title": "Dune" },
+        )?;
+        assert_eq!(filter, doc! { "isbn": "978-0441013593" });
+        assert_eq!(
+            update,
+            doc! {
+                "$setOnInsert": { "_id": 1 },
+                "$set": { "title": "Dune" },
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_upsert_document_without_unique_columns() {
+        let result = upsert_filter_and_update(&["isbn".into()], doc! { "title": "Dune" });
+        assert!(matches!(
+            result,
+            Err(ProcedureError::MissingUniqueColumn { .. })
+        ));
+    }
+
+    fn procedure(operation: CollectionProcedureOperation) -> CollectionProcedure {
+        CollectionProcedure {
+            collection: "books".into(),
+            operation,
+            result_type: Type::Scalar(MongoScalarType::ExtendedJSON),
+        }
+    }
+
+    fn json_argument(
+        name: &str,
+        value: serde_json::Value,
+    ) -> (ndc::ArgumentName, MutationProcedureArgument) {
+        (
+            name.into(),
+            MutationProcedureArgument::Literal {
+                value,
+                argument_type: Type::Scalar(MongoScalarType::ExtendedJSON),
+            },
+        )
+    }
+
+    #[test]
+    fn generates_ids_for_inserted_documents_with_missing_or_null_ids() {
+        let document = with_id(doc! { "_id": null, "title": "Dune" });
+        assert!(matches!(document.get("_id"), Some(Bson::ObjectId(_))));
+        assert_eq!(document.get("title"), Some(&Bson::from("Dune")));
+
+        let document = with_id(doc! { "title": "Dune" });
+        assert!(matches!(document.get("_id"), Some(Bson::ObjectId(_))));
+
+        let document = with_id(doc! { "_id": 1, "title": "Dune" });
+        assert_eq!(document, doc! { "_id": 1, "title": "Dune" });
+    }
+
+    #[test]
+    fn produces_insert_command_without_null_ids() -> anyhow::Result<()> {
+        let commands = collection_procedure_commands(
+            &procedure(CollectionProcedureOperation::InsertMany),
+            [json_argument(
+                "documents",
+                json!([
+                    { "_id": null, "title": "Dune" },
+                    { "_id": 2, "title": "Emma" },
+                ]),
+            )]
+            .into(),
+        )?;
+        assert_eq!(
+            commands,
+            vec![doc! {
+                "insert": "books",
+                "documents": [{ "title": "Dune" }, { "_id": 2, "title": "Emma" }],
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn produces_find_and_modify_command_for_update_by_id() -> anyhow::Result<()> {
+        let commands = collection_procedure_commands(
+            &procedure(CollectionProcedureOperation::UpdateById),
+            [
+                json_argument("_id", json!(1)),
+                json_argument("update", json!({ "_inc": { "pages": 10 } })),
+            ]
+            .into(),
+        )?;
+        assert_eq!(
+            commands,
+            vec![doc! {
+                "findAndModify": "books",
+                "query": { "_id": 1 },
+                "update": { "$inc": { "pages": 10 } },
+                "new": true,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn produces_update_and_find_commands_for_upsert() -> anyhow::Result<()> {
+        let commands = collection_procedure_commands(
+            &procedure(CollectionProcedureOperation::Upsert {
+                unique_columns: vec!["isbn".into()],
+            }),
+            [json_argument(
+                "document",
+                json!({ "isbn": "978-0441013593", "title": "Dune" }),
+            )]
+            .into(),
+        )?;
+        assert_eq!(
+            commands,
+            vec![
+                doc! {
+                    "update": "books",
+                    "updates": [{
+                        "q": { "isbn": "978-0441013593" },
+                        "u": { "$set": { "title": "Dune" } },
+                        "upsert": true,
+                    }],
+                },
+                doc! {
+                    "find": "books",
+                    "filter": { "isbn": "978-0441013593" },
+                    "limit": 1,
+                    "singleBatch": true,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reports_missing_arguments() {
+        let result = collection_procedure_commands(
+            &procedure(CollectionProcedureOperation::DeleteById),
+            Default::default(),
+        );
+        assert!(matches!(result, Err(ProcedureError::MissingArgument(_))));
+    }
+
+    #[test]
+    fn rejects_update_argument_with_no_operations() {
+        let mut arguments = [(
+            "update".into(),
+            bson!({ "_inc": { "pages": null }, "_unset": { "isbn": false } }),
+        )]
+        .into();
+        let result = update_argument(&mut arguments, "update");
+        assert!(matches!(result, Err(ProcedureError::EmptyUpdate(_))));
+    }
+}
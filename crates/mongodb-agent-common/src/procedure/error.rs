@@ -19,15 +19,47 @@ pub enum ProcedureError {
         error: Box<MongoAgentError>,
     },
 
+    #[error("predicate argument \"{0}\" must not reference relationships or other collections because mutation filters are applied directly to the target collection")]
+    RelationshipInPredicate(ndc_models::ArgumentName),
+
+    #[error("argument \"{0}\" must not be empty because deleting every document in a collection is not allowed by connector configuration")]
+    EmptyPredicate(ndc_models::ArgumentName),
+
+    #[error("argument \"{0}\" must include at least one update operator with at least one field")]
+    EmptyUpdate(ndc_models::ArgumentName),
+
     #[error("error executing mongodb command: {0}")]
     ExecutionError(#[from] mongodb::error::Error),
 
     #[error("a required argument was not provided, \"{0}\"")]
     MissingArgument(ndc_models::ArgumentName),
 
+    #[error("argument \"{argument_name}\" must include a value for \"{column}\" to identify the document to insert or update")]
+    MissingUniqueColumn {
+        argument_name: ndc_models::ArgumentName,
+        column: ndc_models::FieldName,
+    },
+
     #[error("found a non-string argument, {0}, in a string context - if you want to use a non-string argument it must be the only thing in the string with no white space around the curly braces")]
     NonStringInStringContext(ndc_models::ArgumentName),
 
     #[error("object keys must be strings, but got: \"{0}\"")]
     NonStringKey(Box<Bson>),
+
+    #[error("argument \"{argument_name}\" must be {expected}")]
+    UnexpectedArgumentType {
+        argument_name: ndc_models::ArgumentName,
+        expected: &'static str,
+    },
+}
+
+impl ProcedureError {
+    /// True if the error came from the database, and has the given label. MongoDB labels errors
+    /// that may succeed if a transaction or commit is retried.
+    pub fn has_error_label(&self, label: &str) -> bool {
+        match self {
+            ProcedureError::ExecutionError(err) => err.contains_label(label),
+            _ => false,
+        }
+    }
 }
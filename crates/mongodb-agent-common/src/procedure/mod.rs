@@ -1,4 +1,5 @@
 mod arguments_to_mongodb_expressions;
+mod collection_procedure;
 mod error;
 mod interpolated_command;
 
@@ -6,22 +7,32 @@ use std::borrow::Cow;
 use std::collections::BTreeMap;
 
 use arguments_to_mongodb_expressions::arguments_to_mongodb_expressions;
-use configuration::native_mutation::NativeMutation;
+use configuration::{collection_procedure::CollectionProcedure, native_mutation::NativeMutation};
 use mongodb::options::SelectionCriteria;
-use mongodb::{bson, Database};
+use mongodb::{bson, ClientSession, Database};
 
 use crate::mongo_query_plan::{MutationProcedureArgument, Type};
 
+use self::collection_procedure::{collection_procedure_commands, execute_collection_procedure};
 pub use self::error::ProcedureError;
 pub use self::interpolated_command::interpolated_command;
 
-/// Encapsulates running arbitrary mongodb commands with interpolated arguments
+/// Encapsulates running arbitrary mongodb commands with interpolated arguments, or running
+/// operations for procedures generated for tracked collections
 #[derive(Clone, Debug)]
 pub struct Procedure<'a> {
     arguments: BTreeMap<ndc_models::ArgumentName, MutationProcedureArgument>,
-    command: Cow<'a, bson::Document>,
+    command: ProcedureCommand<'a>,
     result_type: Type,
-    selection_criteria: Option<Cow<'a, SelectionCriteria>>,
+}
+
+#[derive(Clone, Debug)]
+enum ProcedureCommand<'a> {
+    NativeMutation {
+        command: Cow<'a, bson::Document>,
+        selection_criteria: Option<Cow<'a, SelectionCriteria>>,
+    },
+    Collection(&'a CollectionProcedure),
 }
 
 impl<'a> Procedure<'a> {
@@ -31,32 +42,65 @@ impl<'a> Procedure<'a> {
     ) -> Self {
         Procedure {
             arguments,
-            command: Cow::Borrowed(&native_mutation.command),
+            command: ProcedureCommand::NativeMutation {
+                command: Cow::Borrowed(&native_mutation.command),
+                selection_criteria: native_mutation
+                    .selection_criteria
+                    .as_ref()
+                    .map(Cow::Borrowed),
+            },
             result_type: native_mutation.result_type.clone(),
-            selection_criteria: native_mutation
-                .selection_criteria
-                .as_ref()
-                .map(Cow::Borrowed),
         }
     }
 
+    pub fn from_collection_procedure(
+        collection_procedure: &'a CollectionProcedure,
+        arguments: BTreeMap<ndc_models::ArgumentName, MutationProcedureArgument>,
+    ) -> Self {
+        Procedure {
+            arguments,
+            command: ProcedureCommand::Collection(collection_procedure),
+            result_type: collection_procedure.result_type.clone(),
+        }
+    }
+
+    /// Runs the procedure using the given session so that it can participate in a transaction.
     pub async fn execute(
         self,
         database: Database,
-    ) -> Result<(bson::Document, Type), ProcedureError> {
-        let command = interpolate(self.arguments, &self.command)?;
-        let run_command = database.run_command(command);
-        let run_command = if let Some(selection_criteria) = self.selection_criteria {
-            run_command.selection_criteria(selection_criteria.into_owned())
-        } else {
-            run_command
+        session: &mut ClientSession,
+    ) -> Result<(bson::Bson, Type), ProcedureError> {
+        let result = match self.command {
+            ProcedureCommand::NativeMutation {
+                command,
+                selection_criteria,
+            } => {
+                let command = interpolate(self.arguments, &command)?;
+                let run_command = database.run_command(command).session(&mut *session);
+                let run_command = if let Some(selection_criteria) = selection_criteria {
+                    run_command.selection_criteria(selection_criteria.into_owned())
+                } else {
+                    run_command
+                };
+                run_command.await?.into()
+            }
+            ProcedureCommand::Collection(procedure) => {
+                execute_collection_procedure(database, session, procedure, self.arguments).await?
+            }
         };
-        let result = run_command.await?;
         Ok((result, self.result_type))
     }
 
-    pub fn interpolated_command(self) -> Result<bson::Document, ProcedureError> {
-        interpolate(self.arguments, &self.command)
+    /// Produces the commands that running the procedure sends to the database, in order.
+    pub fn interpolated_commands(self) -> Result<Vec<bson::Document>, ProcedureError> {
+        match self.command {
+            ProcedureCommand::NativeMutation { command, .. } => {
+                Ok(vec![interpolate(self.arguments, &command)?])
+            }
+            ProcedureCommand::Collection(procedure) => {
+                collection_procedure_commands(procedure, self.arguments)
+            }
+        }
     }
 }
 
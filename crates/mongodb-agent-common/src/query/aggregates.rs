@@ -5,9 +5,12 @@ use mongodb::bson::{bson, Bson};
 use mongodb_support::aggregate::{Accumulator, Pipeline, Selection, Stage};
 use ndc_models::FieldName;
 
-use crate::{aggregation_function::AggregationFunction, mongo_query_plan::Aggregate};
+use crate::{
+    aggregation_function::AggregationFunction, mongo_query_plan::Aggregate,
+    mongodb::sanitize::safe_name,
+};
 
-use super::column_ref::ColumnRef;
+use super::{column_ref::ColumnRef, selection::escape_invalid_keys};
 
 pub fn pipeline_for_aggregates(aggregates: &IndexMap<FieldName, Aggregate>) -> Pipeline {
     let group_stage = Stage::Group {
@@ -18,12 +21,19 @@ pub fn pipeline_for_aggregates(aggregates: &IndexMap<FieldName, Aggregate>) -> P
     Pipeline::new(vec![group_stage, replace_with_stage])
 }
 
+/// Output fields of a `$group` stage may not begin with a dollar sign or contain dots, so aggregate
+/// names are escaped as necessary.
 pub fn accumulators_for_aggregates(
     aggregates: &IndexMap<FieldName, Aggregate>,
 ) -> BTreeMap<String, Accumulator> {
     aggregates
         .into_iter()
-        .map(|(name, aggregate)| (name.to_string(), aggregate_to_accumulator(aggregate)))
+        .map(|(name, aggregate)| {
+            (
+                safe_name(name.as_str()).into_owned(),
+                aggregate_to_accumulator(aggregate),
+            )
+        })
         .collect()
 }
 
@@ -79,11 +89,12 @@ fn selection_for_aggregates(aggregates: &IndexMap<FieldName, Aggregate>) -> Sele
         .iter()
         .map(|(key, aggregate)| selection_for_aggregate(key, aggregate))
         .collect();
-    Selection::new(selected_aggregates)
+    Selection::new(escape_invalid_keys(selected_aggregates))
 }
 
 pub fn selection_for_aggregate(key: &FieldName, aggregate: &Aggregate) -> (String, Bson) {
-    let column_ref = ColumnRef::from_field(key.as_ref()).into_aggregate_expression();
+    // Accumulator output fields are named using escaped keys - see [accumulators_for_aggregates]
+    let column_ref = ColumnRef::from_field(&safe_name(key.as_str())).into_aggregate_expression();
 
     // Selecting distinct counts requires some post-processing since the $group stage produces
     // an array of unique values. We need to count the non-null values in that array.
@@ -114,6 +125,71 @@ pub fn selection_for_aggregate(key: &FieldName, aggregate: &Aggregate) -> (Strin
     (key.to_string(), value_expression)
 }
 
+/// Computes an aggregate over an array of documents using aggregation expression operators instead
+/// of accumulators. This is used to aggregate rows of a nested collection which are elements of an
+/// array field instead of pipeline documents. The `rows` reference must resolve to an array.
+pub fn aggregate_expression_for_array(rows: ColumnRef<'_>, aggregate: &Aggregate) -> Bson {
+    fn values_ref<'a>(
+        rows: ColumnRef<'a>,
+        column: &'a FieldName,
+        field_path: Option<&'a Vec<FieldName>>,
+    ) -> Bson {
+        // Referencing a field of an array of documents produces an array of the field values
+        field_path
+            .into_iter()
+            .flatten()
+            .fold(rows.into_nested_field(column.as_ref()), |acc, field| {
+                acc.into_nested_field(field.as_ref())
+            })
+            .into_aggregate_expression()
+            .into_bson()
+    }
+
+    let value_expression = match aggregate {
+        Aggregate::ColumnCount {
+            column,
+            field_path,
+            distinct,
+            ..
+        } => {
+            let values = values_ref(rows, column, field_path.as_ref());
+            let values = if *distinct {
+                bson!({ "$setUnion": [values] })
+            } else {
+                values
+            };
+            bson!({
+                "$size": {
+                    "$filter": {
+                        "input": values,
+                        "cond": { "$ne": ["$$this", null] }, // count non-null, non-missing values
+                    }
+                }
+            })
+        }
+        Aggregate::SingleColumn {
+            column,
+            field_path,
+            function,
+            ..
+        } => {
+            use AggregationFunction as A;
+
+            let values = values_ref(rows, column, field_path.as_ref());
+            match function {
+                A::Avg => bson!({ "$avg": values }),
+                A::Min => bson!({ "$min": values }),
+                A::Max => bson!({ "$max": values }),
+                A::Sum => bson!({ "$sum": values }),
+            }
+        }
+        Aggregate::StarCount => bson!({ "$size": rows.into_aggregate_expression() }),
+    };
+
+    let value_expression = replace_missing_aggregate_value(value_expression, aggregate.is_count());
+    convert_aggregate_result_type(value_expression, aggregate)
+}
+
 pub fn replace_missing_aggregate_value(expression: Bson, is_count: bool) -> Bson {
     bson!({
         "$ifNull": [
@@ -154,7 +230,8 @@ mod tests {
     use mongodb::bson::bson;
     use ndc_test_helpers::{
         binop, collection, column_aggregate, column_count_aggregate, dimension_column, field,
-        group, grouping, named_type, object_type, query, query_request, row_set, target, value,
+        group, grouping, named_type, object_type, query, query_request, row_set,
+        star_count_aggregate, target, value,
     };
     use pretty_assertions::assert_eq;
     use serde_json::json;
@@ -342,19 +419,33 @@ mod tests {
                 "$group": {
                     "_id": ["$year"],
                     "average_viewer_rating": { "$avg": "$tomatoes.viewer.rating" },
-                    "max.runtime": { "$max": "$runtime" },
+                    "__escaped_max·2eruntime": { "$max": "$runtime" },
                 }
             },
             {
                 "$replaceWith": {
-                    "dimensions": "$_id",
-                    "average_viewer_rating": {
-                        "$convert": {
-                            "to": "double",
-                            "input": { "$ifNull": ["$average_viewer_rating", null] },
-                        }
-                    },
-                    "max.runtime": { "$ifNull": [{ "$getField": { "$literal": "max.runtime" } }, null] },
+                    "$setField": {
+                        "field": { "$literal": "max.runtime" },
+                        "value": { "$ifNull": ["$__escaped_max·2eruntime", null] },
+                        "input": {
+                            "$setField": {
+                                "field": { "$literal": "average_viewer_rating" },
+                                "value": {
+                                    "$convert": {
+                                        "to": "double",
+                                        "input": { "$ifNull": ["$average_viewer_rating", null] },
+                                    }
+                                },
+                                "input": {
+                                    "$setField": {
+                                        "field": { "$literal": "dimensions" },
+                                        "value": "$_id",
+                                        "input": { "$literal": {} },
+                                    }
+                                },
+                            }
+                        },
+                    }
                 }
             },
         ]);
@@ -381,6 +472,99 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn filters_sorts_and_paginates_groups() -> Result<(), anyhow::Error> {
+        let query_request = query_request()
+            .collection("movies")
+            .query(
+                query().groups(
+                    grouping()
+                        .dimensions([dimension_column("year")])
+                        .aggregates([
+                            star_count_aggregate!("count"),
+                            ("average_runtime", column_aggregate("runtime", "avg").into()),
+                        ])
+                        .predicate(ndc_models::GroupExpression::BinaryComparisonOperator {
+                            target: ndc_models::GroupComparisonTarget::Aggregate {
+                                aggregate: ndc_models::Aggregate::StarCount {},
+                            },
+                            operator: "_gt".into(),
+                            value: ndc_models::GroupComparisonValue::Scalar { value: json!(100) },
+                        })
+                        .order_by(ndc_models::GroupOrderBy {
+                            elements: vec![ndc_models::GroupOrderByElement {
+                                order_direction: ndc_models::OrderDirection::Desc,
+                                target: ndc_models::GroupOrderByTarget::Aggregate {
+                                    aggregate: column_aggregate("runtime", "max").into(),
+                                },
+                            }],
+                        })
+                        .limit(10)
+                        .offset(5),
+                ),
+            )
+            .into();
+
+        let expected_response = row_set()
+            .groups([group(
+                [2007],
+                [("count", json!(150)), ("average_runtime", json!(98.5))],
+            )])
+            .into_response();
+
+        let expected_pipeline = bson!([
+            {
+                "$group": {
+                    "_id": ["$year"],
+                    "__group_aggregate_0": { "$max": "$runtime" },
+                    "average_runtime": { "$avg": "$runtime" },
+                    "count": { "$sum": 1 },
+                }
+            },
+            {
+                "$match": {
+                    "$expr": {
+                        "$gt": [{ "$ifNull": ["$count", 0] }, { "$literal": 100 }]
+                    }
+                }
+            },
+            {
+                "$addFields": {
+                    "__sort_key_0": { "$ifNull": ["$__group_aggregate_0", null] },
+                }
+            },
+            { "$sort": { "__sort_key_0": -1 } },
+            { "$skip": 5 },
+            { "$limit": 10 },
+            {
+                "$replaceWith": {
+                    "dimensions": "$_id",
+                    "count": { "$ifNull": ["$count", 0] },
+                    "average_runtime": {
+                        "$convert": {
+                            "to": "double",
+                            "input": { "$ifNull": ["$average_runtime", null] },
+                        }
+                    },
+                }
+            },
+        ]);
+
+        let db = mock_collection_aggregate_response_for_pipeline(
+            "movies",
+            expected_pipeline,
+            bson!([{
+                "dimensions": [2007],
+                "count": 150,
+                "average_runtime": 98.5,
+            }]),
+        );
+
+        let result = execute_query_request(db, &mflix_config(), query_request).await?;
+        assert_eq!(result, expected_response);
+        Ok(())
+    }
+
     // TODO: Test:
     // - fields & group by
     // - group by & aggregates
@@ -399,6 +583,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         })
@@ -4,18 +4,22 @@
 
 use std::{borrow::Cow, iter::once};
 
-use mongodb::bson::{doc, Bson};
+use mongodb::bson::{bson, doc, Bson};
 use ndc_models::FieldName;
 use ndc_query_plan::Scope;
 use nonempty::NonEmpty;
 
 use crate::{
     interface_types::MongoAgentError,
-    mongo_query_plan::{ComparisonTarget, OrderByTarget},
+    mongo_query_plan::{Aggregate, ComparisonTarget, OrderByTarget},
     mongodb::sanitize::is_name_safe,
 };
 
-use super::make_selector::AggregationExpression;
+use super::{
+    aggregates::{aggregate_expression_for_array, replace_missing_aggregate_value},
+    make_selector::AggregationExpression,
+    make_sort::{aggregate_sort_key, SORT_AGGREGATE_VALUE_KEY},
+};
 
 /// Reference to a document field, or a nested property of a document field. There are two contexts
 /// where we reference columns:
@@ -71,6 +75,26 @@ impl<'a> ColumnRef<'a> {
         from_column_and_field_path(relationship_path, name, field_path)
     }
 
+    /// Reference a column of a document that was bound to a variable named for the given scope.
+    pub fn from_scope_column_and_field_path<'b>(
+        scope: &'b Scope,
+        name: &'b FieldName,
+        field_path: Option<&'b Vec<FieldName>>,
+    ) -> ColumnRef<'b> {
+        let name_and_path = once(name.as_ref() as &str).chain(
+            field_path
+                .into_iter()
+                .flatten()
+                .map(|field_name| field_name.as_ref() as &str),
+        );
+        // The None case won't come up because we start the path with `name`
+        from_path(
+            Some(ColumnRef::variable(name_from_scope(scope))),
+            name_and_path,
+        )
+        .unwrap()
+    }
+
     /// TODO: This will hopefully become infallible once ENG-1011 & ENG-1010 are implemented.
     pub fn from_order_by_target(target: &OrderByTarget) -> Result<ColumnRef<'_>, MongoAgentError> {
         from_order_by_target(target)
@@ -94,6 +118,12 @@ impl<'a> ColumnRef<'a> {
         fold_path_element(None, relationship_name.as_ref())
     }
 
+    /// Reference the rows of the collection reached by following the given relationship path
+    /// from the current document.
+    pub fn from_related_rows(path: &[ndc_models::RelationshipName]) -> ColumnRef<'_> {
+        from_related_rows(path)
+    }
+
     pub fn from_unrelated_collection(collection_name: &str) -> ColumnRef<'_> {
         fold_path_element(Some(ColumnRef::variable("ROOT")), collection_name)
     }
@@ -129,6 +159,9 @@ fn from_comparison_target(column: &ComparisonTarget) -> ColumnRef<'_> {
         ComparisonTarget::Column {
             name, field_path, ..
         } => from_column_and_field_path(&[], name, field_path.as_ref()),
+        ComparisonTarget::Aggregate { path, aggregate } => {
+            from_aggregate_over_relationship_path(path, aggregate)
+        }
     }
 }
 
@@ -175,13 +208,76 @@ fn from_order_by_target(target: &OrderByTarget) -> Result<ColumnRef<'_>, MongoAg
             // one element, and we know it does because we start the iterable with `name`
             Ok(from_path(None, name_and_path).unwrap())
         }
-        OrderByTarget::Aggregate { .. } => {
-            // TODO: ENG-1011
-            Err(MongoAgentError::NotImplemented("order by aggregate".into()))
+        OrderByTarget::Aggregate { path, aggregate } => {
+            if path.is_empty() {
+                return Err(MongoAgentError::NotImplemented(
+                    "order by aggregate without a relationship path".into(),
+                ));
+            }
+            Ok(from_aggregate_sort_lookup(path, aggregate))
         }
     }
 }
 
+/// Aggregates to sort by are computed inside a `$lookup` sub-pipeline which produces an array with
+/// at most one document holding the aggregate value. An empty array means that there were no
+/// related rows.
+fn from_aggregate_sort_lookup<'a>(
+    path: &[ndc_models::RelationshipName],
+    aggregate: &Aggregate,
+) -> ColumnRef<'a> {
+    let lookup_output = aggregate_sort_key(path, aggregate);
+    ColumnRef::Expression(replace_missing_aggregate_value(
+        bson!({ "$first": format!("${lookup_output}.{SORT_AGGREGATE_VALUE_KEY}") }),
+        aggregate.is_count(),
+    ))
+}
+
+/// Lookups for each relationship in the path select the aggregated column from related rows. The
+/// path is expected to be non-empty. This is used for comparisons with aggregates; sorting by an
+/// aggregate uses [from_aggregate_sort_lookup] instead.
+fn from_aggregate_over_relationship_path<'a>(
+    path: &[ndc_models::RelationshipName],
+    aggregate: &Aggregate,
+) -> ColumnRef<'a> {
+    let rows = from_related_rows(path).into_aggregate_expression();
+    ColumnRef::Expression(bson!({
+        "$let": {
+            "vars": { "rows": rows },
+            "in": aggregate_expression_for_array(ColumnRef::variable("rows"), aggregate),
+        }
+    }))
+}
+
+/// Each relationship lookup stores an array of related rows. With more than one relationship in
+/// the path each row holds an array of rows from the next relationship, so we flatten those into
+/// a single array. An empty path produces an empty array.
+fn from_related_rows(path: &[ndc_models::RelationshipName]) -> ColumnRef<'_> {
+    let Some((first, rest)) = path.split_first() else {
+        return ColumnRef::Expression(bson!([]));
+    };
+    rest.iter()
+        .fold(ColumnRef::from_relationship(first), |rows, relationship| {
+            ColumnRef::Expression(bson!({
+                "$reduce": {
+                    "input": rows.into_aggregate_expression(),
+                    "initialValue": [],
+                    "in": {
+                        "$concatArrays": [
+                            "$$value",
+                            { "$ifNull": [
+                                ColumnRef::variable("this")
+                                    .into_nested_field(relationship.as_str())
+                                    .into_aggregate_expression(),
+                                [],
+                            ] },
+                        ]
+                    },
+                }
+            }))
+        })
+}
+
 pub fn name_from_scope(scope: &Scope) -> Cow<'_, str> {
     match scope {
         Scope::Root => "scope_root".into(),
@@ -244,6 +340,8 @@ mod tests {
     use configuration::MongoScalarType;
     use mongodb::bson::doc;
     use mongodb_support::BsonScalarType;
+    use ndc_models::FieldName;
+    use ndc_query_plan::Scope;
     use pretty_assertions::assert_eq;
 
     use crate::mongo_query_plan::{ComparisonTarget, Type};
@@ -357,121 +455,104 @@ mod tests {
         Ok(())
     }
 
-    // TODO: ENG-1487 `ComparisonTarget::ColumnInScope` is gone, but there is new, similar
-    // functionality in the form of named scopes. It will be useful to modify these tests when
-    // named scopes are supported in this connector.
-
-    // #[test]
-    // fn produces_dot_separated_root_column_reference() -> anyhow::Result<()> {
-    //     let target = ComparisonTarget::ColumnInScope {
-    //         name: "field".into(),
-    //         field_path: Some(vec!["prop1".into(), "prop2".into()]),
-    //         field_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
-    //         scope: Scope::Root,
-    //     };
-    //     let actual = ColumnRef::from_comparison_target(&target);
-    //     let expected =
-    //         ColumnRef::ExpressionStringShorthand("$$scope_root.field.prop1.prop2".into());
-    //     assert_eq!(actual, expected);
-    //     Ok(())
-    // }
-
-    // #[test]
-    // fn escapes_unsafe_field_name_in_root_column_reference() -> anyhow::Result<()> {
-    //     let target = ComparisonTarget::ColumnInScope {
-    //         name: "$field".into(),
-    //         field_path: Default::default(),
-    //         field_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
-    //         scope: Scope::Named("scope_0".into()),
-    //     };
-    //     let actual = ColumnRef::from_comparison_target(&target);
-    //     let expected = ColumnRef::Expression(
-    //         doc! {
-    //             "$getField": {
-    //                 "input": "$$scope_0",
-    //                 "field": { "$literal": "$field" },
-    //             }
-    //         }
-    //         .into(),
-    //     );
-    //     assert_eq!(actual, expected);
-    //     Ok(())
-    // }
-
-    // #[test]
-    // fn escapes_unsafe_nested_property_name_in_root_column_reference() -> anyhow::Result<()> {
-    //     let target = ComparisonTarget::ColumnInScope {
-    //         name: "field".into(),
-    //         field_path: Some(vec!["$unsafe_name".into()]),
-    //         field_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
-    //         scope: Scope::Root,
-    //     };
-    //     let actual = ColumnRef::from_comparison_target(&target);
-    //     let expected = ColumnRef::Expression(
-    //         doc! {
-    //             "$getField": {
-    //                 "input": "$$scope_root.field",
-    //                 "field": { "$literal": "$unsafe_name" },
-    //             }
-    //         }
-    //         .into(),
-    //     );
-    //     assert_eq!(actual, expected);
-    //     Ok(())
-    // }
-
-    // #[test]
-    // fn escapes_multiple_layers_of_nested_property_names_in_root_column_reference(
-    // ) -> anyhow::Result<()> {
-    //     let target = ComparisonTarget::ColumnInScope {
-    //         name: "$field".into(),
-    //         field_path: Some(vec!["$unsafe_name1".into(), "$unsafe_name2".into()]),
-    //         field_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
-    //         scope: Scope::Root,
-    //     };
-    //     let actual = ColumnRef::from_comparison_target(&target);
-    //     let expected = ColumnRef::Expression(
-    //         doc! {
-    //             "$getField": {
-    //                 "input": {
-    //                     "$getField": {
-    //                         "input": {
-    //                             "$getField": {
-    //                                 "input": "$$scope_root",
-    //                                 "field": { "$literal": "$field" },
-    //                             }
-    //                         },
-    //                         "field": { "$literal": "$unsafe_name1" },
-    //                     }
-    //                 },
-    //                 "field": { "$literal": "$unsafe_name2" },
-    //             }
-    //         }
-    //         .into(),
-    //     );
-    //     assert_eq!(actual, expected);
-    //     Ok(())
-    // }
-
-    // #[test]
-    // fn escapes_unsafe_deeply_nested_property_name_in_root_column_reference() -> anyhow::Result<()> {
-    //     let target = ComparisonTarget::ColumnInScope {
-    //         name: "field".into(),
-    //         field_path: Some(vec!["prop1".into(), "$unsafe_name".into()]),
-    //         field_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
-    //         scope: Scope::Root,
-    //     };
-    //     let actual = ColumnRef::from_comparison_target(&target);
-    //     let expected = ColumnRef::Expression(
-    //         doc! {
-    //             "$getField": {
-    //                 "input": "$$scope_root.field.prop1",
-    //                 "field": { "$literal": "$unsafe_name" },
-    //             }
-    //         }
-    //         .into(),
-    //     );
-    //     assert_eq!(actual, expected);
-    //     Ok(())
-    // }
+    #[test]
+    fn produces_dot_separated_root_column_reference() -> anyhow::Result<()> {
+        let name: FieldName = "field".into();
+        let field_path = vec!["prop1".into(), "prop2".into()];
+        let actual =
+            ColumnRef::from_scope_column_and_field_path(&Scope::Root, &name, Some(&field_path));
+        let expected =
+            ColumnRef::ExpressionStringShorthand("$$scope_root.field.prop1.prop2".into());
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn escapes_unsafe_field_name_in_root_column_reference() -> anyhow::Result<()> {
+        let name: FieldName = "$field".into();
+        let actual = ColumnRef::from_scope_column_and_field_path(
+            &Scope::Named("scope_0".into()),
+            &name,
+            None,
+        );
+        let expected = ColumnRef::Expression(
+            doc! {
+                "$getField": {
+                    "input": "$$scope_0",
+                    "field": { "$literal": "$field" },
+                }
+            }
+            .into(),
+        );
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn escapes_unsafe_nested_property_name_in_root_column_reference() -> anyhow::Result<()> {
+        let name: FieldName = "field".into();
+        let field_path = vec!["$unsafe_name".into()];
+        let actual =
+            ColumnRef::from_scope_column_and_field_path(&Scope::Root, &name, Some(&field_path));
+        let expected = ColumnRef::Expression(
+            doc! {
+                "$getField": {
+                    "input": "$$scope_root.field",
+                    "field": { "$literal": "$unsafe_name" },
+                }
+            }
+            .into(),
+        );
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn escapes_multiple_layers_of_nested_property_names_in_root_column_reference(
+    ) -> anyhow::Result<()> {
+        let name: FieldName = "$field".into();
+        let field_path = vec!["$unsafe_name1".into(), "$unsafe_name2".into()];
+        let actual =
+            ColumnRef::from_scope_column_and_field_path(&Scope::Root, &name, Some(&field_path));
+        let expected = ColumnRef::Expression(
+            doc! {
+                "$getField": {
+                    "input": {
+                        "$getField": {
+                            "input": {
+                                "$getField": {
+                                    "input": "$$scope_root",
+                                    "field": { "$literal": "$field" },
+                                }
+                            },
+                            "field": { "$literal": "$unsafe_name1" },
+                        }
+                    },
+                    "field": { "$literal": "$unsafe_name2" },
+                }
+            }
+            .into(),
+        );
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn escapes_unsafe_deeply_nested_property_name_in_root_column_reference() -> anyhow::Result<()> {
+        let name: FieldName = "field".into();
+        let field_path = vec!["prop1".into(), "$unsafe_name".into()];
+        let actual =
+            ColumnRef::from_scope_column_and_field_path(&Scope::Root, &name, Some(&field_path));
+        let expected = ColumnRef::Expression(
+            doc! {
+                "$getField": {
+                    "input": "$$scope_root.field.prop1",
+                    "field": { "$literal": "$unsafe_name" },
+                }
+            }
+            .into(),
+        );
+        assert_eq!(actual, expected);
+        Ok(())
+    }
 }
@@ -515,6 +515,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         })
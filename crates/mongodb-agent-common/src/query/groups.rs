@@ -1,56 +1,118 @@
-use std::borrow::Cow;
+use std::{collections::BTreeMap, iter::once};
 
-use mongodb::bson::{self, bson};
-use mongodb_support::aggregate::{Pipeline, Selection, SortDocument, Stage};
-use ndc_models::OrderDirection;
+use anyhow::anyhow;
+use indexmap::IndexMap;
+use mongodb::bson::{self, bson, doc, Bson, Document};
+use mongodb_support::aggregate::{Accumulator, Pipeline, Selection, SortDocument, Stage};
+use ndc_models::{FieldName, OrderDirection, UnaryComparisonOperator};
 
 use crate::{
     constants::GROUP_DIMENSIONS_KEY,
     interface_types::MongoAgentError,
-    mongo_query_plan::{Dimension, GroupOrderBy, GroupOrderByTarget, Grouping},
+    mongo_query_plan::{
+        Aggregate, Dimension, GroupComparisonTarget, GroupComparisonValue, GroupExpression,
+        GroupOrderBy, GroupOrderByTarget, Grouping,
+    },
 };
 
 use super::{
     aggregates::{accumulators_for_aggregates, selection_for_aggregate},
     column_ref::ColumnRef,
+    query_variable_name::query_variable_name,
+    selection::escape_invalid_keys,
+    serialization::json_to_bson,
 };
 
 type Result<T> = std::result::Result<T, MongoAgentError>;
 
-// TODO: This function can be infallible once ENG-1562 is implemented.
 pub fn pipeline_for_groups(grouping: &Grouping) -> Result<Pipeline> {
-    let group_stage = Stage::Group {
-        key_expression: dimensions_to_expression(&grouping.dimensions).into(),
-        accumulators: accumulators_for_aggregates(&grouping.aggregates),
-    };
+    let mut group_aggregates = GroupAggregates::new(&grouping.aggregates);
 
-    // TODO: ENG-1562 This implementation does not fully implement the
-    // 'query.aggregates.group_by.order' capability! This only orders by dimensions. Before
-    // enabling the capability we also need to be able to order by aggregates. We need partial
-    // support for order by to get consistent integration test snapshots.
-    let sort_groups_stage = grouping
-        .order_by
+    // Group predicates are applied to aggregate values after grouping, similar to a SQL HAVING
+    // clause.
+    let match_stage = grouping
+        .predicate
         .as_ref()
-        .map(sort_stage_for_grouping)
+        .map(|predicate| {
+            let expression = make_group_expression(&mut group_aggregates, predicate)?;
+            Ok(Stage::Match(doc! { "$expr": expression })) as Result<_>
+        })
         .transpose()?;
 
-    // TODO: ENG-1563 to implement 'query.aggregates.group_by.paginate' apply grouping.limit and
-    // grouping.offset **after** group stage because those options count groups, not documents
+    let sort_stages = grouping
+        .order_by
+        .as_ref()
+        .map(|order_by| sort_stages_for_grouping(&mut group_aggregates, order_by))
+        .transpose()?
+        .unwrap_or_default();
+
+    // Limit and offset count groups, not documents, so they are applied after the group stage.
+    let skip_stage = grouping.offset.map(Into::into).map(Stage::Skip);
+    let limit_stage = grouping.limit.map(Into::into).map(Stage::Limit);
+
+    let group_stage = Stage::Group {
+        key_expression: dimensions_to_expression(&grouping.dimensions).into(),
+        accumulators: group_aggregates.accumulators(),
+    };
 
     let replace_with_stage = Stage::ReplaceWith(selection_for_grouping(grouping, "_id"));
 
     Ok(Pipeline::new(
-        [
-            Some(group_stage),
-            sort_groups_stage,
-            Some(replace_with_stage),
-        ]
-        .into_iter()
-        .flatten()
-        .collect(),
+        once(group_stage)
+            .chain(match_stage)
+            .chain(sort_stages)
+            .chain(skip_stage)
+            .chain(limit_stage)
+            .chain(once(replace_with_stage))
+            .collect(),
     ))
 }
 
+/// Group predicates and sort criteria may reference aggregates that were not requested. This
+/// tracks those aggregates so that they can be computed in the $group stage alongside requested
+/// aggregates under generated keys.
+struct GroupAggregates<'a> {
+    requested: &'a IndexMap<FieldName, Aggregate>,
+    additional: IndexMap<FieldName, Aggregate>,
+}
+
+impl<'a> GroupAggregates<'a> {
+    fn new(requested: &'a IndexMap<FieldName, Aggregate>) -> Self {
+        GroupAggregates {
+            requested,
+            additional: Default::default(),
+        }
+    }
+
+    fn key_for(&mut self, aggregate: &Aggregate) -> FieldName {
+        let existing_key = self
+            .requested
+            .iter()
+            .chain(self.additional.iter())
+            .find(|(_, a)| *a == aggregate)
+            .map(|(key, _)| key.clone());
+        existing_key.unwrap_or_else(|| {
+            let key: FieldName = format!("__group_aggregate_{}", self.additional.len()).into();
+            self.additional.insert(key.clone(), aggregate.clone());
+            key
+        })
+    }
+
+    /// Expression that evaluates to the final value of the given aggregate in each group
+    /// document, after post-processing and type conversion.
+    fn value_expression(&mut self, aggregate: &Aggregate) -> Bson {
+        let key = self.key_for(aggregate);
+        let (_, value_expression) = selection_for_aggregate(&key, aggregate);
+        value_expression
+    }
+
+    fn accumulators(&self) -> BTreeMap<String, Accumulator> {
+        let mut accumulators = accumulators_for_aggregates(self.requested);
+        accumulators.extend(accumulators_for_aggregates(&self.additional));
+        accumulators
+    }
+}
+
 /// Converts each dimension to a MongoDB aggregate expression that evaluates to the appropriate
 /// value when applied to each input document. The array of expressions can be used directly as the
 /// group stage key expression.
@@ -87,27 +149,112 @@ fn selection_for_grouping(grouping: &Grouping, dimensions_field_name: &str) -> S
     let selection_doc = std::iter::once(dimensions)
         .chain(selected_aggregates)
         .collect();
-    Selection::new(selection_doc)
+    Selection::new(escape_invalid_keys(selection_doc))
 }
 
-// TODO: ENG-1562 This is where we need to implement sorting by aggregates
-fn sort_stage_for_grouping(order_by: &GroupOrderBy) -> Result<Stage> {
-    let sort_doc = order_by
-        .elements
-        .iter()
-        .map(|element| match element.target {
-            GroupOrderByTarget::Dimension { index } => {
-                let key = format!("_id.{index}");
-                let direction = match element.order_direction {
-                    OrderDirection::Asc => bson!(1),
-                    OrderDirection::Desc => bson!(-1),
-                };
-                Ok((key, direction))
+/// Dimensions can be sorted by referencing the group key directly. Aggregate values need to be
+/// computed into temporary fields first.
+fn sort_stages_for_grouping(
+    group_aggregates: &mut GroupAggregates<'_>,
+    order_by: &GroupOrderBy,
+) -> Result<Vec<Stage>> {
+    let mut sort_keys = Document::new();
+    let mut sort_doc = Document::new();
+    for (index, element) in order_by.elements.iter().enumerate() {
+        let key = match &element.target {
+            GroupOrderByTarget::Dimension { index } => format!("_id.{index}"),
+            GroupOrderByTarget::Aggregate { aggregate } => {
+                let key = format!("__sort_key_{index}");
+                sort_keys.insert(key.clone(), group_aggregates.value_expression(aggregate));
+                key
             }
-            GroupOrderByTarget::Aggregate { .. } => Err(MongoAgentError::NotImplemented(
-                Cow::Borrowed("sorting groups by aggregate"),
-            )),
-        })
-        .collect::<Result<_>>()?;
-    Ok(Stage::Sort(SortDocument::from_doc(sort_doc)))
+        };
+        let direction = match element.order_direction {
+            OrderDirection::Asc => bson!(1),
+            OrderDirection::Desc => bson!(-1),
+        };
+        sort_doc.insert(key, direction);
+    }
+
+    let add_fields_stage = if sort_keys.is_empty() {
+        None
+    } else {
+        Some(Stage::AddFields(sort_keys))
+    };
+    let sort_stage = Stage::Sort(SortDocument::from_doc(sort_doc));
+    Ok(add_fields_stage
+        .into_iter()
+        .chain(once(sort_stage))
+        .collect())
+}
+
+fn make_group_expression(
+    group_aggregates: &mut GroupAggregates<'_>,
+    expression: &GroupExpression,
+) -> Result<Bson> {
+    let expression = match expression {
+        GroupExpression::And { expressions } => {
+            let sub_expressions: Vec<Bson> = expressions
+                .iter()
+                .map(|e| make_group_expression(group_aggregates, e))
+                .collect::<Result<_>>()?;
+            bson!({ "$and": sub_expressions })
+        }
+        GroupExpression::Or { expressions } => {
+            let sub_expressions: Vec<Bson> = expressions
+                .iter()
+                .map(|e| make_group_expression(group_aggregates, e))
+                .collect::<Result<_>>()?;
+            bson!({ "$or": sub_expressions })
+        }
+        GroupExpression::Not { expression } => {
+            let sub_expression = make_group_expression(group_aggregates, expression)?;
+            bson!({ "$not": [sub_expression] })
+        }
+        GroupExpression::UnaryComparisonOperator { target, operator } => match operator {
+            UnaryComparisonOperator::IsNull => {
+                bson!({ "$eq": [group_comparison_target(group_aggregates, target), null] })
+            }
+        },
+        GroupExpression::BinaryComparisonOperator {
+            target,
+            operator,
+            value,
+        } => operator
+            .mongodb_aggregation_expression(
+                group_comparison_target(group_aggregates, target),
+                group_comparison_value(value)?,
+            )
+            .into(),
+    };
+    Ok(expression)
+}
+
+fn group_comparison_target(
+    group_aggregates: &mut GroupAggregates<'_>,
+    target: &GroupComparisonTarget,
+) -> Bson {
+    match target {
+        GroupComparisonTarget::Aggregate { aggregate } => {
+            group_aggregates.value_expression(aggregate)
+        }
+    }
+}
+
+fn group_comparison_value(value: &GroupComparisonValue) -> Result<Bson> {
+    match value {
+        GroupComparisonValue::Scalar { value, value_type } => {
+            let comparison_value = json_to_bson(value_type, value.clone())
+                .map_err(|e| MongoAgentError::BadQuery(anyhow!(e)))?;
+            Ok(bson!({ "$literal": comparison_value }))
+        }
+        GroupComparisonValue::Variable {
+            name,
+            variable_type,
+        } => Ok(
+            ColumnRef::variable(query_variable_name(name, variable_type))
+                .into_aggregate_expression()
+                .into_bson(),
+        ),
+    }
 }
@@ -2,15 +2,17 @@ use anyhow::anyhow;
 use itertools::Itertools as _;
 use mongodb::bson::{self, doc, Bson};
 use ndc_models::UnaryComparisonOperator;
+use ndc_query_plan::Scope;
 
 use crate::{
     comparison_function::ComparisonFunction,
+    constants::SCALAR_ELEMENT_FIELD,
     interface_types::MongoAgentError,
     mongo_query_plan::{
         ArrayComparison, ComparisonTarget, ComparisonValue, ExistsInCollection, Expression, Type,
     },
     query::{
-        column_ref::{column_expression, ColumnRef},
+        column_ref::{column_expression, name_from_scope, ColumnRef},
         query_variable_name::query_variable_name,
         serialization::json_to_bson,
     },
@@ -75,7 +77,12 @@ pub fn make_aggregation_expression(expr: &Expression) -> Result<AggregationExpre
         Expression::Exists {
             in_collection,
             predicate,
-        } => make_aggregation_expression_for_exists(in_collection, predicate.as_deref()),
+            scope,
+        } => {
+            let expression =
+                make_aggregation_expression_for_exists(in_collection, predicate.as_deref())?;
+            Ok(bind_enclosing_scope(scope.as_ref(), expression))
+        }
         Expression::BinaryComparisonOperator {
             column,
             operator,
@@ -154,7 +161,7 @@ pub fn make_aggregation_expression_for_exists(
             Some(predicate),
         ) => {
             let column_ref = ColumnRef::from_column_and_field_path(column_name, Some(field_path));
-            exists_in_array(column_ref, predicate)? // TODO: ENG-1488 predicate expects objects with a __value field
+            exists_in_scalar_array(column_ref, predicate)?
         }
         (
             ExistsInCollection::NestedScalarCollection {
@@ -171,6 +178,25 @@ pub fn make_aggregation_expression_for_exists(
     Ok(expression)
 }
 
+/// If the predicate of an exists expression references the document that encloses the exists
+/// expression then we bind that document to a variable named for the scope. This must happen
+/// before the predicate is evaluated because evaluating the predicate rebinds `$$CURRENT` to
+/// elements of the array that is checked.
+fn bind_enclosing_scope(
+    scope: Option<&Scope>,
+    expression: AggregationExpression,
+) -> AggregationExpression {
+    match scope {
+        Some(scope) => AggregationExpression::new(doc! {
+            "$let": {
+                "vars": { name_from_scope(scope): "$$CURRENT" },
+                "in": expression.into_bson(),
+            }
+        }),
+        None => expression,
+    }
+}
+
 fn exists_in_array(
     array_ref: ColumnRef<'_>,
     predicate: &Expression,
@@ -190,6 +216,33 @@ fn exists_in_array(
     ))
 }
 
+/// The predicate for an exists check over an array of scalars references each array element as
+/// a field named `__value`. So we wrap each element in a document with that field before
+/// evaluating the predicate.
+fn exists_in_scalar_array(
+    array_ref: ColumnRef<'_>,
+    predicate: &Expression,
+) -> Result<AggregationExpression> {
+    let AggregationExpression(sub_expression) = make_aggregation_expression(predicate)?;
+    Ok(AggregationExpression(
+        doc! {
+            "$anyElementTrue": {
+                "$map": {
+                    "input": {
+                        "$map": {
+                            "input": array_ref.into_aggregate_expression(),
+                            "in": { SCALAR_ELEMENT_FIELD: "$$this" },
+                        }
+                    },
+                    "as": "CURRENT", // implicitly changes the document root in `sub_expression` to be the wrapped element
+                    "in": sub_expression,
+                }
+            }
+        }
+        .into(),
+    ))
+}
+
 fn exists_in_array_no_predicate(array_ref: ColumnRef<'_>) -> AggregationExpression {
     AggregationExpression::new(doc! {
         "$gt": [{ "$size": array_ref.into_aggregate_expression() }, 0]
@@ -202,13 +255,11 @@ fn make_binary_comparison_selector(
     value: &ComparisonValue,
 ) -> Result<AggregationExpression> {
     let left_operand = ColumnRef::from_comparison_target(target_column).into_aggregate_expression();
-    let right_operand = value_expression(value)?;
-    let expr = AggregationExpression(
+    compare_to_value(value, |right_operand| {
         operator
             .mongodb_aggregation_expression(left_operand, right_operand)
-            .into(),
-    );
-    Ok(expr)
+            .into()
+    })
 }
 
 fn make_unary_comparison_selector(
@@ -229,34 +280,76 @@ fn make_array_comparison_selector(
     column: &ComparisonTarget,
     comparison: &ArrayComparison,
 ) -> Result<AggregationExpression> {
-    let doc = match comparison {
-        ArrayComparison::Contains { value } => doc! {
-            "$in": [value_expression(value)?, column_expression(column)]
-        },
-        ArrayComparison::IsEmpty => doc! {
+    match comparison {
+        ArrayComparison::Contains { value } => compare_to_value(value, |value| {
+            doc! { "$in": [value, column_expression(column)] }.into()
+        }),
+        ArrayComparison::IsEmpty => Ok(AggregationExpression::new(doc! {
             "$eq": [{ "$size": column_expression(column) }, 0]
-        },
-    };
-    Ok(AggregationExpression(doc.into()))
+        })),
+    }
 }
 
-fn value_expression(value: &ComparisonValue) -> Result<AggregationExpression> {
+/// Produces a comparison against the given value using the `comparison` callback. A column value
+/// that is reached through a relationship may resolve to any number of related rows - in that case
+/// the comparison holds if it holds for at least one of those rows, and does not hold if there are
+/// no related rows.
+fn compare_to_value(
+    value: &ComparisonValue,
+    comparison: impl FnOnce(AggregationExpression) -> Bson,
+) -> Result<AggregationExpression> {
     match value {
         ComparisonValue::Column {
             path,
             name,
             field_path,
-            scope: _, // We'll need to reference scope for ENG-1153
+            scope,
             ..
-        } => {
-            // TODO: ENG-1153 Do we want an implicit exists in the value relationship? If both
-            // target and value reference relationships do we want an exists in a Cartesian product
-            // of the two?
-            if !path.is_empty() {
-                return Err(MongoAgentError::NotImplemented("binary comparisons where the right-side of the comparison references a relationship".into()));
+        } if !path.is_empty() => {
+            if scope.is_some() {
+                return Err(MongoAgentError::NotImplemented(
+                    "binary comparisons where the right-side of the comparison references a relationship of an enclosing scope".into(),
+                ));
             }
+            let row_variable = "related_row";
+            let related_value = field_path
+                .iter()
+                .flatten()
+                .fold(
+                    ColumnRef::variable(row_variable).into_nested_field(name.as_str()),
+                    |column_ref, field_name| column_ref.into_nested_field(field_name.as_str()),
+                )
+                .into_aggregate_expression();
+            Ok(AggregationExpression::new(doc! {
+                "$anyElementTrue": {
+                    "$map": {
+                        "input": ColumnRef::from_related_rows(path).into_aggregate_expression(),
+                        "as": row_variable,
+                        "in": comparison(related_value),
+                    }
+                }
+            }))
+        }
+        _ => Ok(AggregationExpression::new(comparison(value_expression(
+            value,
+        )?))),
+    }
+}
 
-            let value_ref = ColumnRef::from_column_and_field_path(name, field_path.as_ref());
+fn value_expression(value: &ComparisonValue) -> Result<AggregationExpression> {
+    match value {
+        ComparisonValue::Column {
+            name,
+            field_path,
+            scope,
+            ..
+        } => {
+            let value_ref = match scope {
+                Some(scope) => {
+                    ColumnRef::from_scope_column_and_field_path(scope, name, field_path.as_ref())
+                }
+                None => ColumnRef::from_column_and_field_path(name, field_path.as_ref()),
+            };
             Ok(value_ref.into_aggregate_expression())
         }
         ComparisonValue::Scalar { value, value_type } => {
@@ -5,6 +5,7 @@ use ndc_models::UnaryComparisonOperator;
 
 use crate::{
     comparison_function::ComparisonFunction,
+    constants::SCALAR_ELEMENT_FIELD,
     interface_types::MongoAgentError,
     mongo_query_plan::{
         ArrayComparison, ComparisonTarget, ComparisonValue, ExistsInCollection, Expression, Type,
@@ -61,9 +62,13 @@ pub fn make_query_document(expr: &Expression) -> Result<Option<QueryDocument>> {
                 sub_expression.map(|expr| QueryDocument(doc! { "$nor": [expr.into_document()] }));
             Ok(plan)
         }
+        // Query documents cannot bind variables which we need to reference the enclosing document
+        // from the exists predicate.
+        Expression::Exists { scope: Some(_), .. } => Ok(None),
         Expression::Exists {
             in_collection,
             predicate,
+            scope: None,
         } => make_query_document_for_exists(in_collection, predicate.as_deref()),
         Expression::BinaryComparisonOperator {
             column,
@@ -128,7 +133,7 @@ fn make_query_document_for_exists(
             Some(predicate),
         ) => {
             let column_ref = ColumnRef::from_column_and_field_path(column_name, Some(field_path));
-            exists_in_array(column_ref, predicate)? // TODO: predicate expects objects with a __value field
+            exists_in_scalar_array(column_ref, predicate)?
         }
         (
             ExistsInCollection::NestedScalarCollection {
@@ -159,6 +164,40 @@ fn exists_in_array(
     Ok(plan)
 }
 
+/// The predicate for an exists check over an array of scalars references each array element as
+/// a field named `__value`. A query document for `$elemMatch` over scalars applies operators
+/// directly to each element, so we can translate the predicate if it is a set of operators applied
+/// to `__value`. Otherwise the caller must fall back to an aggregation expression.
+fn exists_in_scalar_array(
+    array_ref: ColumnRef<'_>,
+    predicate: &Expression,
+) -> Result<Option<QueryDocument>> {
+    let sub_expression = make_query_document(predicate)?;
+    let plan = match (array_ref, sub_expression) {
+        (ColumnRef::MatchKey(key), Some(QueryDocument(query_doc))) => {
+            scalar_element_operators(query_doc).map(|operators| {
+                QueryDocument(doc! {
+                    key: { "$elemMatch": operators }
+                })
+            })
+        }
+        _ => None,
+    };
+    Ok(plan)
+}
+
+fn scalar_element_operators(mut query_doc: bson::Document) -> Option<bson::Document> {
+    if query_doc.len() != 1 {
+        return None;
+    }
+    match query_doc.remove(SCALAR_ELEMENT_FIELD) {
+        Some(Bson::Document(operators)) if operators.keys().all(|key| key.starts_with('$')) => {
+            Some(operators)
+        }
+        _ => None,
+    }
+}
+
 fn exists_in_array_no_predicate(array_ref: ColumnRef<'_>) -> Option<QueryDocument> {
     match array_ref {
         ColumnRef::MatchKey(key) => Some(QueryDocument(doc! {
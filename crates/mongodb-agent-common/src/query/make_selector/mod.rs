@@ -7,7 +7,7 @@ use mongodb::bson::{doc, Document};
 use crate::{interface_types::MongoAgentError, mongo_query_plan::Expression};
 
 pub use self::{
-    make_aggregation_expression::AggregationExpression,
+    make_aggregation_expression::{make_aggregation_expression, AggregationExpression},
     make_expression_plan::{make_expression_plan, ExpressionPlan},
     make_query_document::QueryDocument,
 };
@@ -32,16 +32,22 @@ pub fn make_selector(expr: &Expression) -> Result<Document> {
 #[cfg(test)]
 mod tests {
     use configuration::MongoScalarType;
-    use mongodb::bson::doc;
+    use mongodb::bson::{self, doc};
     use mongodb_support::BsonScalarType;
     use ndc_models::UnaryComparisonOperator;
+    use ndc_query_plan::{plan_for_query_request, Scope};
+    use ndc_test_helpers::{
+        binop, column_value, exists, field, query, query_request, related, relation_field, target,
+    };
     use pretty_assertions::assert_eq;
 
     use crate::{
         comparison_function::ComparisonFunction,
         mongo_query_plan::{
-            ComparisonTarget, ComparisonValue, ExistsInCollection, Expression, Type,
+            Aggregate, ComparisonTarget, ComparisonValue, ExistsInCollection, Expression, Type,
         },
+        query::{column_ref::name_from_scope, pipeline_for_query_request},
+        test_helpers::{chinook_config, chinook_relationships},
     };
 
     use super::make_selector;
@@ -68,7 +74,9 @@ mod tests {
                         value_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
                     },
                 })),
+                scope: None,
             })),
+            scope: None,
         })?;
 
         let expected = doc! {
@@ -105,7 +113,9 @@ mod tests {
                     ),
                     operator: UnaryComparisonOperator::IsNull,
                 })),
+                scope: None,
             })),
+            scope: None,
         })?;
 
         let expected = doc! {
@@ -148,120 +158,143 @@ mod tests {
         Ok(())
     }
 
-    // TODO: ENG-1487 modify this test for the new named scopes feature
-    // #[test]
-    // fn compares_root_collection_column_to_scalar() -> anyhow::Result<()> {
-    //     let selector = make_selector(&Expression::BinaryComparisonOperator {
-    //         column: ComparisonTarget::ColumnInScope {
-    //             name: "Name".into(),
-    //             field_path: None,
-    //             field_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
-    //             scope: Scope::Named("scope_0".to_string()),
-    //         },
-    //         operator: ComparisonFunction::Equal,
-    //         value: ComparisonValue::Scalar {
-    //             value: "Lady Gaga".into(),
-    //             value_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
-    //         },
-    //     })?;
-    //
-    //     let expected = doc! {
-    //         "$expr": {
-    //             "$eq": ["$$scope_0.Name", "Lady Gaga"]
-    //         }
-    //     };
-    //
-    //     assert_eq!(selector, expected);
-    //     Ok(())
-    // }
-
-    // #[test]
-    // fn root_column_reference_refereces_column_of_nearest_query() -> anyhow::Result<()> {
-    //     let request = query_request()
-    //         .collection("Artist")
-    //         .query(
-    //             query().fields([relation_field!("Albums" => "Albums", query().predicate(
-    //             binop(
-    //                 "_gt",
-    //                 target!("Milliseconds", relations: [
-    //                     path_element("Tracks".into()).predicate(
-    //                         binop("_eq", target!("Name"), column_value!(root("Title")))
-    //                     ),
-    //                 ]),
-    //                 value!(30_000),
-    //             )
-    //             ))]),
-    //         )
-    //         .relationships(chinook_relationships())
-    //         .into();
-    //
-    //     let config = chinook_config();
-    //     let plan = plan_for_query_request(&config, request)?;
-    //     let pipeline = pipeline_for_query_request(&config, &plan)?;
-    //
-    //     let expected_pipeline = bson!([
-    //         {
-    //             "$lookup": {
-    //                 "from": "Album",
-    //                 "localField": "ArtistId",
-    //                 "foreignField": "ArtistId",
-    //                 "as": "Albums",
-    //                 "let": {
-    //                     "scope_root": "$$ROOT",
-    //                 },
-    //                 "pipeline": [
-    //                     {
-    //                         "$lookup": {
-    //                             "from": "Track",
-    //                             "localField": "AlbumId",
-    //                             "foreignField": "AlbumId",
-    //                             "as": "Tracks",
-    //                             "let": {
-    //                                 "scope_0": "$$ROOT",
-    //                             },
-    //                             "pipeline": [
-    //                                 {
-    //                                     "$match": {
-    //                                         "$expr": { "$eq": ["$Name", "$$scope_0.Title"] },
-    //                                     },
-    //                                 },
-    //                                 {
-    //                                     "$replaceWith": {
-    //                                         "Milliseconds": { "$ifNull": ["$Milliseconds", null] }
-    //                                     }
-    //                                 },
-    //                             ]
-    //                         }
-    //                     },
-    //                     {
-    //                         "$match": {
-    //                             "Tracks": {
-    //                                 "$elemMatch": {
-    //                                     "Milliseconds": { "$gt": 30_000 }
-    //                                 }
-    //                             }
-    //                         }
-    //                     },
-    //                     {
-    //                         "$replaceWith": {
-    //                             "Tracks": { "$getField": { "$literal": "Tracks" } }
-    //                         }
-    //                     },
-    //                 ],
-    //             },
-    //         },
-    //         {
-    //             "$replaceWith": {
-    //                 "Albums": {
-    //                     "rows": []
-    //                 }
-    //             }
-    //         },
-    //     ]);
-    //
-    //     assert_eq!(bson::to_bson(&pipeline).unwrap(), expected_pipeline);
-    //     Ok(())
-    // }
+    #[test]
+    fn compares_array_element_to_column_of_enclosing_document() -> anyhow::Result<()> {
+        let selector = make_selector(&Expression::Exists {
+            in_collection: ExistsInCollection::NestedCollection {
+                column_name: "line_items".into(),
+                arguments: Default::default(),
+                field_path: Default::default(),
+            },
+            predicate: Some(Box::new(Expression::BinaryComparisonOperator {
+                column: ComparisonTarget::column(
+                    "sku",
+                    Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
+                ),
+                operator: ComparisonFunction::Equal,
+                value: ComparisonValue::Column {
+                    path: Default::default(),
+                    name: "featured_sku".into(),
+                    arguments: Default::default(),
+                    field_path: None,
+                    field_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
+                    scope: Some(Scope::Named("scope_0".to_string())),
+                },
+            })),
+            scope: Some(Scope::Named("scope_0".to_string())),
+        })?;
+
+        let expected = doc! {
+            "$expr": {
+                "$let": {
+                    "vars": { "scope_0": "$$CURRENT" },
+                    "in": {
+                        "$anyElementTrue": {
+                            "$map": {
+                                "input": "$line_items",
+                                "as": "CURRENT",
+                                "in": { "$eq": ["$sku", "$$scope_0.featured_sku"] },
+                            }
+                        }
+                    },
+                }
+            }
+        };
+
+        assert_eq!(selector, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn compares_column_to_column_of_related_collection() -> anyhow::Result<()> {
+        let date_type = Type::Scalar(MongoScalarType::Bson(BsonScalarType::Date));
+        let selector = make_selector(&Expression::BinaryComparisonOperator {
+            column: ComparisonTarget::column("delivered_at", date_type.clone()),
+            operator: ComparisonFunction::GreaterThan,
+            value: ComparisonValue::Column {
+                path: vec!["shipment_order".into()],
+                name: "promised_at".into(),
+                arguments: Default::default(),
+                field_path: None,
+                field_type: date_type,
+                scope: None,
+            },
+        })?;
+
+        let expected = doc! {
+            "$expr": {
+                "$anyElementTrue": {
+                    "$map": {
+                        "input": "$shipment_order",
+                        "as": "related_row",
+                        "in": { "$gt": ["$delivered_at", "$$related_row.promised_at"] },
+                    }
+                }
+            }
+        };
+
+        assert_eq!(selector, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn column_reference_in_named_scope_refers_to_document_enclosing_exists() -> anyhow::Result<()> {
+        let request = query_request()
+            .collection("Artist")
+            .query(
+                query().fields([relation_field!("Albums" => "Albums", query()
+                    .fields([field!("Title")])
+                    .predicate(exists(
+                        related!("Tracks"),
+                        binop("_eq", target!("Name"), column_value("Title").scope(1).into()),
+                    ))
+                )]),
+            )
+            .relationships(chinook_relationships())
+            .into();
+
+        let config = chinook_config();
+        let plan = plan_for_query_request(&config, request)?;
+        let Some(Expression::Exists {
+            scope: Some(scope), ..
+        }) = &plan.query.relationships["Albums"].query.predicate
+        else {
+            panic!("expected an exists predicate with a named scope");
+        };
+        let scope_name = name_from_scope(scope);
+
+        let pipeline = bson::to_bson(&pipeline_for_query_request(&config, &plan)?)?;
+        let lookup = pipeline.as_array().unwrap()[0]
+            .as_document()
+            .unwrap()
+            .get_document("$lookup")?;
+        assert_eq!(lookup.get_str("as")?, "Albums");
+        let match_stage = lookup
+            .get_array("pipeline")?
+            .iter()
+            .find_map(|stage| stage.as_document()?.get_document("$match").ok())
+            .expect("a match stage in the lookup pipeline");
+
+        // The album is bound to the scope variable before `$$CURRENT` is rebound to each track
+        let expected = doc! {
+            "$expr": {
+                "$let": {
+                    "vars": { scope_name.as_ref(): "$$CURRENT" },
+                    "in": {
+                        "$anyElementTrue": {
+                            "$map": {
+                                "input": "$Tracks",
+                                "as": "CURRENT",
+                                "in": { "$eq": ["$Name", format!("$${scope_name}.Title")] },
+                            }
+                        }
+                    },
+                }
+            }
+        };
+        assert_eq!(match_stage, &expected);
+        Ok(())
+    }
 
     #[test]
     fn compares_value_to_elements_of_array_field() -> anyhow::Result<()> {
@@ -282,6 +315,7 @@ mod tests {
                     value_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
                 },
             })),
+            scope: None,
         })?;
 
         let expected = doc! {
@@ -315,6 +349,7 @@ mod tests {
                     value_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::String)),
                 },
             })),
+            scope: None,
         })?;
 
         let expected = doc! {
@@ -328,4 +363,119 @@ mod tests {
         assert_eq!(selector, expected);
         Ok(())
     }
+
+    #[test]
+    fn compares_value_to_elements_of_scalar_array_field() -> anyhow::Result<()> {
+        let selector = make_selector(&Expression::Exists {
+            in_collection: ExistsInCollection::NestedScalarCollection {
+                column_name: "scores".into(),
+                arguments: Default::default(),
+                field_path: Default::default(),
+            },
+            predicate: Some(Box::new(Expression::BinaryComparisonOperator {
+                column: ComparisonTarget::column(
+                    "__value",
+                    Type::Scalar(MongoScalarType::Bson(BsonScalarType::Int)),
+                ),
+                operator: ComparisonFunction::GreaterThan,
+                value: ComparisonValue::Scalar {
+                    value: 90.into(),
+                    value_type: Type::Scalar(MongoScalarType::Bson(BsonScalarType::Int)),
+                },
+            })),
+            scope: None,
+        })?;
+
+        let expected = doc! {
+            "scores": {
+                "$elemMatch": { "$gt": 90 }
+            }
+        };
+
+        assert_eq!(selector, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn falls_back_to_aggregation_expression_for_complex_predicate_on_scalar_array_elements(
+    ) -> anyhow::Result<()> {
+        let string_type = Type::Scalar(MongoScalarType::Bson(BsonScalarType::String));
+        let tag_equals = |tag: &str| Expression::BinaryComparisonOperator {
+            column: ComparisonTarget::column("__value", string_type.clone()),
+            operator: ComparisonFunction::Equal,
+            value: ComparisonValue::Scalar {
+                value: tag.into(),
+                value_type: string_type.clone(),
+            },
+        };
+        let selector = make_selector(&Expression::Exists {
+            in_collection: ExistsInCollection::NestedScalarCollection {
+                column_name: "tags".into(),
+                arguments: Default::default(),
+                field_path: Default::default(),
+            },
+            predicate: Some(Box::new(Expression::Or {
+                expressions: vec![tag_equals("rust"), tag_equals("mongodb")],
+            })),
+            scope: None,
+        })?;
+
+        let expected = doc! {
+            "$expr": {
+                "$anyElementTrue": {
+                    "$map": {
+                        "input": {
+                            "$map": {
+                                "input": "$tags",
+                                "in": { "__value": "$$this" },
+                            }
+                        },
+                        "as": "CURRENT",
+                        "in": {
+                            "$or": [
+                                { "$eq": ["$__value", { "$literal": "rust" }] },
+                                { "$eq": ["$__value", { "$literal": "mongodb" }] },
+                            ]
+                        },
+                    }
+                }
+            }
+        };
+
+        assert_eq!(selector, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn compares_aggregate_over_related_rows_using_aggregation_expression() -> anyhow::Result<()> {
+        let int_type = Type::Scalar(MongoScalarType::Bson(BsonScalarType::Int));
+        let selector = make_selector(&Expression::BinaryComparisonOperator {
+            column: ComparisonTarget::Aggregate {
+                path: vec!["Albums".into()],
+                aggregate: Aggregate::StarCount,
+            },
+            operator: ComparisonFunction::GreaterThan,
+            value: ComparisonValue::Scalar {
+                value: 2.into(),
+                value_type: int_type,
+            },
+        })?;
+
+        let expected = doc! {
+            "$expr": {
+                "$gt": [
+                    {
+                        "$let": {
+                            "vars": { "rows": "$Albums" },
+                            "in": { "$ifNull": [{ "$size": "$$rows" }, 0] },
+                        }
+                    },
+                    { "$literal": 2 },
+                ]
+            }
+        };
+
+        assert_eq!(selector, expected);
+        Ok(())
+    }
 }
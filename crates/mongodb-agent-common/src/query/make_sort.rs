@@ -2,13 +2,13 @@ use std::{collections::BTreeMap, iter::once};
 
 use indexmap::IndexMap;
 use itertools::join;
-use mongodb::bson::bson;
+use mongodb::bson::{bson, doc, Bson, Document};
 use mongodb_support::aggregate::{SortDocument, Stage};
 use ndc_models::OrderDirection;
 
 use crate::{
     interface_types::MongoAgentError,
-    mongo_query_plan::{OrderBy, OrderByTarget},
+    mongo_query_plan::{Aggregate, OrderBy, OrderByTarget},
     mongodb::sanitize::escape_invalid_variable_chars,
 };
 
@@ -21,6 +21,13 @@ type RequiredAliases<'a> = BTreeMap<String, ColumnRef<'a>>;
 
 type Result<T> = std::result::Result<T, MongoAgentError>;
 
+/// Key for the original array element when wrapping elements to sort by escaped field names
+const SORT_ARRAY_ELEMENT_KEY: &str = "__sort_element";
+
+/// Key for the aggregate value in documents produced by the `$lookup` stage for sorting by an
+/// aggregate over related rows
+pub const SORT_AGGREGATE_VALUE_KEY: &str = "value";
+
 pub fn make_sort_stages(order_by: &OrderBy) -> Result<Vec<Stage>> {
     let (sort_document, required_aliases) = make_sort(order_by)?;
     let mut stages = vec![];
@@ -40,6 +47,59 @@ pub fn make_sort_stages(order_by: &OrderBy) -> Result<Vec<Stage>> {
     Ok(stages)
 }
 
+/// Produces a `$sortArray` aggregation expression that sorts the elements of `input`. This is used
+/// to sort rows of a nested collection where there is no pipeline to add a $sort stage to.
+pub fn make_sort_array_expression(input: Bson, order_by: &OrderBy) -> Result<Bson> {
+    // Aggregates to sort by are computed by lookup stages which are not available here
+    if order_by
+        .elements
+        .iter()
+        .any(|element| matches!(element.target, OrderByTarget::Aggregate { .. }))
+    {
+        return Err(MongoAgentError::NotImplemented(
+            "sorting nested collection rows by aggregates".into(),
+        ));
+    }
+    let (SortDocument(sort_by), required_aliases) = make_sort(order_by)?;
+    if required_aliases.is_empty() {
+        return Ok(bson!({ "$sortArray": { "input": input, "sortBy": sort_by } }));
+    }
+
+    // There is no place to insert alias fields when sorting array elements. Instead we wrap each
+    // element in a document alongside its aliased sort keys, sort the wrappers, and unwrap.
+    let sort_by: Document = sort_by
+        .into_iter()
+        .map(|(key, direction)| {
+            if required_aliases.contains_key(&key) {
+                (key, direction)
+            } else {
+                (format!("{SORT_ARRAY_ELEMENT_KEY}.{key}"), direction)
+            }
+        })
+        .collect();
+    let mut wrapped_element = doc! { SORT_ARRAY_ELEMENT_KEY: "$$CURRENT" };
+    for (alias, expression) in required_aliases {
+        wrapped_element.insert(alias, expression.into_aggregate_expression());
+    }
+    Ok(bson!({
+        "$map": {
+            "input": {
+                "$sortArray": {
+                    "input": {
+                        "$map": {
+                            "input": input,
+                            "as": "CURRENT", // makes column references relative to the array element
+                            "in": wrapped_element,
+                        }
+                    },
+                    "sortBy": sort_by,
+                }
+            },
+            "in": format!("$$this.{SORT_ARRAY_ELEMENT_KEY}"),
+        }
+    }))
+}
+
 fn make_sort(order_by: &OrderBy) -> Result<(SortDocument, RequiredAliases<'_>)> {
     let OrderBy { elements } = order_by;
 
@@ -97,25 +157,59 @@ fn safe_alias(target: &OrderByTarget) -> Result<String> {
                 &combine_all_elements_into_one_name,
             ))
         }
-        ndc_query_plan::OrderByTarget::Aggregate { .. } => {
-            // TODO: ENG-1010, ENG-1011
-            Err(MongoAgentError::NotImplemented("order by aggregate".into()))
+        ndc_query_plan::OrderByTarget::Aggregate { path, aggregate } => {
+            Ok(aggregate_sort_key(path, aggregate))
         }
     }
 }
 
+/// Name of the field that holds the value of an aggregate to sort by. The same field receives the
+/// output of the `$lookup` stage that computes the aggregate - see
+/// [super::relations::lookup_for_order_by_aggregate].
+pub fn aggregate_sort_key(path: &[ndc_models::RelationshipName], aggregate: &Aggregate) -> String {
+    let aggregate_description: Vec<&str> = match aggregate {
+        Aggregate::ColumnCount {
+            column,
+            field_path,
+            distinct,
+            ..
+        } => once(if *distinct { "distinct_count" } else { "count" })
+            .chain([column.as_str()])
+            .chain(field_path.iter().flatten().map(|f| f.as_str()))
+            .collect(),
+        Aggregate::SingleColumn {
+            column,
+            field_path,
+            function,
+            ..
+        } => once(function.graphql_name())
+            .chain([column.as_str()])
+            .chain(field_path.iter().flatten().map(|f| f.as_str()))
+            .collect(),
+        Aggregate::StarCount => vec!["star_count"],
+    };
+    let name_and_path = once("__sort_key_")
+        .chain(path.iter().map(|n| n.as_str()))
+        .chain(aggregate_description);
+    let combine_all_elements_into_one_name = join(name_and_path, "_");
+    escape_invalid_variable_chars(&combine_all_elements_into_one_name)
+}
+
 #[cfg(test)]
 mod tests {
-    use mongodb::bson::{self, doc};
+    use mongodb::bson::{self, bson, doc};
     use mongodb_support::aggregate::SortDocument;
     use ndc_models::{FieldName, OrderDirection};
     use ndc_query_plan::OrderByElement;
     use nonempty::{nonempty, NonEmpty};
     use pretty_assertions::assert_eq;
 
-    use crate::{mongo_query_plan::OrderBy, query::column_ref::ColumnRef};
+    use crate::{
+        mongo_query_plan::{Aggregate, OrderBy},
+        query::column_ref::ColumnRef,
+    };
 
-    use super::make_sort;
+    use super::{make_sort, make_sort_array_expression};
 
     #[test]
     fn escapes_field_names() -> anyhow::Result<()> {
@@ -173,6 +267,89 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sorts_array_elements_by_field_names_that_require_escaping() -> anyhow::Result<()> {
+        let order_by = OrderBy {
+            elements: vec![
+                OrderByElement {
+                    order_direction: OrderDirection::Asc,
+                    target: ndc_query_plan::OrderByTarget::Column {
+                        name: "price.usd".into(),
+                        field_path: Default::default(),
+                        path: Default::default(),
+                        arguments: Default::default(),
+                    },
+                },
+                OrderByElement {
+                    order_direction: OrderDirection::Desc,
+                    target: ndc_query_plan::OrderByTarget::Column {
+                        name: "title".into(),
+                        field_path: Default::default(),
+                        path: Default::default(),
+                        arguments: Default::default(),
+                    },
+                },
+            ],
+        };
+
+        let actual = make_sort_array_expression(bson!("$items"), &order_by)?;
+        let expected = bson!({
+            "$map": {
+                "input": {
+                    "$sortArray": {
+                        "input": {
+                            "$map": {
+                                "input": "$items",
+                                "as": "CURRENT",
+                                "in": {
+                                    "__sort_element": "$$CURRENT",
+                                    "__sort_key__price·2eusd": { "$getField": { "$literal": "price.usd" } },
+                                },
+                            }
+                        },
+                        "sortBy": {
+                            "__sort_key__price·2eusd": 1,
+                            "__sort_element.title": -1,
+                        },
+                    }
+                },
+                "in": "$$this.__sort_element",
+            }
+        });
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn sorts_by_aggregate_over_multi_hop_relationship_path() -> anyhow::Result<()> {
+        let order_by = OrderBy {
+            elements: vec![OrderByElement {
+                order_direction: OrderDirection::Desc,
+                target: ndc_query_plan::OrderByTarget::Aggregate {
+                    path: vec!["author_articles".into(), "article_comments".into()],
+                    aggregate: Aggregate::StarCount,
+                },
+            }],
+        };
+
+        let actual = make_sort(&order_by)?;
+        let expected_sort_doc = SortDocument(doc! {
+            "__sort_key__author_articles_article_comments_star_count": -1
+        });
+        let expected_aliases = [(
+            "__sort_key__author_articles_article_comments_star_count".into(),
+            ColumnRef::Expression(bson!({
+                "$ifNull": [
+                    { "$first": "$__sort_key__author_articles_article_comments_star_count.value" },
+                    0,
+                ]
+            })),
+        )]
+        .into();
+        assert_eq!(actual, (expected_sort_doc, expected_aliases));
+        Ok(())
+    }
+
     #[test]
     fn serializes_sort_criteria_in_expected_order() -> anyhow::Result<()> {
         let first_criteria = "year";
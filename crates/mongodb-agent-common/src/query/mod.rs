@@ -6,6 +6,7 @@ mod groups;
 mod is_response_faceted;
 mod make_selector;
 mod make_sort;
+mod mutation_result;
 mod native_query;
 mod pipeline;
 mod query_level;
@@ -22,6 +23,7 @@ use self::execute_query_request::execute_query_request;
 pub use self::{
     make_selector::make_selector,
     make_sort::make_sort_stages,
+    mutation_result::select_mutation_result_fields,
     pipeline::{pipeline_for_non_foreach, pipeline_for_query_request},
     query_target::QueryTarget,
     response::QueryResponseError,
@@ -166,6 +168,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         })
@@ -182,6 +185,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         })
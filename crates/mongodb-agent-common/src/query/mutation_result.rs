@@ -0,0 +1,239 @@
+use indexmap::IndexMap;
+use mongodb::{
+    bson::{doc, Bson},
+    ClientSession, Database,
+};
+use mongodb_support::aggregate::{Pipeline, Stage};
+use ndc_query_plan::SourcePathElement;
+
+use crate::{
+    interface_types::MongoAgentError,
+    mongo_query_plan::{
+        Field, MongoConfiguration, NestedField, Query, QueryPlan, Relationships, Type,
+    },
+};
+
+use super::{relations::pipeline_for_relations, selection::selection_for_fields};
+
+type Result<T> = std::result::Result<T, MongoAgentError>;
+
+/// Mutation results are placed in a document under this key so that a result of any type can be
+/// fed into an aggregation pipeline.
+const RESULT_KEY: &str = "__result";
+
+/// Applies requested fields to the result of a procedure when those fields reference
+/// relationships. The result is fed into an aggregation pipeline that looks up related rows the
+/// same way as a query, and selects fields with the same shape that a query would produce.
+pub async fn select_mutation_result_fields(
+    config: &MongoConfiguration,
+    database: &Database,
+    session: &mut ClientSession,
+    result: Bson,
+    result_type: &Type,
+    fields: &NestedField,
+    relationships: &Relationships,
+) -> Result<Bson> {
+    let pipeline =
+        pipeline_for_mutation_result(config, result, result_type, fields, relationships)?;
+    tracing::debug!(pipeline = %serde_json::to_string(&pipeline).unwrap(), "selecting mutation result fields");
+
+    let mut cursor = database.aggregate(pipeline).session(&mut *session).await?;
+    let selected = match cursor.next(&mut *session).await {
+        Some(document) => document?.remove(RESULT_KEY).unwrap_or(Bson::Null),
+        None => Bson::Null,
+    };
+    Ok(selected)
+}
+
+fn pipeline_for_mutation_result(
+    config: &MongoConfiguration,
+    result: Bson,
+    result_type: &Type,
+    fields: &NestedField,
+    relationships: &Relationships,
+) -> Result<Pipeline> {
+    // Relationship source paths are relative to the result value which is nested under
+    // RESULT_KEY in the pipeline input.
+    let relationships = relationships
+        .iter()
+        .map(|(name, relationship)| {
+            let mut relationship = relationship.clone();
+            relationship
+                .source_path
+                .insert(0, SourcePathElement::Field(RESULT_KEY.into()));
+            (name.clone(), relationship)
+        })
+        .collect();
+    let query_plan = QueryPlan {
+        collection: Default::default(),
+        query: Query {
+            relationships,
+            ..Default::default()
+        },
+        arguments: Default::default(),
+        variables: None,
+        variable_types: Default::default(),
+        unrelated_collections: Default::default(),
+    };
+
+    let result_field: IndexMap<_, _> = [(
+        RESULT_KEY.into(),
+        Field::Column {
+            column: RESULT_KEY.into(),
+            column_type: result_type.clone(),
+            fields: Some(fields.clone()),
+        },
+    )]
+    .into();
+
+    let mut pipeline = Pipeline::new(vec![Stage::Documents(vec![doc! { RESULT_KEY: result }])]);
+    pipeline.append(pipeline_for_relations(config, &query_plan)?);
+    pipeline.push(Stage::ReplaceWith(selection_for_fields(Some(
+        &result_field,
+    ))?));
+    Ok(pipeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use configuration::{collection_procedure::collection_procedures, GeneratedProceduresConfig};
+    use mongodb::bson::{self, bson};
+    use ndc_models::MutationRequest;
+    use ndc_query_plan::plan_for_mutation_request;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::{
+        mongo_query_plan::MutationOperation,
+        test_helpers::{chinook_config, chinook_relationships},
+    };
+
+    use super::*;
+
+    #[test]
+    fn looks_up_related_rows_for_procedure_result() -> anyhow::Result<()> {
+        let mut config = chinook_config();
+        let options = GeneratedProceduresConfig {
+            insert: true,
+            ..Default::default()
+        };
+        let artist_collection = config.0.collections["Artist"].clone();
+        for (procedure_info, procedure) in
+            collection_procedures(&mut config.0.object_types, &options, &artist_collection)?
+        {
+            config
+                .0
+                .procedures
+                .insert(procedure_info.name.clone(), procedure_info.clone());
+            config
+                .0
+                .collection_procedures
+                .insert(procedure_info.name, procedure);
+        }
+
+        let request: MutationRequest = serde_json::from_value(json!({
+            "operations": [{
+                "type": "procedure",
+                "name": "insert_Artist_one",
+                "arguments": { "document": { "ArtistId": 1, "Name": "Bob" } },
+                "fields": {
+                    "type": "object",
+                    "fields": {
+                        "name": { "type": "column", "column": "Name" },
+                        "albums": {
+                            "type": "relationship",
+                            "relationship": "Albums",
+                            "arguments": {},
+                            "query": {
+                                "fields": { "title": { "type": "column", "column": "Title" } },
+                            },
+                        },
+                    },
+                },
+            }],
+            "collection_relationships": chinook_relationships(),
+        }))?;
+        let plan = plan_for_mutation_request(&config, request)?;
+        let MutationOperation::Procedure {
+            name,
+            fields: Some(fields),
+            relationships,
+            ..
+        } = &plan.operations[0]
+        else {
+            panic!("expected a procedure with requested fields");
+        };
+        let result_type = &config.collection_procedures()[name].result_type;
+
+        let pipeline = pipeline_for_mutation_result(
+            &config,
+            bson!({ "_id": 1, "ArtistId": 1, "Name": "Bob" }),
+            result_type,
+            fields,
+            relationships,
+        )?;
+        let stages = bson::to_bson(&pipeline)?;
+        let stages = stages.as_array().unwrap();
+
+        assert_eq!(
+            stages[0],
+            bson!({ "$documents": [{ "__result": { "_id": 1, "ArtistId": 1, "Name": "Bob" } }] })
+        );
+        let lookup = stages[1].as_document().unwrap().get_document("$lookup")?;
+        assert_eq!(lookup.get_str("from")?, "Album");
+        assert_eq!(lookup.get_str("as")?, "Albums");
+        assert_eq!(lookup.get_str("localField")?, "__result.ArtistId");
+        assert!(stages
+            .last()
+            .unwrap()
+            .as_document()
+            .unwrap()
+            .contains_key("$replaceWith"));
+        Ok(())
+    }
+    #[test]
+    fn does_not_look_up_relationships_referenced_only_by_predicate_arguments() -> anyhow::Result<()>
+    {
+        let mut config = chinook_config();
+        let options = GeneratedProceduresConfig {
+            update: true,
+            ..Default::default()
+        };
+        let artist_collection = config.0.collections["Artist"].clone();
+        for (procedure_info, procedure) in
+            collection_procedures(&mut config.0.object_types, &options, &artist_collection)?
+        {
+            config
+                .0
+                .procedures
+                .insert(procedure_info.name.clone(), procedure_info.clone());
+            config
+                .0
+                .collection_procedures
+                .insert(procedure_info.name, procedure);
+        }
+
+        let request: MutationRequest = serde_json::from_value(json!({
+            "operations": [{
+                "type": "procedure",
+                "name": "update_Artist_many",
+                "arguments": {
+                    "predicate": {
+                        "type": "exists",
+                        "in_collection": {
+                            "type": "related",
+                            "relationship": "Albums",
+                            "arguments": {},
+                        },
+                    },
+                    "update": { "_set": { "Name": "Bob" } },
+                },
+            }],
+            "collection_relationships": chinook_relationships(),
+        }))?;
+        let plan = plan_for_mutation_request(&config, request)?;
+        let MutationOperation::Procedure { relationships, .. } = &plan.operations[0];
+        assert!(relationships.is_empty());
+        Ok(())
+    }
+}
@@ -12,10 +12,17 @@ use crate::{
 };
 
 use super::{
-    aggregates::pipeline_for_aggregates, column_ref::ColumnRef, foreach::pipeline_for_foreach,
-    groups::pipeline_for_groups, is_response_faceted::ResponseFacets, make_selector,
-    make_sort::make_sort_stages, native_query::pipeline_for_native_query, query_level::QueryLevel,
-    relations::pipeline_for_relations, selection::selection_for_fields,
+    aggregates::pipeline_for_aggregates,
+    column_ref::ColumnRef,
+    foreach::pipeline_for_foreach,
+    groups::pipeline_for_groups,
+    is_response_faceted::ResponseFacets,
+    make_selector,
+    make_sort::make_sort_stages,
+    native_query::pipeline_for_native_query,
+    query_level::QueryLevel,
+    relations::{lookups_for_order_by_aggregates, pipeline_for_relations},
+    selection::{insert_into_selection, selection_for_fields},
 };
 
 type Result<T> = std::result::Result<T, MongoAgentError>;
@@ -62,6 +69,7 @@ pub fn pipeline_for_non_foreach(
         .map(make_selector)
         .transpose()?
         .map(Stage::Match);
+    let order_by_aggregate_lookup_stages = lookups_for_order_by_aggregates(config, query_plan)?;
     let sort_stages: Vec<Stage> = order_by
         .iter()
         .map(make_sort_stages)
@@ -72,6 +80,7 @@ pub fn pipeline_for_non_foreach(
 
     match_stage
         .into_iter()
+        .chain(order_by_aggregate_lookup_stages)
         .chain(sort_stages)
         .chain(skip_stage)
         .chain(limit_stage)
@@ -188,12 +197,14 @@ pub fn pipeline_for_fields_facet(
         // Queries higher up the chain might need to reference relationships from this query. So we
         // forward relationship arrays if this is not the top-level query.
         for relationship_key in relationships.keys() {
-            selection = selection.try_map_document(|mut doc| {
-                doc.insert(
-                    relationship_key.to_owned(),
-                    ColumnRef::from_field(relationship_key.as_str()).into_aggregate_expression(),
-                );
-                doc
+            selection = selection.try_map_document(|doc| {
+                insert_into_selection(
+                    doc,
+                    relationship_key.to_string(),
+                    ColumnRef::from_field(relationship_key.as_str())
+                        .into_aggregate_expression()
+                        .into_bson(),
+                )
             })?;
         }
     }
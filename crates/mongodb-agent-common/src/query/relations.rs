@@ -1,18 +1,26 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, iter::once};
 
 use itertools::Itertools as _;
-use mongodb::bson::{doc, Document};
+use mongodb::bson::{bson, doc, Bson, Document};
 use mongodb_support::aggregate::{Pipeline, Stage};
-use ndc_query_plan::Scope;
+use ndc_models::{FieldName, RelationshipName};
+use ndc_query_plan::{QueryContext as _, QueryPlanError, Scope, SourcePathElement};
 use nonempty::NonEmpty;
 
-use crate::mongo_query_plan::{MongoConfiguration, Query, QueryPlan};
+use crate::mongo_query_plan::{
+    Aggregate, Field, MongoConfiguration, OrderByTarget, Query, QueryPlan, Relationship,
+};
 use crate::query::column_ref::name_from_scope;
 use crate::{interface_types::MongoAgentError, mongodb::sanitize::variable};
 
+use super::aggregates::pipeline_for_aggregates;
 use super::column_ref::ColumnRef;
+use super::make_selector::make_selector;
+use super::make_sort::{aggregate_sort_key, SORT_AGGREGATE_VALUE_KEY};
+use super::native_query::pipeline_for_native_query;
 use super::pipeline::pipeline_for_non_foreach;
 use super::query_level::QueryLevel;
+use super::selection::escape_invalid_keys;
 
 type Result<T> = std::result::Result<T, MongoAgentError>;
 
@@ -30,37 +38,526 @@ pub fn pipeline_for_relations(
     } = query;
 
     // Lookup stages perform the join for each relationship, and assign the list of rows or mapping
-    // of aggregate results to a field in the parent document.
+    // of aggregate results to a field in the parent document. Relationships that are referenced
+    // from nested objects get an additional stage that copies related rows into those objects.
     let lookup_stages = relationships
         .iter()
         .map(|(name, relationship)| {
+            let related_rows = RelatedRows::for_relationship(relationship);
+
             // Recursively build pipeline according to relation query
             let lookup_pipeline = pipeline_for_non_foreach(
                 config,
                 &QueryPlan {
-                    query: relationship.query.clone(),
+                    query: query_for_relationship(config, relationship, related_rows)?,
                     collection: relationship.target_collection.clone(),
                     ..query_plan.clone()
                 },
                 QueryLevel::Relationship,
             )?;
 
-            Ok(make_lookup_stage(
-                relationship.target_collection.clone(),
-                &relationship.column_mapping,
-                name.to_owned(),
-                lookup_pipeline,
-                scope.as_ref(),
-            )) as Result<_>
+            let lookup_stage = match related_rows {
+                RelatedRows::PerJoinKey => lookup_for_each_join_key(
+                    relationship,
+                    name.to_owned(),
+                    lookup_pipeline,
+                    scope.as_ref(),
+                ),
+                _ => make_lookup_stage(
+                    relationship.target_collection.clone(),
+                    &relationship.column_mapping,
+                    &relationship.source_path,
+                    name.to_owned(),
+                    lookup_pipeline,
+                    scope.as_ref(),
+                ),
+            };
+
+            let distribute_stage = if relationship.source_path.is_empty() {
+                None
+            } else {
+                Some(distribute_related_rows(relationship, name, related_rows)?)
+            };
+
+            Ok(once(lookup_stage).chain(distribute_stage)) as Result<_>
         })
+        .flatten_ok()
         .try_collect()?;
 
     Ok(lookup_stages)
 }
 
+/// Sorting by an aggregate over related rows requires a `$lookup` stage for each such aggregate.
+/// These stages are separate from the lookups in [pipeline_for_relations] because the aggregate is
+/// computed inside the lookup sub-pipeline. That way only the aggregate value is joined to each
+/// document instead of all of the related rows.
+pub fn lookups_for_order_by_aggregates(
+    config: &MongoConfiguration,
+    query_plan: &QueryPlan,
+) -> Result<Vec<Stage>> {
+    let Some(order_by) = &query_plan.query.order_by else {
+        return Ok(vec![]);
+    };
+    order_by
+        .elements
+        .iter()
+        .filter_map(|element| match &element.target {
+            OrderByTarget::Aggregate { path, aggregate } => Some((path, aggregate)),
+            OrderByTarget::Column { .. } => None,
+        })
+        .map(|(path, aggregate)| lookup_for_order_by_aggregate(config, query_plan, path, aggregate))
+        .try_collect()
+}
+
+/// Produces a `$lookup` stage that writes an array with at most one document to the field named by
+/// [aggregate_sort_key]. The document holds the aggregate value under [SORT_AGGREGATE_VALUE_KEY].
+/// If the path has more than one relationship then rows of the following relationships are
+/// flattened into one set of rows before aggregating.
+fn lookup_for_order_by_aggregate(
+    config: &MongoConfiguration,
+    query_plan: &QueryPlan,
+    path: &[RelationshipName],
+    aggregate: &Aggregate,
+) -> Result<Stage> {
+    let Query {
+        relationships,
+        scope,
+        ..
+    } = &query_plan.query;
+    let Some((first, rest)) = path.split_first() else {
+        return Err(MongoAgentError::NotImplemented(
+            "order by aggregate without a relationship path".into(),
+        ));
+    };
+    let relationship = relationships
+        .get(first)
+        .ok_or_else(|| MongoAgentError::UnspecifiedRelation(first.to_string()))?;
+    let relationship_plan = QueryPlan {
+        query: relationship.query.clone(),
+        collection: relationship.target_collection.clone(),
+        ..query_plan.clone()
+    };
+
+    // Rows of the first relationship are filtered the same way as in the relationship's own
+    // lookup. Lookups for the rest of the path come from the nested relationships of the first
+    // relationship's query.
+    let mut lookup_pipeline = pipeline_for_native_query(config, &relationship_plan)?;
+    lookup_pipeline.append(pipeline_for_relations(config, &relationship_plan)?);
+    if let Some(predicate) = &relationship.query.predicate {
+        lookup_pipeline.push(Stage::Match(make_selector(predicate)?));
+    }
+
+    if !rest.is_empty() {
+        let rows_key = "__rows";
+        lookup_pipeline.append(Pipeline::new(vec![
+            Stage::Project(doc! {
+                rows_key: ColumnRef::from_related_rows(rest).into_aggregate_expression(),
+            }),
+            Stage::Unwind {
+                path: format!("${rows_key}"),
+                include_array_index: None,
+                preserve_null_and_empty_arrays: None,
+            },
+            Stage::Other(doc! { "$replaceWith": format!("${rows_key}") }),
+        ]));
+    }
+
+    lookup_pipeline.append(pipeline_for_aggregates(
+        &[(SORT_AGGREGATE_VALUE_KEY.into(), aggregate.clone())].into(),
+    ));
+
+    Ok(make_lookup_stage(
+        relationship.target_collection.clone(),
+        &relationship.column_mapping,
+        &relationship.source_path,
+        aggregate_sort_key(path, aggregate).into(),
+        lookup_pipeline,
+        scope.as_ref(),
+    ))
+}
+
+/// Field in documents produced by [lookup_for_each_join_key] that holds the result of the
+/// relationship query for one join key
+const PER_JOIN_KEY_RESULT: &str = "__related";
+
+/// Prefix for the field that [distribute_related_rows] adds to nested objects to hold related
+/// rows. The prefix keeps related rows from overwriting fields of the nested object.
+const NESTED_RELATED_ROWS_PREFIX: &str = "__related_rows_";
+
+/// Field of a nested object that holds rows of the given relationship after
+/// [distribute_related_rows] has run.
+pub fn nested_related_rows_field(name: &RelationshipName) -> String {
+    format!("{NESTED_RELATED_ROWS_PREFIX}{name}")
+}
+
+/// Describes how rows from a relationship's `$lookup` stage are matched up with the nested objects
+/// that reference the relationship.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RelatedRows {
+    /// The relationship is not referenced from inside an array, so every nested object gets all
+    /// rows from the lookup.
+    All,
+    /// Rows for all array elements are looked up at once, and each element gets the rows with
+    /// join keys that match that element.
+    FilterByJoinKey,
+    /// The relationship query is evaluated separately for each distinct join key. This is
+    /// necessary when aggregates, groups, or pagination must apply to the rows of each array
+    /// element separately.
+    PerJoinKey,
+}
+
+impl RelatedRows {
+    fn for_relationship(relationship: &Relationship) -> Self {
+        if !is_in_nested_array(&relationship.source_path) {
+            return RelatedRows::All;
+        }
+        let Query {
+            aggregates,
+            groups,
+            limit,
+            offset,
+            ..
+        } = &relationship.query;
+        if aggregates.is_some() || groups.is_some() || limit.is_some() || offset.is_some() {
+            RelatedRows::PerJoinKey
+        } else {
+            RelatedRows::FilterByJoinKey
+        }
+    }
+}
+
+/// Relationships that are referenced from objects in a nested array may be looked up once for all
+/// elements of the array. In that case related rows are matched up with array elements afterward
+/// by comparing join keys, so those keys need to be included in related rows.
+fn query_for_relationship(
+    config: &MongoConfiguration,
+    relationship: &Relationship,
+    related_rows: RelatedRows,
+) -> Result<Query> {
+    if related_rows != RelatedRows::FilterByJoinKey {
+        return Ok(relationship.query.clone());
+    }
+
+    let target_object_type = config.find_collection_object_type(&relationship.target_collection)?;
+    let mut query = relationship.query.clone();
+    let fields = query.fields.get_or_insert_with(Default::default);
+    for (source_column, target_path) in relationship.column_mapping.iter() {
+        let column = target_path.head.clone();
+        let column_type = target_object_type
+            .fields
+            .get(&column)
+            .ok_or_else(|| QueryPlanError::UnknownObjectTypeField {
+                object_type: target_object_type.name.clone(),
+                field_name: column.clone(),
+                path: Default::default(),
+            })?
+            .r#type
+            .clone();
+        fields.insert(
+            join_key_alias(source_column),
+            Field::Column {
+                column,
+                column_type,
+                fields: None,
+            },
+        );
+    }
+    Ok(query)
+}
+
+fn join_key_alias(source_column: &FieldName) -> FieldName {
+    format!("__join_key_{source_column}").into()
+}
+
+fn is_in_nested_array(source_path: &[SourcePathElement]) -> bool {
+    source_path
+        .iter()
+        .any(|elem| matches!(elem, SourcePathElement::ArrayElements))
+}
+
+/// Reference to a source column of a relationship from the top level of a source document.
+fn source_column_ref<'a>(
+    source_path: &'a [SourcePathElement],
+    column: &'a FieldName,
+) -> ColumnRef<'a> {
+    let path = source_path
+        .iter()
+        .filter_map(|elem| match elem {
+            SourcePathElement::Field(field_name) => Some(field_name),
+            SourcePathElement::ArrayElements => None,
+        })
+        .chain([column]);
+    // safety: the path always includes at least `column`
+    ColumnRef::from_field_path(NonEmpty::collect(path).unwrap())
+}
+
+/// Source paths with an array directly inside another array cannot be expressed as a `localField`
+/// for `$lookup` because `localField` paths only descend into arrays of documents.
+fn has_directly_nested_arrays(source_path: &[SourcePathElement]) -> bool {
+    source_path.windows(2).any(|pair| {
+        matches!(
+            pair,
+            [
+                SourcePathElement::ArrayElements,
+                SourcePathElement::ArrayElements
+            ]
+        )
+    })
+}
+
+/// Expression that evaluates to an array of every object found at the given path from the root
+/// of the source document. Arrays are flattened, including arrays nested in other arrays.
+fn nested_objects(source_path: &[SourcePathElement]) -> Bson {
+    nested_objects_helper(ColumnRef::variable("ROOT"), source_path)
+}
+
+fn nested_objects_helper<'a>(value: ColumnRef<'a>, path: &'a [SourcePathElement]) -> Bson {
+    let value_expression = value.clone().into_aggregate_expression().into_bson();
+    match path.split_first() {
+        None => bson!({
+            "$cond": {
+                "if": { "$eq": [{ "$type": value_expression.clone() }, "object"] },
+                "then": [value_expression],
+                "else": [],
+            }
+        }),
+        Some((SourcePathElement::Field(field_name), rest)) => {
+            nested_objects_helper(value.into_nested_field(field_name.as_ref()), rest)
+        }
+        Some((SourcePathElement::ArrayElements, rest)) => {
+            let objects_in_element = nested_objects_helper(ColumnRef::variable("this"), rest);
+            bson!({
+                "$reduce": {
+                    "input": {
+                        "$cond": {
+                            "if": { "$isArray": value_expression.clone() },
+                            "then": value_expression,
+                            "else": [],
+                        }
+                    },
+                    "initialValue": [],
+                    "in": { "$concatArrays": ["$$value", objects_in_element] },
+                }
+            })
+        }
+    }
+}
+
+/// Expression that evaluates to the values of a source column in every object at the source path
+fn nested_source_column_values(source_path: &[SourcePathElement], column: &FieldName) -> Bson {
+    bson!({
+        "$map": {
+            "input": nested_objects(source_path),
+            "as": "object",
+            "in": ColumnRef::variable("object")
+                .into_nested_field(column.as_ref())
+                .into_aggregate_expression(),
+        }
+    })
+}
+
+/// The $lookup stage writes related rows to a top-level field of each source document. When
+/// a relationship is referenced from a nested object this stage copies those rows into the nested
+/// object where the selection for that object expects to find them. If the path to the nested
+/// object passes through an array then each array element gets only the rows with join keys that
+/// match that element.
+fn distribute_related_rows(
+    relationship: &Relationship,
+    name: &RelationshipName,
+    related_rows: RelatedRows,
+) -> Result<Stage> {
+    let (first, rest) = match relationship.source_path.split_first() {
+        Some((SourcePathElement::Field(first), rest)) => (first, rest),
+        _ => {
+            return Err(MongoAgentError::NotImplemented(
+                "relationship source path that does not begin with a field".into(),
+            ))
+        }
+    };
+    let rows_for =
+        |object: ColumnRef<'_>| related_rows_for(relationship, name, related_rows, object);
+    let value = distribute_helper(ColumnRef::from_field(first.as_ref()), rest, name, &rows_for);
+    Ok(Stage::AddFields(doc! { first.to_string(): value }))
+}
+
+fn distribute_helper<'a>(
+    value: ColumnRef<'a>,
+    path: &'a [SourcePathElement],
+    name: &RelationshipName,
+    rows_for: &dyn Fn(ColumnRef<'_>) -> Bson,
+) -> Bson {
+    let value_expression = value.clone().into_aggregate_expression().into_bson();
+    let if_object = |then: Bson| {
+        bson!({
+            "$cond": {
+                "if": { "$eq": [{ "$type": value_expression.clone() }, "object"] },
+                "then": then,
+                "else": value_expression.clone(),
+            }
+        })
+    };
+    match path.split_first() {
+        None => if_object(bson!({
+            "$mergeObjects": [value_expression.clone(), { nested_related_rows_field(name): rows_for(value) }]
+        })),
+        Some((SourcePathElement::Field(field_name), rest)) => {
+            let nested_value = distribute_helper(
+                value.into_nested_field(field_name.as_ref()),
+                rest,
+                name,
+                rows_for,
+            );
+            if_object(bson!({
+                "$mergeObjects": [value_expression.clone(), { field_name.to_string(): nested_value }]
+            }))
+        }
+        Some((SourcePathElement::ArrayElements, rest)) => {
+            let element = distribute_helper(ColumnRef::variable("this"), rest, name, rows_for);
+            bson!({
+                "$cond": {
+                    "if": { "$isArray": value_expression.clone() },
+                    "then": { "$map": { "input": value_expression.clone(), "in": element } },
+                    "else": value_expression,
+                }
+            })
+        }
+    }
+}
+
+/// Related rows for one nested object. Outside of arrays the rows from the $lookup stage are
+/// exactly the rows for the single nested object in each document.
+fn related_rows_for(
+    relationship: &Relationship,
+    name: &RelationshipName,
+    related_rows: RelatedRows,
+    object: ColumnRef<'_>,
+) -> Bson {
+    let all_rows = ColumnRef::from_relationship(name)
+        .into_aggregate_expression()
+        .into_bson();
+    match related_rows {
+        RelatedRows::All => all_rows,
+        RelatedRows::FilterByJoinKey => {
+            let matchers: Vec<Bson> = relationship
+                .column_mapping
+                .iter()
+                .map(|(source_column, target_path)| {
+                    let alias = join_key_alias(source_column);
+                    let join_key = target_path.tail.iter().fold(
+                        ColumnRef::variable("related").into_nested_field(alias.as_ref()),
+                        |column_ref, field_name| column_ref.into_nested_field(field_name.as_ref()),
+                    );
+                    bson!({ "$eq": [
+                        join_key.into_aggregate_expression(),
+                        object.clone().into_nested_field(source_column.as_ref()).into_aggregate_expression(),
+                    ] })
+                })
+                .collect();
+
+            bson!({
+                "$filter": {
+                    "input": all_rows,
+                    "as": "related",
+                    "cond": { "$and": matchers },
+                }
+            })
+        }
+        RelatedRows::PerJoinKey => {
+            let matchers: Vec<Bson> = relationship
+                .column_mapping
+                .keys()
+                .map(|source_column| {
+                    bson!({ "$eq": [
+                        ColumnRef::variable("result").into_nested_field(source_column.as_ref()).into_aggregate_expression(),
+                        object.clone().into_nested_field(source_column.as_ref()).into_aggregate_expression(),
+                    ] })
+                })
+                .collect();
+            let result_for_object = bson!({
+                "$first": {
+                    "$filter": {
+                        "input": all_rows,
+                        "as": "result",
+                        "cond": { "$and": matchers },
+                    }
+                }
+            });
+            bson!({
+                "$ifNull": [
+                    { "$getField": { "field": PER_JOIN_KEY_RESULT, "input": result_for_object } },
+                    [],
+                ]
+            })
+        }
+    }
+}
+
+/// Lookup for a relationship whose query must be evaluated separately for each element of
+/// a nested array. The lookup produces one document for each distinct combination of join key
+/// values found in the array. A nested `$lookup` runs the relationship query for each of those
+/// documents, and stores the result in the [PER_JOIN_KEY_RESULT] field.
+fn lookup_for_each_join_key(
+    relationship: &Relationship,
+    r#as: ndc_models::RelationshipName,
+    lookup_pipeline: Pipeline,
+    scope: Option<&Scope>,
+) -> Stage {
+    let join_key: Document = relationship
+        .column_mapping
+        .keys()
+        .map(|source_column| {
+            (
+                source_column.to_string(),
+                ColumnRef::variable("object")
+                    .into_nested_field(source_column.as_ref())
+                    .into_aggregate_expression()
+                    .into_bson(),
+            )
+        })
+        .collect();
+    let join_keys = bson!({
+        "$setUnion": [{
+            "$map": {
+                "input": nested_objects(&relationship.source_path),
+                "as": "object",
+                "in": escape_invalid_keys(join_key),
+            }
+        }]
+    });
+
+    let mut let_bindings = doc! { "join_keys": join_keys };
+    if let Some(scope) = scope {
+        let_bindings.insert(name_from_scope(scope), "$$ROOT");
+    }
+
+    // Variables bound by the outer lookup, including the scope variable, are visible in the
+    // nested lookup's pipeline so they do not need to be bound again.
+    let lookup_per_join_key = make_lookup_stage(
+        relationship.target_collection.clone(),
+        &relationship.column_mapping,
+        &[],
+        PER_JOIN_KEY_RESULT.into(),
+        lookup_pipeline,
+        None,
+    );
+
+    Stage::Lookup {
+        from: None,
+        local_field: None,
+        foreign_field: None,
+        r#let: Some(let_bindings),
+        pipeline: Some(Pipeline::new(vec![
+            Stage::Other(doc! { "$documents": "$$join_keys" }),
+            lookup_per_join_key,
+        ])),
+        r#as: r#as.to_string(),
+    }
+}
+
 fn make_lookup_stage(
     from: ndc_models::CollectionName,
     column_mapping: &BTreeMap<ndc_models::FieldName, NonEmpty<ndc_models::FieldName>>,
+    source_path: &[SourcePathElement],
     r#as: ndc_models::RelationshipName,
     lookup_pipeline: Pipeline,
     scope: Option<&Scope>,
@@ -76,8 +573,9 @@ fn make_lookup_stage(
     let source_selector = single_mapping.map(|(field_name, _)| field_name);
     let target_selector = single_mapping.map(|(_, target_path)| target_path);
 
-    let source_key =
-        source_selector.and_then(|f| ColumnRef::from_field(f.as_ref()).into_match_key());
+    let source_key = source_selector
+        .filter(|_| !has_directly_nested_arrays(source_path))
+        .and_then(|f| source_column_ref(source_path, f).into_match_key());
     let target_key =
         target_selector.and_then(|path| ColumnRef::from_field_path(path.as_ref()).into_match_key());
 
@@ -91,7 +589,14 @@ fn make_lookup_stage(
             scope,
         ),
 
-        _ => lookup_with_uncorrelated_subquery(from, column_mapping, r#as, lookup_pipeline, scope),
+        _ => lookup_with_uncorrelated_subquery(
+            from,
+            column_mapping,
+            source_path,
+            r#as,
+            lookup_pipeline,
+            scope,
+        ),
     }
 }
 
@@ -129,19 +634,23 @@ fn lookup_with_concise_correlated_subquery(
 fn lookup_with_uncorrelated_subquery(
     from: ndc_models::CollectionName,
     column_mapping: &BTreeMap<ndc_models::FieldName, NonEmpty<ndc_models::FieldName>>,
+    source_path: &[SourcePathElement],
     r#as: ndc_models::RelationshipName,
     lookup_pipeline: Pipeline,
     scope: Option<&Scope>,
 ) -> Stage {
+    let in_nested_array = is_in_nested_array(source_path);
     let mut let_bindings: Document = column_mapping
         .keys()
         .map(|local_field| {
-            (
-                variable(local_field.as_str()),
-                ColumnRef::from_field(local_field.as_ref())
+            let local_value = if in_nested_array {
+                nested_source_column_values(source_path, local_field)
+            } else {
+                source_column_ref(source_path, local_field)
                     .into_aggregate_expression()
-                    .into_bson(),
-            )
+                    .into_bson()
+            };
+            (variable(local_field.as_str()), local_value)
         })
         .collect();
 
@@ -159,10 +668,16 @@ fn lookup_with_uncorrelated_subquery(
     let matchers: Vec<Document> = column_pairs
         .into_iter()
         .map(|(local_field, remote_field_path)| {
-            doc! { "$eq": [
-                ColumnRef::variable(variable(local_field.as_str())).into_aggregate_expression(),
-                ColumnRef::from_field_path(remote_field_path.as_ref()).into_aggregate_expression(),
-            ] }
+            let local_value =
+                ColumnRef::variable(variable(local_field.as_str())).into_aggregate_expression();
+            let remote_value =
+                ColumnRef::from_field_path(remote_field_path.as_ref()).into_aggregate_expression();
+            if in_nested_array {
+                // Local values are collected from every object in the nested arrays
+                doc! { "$in": [remote_value, local_value] }
+            } else {
+                doc! { "$eq": [local_value, remote_value] }
+            }
         })
         .collect();
 
@@ -187,16 +702,18 @@ fn lookup_with_uncorrelated_subquery(
 #[cfg(test)]
 mod tests {
     use configuration::Configuration;
-    use mongodb::bson::{bson, Bson};
+    use mongodb::bson::{self, bson, Bson};
     use ndc_models::{FieldName, QueryResponse};
+    use ndc_query_plan::plan_for_query_request;
     use ndc_test_helpers::{
-        binop, collection, exists, field, named_type, object, object_type, query, query_request,
-        relation_field, relationship, row_set, star_count_aggregate, target, value,
+        array, array_of, binop, collection, exists, field, named_type, object, object_type,
+        path_element, query, query_request, relation_field, relationship, row_set,
+        star_count_aggregate, target, value,
     };
     use pretty_assertions::assert_eq;
     use serde_json::json;
 
-    use super::super::execute_query_request;
+    use super::super::{execute_query_request, pipeline::pipeline_for_query_request};
     use crate::{
         mongo_query_plan::MongoConfiguration,
         mongodb::test_helpers::mock_collection_aggregate_response_for_pipeline,
@@ -378,6 +895,640 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn looks_up_a_relation_from_objects_in_a_nested_array() -> Result<(), anyhow::Error> {
+        let query_request = query_request()
+            .collection("classes")
+            .query(query().fields([
+                field!("class_title" => "title"),
+                field!("enrollments" => "enrollments", array!(object!([
+                    field!("grade"),
+                    relation_field!("student" => "enrollment_student", query().fields([
+                        field!("student_name" => "name")
+                    ])),
+                ]))),
+            ]))
+            .relationships([(
+                "enrollment_student",
+                relationship("students", [("student_id", &["_id"])]).object_type(),
+            )])
+            .into();
+
+        let expected_response = row_set()
+            .row([
+                ("class_title", json!("MongoDB 101")),
+                (
+                    "enrollments",
+                    json!([
+                        { "grade": "A", "student": { "rows": [{ "student_name": "Alice" }] } },
+                        { "grade": "B", "student": { "rows": [{ "student_name": "Bob" }] } },
+                    ]),
+                ),
+            ])
+            .into_response();
+
+        let expected_pipeline = bson!([
+            {
+                "$lookup": {
+                    "from": "students",
+                    "localField": "enrollments.student_id",
+                    "foreignField": "_id",
+                    "let": {
+                        "scope_root": "$$ROOT",
+                    },
+                    "pipeline": [
+                        {
+                            "$replaceWith": {
+                                "student_name": { "$ifNull": ["$name", null] },
+                                "__join_key_student_id": { "$ifNull": ["$_id", null] },
+                            },
+                        }
+                    ],
+                    "as": "enrollment_student",
+                },
+            },
+            {
+                "$addFields": {
+                    "enrollments": {
+                        "$cond": {
+                            "if": { "$isArray": "$enrollments" },
+                            "then": {
+                                "$map": {
+                                    "input": "$enrollments",
+                                    "in": {
+                                        "$cond": {
+                                            "if": { "$eq": [{ "$type": "$$this" }, "object"] },
+                                            "then": {
+                                                "$mergeObjects": ["$$this", {
+                                                    "__related_rows_enrollment_student": {
+                                                        "$filter": {
+                                                            "input": "$enrollment_student",
+                                                            "as": "related",
+                                                            "cond": {
+                                                                "$and": [{
+                                                                    "$eq": [
+                                                                        "$$related.__join_key_student_id",
+                                                                        "$$this.student_id",
+                                                                    ]
+                                                                }]
+                                                            },
+                                                        }
+                                                    }
+                                                }]
+                                            },
+                                            "else": "$$this",
+                                        }
+                                    },
+                                }
+                            },
+                            "else": "$enrollments",
+                        }
+                    }
+                }
+            },
+            {
+                "$replaceWith": {
+                    "class_title": { "$ifNull": ["$title", null] },
+                    "enrollments": {
+                        "$cond": {
+                            "if": "$enrollments",
+                            "then": {
+                                "$map": {
+                                    "input": "$enrollments",
+                                    "in": {
+                                        "grade": { "$ifNull": ["$$this.grade", null] },
+                                        "student": {
+                                            "rows": {
+                                                "$map": {
+                                                    "input": "$$this.__related_rows_enrollment_student",
+                                                    "in": {
+                                                        "student_name": "$$this.student_name"
+                                                    }
+                                                }
+                                            }
+                                        },
+                                    }
+                                }
+                            },
+                            "else": null,
+                        }
+                    },
+                },
+            },
+        ]);
+
+        let db = mock_collection_aggregate_response_for_pipeline(
+            "classes",
+            expected_pipeline,
+            bson!([{
+                "class_title": "MongoDB 101",
+                "enrollments": [
+                    { "grade": "A", "student": { "rows": [{ "student_name": "Alice" }] } },
+                    { "grade": "B", "student": { "rows": [{ "student_name": "Bob" }] } },
+                ],
+            }]),
+        );
+
+        let result = execute_query_request(db, &students_config(), query_request).await?;
+        assert_eq!(expected_response, result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn looks_up_a_relation_from_objects_in_doubly_nested_arrays() -> Result<(), anyhow::Error> {
+        let config = students_config();
+        let query_request = query_request()
+            .collection("classes")
+            .query(
+                query().fields([field!("sections" => "sections", array!(object!([
+                    field!("enrollments" => "enrollments", array!(object!([
+                        relation_field!("student" => "enrollment_student", query().fields([
+                            field!("student_name" => "name")
+                        ])),
+                    ]))),
+                ])))]),
+            )
+            .relationships([(
+                "enrollment_student",
+                relationship("students", [("student_id", &["_id"]), ("year", &["year"])])
+                    .object_type(),
+            )])
+            .into();
+        let query_plan = plan_for_query_request(&config, query_request)?;
+        let pipeline = bson::to_bson(&pipeline_for_query_request(&config, &query_plan)?)?;
+
+        // Local values are collected from enrollments in every section
+        let enrollments = bson!({
+            "$reduce": {
+                "input": {
+                    "$cond": {
+                        "if": { "$isArray": "$$ROOT.sections" },
+                        "then": "$$ROOT.sections",
+                        "else": [],
+                    }
+                },
+                "initialValue": [],
+                "in": { "$concatArrays": ["$$value", {
+                    "$reduce": {
+                        "input": {
+                            "$cond": {
+                                "if": { "$isArray": "$$this.enrollments" },
+                                "then": "$$this.enrollments",
+                                "else": [],
+                            }
+                        },
+                        "initialValue": [],
+                        "in": { "$concatArrays": ["$$value", {
+                            "$cond": {
+                                "if": { "$eq": [{ "$type": "$$this" }, "object"] },
+                                "then": ["$$this"],
+                                "else": [],
+                            }
+                        }] },
+                    }
+                }] },
+            }
+        });
+        let expected_lookup = bson!({
+            "$lookup": {
+                "from": "students",
+                "let": {
+                    "student_id": {
+                        "$map": { "input": enrollments.clone(), "as": "object", "in": "$$object.student_id" }
+                    },
+                    "year": {
+                        "$map": { "input": enrollments, "as": "object", "in": "$$object.year" }
+                    },
+                    "scope_root": "$$ROOT",
+                },
+                "pipeline": [
+                    {
+                        "$match": { "$expr": { "$and": [
+                            { "$in": ["$_id", "$$student_id"] },
+                            { "$in": ["$year", "$$year"] },
+                        ] } },
+                    },
+                    {
+                        "$replaceWith": {
+                            "student_name": { "$ifNull": ["$name", null] },
+                            "__join_key_student_id": { "$ifNull": ["$_id", null] },
+                            "__join_key_year": { "$ifNull": ["$year", null] },
+                        },
+                    },
+                ],
+                "as": "enrollment_student",
+            },
+        });
+
+        assert_eq!(pipeline.as_array().unwrap()[0], expected_lookup);
+        Ok(())
+    }
+
+    #[test]
+    fn evaluates_relationship_aggregates_separately_for_each_nested_array_element(
+    ) -> Result<(), anyhow::Error> {
+        let config = students_config();
+        let query_request = query_request()
+            .collection("classes")
+            .query(
+                query().fields([field!("enrollments" => "enrollments", array!(object!([
+                    relation_field!("student" => "enrollment_student", query()
+                        .aggregates([star_count_aggregate!("count")])
+                        .limit(1)),
+                ])))]),
+            )
+            .relationships([(
+                "enrollment_student",
+                relationship("students", [("student_id", &["_id"])]).object_type(),
+            )])
+            .into();
+        let query_plan = plan_for_query_request(&config, query_request)?;
+        let pipeline = bson::to_bson(&pipeline_for_query_request(&config, &query_plan)?)?;
+        let stages = pipeline.as_array().unwrap();
+
+        let lookup = stages[0].as_document().unwrap().get_document("$lookup")?;
+        assert!(!lookup.contains_key("from"));
+        assert_eq!(lookup.get_str("as")?, "enrollment_student");
+        assert_eq!(
+            lookup.get_document("let")?.get("join_keys"),
+            Some(&bson!({
+                "$setUnion": [{
+                    "$map": {
+                        "input": {
+                            "$reduce": {
+                                "input": {
+                                    "$cond": {
+                                        "if": { "$isArray": "$$ROOT.enrollments" },
+                                        "then": "$$ROOT.enrollments",
+                                        "else": [],
+                                    }
+                                },
+                                "initialValue": [],
+                                "in": { "$concatArrays": ["$$value", {
+                                    "$cond": {
+                                        "if": { "$eq": [{ "$type": "$$this" }, "object"] },
+                                        "then": ["$$this"],
+                                        "else": [],
+                                    }
+                                }] },
+                            }
+                        },
+                        "as": "object",
+                        "in": { "student_id": "$$object.student_id" },
+                    }
+                }]
+            }))
+        );
+
+        // The relationship query, including the aggregate and limit, runs once for each join key
+        let lookup_pipeline = lookup.get_array("pipeline")?;
+        assert_eq!(lookup_pipeline[0], bson!({ "$documents": "$$join_keys" }));
+        let lookup_per_join_key = lookup_pipeline[1]
+            .as_document()
+            .unwrap()
+            .get_document("$lookup")?;
+        assert_eq!(lookup_per_join_key.get_str("from")?, "students");
+        assert_eq!(lookup_per_join_key.get_str("localField")?, "student_id");
+        assert_eq!(lookup_per_join_key.get_str("foreignField")?, "_id");
+        assert_eq!(lookup_per_join_key.get_str("as")?, "__related");
+        assert!(lookup_per_join_key
+            .get_array("pipeline")?
+            .contains(&bson!({ "$limit": 1 })));
+
+        // Each enrollment gets the result for its own join key
+        assert_eq!(
+            stages[1],
+            bson!({
+                "$addFields": {
+                    "enrollments": {
+                        "$cond": {
+                            "if": { "$isArray": "$enrollments" },
+                            "then": {
+                                "$map": {
+                                    "input": "$enrollments",
+                                    "in": {
+                                        "$cond": {
+                                            "if": { "$eq": [{ "$type": "$$this" }, "object"] },
+                                            "then": {
+                                                "$mergeObjects": ["$$this", {
+                                                    "__related_rows_enrollment_student": {
+                                                        "$ifNull": [
+                                                            {
+                                                                "$getField": {
+                                                                    "field": "__related",
+                                                                    "input": {
+                                                                        "$first": {
+                                                                            "$filter": {
+                                                                                "input": "$enrollment_student",
+                                                                                "as": "result",
+                                                                                "cond": {
+                                                                                    "$and": [{
+                                                                                        "$eq": [
+                                                                                            "$$result.student_id",
+                                                                                            "$$this.student_id",
+                                                                                        ]
+                                                                                    }]
+                                                                                },
+                                                                            }
+                                                                        }
+                                                                    },
+                                                                }
+                                                            },
+                                                            [],
+                                                        ]
+                                                    }
+                                                }]
+                                            },
+                                            "else": "$$this",
+                                        }
+                                    },
+                                }
+                            },
+                            "else": "$enrollments",
+                        }
+                    }
+                }
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn filters_by_relationship_from_nested_object() -> Result<(), anyhow::Error> {
+        let config = students_config();
+        let query_request = query_request()
+            .collection("classes")
+            .query(
+                query()
+                    .fields([field!("class_title" => "title")])
+                    .predicate(exists(
+                        ndc_models::ExistsInCollection::Related {
+                            field_path: Some(vec!["advisor".into()]),
+                            relationship: "advisor_student".into(),
+                            arguments: Default::default(),
+                        },
+                        binop("Equal", target!("name"), value!("Alice")),
+                    )),
+            )
+            .relationships([(
+                "advisor_student",
+                relationship("students", [("student_id", &["_id"])]).object_type(),
+            )])
+            .into();
+        let query_plan = plan_for_query_request(&config, query_request)?;
+        let pipeline = bson::to_bson(&pipeline_for_query_request(&config, &query_plan)?)?;
+        let stages = pipeline.as_array().unwrap();
+
+        let lookup = stages[0].as_document().unwrap().get_document("$lookup")?;
+        assert_eq!(lookup.get_str("from")?, "students");
+        assert_eq!(lookup.get_str("localField")?, "advisor.student_id");
+        assert_eq!(lookup.get_str("foreignField")?, "_id");
+        let relationship_key = lookup.get_str("as")?;
+        assert!(stages.iter().any(|stage| stage
+            .as_document()
+            .and_then(|stage| stage.get_document("$match").ok())
+            .is_some_and(|filter| filter.contains_key(relationship_key))));
+        Ok(())
+    }
+
+    #[test]
+    fn sorts_by_aggregate_computed_in_lookup_over_two_relationships() -> Result<(), anyhow::Error> {
+        let config = students_config();
+        let query_request = query_request()
+            .collection("classes")
+            .query(
+                query()
+                    .fields([field!("class_title" => "title")])
+                    .order_by([ndc_models::OrderByElement {
+                        order_direction: ndc_models::OrderDirection::Desc,
+                        target: ndc_models::OrderByTarget::Aggregate {
+                            path: vec![
+                                path_element("class_students").into(),
+                                path_element("student_assignments").into(),
+                            ],
+                            aggregate: ndc_models::Aggregate::StarCount {},
+                        },
+                    }]),
+            )
+            .relationships([
+                (
+                    "class_students",
+                    relationship("students", [("_id", &["classId"])]),
+                ),
+                (
+                    "student_assignments",
+                    relationship("assignments", [("_id", &["student_id"])]),
+                ),
+            ])
+            .into();
+        let query_plan = plan_for_query_request(&config, query_request)?;
+        let pipeline = bson::to_bson(&pipeline_for_query_request(&config, &query_plan)?)?;
+        let stages = pipeline.as_array().unwrap();
+
+        let sort_key = "__sort_key__class_students_student_assignments_star_count";
+        let lookup = stages
+            .iter()
+            .filter_map(|stage| stage.as_document()?.get_document("$lookup").ok())
+            .find(|lookup| lookup.get_str("as").is_ok_and(|name| name == sort_key))
+            .expect("a lookup stage for the sort aggregate");
+        assert_eq!(lookup.get_str("from")?, "students");
+        assert_eq!(lookup.get_str("localField")?, "_id");
+        assert_eq!(lookup.get_str("foreignField")?, "classId");
+
+        // The lookup for the second relationship is nested in the lookup for the first. Rows from
+        // the second relationship are flattened, and then aggregated.
+        let lookup_pipeline = lookup.get_array("pipeline")?;
+        let nested_lookup = lookup_pipeline[0]
+            .as_document()
+            .unwrap()
+            .get_document("$lookup")?;
+        assert_eq!(nested_lookup.get_str("from")?, "assignments");
+        assert_eq!(
+            Bson::Array(lookup_pipeline[1..].to_vec()),
+            bson!([
+                { "$project": { "__rows": "$student_assignments" } },
+                { "$unwind": { "path": "$__rows" } },
+                { "$replaceWith": "$__rows" },
+                { "$group": { "_id": null, "value": { "$sum": 1 } } },
+                { "$replaceWith": { "value": { "$ifNull": ["$value", 0] } } },
+            ])
+        );
+
+        // Only the aggregate value is used as the sort key
+        let sort_stage_index = stages
+            .iter()
+            .position(|stage| stage.as_document().unwrap().contains_key("$sort"))
+            .expect("a sort stage");
+        assert_eq!(
+            stages[sort_stage_index - 1],
+            bson!({
+                "$addFields": {
+                    sort_key: {
+                        "$ifNull": [{ "$first": format!("${sort_key}.value") }, 0]
+                    }
+                }
+            })
+        );
+        assert_eq!(
+            stages[sort_stage_index],
+            bson!({ "$sort": { sort_key: -1 } })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn looks_up_relationship_before_filtering_by_aggregate_over_related_rows(
+    ) -> Result<(), anyhow::Error> {
+        let config = students_config();
+        let query_request = query_request()
+            .collection("classes")
+            .query(
+                query()
+                    .fields([field!("class_title" => "title")])
+                    .predicate(binop(
+                        "_gt",
+                        ndc_models::ComparisonTarget::Aggregate {
+                            path: vec![path_element("class_students").into()],
+                            aggregate: ndc_models::Aggregate::StarCount {},
+                        },
+                        value!(2),
+                    )),
+            )
+            .relationships([(
+                "class_students",
+                relationship("students", [("_id", &["classId"])]),
+            )])
+            .into();
+        let query_plan = plan_for_query_request(&config, query_request)?;
+        let pipeline = bson::to_bson(&pipeline_for_query_request(&config, &query_plan)?)?;
+        let stages = pipeline.as_array().unwrap();
+
+        let lookup_index = stages
+            .iter()
+            .position(|stage| stage.as_document().unwrap().contains_key("$lookup"))
+            .expect("a lookup stage");
+        let match_index = stages
+            .iter()
+            .position(|stage| stage.as_document().unwrap().contains_key("$match"))
+            .expect("a match stage");
+        assert!(lookup_index < match_index);
+
+        let lookup = stages[lookup_index]
+            .as_document()
+            .unwrap()
+            .get_document("$lookup")?;
+        assert_eq!(lookup.get_str("from")?, "students");
+        assert_eq!(lookup.get_str("as")?, "class_students");
+        assert_eq!(
+            stages[match_index],
+            bson!({
+                "$match": {
+                    "$expr": {
+                        "$gt": [
+                            {
+                                "$let": {
+                                    "vars": { "rows": "$class_students" },
+                                    "in": { "$ifNull": [{ "$size": "$$rows" }, 0] },
+                                }
+                            },
+                            { "$literal": 2 },
+                        ]
+                    }
+                }
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn filters_by_aggregate_over_two_relationships_after_nested_lookups(
+    ) -> Result<(), anyhow::Error> {
+        let config = students_config();
+        let query_request = query_request()
+            .collection("classes")
+            .query(
+                query()
+                    .fields([field!("class_title" => "title")])
+                    .predicate(binop(
+                        "_gt",
+                        ndc_models::ComparisonTarget::Aggregate {
+                            path: vec![
+                                path_element("class_students").into(),
+                                path_element("student_assignments").into(),
+                            ],
+                            aggregate: ndc_models::Aggregate::StarCount {},
+                        },
+                        value!(2),
+                    )),
+            )
+            .relationships([
+                (
+                    "class_students",
+                    relationship("students", [("_id", &["classId"])]),
+                ),
+                (
+                    "student_assignments",
+                    relationship("assignments", [("_id", &["student_id"])]),
+                ),
+            ])
+            .into();
+        let query_plan = plan_for_query_request(&config, query_request)?;
+        let pipeline = bson::to_bson(&pipeline_for_query_request(&config, &query_plan)?)?;
+        let stages = pipeline.as_array().unwrap();
+
+        // The lookup for the second relationship is nested in the lookup for the first, and rows
+        // of the second relationship are forwarded so that the filter can reach them.
+        let lookup = stages[0].as_document().unwrap().get_document("$lookup")?;
+        assert_eq!(lookup.get_str("from")?, "students");
+        assert_eq!(lookup.get_str("as")?, "class_students");
+        let lookup_pipeline = lookup.get_array("pipeline")?;
+        let nested_lookup = lookup_pipeline[0]
+            .as_document()
+            .unwrap()
+            .get_document("$lookup")?;
+        assert_eq!(nested_lookup.get_str("from")?, "assignments");
+        assert_eq!(nested_lookup.get_str("as")?, "student_assignments");
+        assert!(lookup_pipeline.iter().any(|stage| stage
+            .as_document()
+            .and_then(|stage| stage.get_document("$replaceWith").ok())
+            .is_some_and(|selection| selection.contains_key("student_assignments"))));
+
+        // Rows from the second relationship are flattened, and then aggregated
+        assert_eq!(
+            stages[1],
+            bson!({
+                "$match": {
+                    "$expr": {
+                        "$gt": [
+                            {
+                                "$let": {
+                                    "vars": {
+                                        "rows": {
+                                            "$reduce": {
+                                                "input": "$class_students",
+                                                "initialValue": [],
+                                                "in": {
+                                                    "$concatArrays": [
+                                                        "$$value",
+                                                        { "$ifNull": ["$$this.student_assignments", []] },
+                                                    ]
+                                                },
+                                            }
+                                        }
+                                    },
+                                    "in": { "$ifNull": [{ "$size": "$$rows" }, 0] },
+                                }
+                            },
+                            { "$literal": 2 },
+                        ]
+                    }
+                }
+            })
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn looks_up_a_relation_with_multiple_column_mappings() -> Result<(), anyhow::Error> {
         let query_request = query_request()
@@ -1026,8 +2177,27 @@ mod tests {
                         ("_id", named_type("ObjectId")),
                         ("title", named_type("String")),
                         ("year", named_type("Int")),
+                        ("enrollments", array_of(named_type("enrollment"))),
+                        ("sections", array_of(named_type("section"))),
+                        ("advisor", named_type("advisor")),
                     ]),
                 ),
+                (
+                    "advisor".into(),
+                    object_type([("student_id", named_type("ObjectId"))]),
+                ),
+                (
+                    "enrollment".into(),
+                    object_type([
+                        ("student_id", named_type("ObjectId")),
+                        ("grade", named_type("String")),
+                        ("year", named_type("Int")),
+                    ]),
+                ),
+                (
+                    "section".into(),
+                    object_type([("enrollments", array_of(named_type("enrollment")))]),
+                ),
                 (
                     "students".into(),
                     object_type([
@@ -1043,6 +2213,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         })
@@ -1062,6 +2233,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         })
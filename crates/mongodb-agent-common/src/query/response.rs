@@ -15,8 +15,8 @@ use crate::{
         ROW_SET_ROWS_KEY,
     },
     mongo_query_plan::{
-        Aggregate, Dimension, Field, Grouping, NestedArray, NestedField, NestedObject, ObjectField,
-        ObjectType, Query, QueryPlan, Type,
+        Aggregate, Dimension, Field, Grouping, NestedArray, NestedCollection, NestedField,
+        NestedObject, ObjectField, ObjectType, Query, QueryPlan, Type,
     },
     query::{
         is_response_faceted::ResponseFacets,
@@ -472,6 +472,13 @@ pub fn type_for_nested_field(
                 t
             }
         }
+        // A null or missing array is selected as an empty row set so the row set is not nullable
+        ndc_query_plan::NestedField::Collection(NestedCollection { query }) => type_for_row_set(
+            path,
+            &query.aggregates,
+            &query.fields,
+            &query.groups,
+        )?,
     };
     Ok(field_type)
 }
@@ -694,6 +701,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         });
@@ -747,6 +755,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         });
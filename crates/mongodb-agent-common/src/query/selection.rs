@@ -9,12 +9,20 @@ use crate::{
         GROUP_DIMENSIONS_KEY, ROW_SET_AGGREGATES_KEY, ROW_SET_GROUPS_KEY, ROW_SET_ROWS_KEY,
     },
     interface_types::MongoAgentError,
-    mongo_query_plan::{Aggregate, Field, Grouping, NestedArray, NestedField, NestedObject},
+    mongo_query_plan::{
+        Aggregate, Field, Grouping, NestedArray, NestedCollection, NestedField, NestedObject, Query,
+    },
     mongodb::sanitize::is_name_safe,
     query::column_ref::ColumnRef,
 };
 
-use super::{aggregates::replace_missing_aggregate_value, is_response_faceted::ResponseFacets};
+use super::{
+    aggregates::{aggregate_expression_for_array, replace_missing_aggregate_value},
+    is_response_faceted::ResponseFacets,
+    make_selector::{make_aggregation_expression, AggregationExpression},
+    make_sort::make_sort_array_expression,
+    relations::nested_related_rows_field,
+};
 
 /// Creates a document to use in a $replaceWith stage to limit query results to the specific fields
 /// requested. Assumes that only fields are requested.
@@ -80,6 +88,11 @@ fn selection_for_field(
                 })),
             ..
         } => selection_for_array(nested_column_reference(parent, column), nested_field, 0),
+        Field::Column {
+            column,
+            fields: Some(NestedField::Collection(NestedCollection { query })),
+            ..
+        } => selection_for_nested_collection(nested_column_reference(parent, column), query),
         Field::Relationship {
             relationship,
             aggregates,
@@ -100,7 +113,7 @@ fn selection_for_field(
             // to requested fields, omitting fields of the relationship that were selected for
             // filtering and sorting.
             fn field_selection(fields: &IndexMap<FieldName, Field>) -> Document {
-                fields
+                let selection = fields
                     .iter()
                     .map(|(field_name, _)| {
                         (
@@ -111,7 +124,8 @@ fn selection_for_field(
                                 .into_bson(),
                         )
                     })
-                    .collect()
+                    .collect();
+                escape_invalid_keys(selection)
             }
 
             fn aggregates_selection(
@@ -145,12 +159,17 @@ fn selection_for_field(
                         .into_nested_field(GROUP_DIMENSIONS_KEY)
                         .into_aggregate_expression(),
                 );
-                selection
+                escape_invalid_keys(selection)
             }
 
             // Field of the incoming pipeline document that contains data fetched for the
-            // relationship.
-            let relationship_field = ColumnRef::from_field(relationship.as_ref());
+            // relationship. If the relationship is referenced from a nested object then rows have
+            // been copied into that object so the reference is relative to the parent.
+            let nested_rows_field = nested_related_rows_field(relationship);
+            let relationship_field = match parent {
+                Some(parent) => parent.into_nested_field(&nested_rows_field),
+                None => ColumnRef::from_relationship(relationship),
+            };
 
             let doc = match ResponseFacets::from_parameters(
                 aggregates.as_ref(),
@@ -167,12 +186,12 @@ fn selection_for_field(
                     if let Some(aggregates) = aggregates {
                         new_row_set.insert(
                             ROW_SET_AGGREGATES_KEY,
-                            aggregates_selection(
+                            escape_invalid_keys(aggregates_selection(
                                 ColumnRef::variable("row_set")
                                     .into_nested_field(ROW_SET_AGGREGATES_KEY),
                                 aggregates,
                                 false,
-                            ),
+                            )),
                         );
                     }
 
@@ -211,7 +230,7 @@ fn selection_for_field(
                     ROW_SET_AGGREGATES_KEY: {
                         "$let": {
                             "vars": { "aggregates": { "$first": relationship_field.into_aggregate_expression() } },
-                            "in": aggregates_selection(ColumnRef::variable("aggregates"), aggregates, true),
+                            "in": escape_invalid_keys(aggregates_selection(ColumnRef::variable("aggregates"), aggregates, true)),
                         }
                     }
                 },
@@ -255,9 +274,114 @@ fn selection_for_array(
         NestedField::Array(NestedArray {
             fields: nested_field,
         }) => selection_for_array(parent, nested_field, array_nesting_level + 1),
+        NestedField::Collection(NestedCollection { query }) => {
+            let mut nested_selection =
+                selection_for_nested_collection(ColumnRef::variable("this"), query)?;
+            for _ in 0..array_nesting_level {
+                nested_selection = doc! {"$map": {"input": "$$this", "in": nested_selection}}.into()
+            }
+            let map_expression = doc! {"$map": {"input": parent.clone().into_aggregate_expression(), "in": nested_selection}};
+            Ok(doc! {"$cond": {"if": parent.into_aggregate_expression(), "then": map_expression, "else": Bson::Null}}.into())
+        }
     }
 }
 
+/// Rows of a nested collection are elements of an array field, so instead of pipeline stages we
+/// use array expression operators to filter, sort, paginate, and aggregate. Produces a row set
+/// document in the same shape as a query response.
+fn selection_for_nested_collection(
+    array_ref: ColumnRef<'_>,
+    query: &Query,
+) -> Result<Bson, MongoAgentError> {
+    let Query {
+        aggregates,
+        fields,
+        groups,
+        limit,
+        offset,
+        order_by,
+        predicate,
+        relationships,
+        ..
+    } = query;
+
+    if !relationships.is_empty() {
+        return Err(MongoAgentError::NotImplemented(
+            "relationships in nested collection queries".into(),
+        ));
+    }
+    if groups.is_some() {
+        return Err(MongoAgentError::NotImplemented(
+            "groups in nested collection queries".into(),
+        ));
+    }
+
+    // A missing or null array field is treated as an empty collection
+    let mut rows = bson!({ "$ifNull": [array_ref.into_aggregate_expression(), []] });
+
+    if let Some(predicate) = predicate {
+        let AggregationExpression(condition) = make_aggregation_expression(predicate)?;
+        rows = bson!({
+            "$filter": {
+                "input": rows,
+                "as": "CURRENT", // implicitly changes the document root in `condition` to be the array element
+                "cond": condition,
+            }
+        });
+    }
+
+    if let Some(order_by) = order_by {
+        rows = make_sort_array_expression(rows, order_by)?;
+    }
+
+    match (offset, limit) {
+        (None, None) => (),
+        // `$slice` rejects a count that is not positive
+        (_, Some(0)) => rows = Bson::Array(vec![]),
+        (offset, limit) => {
+            rows = bson!({
+                "$slice": [
+                    rows,
+                    offset.unwrap_or(0) as i64,
+                    limit.map(|n| n as i64).unwrap_or(i32::MAX as i64),
+                ]
+            });
+        }
+    }
+
+    let mut row_set = Document::new();
+    if let Some(aggregates) = aggregates {
+        let aggregates_selection: Document = aggregates
+            .iter()
+            .map(|(key, aggregate)| {
+                (
+                    key.to_string(),
+                    aggregate_expression_for_array(ColumnRef::variable("rows"), aggregate),
+                )
+            })
+            .collect();
+        row_set.insert(
+            ROW_SET_AGGREGATES_KEY,
+            escape_invalid_keys(aggregates_selection),
+        );
+    }
+    if let Some(fields) = fields {
+        let row_selection = for_fields_helper(Some(ColumnRef::variable("this")), fields)?;
+        row_set.insert(
+            ROW_SET_ROWS_KEY,
+            doc! { "$map": { "input": "$$rows", "in": row_selection } },
+        );
+    }
+
+    Ok(doc! {
+        "$let": {
+            "vars": { "rows": rows },
+            "in": row_set,
+        }
+    }
+    .into())
+}
+
 fn nested_column_reference<'a>(
     parent: Option<ColumnRef<'a>>,
     column: &'a FieldName,
@@ -271,8 +395,9 @@ fn nested_column_reference<'a>(
 /// If any key in our selection document contains invalid characters then we have to switch syntax
 /// to using $setField to escape the field name. Unfortunately this syntax is only capable of
 /// adding _one_ field to an object at a time, so we have to nest invocations of $setField for
-/// every selected field.
-fn escape_invalid_keys(doc: Document) -> Document {
+/// every selected field. Field names are wrapped in `$literal` so that names beginning with
+/// a dollar sign are not evaluated as field references.
+pub fn escape_invalid_keys(doc: Document) -> Document {
     if doc.keys().all(is_name_safe) {
         return doc;
     }
@@ -280,23 +405,40 @@ fn escape_invalid_keys(doc: Document) -> Document {
         .fold(None, |input: Option<Document>, (key, value)| {
             Some(doc! {
                 "$setField": {
-                    "field": key,
+                    "field": { "$literal": key },
                     "value": value,
-                    "input": if let Some(prev_doc) = input { prev_doc.into() } else { bson!("$$CURRENT") },
+                    "input": if let Some(prev_doc) = input { prev_doc.into() } else { bson!({ "$literal": {} }) },
                 }
             })
         })
         .expect("selected at least one field") // safety: if the doc was empty we would have hit the early return
 }
 
+/// Adds a field to a selection document that may have been escaped by [escape_invalid_keys].
+pub fn insert_into_selection(mut doc: Document, key: String, value: Bson) -> Document {
+    if doc.contains_key("$setField") {
+        doc! {
+            "$setField": {
+                "field": { "$literal": key },
+                "value": value,
+                "input": doc,
+            }
+        }
+    } else {
+        doc.insert(key, value);
+        doc
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use configuration::Configuration;
     use mongodb::bson::{doc, Document};
     use ndc_query_plan::plan_for_query_request;
     use ndc_test_helpers::{
-        array, array_of, collection, field, named_type, nullable, object, object_type, query,
-        query_request, relation_field, relationship,
+        array, array_of, asc, binop, collection, field, named_type, nested_collection, nullable,
+        object, object_type, query, query_request, relation_field, relationship,
+        star_count_aggregate, target, value,
     };
     use pretty_assertions::assert_eq;
 
@@ -398,6 +540,35 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn escapes_selected_field_names_with_dollar_signs_or_dots() -> Result<(), anyhow::Error> {
+        let query_request = query_request()
+            .collection("test")
+            .query(query().fields([field!("price.usd" => "foo"), field!("$meta" => "foo")]))
+            .into();
+
+        let query_plan = plan_for_query_request(&foo_config(), query_request)?;
+
+        let selection = selection_for_fields(query_plan.query.fields.as_ref())?;
+        assert_eq!(
+            Into::<Document>::into(selection),
+            doc! {
+                "$setField": {
+                    "field": { "$literal": "$meta" },
+                    "value": { "$ifNull": ["$foo", null] },
+                    "input": {
+                        "$setField": {
+                            "field": { "$literal": "price.usd" },
+                            "value": { "$ifNull": ["$foo", null] },
+                            "input": { "$literal": {} },
+                        }
+                    },
+                }
+            }
+        );
+        Ok(())
+    }
+
     #[test]
     fn produces_selection_for_relation() -> Result<(), anyhow::Error> {
         let query_request = query_request()
@@ -451,6 +622,114 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn produces_selection_for_nested_collection() -> Result<(), anyhow::Error> {
+        let query_request = query_request()
+            .collection("test")
+            .query(
+                query().fields([field!("first_cats" => "os", nested_collection!(
+                    query()
+                        .aggregates([star_count_aggregate!("count")])
+                        .fields([field!("cat")])
+                        .predicate(binop("_eq", target!("cat"), value!("meow")))
+                        .order_by([asc!("cat")])
+                        .offset(1)
+                        .limit(2)
+                ))]),
+            )
+            .into();
+
+        let query_plan = plan_for_query_request(&foo_config(), query_request)?;
+
+        let selection = selection_for_fields(query_plan.query.fields.as_ref())?;
+        assert_eq!(
+            Into::<Document>::into(selection),
+            doc! {
+                "first_cats": {
+                    "$let": {
+                        "vars": {
+                            "rows": {
+                                "$slice": [
+                                    {
+                                        "$sortArray": {
+                                            "input": {
+                                                "$filter": {
+                                                    "input": { "$ifNull": ["$os", []] },
+                                                    "as": "CURRENT",
+                                                    "cond": { "$eq": ["$cat", { "$literal": "meow" }] },
+                                                }
+                                            },
+                                            "sortBy": { "cat": 1 },
+                                        }
+                                    },
+                                    1_i64,
+                                    2_i64,
+                                ]
+                            }
+                        },
+                        "in": {
+                            "aggregates": {
+                                "count": { "$ifNull": [{ "$size": "$$rows" }, 0] },
+                            },
+                            "rows": {
+                                "$map": {
+                                    "input": "$$rows",
+                                    "in": {
+                                        "cat": { "$ifNull": ["$$this.cat", null] },
+                                    },
+                                }
+                            },
+                        },
+                    }
+                },
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn produces_empty_rows_for_nested_collection_with_limit_zero() -> Result<(), anyhow::Error> {
+        let query_request = query_request()
+            .collection("test")
+            .query(
+                query().fields([field!("first_cats" => "os", nested_collection!(
+                    query()
+                        .aggregates([star_count_aggregate!("count")])
+                        .fields([field!("cat")])
+                        .limit(0)
+                ))]),
+            )
+            .into();
+
+        let query_plan = plan_for_query_request(&foo_config(), query_request)?;
+
+        let selection = selection_for_fields(query_plan.query.fields.as_ref())?;
+        assert_eq!(
+            Into::<Document>::into(selection),
+            doc! {
+                "first_cats": {
+                    "$let": {
+                        "vars": { "rows": [] },
+                        "in": {
+                            "aggregates": {
+                                "count": { "$ifNull": [{ "$size": "$$rows" }, 0] },
+                            },
+                            "rows": {
+                                "$map": {
+                                    "input": "$$rows",
+                                    "in": {
+                                        "cat": { "$ifNull": ["$$this.cat", null] },
+                                    },
+                                }
+                            },
+                        },
+                    }
+                },
+            }
+        );
+        Ok(())
+    }
+
     fn students_config() -> MongoConfiguration {
         MongoConfiguration(Configuration {
             collections: [collection("classes"), collection("students")].into(),
@@ -486,6 +765,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         })
@@ -521,6 +801,7 @@ mod tests {
             functions: Default::default(),
             procedures: Default::default(),
             native_mutations: Default::default(),
+            collection_procedures: Default::default(),
             native_queries: Default::default(),
             options: Default::default(),
         })
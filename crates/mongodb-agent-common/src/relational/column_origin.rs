@@ -6,6 +6,8 @@
 
 use ndc_models::{Relation, RelationalExpression};
 
+use crate::mongodb::sanitize::is_name_safe;
+
 use super::normalize_joins::column_count;
 
 /// Represents the origin of a column in the relation tree.
@@ -60,10 +62,12 @@ pub fn trace_column_origin(relation: &Relation, column_index: u64) -> ColumnOrig
             columns,
             ..
         } => {
-            if let Some(field_name) = columns.get(column_index as usize) {
-                ColumnOrigin::from_field(collection.as_str(), field_name.as_str())
-            } else {
-                ColumnOrigin::computed() // Index out of bounds
+            match columns.get(column_index as usize) {
+                // Names with dollar signs or dots cannot be expressed as a dotted path
+                Some(field_name) if is_name_safe(field_name) => {
+                    ColumnOrigin::from_field(collection.as_str(), field_name.as_str())
+                }
+                _ => ColumnOrigin::computed(), // Index out of bounds, or name requires escaping
             }
         }
 
@@ -165,6 +169,9 @@ fn trace_expression_origin(input: &Relation, expr: &RelationalExpression) -> Col
         RelationalExpression::Column { index } => trace_column_origin(input, *index),
 
         // GetField accesses a nested field - trace the base column and append the field name
+        RelationalExpression::GetField { field, .. } if !is_name_safe(field) => {
+            ColumnOrigin::computed()
+        }
         RelationalExpression::GetField { column, field } => {
             let base_origin = trace_expression_origin(input, column);
             match base_origin.original_path {
@@ -73,4 +73,24 @@ pub enum RelationalError {
         native_query: String,
         message: String,
     },
+
+    /// A relational mutation targets a collection that does not accept writes.
+    #[error("Collection \"{0}\" does not accept relational mutations; only collections in the connector schema can be modified")]
+    NotWritableCollection(String),
+
+    /// A relational insert row has a different number of values than the insert has columns.
+    #[error("Insert into \"{collection}\" has {expected} columns, but a row has {actual} values")]
+    RowLengthMismatch {
+        collection: String,
+        expected: usize,
+        actual: usize,
+    },
+
+    /// A relational insert names a column that is not a field of the collection's object type.
+    #[error("Collection \"{collection}\" has no column \"{column}\"")]
+    UnknownColumn { collection: String, column: String },
+
+    /// A value supplied for a column in a relational insert could not be converted to BSON.
+    #[error("Invalid value for column \"{column}\": {message}")]
+    InvalidColumnValue { column: String, message: String },
 }
@@ -711,7 +711,7 @@ pub fn translate_expression(
             let column_bson = translate_expression(column, ctx)?;
             Ok(bson!({
                 "$getField": {
-                    "field": field,
+                    "field": field_name_expression(field),
                     "input": column_bson
                 }
             }))
@@ -1258,6 +1258,16 @@ fn translate_date_part(
     }
 }
 
+/// The `field` argument of `$getField` is evaluated as an expression, so a name that begins with
+/// a dollar sign must be wrapped in `$literal` to avoid being read as a field reference.
+fn field_name_expression(field: &str) -> Bson {
+    if field.starts_with('$') {
+        bson!({ "$literal": field })
+    } else {
+        Bson::String(field.to_string())
+    }
+}
+
 /// Helper function to translate JSON path access with nested keys.
 /// Chains $getField operations for each key in the path.
 /// Optionally wraps the result in a type conversion operator.
@@ -1277,7 +1287,7 @@ fn translate_json_path_access(
             for segment in path_segments {
                 result = bson!({
                     "$getField": {
-                        "field": segment,
+                        "field": field_name_expression(segment),
                         "input": result
                     }
                 });
@@ -8,6 +8,7 @@ mod column_origin;
 mod error;
 mod execute;
 pub mod expression;
+mod mutation;
 mod native_query;
 mod normalize_joins;
 mod optimize_filters;
@@ -23,6 +24,7 @@ pub use execute::{
     execute_relational_query, execute_relational_query_stream,
     execute_relational_query_stream_with_config, execute_relational_query_with_config,
 };
+pub use mutation::{execute_relational_write, translate_relational_mutation, RelationalWrite};
 pub use normalize_joins::{column_count, normalize_right_joins};
 pub use optimize_filters::{extract_early_match, EarlyMatchResult};
 pub use pipeline_builder::build_relational_pipeline;
@@ -0,0 +1,303 @@
+//! Execution of relational mutations against MongoDB.
+//!
+//! Each operation is translated to a single write command: inserts run `insertMany`, updates run
+//! `updateMany` with a pipeline-style update, and deletes run `deleteMany`. Predicates are
+//! translated the same way as relational query filters. The connector runs the writes for
+//! a request in a transaction.
+
+use std::collections::BTreeMap;
+
+use configuration::MongoScalarType;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    ClientSession, Database,
+};
+use ndc_models::{self as ndc, FieldName, RelationalExpression, RelationalMutationOperation};
+use ndc_query_plan::QueryContext as _;
+
+use crate::{
+    interface_types::MongoAgentError,
+    mongo_query_plan::{MongoConfiguration, Type},
+    query::serialization::json_to_bson,
+};
+
+use super::{
+    expression::{translate_expression, ExpressionContext},
+    pipeline_builder::try_make_query_document,
+    type_lookup::{literal_to_bson_with_field_type, lookup_field_type},
+    ColumnMapping, RelationalError,
+};
+
+/// A MongoDB write translated from a relational mutation operation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RelationalWrite {
+    InsertMany {
+        collection: String,
+        documents: Vec<Document>,
+    },
+    UpdateMany {
+        collection: String,
+        filter: Document,
+        update: Vec<Document>,
+    },
+    DeleteMany {
+        collection: String,
+        filter: Document,
+    },
+}
+
+/// Translate a relational mutation operation to the MongoDB write that implements it.
+pub fn translate_relational_mutation(
+    config: &MongoConfiguration,
+    operation: &RelationalMutationOperation,
+) -> Result<RelationalWrite, RelationalError> {
+    match operation {
+        RelationalMutationOperation::Insert {
+            collection,
+            columns,
+            rows,
+        } => {
+            let collection = writable_collection(config, collection)?;
+            let column_types = insert_column_types(config, collection, columns)?;
+            let documents = rows
+                .iter()
+                .map(|row| insert_document(config, collection, columns, &column_types, row))
+                .collect::<Result<_, _>>()?;
+            Ok(RelationalWrite::InsertMany {
+                collection: collection.to_string(),
+                documents,
+            })
+        }
+        RelationalMutationOperation::Update {
+            collection,
+            columns,
+            set,
+            predicate,
+        } => {
+            let collection = writable_collection(config, collection)?;
+            let column_mapping = ColumnMapping::new(columns.iter().map(|c| c.as_str()));
+            let filter = filter_document(config, collection, &column_mapping, predicate.as_ref())?;
+            let update = update_pipeline(config, collection, &column_mapping, set)?;
+            Ok(RelationalWrite::UpdateMany {
+                collection: collection.to_string(),
+                filter,
+                update,
+            })
+        }
+        RelationalMutationOperation::Delete {
+            collection,
+            columns,
+            predicate,
+        } => {
+            let collection = writable_collection(config, collection)?;
+            let column_mapping = ColumnMapping::new(columns.iter().map(|c| c.as_str()));
+            let filter = filter_document(config, collection, &column_mapping, predicate.as_ref())?;
+            Ok(RelationalWrite::DeleteMany {
+                collection: collection.to_string(),
+                filter,
+            })
+        }
+    }
+}
+
+/// Run a translated relational write, and return the number of affected rows.
+pub async fn execute_relational_write(
+    database: &Database,
+    session: &mut ClientSession,
+    write: RelationalWrite,
+) -> Result<u64, MongoAgentError> {
+    tracing::debug!(?write, "executing relational mutation write");
+    let affected_rows = match write {
+        RelationalWrite::InsertMany {
+            collection,
+            documents,
+        } => {
+            // insertMany rejects an empty list of documents
+            if documents.is_empty() {
+                return Ok(0);
+            }
+            let result = database
+                .collection::<Document>(&collection)
+                .insert_many(documents)
+                .session(&mut *session)
+                .await?;
+            result.inserted_ids.len() as u64
+        }
+        RelationalWrite::UpdateMany {
+            collection,
+            filter,
+            update,
+        } => {
+            let result = database
+                .collection::<Document>(&collection)
+                .update_many(filter, update)
+                .session(&mut *session)
+                .await?;
+            // Rows that already hold the new values are affected rows too, as in SQL.
+            result.matched_count
+        }
+        RelationalWrite::DeleteMany { collection, filter } => {
+            let result = database
+                .collection::<Document>(&collection)
+                .delete_many(filter)
+                .session(&mut *session)
+                .await?;
+            result.deleted_count
+        }
+    };
+    Ok(affected_rows)
+}
+
+/// Only collections that advertise relational mutations in the connector schema accept writes.
+/// Collections defined by native queries do not.
+fn writable_collection<'a>(
+    config: &MongoConfiguration,
+    collection: &'a ndc::CollectionName,
+) -> Result<&'a str, RelationalError> {
+    match config.0.collections.get(collection) {
+        Some(collection_info) if collection_info.relational_mutations.is_some() => {
+            Ok(collection.as_str())
+        }
+        _ => Err(RelationalError::NotWritableCollection(
+            collection.to_string(),
+        )),
+    }
+}
+
+/// Every insert column must be a field of the collection's object type. Values are converted
+/// according to the declared field types.
+fn insert_column_types(
+    config: &MongoConfiguration,
+    collection: &str,
+    columns: &[FieldName],
+) -> Result<Vec<Type>, RelationalError> {
+    let object_type = config
+        .find_collection_object_type(&collection.into())
+        .map_err(|_| RelationalError::NotWritableCollection(collection.to_string()))?;
+    columns
+        .iter()
+        .map(|column| match object_type.fields.get(column) {
+            Some(field) => Ok(field.r#type.clone()),
+            None => Err(RelationalError::UnknownColumn {
+                collection: collection.to_string(),
+                column: column.to_string(),
+            }),
+        })
+        .collect()
+}
+
+fn insert_document(
+    config: &MongoConfiguration,
+    collection: &str,
+    columns: &[FieldName],
+    column_types: &[Type],
+    row: &[serde_json::Value],
+) -> Result<Document, RelationalError> {
+    if row.len() != columns.len() {
+        return Err(RelationalError::RowLengthMismatch {
+            collection: collection.to_string(),
+            expected: columns.len(),
+            actual: row.len(),
+        });
+    }
+    columns
+        .iter()
+        .zip(column_types)
+        .zip(row)
+        .map(|((column, column_type), value)| {
+            let value =
+                column_value_to_bson(config, column_type, value.clone()).map_err(|message| {
+                    RelationalError::InvalidColumnValue {
+                        column: column.to_string(),
+                        message,
+                    }
+                })?;
+            Ok((column.to_string(), value))
+        })
+        .collect()
+}
+
+/// Insert values are converted the same way as other mutation inputs. In relational mode nested
+/// values are sent as JSON strings, so those are parsed before conversion.
+fn column_value_to_bson(
+    config: &MongoConfiguration,
+    column_type: &Type,
+    value: serde_json::Value,
+) -> Result<Bson, String> {
+    let value = match value {
+        serde_json::Value::String(s)
+            if config.relational_mode().enabled && is_nested_type(column_type) =>
+        {
+            serde_json::from_str(&s).map_err(|err| err.to_string())?
+        }
+        value => value,
+    };
+    json_to_bson(column_type, value).map_err(|err| err.to_string())
+}
+
+/// Nested types (objects, arrays, and ExtendedJSON) are represented as JSON strings in relational
+/// mode.
+fn is_nested_type(column_type: &Type) -> bool {
+    match column_type {
+        Type::Scalar(MongoScalarType::ExtendedJSON) => true,
+        Type::Scalar(MongoScalarType::Bson(_)) => false,
+        Type::Object(_) | Type::ArrayOf(_) => true,
+        Type::Nullable(underlying_type) => is_nested_type(underlying_type),
+        Type::Tuple(_) => false,
+    }
+}
+
+/// Prefer a query document which can use indexes, and fall back to `$expr` for predicates that
+/// cannot be expressed as one. A missing predicate matches every document.
+fn filter_document(
+    config: &MongoConfiguration,
+    collection: &str,
+    column_mapping: &ColumnMapping,
+    predicate: Option<&RelationalExpression>,
+) -> Result<Document, RelationalError> {
+    let Some(predicate) = predicate else {
+        return Ok(doc! {});
+    };
+    if let Some(query_doc) =
+        try_make_query_document(predicate, column_mapping, Some(collection), Some(config))
+    {
+        return Ok(query_doc);
+    }
+    let expr_ctx = ExpressionContext::new(column_mapping);
+    Ok(doc! { "$expr": translate_expression(predicate, &expr_ctx)? })
+}
+
+/// Updates use a pipeline so that new values may be computed from existing columns, e.g.
+/// `SET count = count + 1`.
+fn update_pipeline(
+    config: &MongoConfiguration,
+    collection: &str,
+    column_mapping: &ColumnMapping,
+    set: &BTreeMap<FieldName, RelationalExpression>,
+) -> Result<Vec<Document>, RelationalError> {
+    if set.is_empty() {
+        return Err(RelationalError::UnsupportedExpression(
+            "update must set at least one column".to_string(),
+        ));
+    }
+    let expr_ctx = ExpressionContext::new(column_mapping);
+    let fields = set
+        .iter()
+        .map(|(column, expression)| {
+            let value = match expression {
+                // Literal values are wrapped so that strings beginning with `$` are not read as
+                // field references.
+                RelationalExpression::Literal { literal } => {
+                    let field_type = lookup_field_type(config, collection, column.as_str());
+                    match literal_to_bson_with_field_type(literal, field_type) {
+                        Some(value) => doc! { "$literal": value }.into(),
+                        None => translate_expression(expression, &expr_ctx)?,
+                    }
+                }
+                _ => translate_expression(expression, &expr_ctx)?,
+            };
+            Ok((column.to_string(), value))
+        })
+        .collect::<Result<Document, RelationalError>>()?;
+    Ok(vec![doc! { "$set": fields }])
+}
@@ -7,7 +7,7 @@
 use mongodb::bson::{doc, Bson, Document};
 use ndc_models::{Relation, RelationalExpression};
 
-use crate::mongo_query_plan::MongoConfiguration;
+use crate::{mongo_query_plan::MongoConfiguration, mongodb::sanitize::is_name_safe};
 
 use super::{
     column_origin::trace_column_origin,
@@ -191,6 +191,7 @@ fn trace_expression_to_path(
                 None
             }
         }
+        RelationalExpression::GetField { field, .. } if !is_name_safe(field) => None,
         RelationalExpression::GetField { column, field } => {
             let base_path = trace_expression_to_path(input, column, root_collection)?;
             Some(format!("{}.{}", base_path, field))
@@ -10,7 +10,11 @@ use ndc_models::{
     JoinOn, JoinType, NullsSort, OrderDirection, Relation, RelationalExpression, Sort,
 };
 
-use crate::mongo_query_plan::MongoConfiguration;
+use crate::{
+    mongo_query_plan::MongoConfiguration,
+    mongodb::sanitize::{is_name_safe, safe_name},
+    query::column_ref::ColumnRef,
+};
 
 use super::{
     expression::{translate_aggregate_expression, translate_expression, ExpressionContext},
@@ -195,7 +199,7 @@ fn build_from(
             // The configured native pipeline is the immutable source prefix. Generated relational
             // stages are appended after it by subsequent build steps.
             ctx.stages.extend(materialized.prefix_stages);
-            ctx.column_mapping = ColumnMapping::new(columns.iter().map(|c| c.as_str()));
+            alias_columns_that_require_escaping(columns, ctx);
             return Ok(());
         }
     }
@@ -209,11 +213,39 @@ fn build_from(
 
     ctx.collection = Some(collection.to_string());
     ctx.target_collection = Some(collection.to_string());
-    ctx.column_mapping = ColumnMapping::new(columns.iter().map(|c| c.as_str()));
-    // No pipeline stages needed - collection scan is implicit
+    // The collection scan is implicit - the only stage we may need aliases escaped field names
+    alias_columns_that_require_escaping(columns, ctx);
     Ok(())
 }
 
+/// Set the column mapping for the columns of a From relation. Fields with names that begin with
+/// a dollar sign or contain dots cannot be referenced with `$` shorthand, or used as keys in query
+/// and sort documents. Such fields are copied to safe aliases in an `$addFields` stage so that
+/// subsequent stages can reference every column by name.
+fn alias_columns_that_require_escaping(
+    columns: &[ndc_models::FieldName],
+    ctx: &mut PipelineContext<'_>,
+) {
+    let mut aliases = Document::new();
+    let mapping: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            let alias = safe_name(column.as_str());
+            if alias != column.as_str() {
+                aliases.insert(
+                    alias.to_string(),
+                    ColumnRef::from_field(column.as_str()).into_aggregate_expression(),
+                );
+            }
+            alias.into_owned()
+        })
+        .collect();
+    if !aliases.is_empty() {
+        ctx.stages.push(Stage::AddFields(aliases));
+    }
+    ctx.column_mapping = ColumnMapping::new(mapping.iter().map(|s| s.as_str()));
+}
+
 /// Build the Filter relation ($match stage).
 ///
 /// This function first tries to generate an index-friendly query document
@@ -246,7 +278,7 @@ fn build_filter(
 ///
 /// Returns `Some(document)` if the predicate can be expressed as a query document,
 /// `None` if we need to fall back to `$expr`.
-fn try_make_query_document(
+pub(super) fn try_make_query_document(
     predicate: &RelationalExpression,
     column_mapping: &ColumnMapping,
     collection: Option<&str>,
@@ -326,6 +358,7 @@ fn extract_field_path(
         RelationalExpression::Column { index } => column_mapping
             .field_for_index(*index)
             .map(|s| s.to_string()),
+        RelationalExpression::GetField { field, .. } if !is_name_safe(field) => None,
         RelationalExpression::GetField { column, field } => {
             let base_path = extract_field_path(column, column_mapping)?;
             Some(format!("{}.{}", base_path, field))
@@ -353,11 +386,14 @@ fn literal_to_bson(
 
 /// Check if an expression is a simple field reference (Column or GetField chain on a Column).
 ///
-/// Simple field references can be used directly in $sort stages.
+/// Simple field references can be used directly in $sort stages. Nested field names that require
+/// escaping cannot be, so those are not simple.
 fn is_simple_field_expr(expr: &RelationalExpression) -> bool {
     match expr {
         RelationalExpression::Column { .. } => true,
-        RelationalExpression::GetField { column, .. } => is_simple_field_expr(column),
+        RelationalExpression::GetField { column, field } => {
+            is_name_safe(field) && is_simple_field_expr(column)
+        }
         _ => false,
     }
 }
@@ -541,6 +577,11 @@ fn extract_column_field(
             .field_for_index(*index)
             .map(|s| s.to_string())
             .ok_or(RelationalError::InvalidColumnIndex(*index)),
+        RelationalExpression::GetField { field, .. } if !is_name_safe(field) => {
+            Err(RelationalError::UnsupportedExpression(format!(
+                "window partition or sort by nested field \"{field}\" which requires escaping"
+            )))
+        }
         RelationalExpression::GetField { column, field } => {
             let base = extract_column_field(column, column_mapping)?;
             Ok(format!("{}.{}", base, field))
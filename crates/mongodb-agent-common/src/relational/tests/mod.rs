@@ -1,5 +1,6 @@
 //! Tests for relational query processing.
 
 mod expression_tests;
+mod mutation_tests;
 mod native_query_tests;
 mod pipeline_builder_tests;
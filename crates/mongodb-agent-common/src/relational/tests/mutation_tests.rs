@@ -0,0 +1,244 @@
+//! Tests for relational mutation translation.
+
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use ndc_models::{
+    RelationalExpression, RelationalLiteral, RelationalMutationInfo, RelationalMutationOperation,
+};
+use serde_json::json;
+
+use crate::{
+    mongo_query_plan::MongoConfiguration,
+    relational::{translate_relational_mutation, RelationalError, RelationalWrite},
+    test_helpers::mflix_config,
+};
+
+fn writable_mflix_config() -> MongoConfiguration {
+    let mut config = mflix_config();
+    for collection in config.0.collections.values_mut() {
+        collection.relational_mutations = Some(RelationalMutationInfo {
+            insertable: true,
+            updatable: true,
+            deletable: true,
+        });
+    }
+    config
+}
+
+#[test]
+fn translates_insert_to_insert_many() {
+    let config = writable_mflix_config();
+    let movie_id = ObjectId::new();
+    let operation = RelationalMutationOperation::Insert {
+        collection: "comments".into(),
+        columns: vec!["movie_id".into(), "name".into()],
+        rows: vec![
+            vec![json!(movie_id.to_hex()), json!("Alice")],
+            vec![json!(movie_id.to_hex()), json!("Bob")],
+        ],
+    };
+
+    let write = translate_relational_mutation(&config, &operation).unwrap();
+
+    assert_eq!(
+        write,
+        RelationalWrite::InsertMany {
+            collection: "comments".to_string(),
+            documents: vec![
+                doc! { "movie_id": movie_id, "name": "Alice" },
+                doc! { "movie_id": movie_id, "name": "Bob" },
+            ],
+        }
+    );
+}
+
+#[test]
+fn rejects_insert_row_with_wrong_number_of_values() {
+    let config = writable_mflix_config();
+    let operation = RelationalMutationOperation::Insert {
+        collection: "comments".into(),
+        columns: vec!["movie_id".into(), "name".into()],
+        rows: vec![vec![json!("Alice")]],
+    };
+
+    let result = translate_relational_mutation(&config, &operation);
+
+    assert!(matches!(
+        result,
+        Err(RelationalError::RowLengthMismatch {
+            expected: 2,
+            actual: 1,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn converts_insert_values_according_to_declared_field_types() {
+    let config = writable_mflix_config();
+    let operation = RelationalMutationOperation::Insert {
+        collection: "movies".into(),
+        columns: vec!["title".into(), "released".into()],
+        rows: vec![vec![json!("Alien"), json!("1979-05-25T00:00:00Z")]],
+    };
+
+    let write = translate_relational_mutation(&config, &operation).unwrap();
+
+    assert_eq!(
+        write,
+        RelationalWrite::InsertMany {
+            collection: "movies".to_string(),
+            documents: vec![doc! {
+                "title": "Alien",
+                "released": DateTime::parse_rfc3339_str("1979-05-25T00:00:00Z").unwrap(),
+            }],
+        }
+    );
+}
+
+#[test]
+fn rejects_insert_value_that_is_not_a_valid_object_id() {
+    let config = writable_mflix_config();
+    let operation = RelationalMutationOperation::Insert {
+        collection: "comments".into(),
+        columns: vec!["movie_id".into()],
+        rows: vec![vec![json!("not an object id")]],
+    };
+
+    let result = translate_relational_mutation(&config, &operation);
+
+    assert!(matches!(
+        result,
+        Err(RelationalError::InvalidColumnValue { column, .. }) if column == "movie_id"
+    ));
+}
+
+#[test]
+fn rejects_insert_into_column_that_is_not_in_collection_type() {
+    let config = writable_mflix_config();
+    let operation = RelationalMutationOperation::Insert {
+        collection: "comments".into(),
+        columns: vec!["name".into(), "rating".into()],
+        rows: vec![vec![json!("Alice"), json!(5)]],
+    };
+
+    let result = translate_relational_mutation(&config, &operation);
+
+    assert!(matches!(
+        result,
+        Err(RelationalError::UnknownColumn { collection, column })
+            if collection == "comments" && column == "rating"
+    ));
+}
+
+#[test]
+fn translates_update_to_update_many_with_pipeline() {
+    let config = writable_mflix_config();
+    let operation = RelationalMutationOperation::Update {
+        collection: "movies".into(),
+        columns: vec!["title".into(), "runtime".into(), "rated".into()],
+        set: [
+            (
+                "runtime".into(),
+                RelationalExpression::Plus {
+                    left: Box::new(RelationalExpression::Column { index: 1 }),
+                    right: Box::new(RelationalExpression::Literal {
+                        literal: RelationalLiteral::Int32 { value: 5 },
+                    }),
+                },
+            ),
+            (
+                "rated".into(),
+                RelationalExpression::Literal {
+                    literal: RelationalLiteral::String {
+                        value: "$PG".to_string(),
+                    },
+                },
+            ),
+        ]
+        .into(),
+        predicate: Some(RelationalExpression::Eq {
+            left: Box::new(RelationalExpression::Column { index: 0 }),
+            right: Box::new(RelationalExpression::Literal {
+                literal: RelationalLiteral::String {
+                    value: "Alien".to_string(),
+                },
+            }),
+        }),
+    };
+
+    let write = translate_relational_mutation(&config, &operation).unwrap();
+
+    assert_eq!(
+        write,
+        RelationalWrite::UpdateMany {
+            collection: "movies".to_string(),
+            filter: doc! { "title": { "$eq": "Alien" } },
+            update: vec![doc! {
+                "$set": {
+                    "rated": { "$literal": "$PG" },
+                    "runtime": { "$add": ["$runtime", 5] },
+                }
+            }],
+        }
+    );
+}
+
+#[test]
+fn translates_delete_filter_that_is_not_a_query_document_to_expr() {
+    let config = writable_mflix_config();
+    let operation = RelationalMutationOperation::Delete {
+        collection: "movies".into(),
+        columns: vec!["runtime".into(), "year".into()],
+        predicate: Some(RelationalExpression::Gt {
+            left: Box::new(RelationalExpression::Column { index: 0 }),
+            right: Box::new(RelationalExpression::Column { index: 1 }),
+        }),
+    };
+
+    let write = translate_relational_mutation(&config, &operation).unwrap();
+
+    assert_eq!(
+        write,
+        RelationalWrite::DeleteMany {
+            collection: "movies".to_string(),
+            filter: doc! { "$expr": { "$gt": ["$runtime", "$year"] } },
+        }
+    );
+}
+
+#[test]
+fn delete_without_predicate_matches_every_document() {
+    let config = writable_mflix_config();
+    let operation = RelationalMutationOperation::Delete {
+        collection: "comments".into(),
+        columns: vec![],
+        predicate: None,
+    };
+
+    let write = translate_relational_mutation(&config, &operation).unwrap();
+
+    assert_eq!(
+        write,
+        RelationalWrite::DeleteMany {
+            collection: "comments".to_string(),
+            filter: doc! {},
+        }
+    );
+}
+
+#[test]
+fn rejects_mutation_of_collection_without_relational_mutations() {
+    let config = mflix_config();
+    let operation = RelationalMutationOperation::Delete {
+        collection: "comments".into(),
+        columns: vec![],
+        predicate: None,
+    };
+
+    let result = translate_relational_mutation(&config, &operation);
+
+    assert!(matches!(
+        result,
+        Err(RelationalError::NotWritableCollection(name)) if name == "comments"
+    ));
+}
@@ -64,6 +64,44 @@ fn builds_pipeline_for_filter_relation() {
     );
 }
 
+#[test]
+fn aliases_columns_with_names_that_require_escaping() {
+    let relation = Relation::Filter {
+        input: Box::new(Relation::From {
+            collection: "products".into(),
+            columns: vec!["name".into(), "price.usd".into(), "$meta".into()],
+            arguments: Default::default(),
+        }),
+        predicate: RelationalExpression::Gt {
+            left: Box::new(RelationalExpression::Column { index: 1 }),
+            right: Box::new(RelationalExpression::Literal {
+                literal: RelationalLiteral::Int64 { value: 10 },
+            }),
+        },
+    };
+
+    let result = build_relational_pipeline(&relation).unwrap();
+
+    // Escaped names cannot be referenced by an early match, so the filter follows the aliases
+    assert_eq!(result.pipeline.stages.len(), 2);
+    assert_eq!(
+        result.pipeline.stages[0],
+        Stage::AddFields(doc! {
+            "__escaped_price·2eusd": { "$getField": { "$literal": "price.usd" } },
+            "__escaped_·24meta": { "$getField": { "$literal": "$meta" } },
+        })
+    );
+    assert_eq!(
+        result.pipeline.stages[1],
+        Stage::Match(doc! { "__escaped_price·2eusd": { "$gt": 10_i64 } })
+    );
+    assert_eq!(result.output_columns.field_for_index(0), Some("name"));
+    assert_eq!(
+        result.output_columns.field_for_index(2),
+        Some("__escaped_·24meta")
+    );
+}
+
 #[test]
 fn builds_pipeline_for_sort_relation() {
     let relation = Relation::Sort {
@@ -16,6 +16,10 @@ pub struct ConnectorState {
 }
 
 impl ConnectorState {
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
     pub fn database(&self) -> Database {
         self.client.database(&self.database)
     }
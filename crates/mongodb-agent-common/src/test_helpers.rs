@@ -81,6 +81,7 @@ pub fn make_nested_schema() -> MongoConfiguration {
         ]),
         procedures: Default::default(),
         native_mutations: Default::default(),
+        collection_procedures: Default::default(),
         native_queries: Default::default(),
         options: Default::default(),
     })
@@ -135,6 +136,7 @@ pub fn chinook_config() -> MongoConfiguration {
         functions: Default::default(),
         procedures: Default::default(),
         native_mutations: Default::default(),
+        collection_procedures: Default::default(),
         native_queries: Default::default(),
         options: Default::default(),
     })
@@ -1,14 +1,15 @@
 use ndc_sdk::models::{
     AggregateCapabilities, Capabilities, DatePartScalarExpressionCapability, ExistsCapabilities,
     GroupByCapabilities, LeafCapability, NestedArrayFilterByCapabilities, NestedFieldCapabilities,
-    NestedFieldFilterByCapabilities, QueryCapabilities, RelationalAggregateCapabilities,
-    RelationalAggregateExpressionCapabilities, RelationalAggregateFunctionCapabilities,
-    RelationalCaseCapabilities, RelationalComparisonExpressionCapabilities,
-    RelationalConditionalExpressionCapabilities, RelationalExpressionCapabilities,
-    RelationalJoinCapabilities, RelationalJoinTypeCapabilities,
-    RelationalOrderedAggregateFunctionCapabilities, RelationalProjectionCapabilities,
-    RelationalQueryCapabilities, RelationalScalarExpressionCapabilities,
-    RelationalScalarTypeCapabilities, RelationalSortCapabilities, RelationalWindowCapabilities,
+    NestedFieldFilterByCapabilities, NestedRelationshipCapabilities, QueryCapabilities,
+    RelationalAggregateCapabilities, RelationalAggregateExpressionCapabilities,
+    RelationalAggregateFunctionCapabilities, RelationalCaseCapabilities,
+    RelationalComparisonExpressionCapabilities, RelationalConditionalExpressionCapabilities,
+    RelationalExpressionCapabilities, RelationalJoinCapabilities, RelationalJoinTypeCapabilities,
+    RelationalMutationCapabilities, RelationalOrderedAggregateFunctionCapabilities,
+    RelationalProjectionCapabilities, RelationalQueryCapabilities,
+    RelationalScalarExpressionCapabilities, RelationalScalarTypeCapabilities,
+    RelationalSortCapabilities, RelationalWindowCapabilities,
     RelationalWindowExpressionCapabilities, RelationshipCapabilities,
 };
 
@@ -16,11 +17,11 @@ pub fn mongo_capabilities() -> Capabilities {
     Capabilities {
         query: QueryCapabilities {
             aggregates: Some(AggregateCapabilities {
-                filter_by: None,
+                filter_by: Some(LeafCapability {}),
                 group_by: Some(GroupByCapabilities {
-                    filter: None,
-                    order: None,
-                    paginate: None,
+                    filter: Some(LeafCapability {}),
+                    order: Some(LeafCapability {}),
+                    paginate: Some(LeafCapability {}),
                 }),
             }),
             variables: Some(LeafCapability {}),
@@ -34,29 +35,44 @@ pub fn mongo_capabilities() -> Capabilities {
                 }),
                 order_by: Some(LeafCapability {}),
                 aggregates: Some(LeafCapability {}),
-                nested_collections: None, // TODO: ENG-1464
+                nested_collections: Some(LeafCapability {}),
             },
             exists: ExistsCapabilities {
-                named_scopes: None, // TODO: ENG-1487
+                named_scopes: Some(LeafCapability {}),
                 unrelated: Some(LeafCapability {}),
                 nested_collections: Some(LeafCapability {}),
-                nested_scalar_collections: None, // TODO: ENG-1488
+                nested_scalar_collections: Some(LeafCapability {}),
             },
         },
         mutation: ndc_sdk::models::MutationCapabilities {
-            transactional: None,
-            explain: None,
+            transactional: Some(LeafCapability {}),
+            explain: Some(LeafCapability {}),
         },
         relationships: Some(RelationshipCapabilities {
             relation_comparisons: Some(LeafCapability {}),
-            order_by_aggregate: None,
-            nested: None, // TODO: ENG-1490
+            order_by_aggregate: Some(LeafCapability {}),
+            nested: Some(NestedRelationshipCapabilities {
+                array: Some(LeafCapability {}),
+                filtering: Some(LeafCapability {}),
+                ordering: None,
+            }),
         }),
-        relational_mutation: None,
+        relational_mutation: Some(relational_mutation_capabilities()),
         relational_query: Some(relational_query_capabilities()),
     }
 }
 
+/// Relational inserts, updates, and deletes run as `insertMany`, `updateMany`, and `deleteMany`
+/// commands in a single transaction. Collections only accept relational mutations in the schema
+/// when the `relationalMode.mutations` option is enabled.
+fn relational_mutation_capabilities() -> RelationalMutationCapabilities {
+    RelationalMutationCapabilities {
+        insert: Some(LeafCapability {}),
+        update: Some(LeafCapability {}),
+        delete: Some(LeafCapability {}),
+    }
+}
+
 /// Phase 2 relational query capabilities.
 ///
 /// Supports: From, Filter, Sort, Paginate, Project relations with scalar functions.
@@ -14,21 +14,30 @@ use mongodb_agent_common::{
     interface_types::MongoAgentError,
     mongo_query_plan::MongoConfiguration,
     query::handle_query_request,
-    relational::{execute_relational_query_stream_with_config, execute_relational_query_with_config},
+    relational::{
+        execute_relational_query_stream_with_config, execute_relational_query_with_config,
+    },
     state::{self, ConnectorState},
 };
 use ndc_sdk::{
     connector::{self, Connector, ConnectorSetup, ErrorResponse},
     json_response::JsonResponse,
     models::{
-        Capabilities, ExplainResponse, MutationRequest, MutationResponse, QueryRequest,
-        QueryResponse, RelationalQuery, RelationalQueryResponse, SchemaResponse,
+        Capabilities, ExplainResponse, MutationOperation, MutationRequest, MutationResponse,
+        QueryRequest, QueryResponse, RelationalMutationOperation, RelationalMutationRequest,
+        RelationalMutationResponse, RelationalQuery, RelationalQueryResponse, SchemaResponse,
     },
 };
 use serde_json::json;
 use tracing::instrument;
 
-use crate::{capabilities::mongo_capabilities, mutation::handle_mutation_request};
+use crate::{
+    capabilities::mongo_capabilities,
+    mutation::{
+        handle_mutation_explain_request, handle_mutation_request,
+        handle_relational_mutation_request,
+    },
+};
 
 /// The connector's configuration type. In JSON mode, the full configuration is loaded at startup.
 /// In Postgres mode, configuration is fetched on-demand per request from the config store.
@@ -42,7 +51,8 @@ pub enum ConnectorConfig {
 
 impl ConnectorConfig {
     /// Resolve configuration for the collections referenced by a query.
-    /// Fetches schemas for all collections (primary + relationship targets) and merges them.
+    /// Fetches schemas for all collections (primary + relationship targets) and definitions for
+    /// referenced native queries, and merges them.
     async fn resolve_for_collections(
         &self,
         collection_names: &[&str],
@@ -67,6 +77,40 @@ impl ConnectorConfig {
             }
         }
     }
+
+    /// Resolve configuration for a mutation request.
+    /// Fetches native mutations for the procedures named in the request, and schemas for the
+    /// target collections of relationships that mutation results may select.
+    async fn resolve_for_mutation(
+        &self,
+        request: &MutationRequest,
+    ) -> connector::Result<MongoConfiguration> {
+        match self {
+            ConnectorConfig::Static(config) => Ok(config.clone()),
+            ConnectorConfig::Postgres(store) => {
+                let procedure_names = collect_mutation_procedure_names(request);
+                let procedure_refs: Vec<&str> =
+                    procedure_names.iter().map(|s| s.as_str()).collect();
+                let collection_names = collect_mutation_collection_names(request);
+                let collection_refs: Vec<&str> =
+                    collection_names.iter().map(|s| s.as_str()).collect();
+                let configuration = store
+                    .read_mutation_configuration(&procedure_refs, &collection_refs)
+                    .await
+                    .map_err(|err| {
+                        ErrorResponse::new(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            format!(
+                                "failed to read configuration for procedures {}: {err:#}",
+                                procedure_names.join(", ")
+                            ),
+                            json!({}),
+                        )
+                    })?;
+                Ok(MongoConfiguration(configuration))
+            }
+        }
+    }
 }
 
 #[derive(Clone, Default)]
@@ -109,14 +153,15 @@ impl ConnectorSetup for MongoConnector {
                 url,
                 connector_id,
                 schema,
+                page_size,
             } => {
                 tracing::info!(
                     connector_id = %connector_id,
                     schema = %schema,
                     "using postgres-based configuration (on-demand per request)"
                 );
-                let store =
-                    PostgresConfigurationStore::new(url, connector_id, schema).map_err(|err| {
+                let store = PostgresConfigurationStore::new(url, connector_id, schema, page_size)
+                    .map_err(|err| {
                         ErrorResponse::new(
                             StatusCode::INTERNAL_SERVER_ERROR,
                             format!("failed to create postgres config store: {err:#}"),
@@ -203,18 +248,19 @@ impl Connector for MongoConnector {
                 let response = crate::schema::get_schema(config).await?;
                 Ok(response.into())
             }
-            ConnectorConfig::Postgres(_) => {
-                // In postgres mode, schema is managed externally.
-                // Return an empty schema response.
-                Ok(JsonResponse::Value(SchemaResponse {
-                    collections: vec![],
-                    functions: vec![],
-                    procedures: vec![],
-                    object_types: Default::default(),
-                    scalar_types: Default::default(),
-                    capabilities: None,
-                    request_arguments: None,
-                }))
+            ConnectorConfig::Postgres(store) => {
+                // In postgres mode the schema is assembled from every entry stored for the
+                // connector.
+                let configuration = store.read_full_configuration().await.map_err(|err| {
+                    ErrorResponse::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("failed to read configuration from config store: {err:#}"),
+                        json!({}),
+                    )
+                })?;
+                let response =
+                    crate::schema::get_schema(&MongoConfiguration(configuration)).await?;
+                Ok(response.into())
             }
         }
     }
@@ -236,15 +282,13 @@ impl Connector for MongoConnector {
 
     #[instrument(err, skip_all)]
     async fn mutation_explain(
-        _configuration: &Self::Configuration,
-        _state: &Self::State,
-        _request: MutationRequest,
+        configuration: &Self::Configuration,
+        state: &Self::State,
+        request: MutationRequest,
     ) -> connector::Result<JsonResponse<ExplainResponse>> {
-        Err(ErrorResponse::new(
-            StatusCode::NOT_IMPLEMENTED,
-            "Explain for mutations is not implemented yet".to_string(),
-            json!({}),
-        ))
+        let config = configuration.resolve_for_mutation(&request).await?;
+        let response = handle_mutation_explain_request(&config, state, request).await?;
+        Ok(response)
     }
 
     #[instrument(err, skip_all)]
@@ -253,17 +297,9 @@ impl Connector for MongoConnector {
         state: &Self::State,
         request: MutationRequest,
     ) -> connector::Result<JsonResponse<MutationResponse>> {
-        match configuration {
-            ConnectorConfig::Static(config) => {
-                let response = handle_mutation_request(config, state, request).await?;
-                Ok(response)
-            }
-            ConnectorConfig::Postgres(_) => Err(ErrorResponse::new(
-                StatusCode::NOT_IMPLEMENTED,
-                "Mutations are not supported in postgres configuration mode".to_string(),
-                json!({}),
-            )),
-        }
+        let config = configuration.resolve_for_mutation(&request).await?;
+        let response = handle_mutation_request(&config, state, request).await?;
+        Ok(response)
     }
 
     #[instrument(name = "/query", err, skip_all, fields(internal.visibility = "user"))]
@@ -314,6 +350,27 @@ impl Connector for MongoConnector {
 
         Ok(Box::pin(mapped_stream))
     }
+
+    #[instrument(name = "/mutation/relational", err, skip_all, fields(internal.visibility = "user"))]
+    async fn mutation_relational(
+        configuration: &Self::Configuration,
+        state: &Self::State,
+        request: RelationalMutationRequest,
+    ) -> connector::Result<JsonResponse<RelationalMutationResponse>> {
+        let collection_names: BTreeSet<String> = request
+            .operations
+            .iter()
+            .map(|operation| match operation {
+                RelationalMutationOperation::Insert { collection, .. }
+                | RelationalMutationOperation::Update { collection, .. }
+                | RelationalMutationOperation::Delete { collection, .. } => collection.to_string(),
+            })
+            .collect();
+        let name_refs: Vec<&str> = collection_names.iter().map(|s| s.as_str()).collect();
+        let config = configuration.resolve_for_collections(&name_refs).await?;
+        let response = handle_relational_mutation_request(&config, state, request).await?;
+        Ok(response)
+    }
 }
 
 /// Collect all collection names referenced by a query request: the primary collection
@@ -327,6 +384,28 @@ fn collect_query_collection_names(request: &QueryRequest) -> Vec<String> {
     names.into_iter().collect()
 }
 
+/// Collect the names of all procedures invoked by a mutation request.
+fn collect_mutation_procedure_names(request: &MutationRequest) -> Vec<String> {
+    let names: BTreeSet<String> = request
+        .operations
+        .iter()
+        .map(|operation| match operation {
+            MutationOperation::Procedure { name, .. } => name.to_string(),
+        })
+        .collect();
+    names.into_iter().collect()
+}
+
+/// Collect the target collections of all relationships in a mutation request.
+fn collect_mutation_collection_names(request: &MutationRequest) -> Vec<String> {
+    let names: BTreeSet<String> = request
+        .collection_relationships
+        .values()
+        .map(|relationship| relationship.target_collection.to_string())
+        .collect();
+    names.into_iter().collect()
+}
+
 fn collect_relational_collection_names(relation: &ndc_sdk::models::Relation) -> Vec<String> {
     let mut names = BTreeSet::new();
     collect_relational_collection_names_into(relation, &mut names);
@@ -369,3 +448,58 @@ fn map_mongo_agent_error(err: MongoAgentError) -> ErrorResponse {
     };
     ErrorResponse::new(status_code, err_response.message, details)
 }
+
+#[cfg(test)]
+mod tests {
+    use ndc_sdk::models::{MutationRequest, QueryRequest};
+    use ndc_test_helpers::{query, query_request, relationship};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::{
+        collect_mutation_collection_names, collect_mutation_procedure_names,
+        collect_query_collection_names,
+    };
+
+    #[test]
+    fn collects_query_collection_and_relationship_targets() {
+        let request: QueryRequest = query_request()
+            .collection("albums")
+            .query(query())
+            .relationships([
+                ("artist", relationship("artists", [("artistId", &["_id"])])),
+                ("tracks", relationship("tracks", [("_id", &["albumId"])])),
+                ("similar", relationship("albums", [("genre", &["genre"])])),
+            ])
+            .into();
+        assert_eq!(
+            collect_query_collection_names(&request),
+            ["albums", "artists", "tracks"]
+        );
+    }
+
+    #[test]
+    fn collects_mutation_procedures_and_relationship_targets() -> anyhow::Result<()> {
+        let request: MutationRequest = serde_json::from_value(json!({
+            "operations": [
+                { "type": "procedure", "name": "insert_albums_one", "arguments": {} },
+                { "type": "procedure", "name": "archive_album", "arguments": {} },
+                { "type": "procedure", "name": "insert_albums_one", "arguments": {} },
+            ],
+            "collection_relationships": {
+                "artist": {
+                    "column_mapping": { "artistId": ["_id"] },
+                    "relationship_type": "object",
+                    "target_collection": "artists",
+                    "arguments": {},
+                },
+            },
+        }))?;
+        assert_eq!(
+            collect_mutation_procedure_names(&request),
+            ["archive_album", "insert_albums_one"]
+        );
+        assert_eq!(collect_mutation_collection_names(&request), ["artists"]);
+        Ok(())
+    }
+}
@@ -1,23 +1,34 @@
-use futures::future::try_join_all;
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
 use itertools::Itertools;
 use mongodb::{
-    bson::{self, Bson},
-    Database,
+    bson::{self, doc, Bson},
+    error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT},
+    ClientSession, Database,
 };
 use mongodb_agent_common::{
+    interface_types::MongoAgentError,
     mongo_query_plan::{
         Field, MongoConfiguration, MutationOperation, MutationPlan, NestedArray, NestedField,
-        NestedObject,
+        NestedObject, Relationships,
     },
     procedure::Procedure,
-    query::{response::type_for_nested_field, serialization::bson_to_json},
+    query::{
+        response::type_for_nested_field, select_mutation_result_fields, serialization::bson_to_json,
+    },
+    relational::{execute_relational_write, translate_relational_mutation, RelationalWrite},
     state::ConnectorState,
 };
 use ndc_query_plan::plan_for_mutation_request;
 use ndc_sdk::{
     connector::MutationError,
     json_response::JsonResponse,
-    models::{ErrorResponse, MutationOperationResults, MutationRequest, MutationResponse},
+    models::{
+        ErrorResponse, ExplainResponse, MutationOperationResults, MutationRequest,
+        MutationResponse, RelationalMutationRequest, RelationalMutationResponse,
+        RelationalMutationResult,
+    },
 };
 use serde_json::json;
 
@@ -35,19 +46,422 @@ pub async fn handle_mutation_request(
     })?;
     let database = state.database();
     let jobs = look_up_procedures(config, &mutation_plan)?;
-    let operation_results = try_join_all(jobs.into_iter().map(|(procedure, requested_fields)| {
-        execute_procedure(config, database.clone(), procedure, requested_fields)
-    }))
-    .await?;
+    let operation_count = jobs.len();
+
+    let session = state
+        .client()
+        .start_session()
+        .await
+        .map_err(transaction_error)?;
+    let mut runner = SessionOperationRunner {
+        config,
+        database,
+        session,
+        jobs,
+    };
+
+    let operation_results = run_operations_in_transaction(&mut runner, operation_count).await?;
     Ok(JsonResponse::Value(MutationResponse { operation_results }))
 }
 
+/// Runs relational inserts, updates, and deletes in request order in a single transaction, with the
+/// same retries as other mutation requests. The response reports the number of affected rows for
+/// each operation.
+pub async fn handle_relational_mutation_request(
+    config: &MongoConfiguration,
+    state: &ConnectorState,
+    request: RelationalMutationRequest,
+) -> Result<JsonResponse<RelationalMutationResponse>, MutationError> {
+    tracing::debug!(relational_mutation = %serde_json::to_string(&request).unwrap(), "executing relational mutation");
+    // Translate every operation before writing anything so that an invalid operation does not
+    // start a transaction.
+    let writes = request
+        .operations
+        .iter()
+        .map(|operation| translate_relational_mutation(config, operation))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| {
+            MutationError::UnprocessableContent(ErrorResponse {
+                message: format!("error processing relational mutation request: {err}"),
+                details: json!({}),
+            })
+        })?;
+    let operation_count = writes.len();
+
+    let session = state
+        .client()
+        .start_session()
+        .await
+        .map_err(transaction_error)?;
+    let mut runner = RelationalOperationRunner {
+        database: state.database(),
+        session,
+        writes,
+    };
+
+    let results = run_operations_in_transaction(&mut runner, operation_count).await?;
+    Ok(JsonResponse::Value(RelationalMutationResponse { results }))
+}
+
+/// Number of times a mutation transaction is attempted when it fails with a transient error, and
+/// number of times a commit is attempted when its result is unknown.
+const MAX_TRANSACTION_ATTEMPTS: u32 = 3;
+
+type Job<'a, 'b> = (Procedure<'a>, Option<&'b NestedField>, &'b Relationships);
+
+/// An error from running one step of a mutation request. MongoDB labels errors that may not
+/// happen again if the transaction or commit is retried, and those are kept apart from other
+/// errors.
+#[derive(Debug)]
+enum OperationError {
+    /// The error has the `TransientTransactionError` label. Running the whole transaction again
+    /// may succeed.
+    TransientTransaction(String),
+    /// The error has the `UnknownTransactionCommitResult` label. Committing again may succeed.
+    UnknownCommitResult(String),
+    Other(MutationError),
+}
+
+impl OperationError {
+    fn from_transaction_error(err: mongodb::error::Error) -> Self {
+        if err.contains_label(TRANSIENT_TRANSACTION_ERROR) {
+            OperationError::TransientTransaction(err.to_string())
+        } else if err.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+            OperationError::UnknownCommitResult(err.to_string())
+        } else {
+            OperationError::Other(transaction_error(err))
+        }
+    }
+
+    /// Errors that are still transient after retries have run out are reported as conflicts
+    /// because they are caused by concurrent writes or by elections in the replica set.
+    fn into_mutation_error(self) -> MutationError {
+        match self {
+            OperationError::TransientTransaction(message) => {
+                MutationError::Conflict(ErrorResponse {
+                    message: format!("error running mutation: {message}"),
+                    details: json!({}),
+                })
+            }
+            OperationError::UnknownCommitResult(message) => {
+                MutationError::UnprocessableContent(ErrorResponse {
+                    message: format!("error committing mutation transaction: {message}"),
+                    details: json!({}),
+                })
+            }
+            OperationError::Other(err) => err,
+        }
+    }
+}
+
+impl From<MutationError> for OperationError {
+    fn from(err: MutationError) -> Self {
+        OperationError::Other(err)
+    }
+}
+
+/// Database interactions for running the operations of a mutation request. Ordering, rollback,
+/// and retries are implemented in terms of this trait so that they can be tested without
+/// a database.
+#[async_trait]
+trait OperationRunner {
+    /// The result of one operation
+    type Output: Send;
+
+    async fn start_transaction(&mut self) -> Result<(), OperationError>;
+    async fn run_operation(&mut self, index: usize) -> Result<Self::Output, OperationError>;
+    async fn commit_transaction(&mut self) -> Result<(), OperationError>;
+    async fn abort_transaction(&mut self) -> Result<(), OperationError>;
+}
+
+/// Runs operations with a session of the connector's MongoDB client
+struct SessionOperationRunner<'a, 'b> {
+    config: &'a MongoConfiguration,
+    database: Database,
+    session: ClientSession,
+    jobs: Vec<Job<'a, 'b>>,
+}
+
+#[async_trait]
+impl OperationRunner for SessionOperationRunner<'_, '_> {
+    type Output = MutationOperationResults;
+
+    async fn start_transaction(&mut self) -> Result<(), OperationError> {
+        start_session_transaction(&mut self.session).await
+    }
+
+    async fn run_operation(
+        &mut self,
+        index: usize,
+    ) -> Result<MutationOperationResults, OperationError> {
+        let (procedure, requested_fields, relationships) = &self.jobs[index];
+        execute_procedure(
+            self.config,
+            self.database.clone(),
+            &mut self.session,
+            procedure.clone(),
+            *requested_fields,
+            relationships,
+        )
+        .await
+    }
+
+    async fn commit_transaction(&mut self) -> Result<(), OperationError> {
+        commit_session_transaction(&mut self.session).await
+    }
+
+    async fn abort_transaction(&mut self) -> Result<(), OperationError> {
+        abort_session_transaction(&mut self.session).await
+    }
+}
+
+/// Runs relational writes with a session of the connector's MongoDB client
+struct RelationalOperationRunner {
+    database: Database,
+    session: ClientSession,
+    writes: Vec<RelationalWrite>,
+}
+
+#[async_trait]
+impl OperationRunner for RelationalOperationRunner {
+    type Output = RelationalMutationResult;
+
+    async fn start_transaction(&mut self) -> Result<(), OperationError> {
+        start_session_transaction(&mut self.session).await
+    }
+
+    async fn run_operation(
+        &mut self,
+        index: usize,
+    ) -> Result<RelationalMutationResult, OperationError> {
+        let affected_rows = execute_relational_write(
+            &self.database,
+            &mut self.session,
+            self.writes[index].clone(),
+        )
+        .await
+        .map_err(relational_write_error)?;
+        Ok(RelationalMutationResult { affected_rows })
+    }
+
+    async fn commit_transaction(&mut self) -> Result<(), OperationError> {
+        commit_session_transaction(&mut self.session).await
+    }
+
+    async fn abort_transaction(&mut self) -> Result<(), OperationError> {
+        abort_session_transaction(&mut self.session).await
+    }
+}
+
+async fn start_session_transaction(session: &mut ClientSession) -> Result<(), OperationError> {
+    session
+        .start_transaction()
+        .await
+        .map_err(OperationError::from_transaction_error)
+}
+
+async fn commit_session_transaction(session: &mut ClientSession) -> Result<(), OperationError> {
+    session
+        .commit_transaction()
+        .await
+        .map_err(OperationError::from_transaction_error)
+}
+
+async fn abort_session_transaction(session: &mut ClientSession) -> Result<(), OperationError> {
+    session
+        .abort_transaction()
+        .await
+        .map_err(OperationError::from_transaction_error)
+}
+
+fn relational_write_error(err: MongoAgentError) -> OperationError {
+    match err {
+        MongoAgentError::MongoDB(err) if err.contains_label(TRANSIENT_TRANSACTION_ERROR) => {
+            OperationError::TransientTransaction(err.to_string())
+        }
+        err => OperationError::Other(MutationError::UnprocessableContent(ErrorResponse {
+            message: err.to_string(),
+            details: json!({}),
+        })),
+    }
+}
+
+/// Runs operations in request order, and stops at the first error.
+async fn run_operations<R: OperationRunner + Send>(
+    runner: &mut R,
+    operation_count: usize,
+) -> Result<Vec<R::Output>, OperationError> {
+    let mut operation_results = Vec::with_capacity(operation_count);
+    for index in 0..operation_count {
+        operation_results.push(runner.run_operation(index).await?);
+    }
+    Ok(operation_results)
+}
+
+/// Runs all operations in request order in a single transaction so that a failure in any
+/// operation rolls back writes from the others. If an operation or the commit fails with
+/// a transient transaction error the whole transaction is run again.
+async fn run_operations_in_transaction<R: OperationRunner + Send>(
+    runner: &mut R,
+    operation_count: usize,
+) -> Result<Vec<R::Output>, MutationError> {
+    let mut attempt = 1;
+    loop {
+        match run_transaction_attempt(runner, operation_count).await {
+            Err(OperationError::TransientTransaction(message))
+                if attempt < MAX_TRANSACTION_ATTEMPTS =>
+            {
+                tracing::debug!(attempt, error = %message, "retrying mutation transaction");
+                attempt += 1;
+            }
+            result => return result.map_err(OperationError::into_mutation_error),
+        }
+    }
+}
+
+async fn run_transaction_attempt<R: OperationRunner + Send>(
+    runner: &mut R,
+    operation_count: usize,
+) -> Result<Vec<R::Output>, OperationError> {
+    runner.start_transaction().await?;
+    let operation_results = match run_operations(runner, operation_count).await {
+        Ok(operation_results) => operation_results,
+        Err(err) => {
+            if let Err(abort_err) = runner.abort_transaction().await {
+                tracing::warn!(error = ?abort_err, "error aborting mutation transaction");
+            }
+            return Err(err);
+        }
+    };
+    commit_transaction(runner).await?;
+    Ok(operation_results)
+}
+
+async fn commit_transaction(
+    runner: &mut (impl OperationRunner + Send),
+) -> Result<(), OperationError> {
+    let mut attempt = 1;
+    loop {
+        match runner.commit_transaction().await {
+            Err(OperationError::UnknownCommitResult(message))
+                if attempt < MAX_TRANSACTION_ATTEMPTS =>
+            {
+                tracing::debug!(attempt, error = %message, "retrying mutation commit");
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Produces the command that each operation in the request would run, without running it. For
+/// commands that MongoDB can explain the server-side query plan is included as well.
+pub async fn handle_mutation_explain_request(
+    config: &MongoConfiguration,
+    state: &ConnectorState,
+    mutation_request: MutationRequest,
+) -> Result<JsonResponse<ExplainResponse>, MutationError> {
+    let mutation_plan = plan_for_mutation_request(config, mutation_request).map_err(|err| {
+        MutationError::UnprocessableContent(ErrorResponse {
+            message: format!("error processing mutation request: {}", err),
+            details: json!({}),
+        })
+    })?;
+    let database = state.database();
+    let jobs = look_up_procedures(config, &mutation_plan)?;
+
+    let mut details = BTreeMap::new();
+    for (index, (procedure, _, _)) in jobs.into_iter().enumerate() {
+        let commands = procedure.interpolated_commands().map_err(|err| {
+            MutationError::UnprocessableContent(ErrorResponse {
+                message: err.to_string(),
+                details: json!({}),
+            })
+        })?;
+
+        let command_count = commands.len();
+        for (command_index, command) in commands.into_iter().enumerate() {
+            let keys = ExplainDetailKeys::new(index, command_index, command_count);
+
+            if is_explainable(&command) {
+                let explain_command = doc! {
+                    "explain": &command,
+                    "verbosity": "queryPlanner",
+                };
+                tracing::debug!(explain_command = %serde_json::to_string(&explain_command).unwrap());
+                let plan = database.run_command(explain_command).await.map_err(|err| {
+                    MutationError::UnprocessableContent(ErrorResponse {
+                        message: format!("error explaining mutation command: {err}"),
+                        details: json!({}),
+                    })
+                })?;
+                details.insert(keys.plan, to_pretty_json(&plan)?);
+            }
+
+            details.insert(keys.command, to_pretty_json(&command)?);
+        }
+    }
+
+    Ok(JsonResponse::Value(ExplainResponse { details }))
+}
+
+/// Keys under which a command and its plan appear in explain details. Most operations run a single
+/// command, which is shown as `operations[i].command`. Operations that run several commands, such
+/// as upserts that read back the upserted document, show each one as `operations[i].commands[j]`.
+#[derive(Debug, PartialEq)]
+struct ExplainDetailKeys {
+    command: String,
+    plan: String,
+}
+
+impl ExplainDetailKeys {
+    fn new(operation_index: usize, command_index: usize, command_count: usize) -> Self {
+        if command_count == 1 {
+            ExplainDetailKeys {
+                command: format!("operations[{operation_index}].command"),
+                plan: format!("operations[{operation_index}].plan"),
+            }
+        } else {
+            ExplainDetailKeys {
+                command: format!("operations[{operation_index}].commands[{command_index}]"),
+                plan: format!("operations[{operation_index}].plans[{command_index}]"),
+            }
+        }
+    }
+}
+
+/// MongoDB can explain these commands. Other commands, such as `insert`, are only shown in
+/// explain responses without a plan.
+fn is_explainable(command: &bson::Document) -> bool {
+    matches!(
+        command.keys().next().map(|key| key.as_str()),
+        Some("update" | "delete" | "findAndModify" | "aggregate" | "find")
+    )
+}
+
+fn to_pretty_json(document: &bson::Document) -> Result<String, MutationError> {
+    serde_json::to_string_pretty(document).map_err(|err| {
+        MutationError::UnprocessableContent(ErrorResponse {
+            message: err.to_string(),
+            details: json!({}),
+        })
+    })
+}
+
+fn transaction_error(err: mongodb::error::Error) -> MutationError {
+    MutationError::UnprocessableContent(ErrorResponse {
+        message: format!("error running mutation transaction: {err}"),
+        details: json!({}),
+    })
+}
+
 /// Looks up procedures according to the names given in the mutation request, and pairs them with
-/// arguments and requested fields. Returns an error if any procedures cannot be found.
+/// arguments, requested fields, and relationships referenced by requested fields. Returns an error
+/// if any procedures cannot be found.
 fn look_up_procedures<'a, 'b>(
     config: &'a MongoConfiguration,
     mutation_plan: &'b MutationPlan,
-) -> Result<Vec<(Procedure<'a>, Option<&'b NestedField>)>, MutationError> {
+) -> Result<Vec<(Procedure<'a>, Option<&'b NestedField>, &'b Relationships)>, MutationError> {
     let (procedures, not_found): (Vec<_>, Vec<String>) = mutation_plan
         .operations
         .iter()
@@ -56,15 +470,17 @@ fn look_up_procedures<'a, 'b>(
                 name,
                 arguments,
                 fields,
-                relationships: _,
+                relationships,
             } => {
-                let native_mutation = config.native_mutations().get(name);
-                let procedure = native_mutation
-                    .ok_or(name.to_string())
-                    .map(|native_mutation| {
-                        Procedure::from_native_mutation(native_mutation, arguments.clone())
-                    })?;
-                Ok((procedure, fields.as_ref()))
+                let procedure = if let Some(native_mutation) = config.native_mutations().get(name) {
+                    Procedure::from_native_mutation(native_mutation, arguments.clone())
+                } else if let Some(collection_procedure) = config.collection_procedures().get(name)
+                {
+                    Procedure::from_collection_procedure(collection_procedure, arguments.clone())
+                } else {
+                    return Err(name.to_string());
+                };
+                Ok((procedure, fields.as_ref(), relationships))
             }
         })
         .partition_result();
@@ -85,17 +501,47 @@ fn look_up_procedures<'a, 'b>(
 async fn execute_procedure(
     config: &MongoConfiguration,
     database: Database,
+    session: &mut ClientSession,
     procedure: Procedure<'_>,
     requested_fields: Option<&NestedField>,
-) -> Result<MutationOperationResults, MutationError> {
-    let (result, result_type) = procedure.execute(database.clone()).await.map_err(|err| {
-        MutationError::UnprocessableContent(ErrorResponse {
-            message: err.to_string(),
-            details: json!({}),
-        })
-    })?;
+    relationships: &Relationships,
+) -> Result<MutationOperationResults, OperationError> {
+    let (result, result_type) =
+        procedure
+            .execute(database.clone(), session)
+            .await
+            .map_err(|err| {
+                if err.has_error_label(TRANSIENT_TRANSACTION_ERROR) {
+                    OperationError::TransientTransaction(err.to_string())
+                } else {
+                    OperationError::Other(MutationError::UnprocessableContent(ErrorResponse {
+                        message: err.to_string(),
+                        details: json!({}),
+                    }))
+                }
+            })?;
 
-    let rewritten_result = rewrite_response(requested_fields, result.into())?;
+    // Related rows are looked up with an aggregation pipeline which also applies field selection.
+    // Otherwise fields are selected by rewriting the procedure result directly.
+    let rewritten_result = match requested_fields {
+        Some(fields) if !relationships.is_empty() => select_mutation_result_fields(
+            config,
+            &database,
+            session,
+            result,
+            &result_type,
+            fields,
+            relationships,
+        )
+        .await
+        .map_err(|err| {
+            MutationError::UnprocessableContent(ErrorResponse {
+                message: err.to_string(),
+                details: json!({}),
+            })
+        })?,
+        _ => rewrite_response(requested_fields, result)?,
+    };
 
     let requested_result_type = if let Some(fields) = requested_fields {
         type_for_nested_field(&[], &result_type, fields).map_err(|err| {
@@ -153,6 +599,13 @@ fn rewrite_response(
                 details: json!({}),
             }))
         }
+        (Some(NestedField::Collection(_)), _) => {
+            Err(MutationError::UnsupportedOperation(ErrorResponse {
+                message: "nested collection queries are not supported in mutation responses"
+                    .to_owned(),
+                details: json!({}),
+            }))
+        }
     }
 }
 
@@ -198,3 +651,246 @@ fn rewrite_array(fields: &NestedArray, values: Vec<Bson>) -> Result<Vec<Bson>, M
         .map(|value| rewrite_response(Some(nested), value))
         .try_collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, VecDeque};
+
+    use async_trait::async_trait;
+    use mongodb::bson::doc;
+    use ndc_sdk::{
+        connector::MutationError,
+        models::{ErrorResponse, MutationOperationResults},
+    };
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::{
+        is_explainable, run_operations, run_operations_in_transaction, ExplainDetailKeys,
+        OperationError, OperationRunner, MAX_TRANSACTION_ATTEMPTS,
+    };
+
+    /// Records each database interaction, and fails interactions according to `failures`. Keys
+    /// of `failures` are event names as they appear in `events`.
+    #[derive(Default)]
+    struct FakeRunner {
+        events: Vec<String>,
+        failures: BTreeMap<String, VecDeque<OperationError>>,
+    }
+
+    impl FakeRunner {
+        fn failing(event: &str, errors: impl IntoIterator<Item = OperationError>) -> Self {
+            FakeRunner {
+                events: vec![],
+                failures: [(event.to_owned(), errors.into_iter().collect())].into(),
+            }
+        }
+
+        fn record(&mut self, event: String) -> Result<(), OperationError> {
+            let failure = self
+                .failures
+                .get_mut(&event)
+                .and_then(|errors| errors.pop_front());
+            self.events.push(event);
+            match failure {
+                Some(err) => Err(err),
+                None => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl OperationRunner for FakeRunner {
+        type Output = MutationOperationResults;
+
+        async fn start_transaction(&mut self) -> Result<(), OperationError> {
+            self.record("start".to_owned())
+        }
+
+        async fn run_operation(
+            &mut self,
+            index: usize,
+        ) -> Result<MutationOperationResults, OperationError> {
+            self.record(format!("operation {index}"))?;
+            Ok(MutationOperationResults::Procedure {
+                result: json!(index),
+            })
+        }
+
+        async fn commit_transaction(&mut self) -> Result<(), OperationError> {
+            self.record("commit".to_owned())
+        }
+
+        async fn abort_transaction(&mut self) -> Result<(), OperationError> {
+            self.record("abort".to_owned())
+        }
+    }
+
+    fn other_error() -> OperationError {
+        OperationError::Other(MutationError::UnprocessableContent(ErrorResponse {
+            message: "duplicate key".to_owned(),
+            details: json!({}),
+        }))
+    }
+
+    fn results(indexes: impl IntoIterator<Item = usize>) -> Vec<MutationOperationResults> {
+        indexes
+            .into_iter()
+            .map(|index| MutationOperationResults::Procedure {
+                result: json!(index),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn runs_operations_in_order_in_one_transaction() -> Result<(), anyhow::Error> {
+        let mut runner = FakeRunner::default();
+        let operation_results = run_operations_in_transaction(&mut runner, 3)
+            .await
+            .map_err(|err| anyhow::anyhow!("{err:?}"))?;
+        assert_eq!(operation_results, results([0, 1, 2]));
+        assert_eq!(
+            runner.events,
+            [
+                "start",
+                "operation 0",
+                "operation 1",
+                "operation 2",
+                "commit"
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn aborts_transaction_and_skips_remaining_operations_on_error() {
+        let mut runner = FakeRunner::failing("operation 1", [other_error()]);
+        let result = run_operations_in_transaction(&mut runner, 3).await;
+        assert!(matches!(
+            result,
+            Err(MutationError::UnprocessableContent(ErrorResponse { message, .. })) if message == "duplicate key"
+        ));
+        assert_eq!(
+            runner.events,
+            ["start", "operation 0", "operation 1", "abort"]
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_transaction_after_transient_error() -> Result<(), anyhow::Error> {
+        let mut runner = FakeRunner::failing(
+            "operation 1",
+            [OperationError::TransientTransaction(
+                "write conflict".to_owned(),
+            )],
+        );
+        let operation_results = run_operations_in_transaction(&mut runner, 2)
+            .await
+            .map_err(|err| anyhow::anyhow!("{err:?}"))?;
+        assert_eq!(operation_results, results([0, 1]));
+        assert_eq!(
+            runner.events,
+            [
+                "start",
+                "operation 0",
+                "operation 1",
+                "abort",
+                "start",
+                "operation 0",
+                "operation 1",
+                "commit"
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retries_commit_when_result_is_unknown() -> Result<(), anyhow::Error> {
+        let mut runner = FakeRunner::failing(
+            "commit",
+            [OperationError::UnknownCommitResult("timed out".to_owned())],
+        );
+        let operation_results = run_operations_in_transaction(&mut runner, 1)
+            .await
+            .map_err(|err| anyhow::anyhow!("{err:?}"))?;
+        assert_eq!(operation_results, results([0]));
+        assert_eq!(runner.events, ["start", "operation 0", "commit", "commit"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_conflict_when_transient_errors_persist() {
+        let mut runner = FakeRunner::failing(
+            "operation 0",
+            (0..MAX_TRANSACTION_ATTEMPTS)
+                .map(|_| OperationError::TransientTransaction("write conflict".to_owned())),
+        );
+        let result = run_operations_in_transaction(&mut runner, 1).await;
+        assert!(matches!(result, Err(MutationError::Conflict(_))));
+        assert_eq!(
+            runner
+                .events
+                .iter()
+                .filter(|event| *event == "start")
+                .count(),
+            MAX_TRANSACTION_ATTEMPTS as usize
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_commit_result_when_commit_retries_run_out() {
+        let mut runner = FakeRunner::failing(
+            "commit",
+            (0..MAX_TRANSACTION_ATTEMPTS)
+                .map(|_| OperationError::UnknownCommitResult("timed out".to_owned())),
+        );
+        let result = run_operations_in_transaction(&mut runner, 1).await;
+        assert!(matches!(
+            result,
+            Err(MutationError::UnprocessableContent(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stops_running_operations_at_first_error() {
+        let mut runner = FakeRunner::failing("operation 1", [other_error()]);
+        let result = run_operations(&mut runner, 3).await;
+        assert!(matches!(result, Err(OperationError::Other(_))));
+        assert_eq!(runner.events, ["operation 0", "operation 1"]);
+    }
+
+    #[test]
+    fn explains_commands_that_read_or_write_existing_documents() {
+        assert!(is_explainable(&doc! { "update": "books", "updates": [] }));
+        assert!(is_explainable(&doc! { "delete": "books", "deletes": [] }));
+        assert!(is_explainable(
+            &doc! { "findAndModify": "books", "remove": true }
+        ));
+        assert!(is_explainable(
+            &doc! { "aggregate": "books", "pipeline": [] }
+        ));
+        assert!(is_explainable(&doc! { "find": "books", "filter": {} }));
+        assert!(!is_explainable(
+            &doc! { "insert": "books", "documents": [] }
+        ));
+        assert!(!is_explainable(&doc! {}));
+    }
+
+    #[test]
+    fn numbers_commands_in_explain_details_when_an_operation_runs_several() {
+        assert_eq!(
+            ExplainDetailKeys::new(2, 0, 1),
+            ExplainDetailKeys {
+                command: "operations[2].command".to_owned(),
+                plan: "operations[2].plan".to_owned(),
+            }
+        );
+        assert_eq!(
+            ExplainDetailKeys::new(0, 1, 2),
+            ExplainDetailKeys {
+                command: "operations[0].commands[1]".to_owned(),
+                plan: "operations[0].plans[1]".to_owned(),
+            }
+        );
+    }
+}
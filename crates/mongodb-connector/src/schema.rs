@@ -234,7 +234,10 @@ mod tests {
                 ),
             ]),
             options: ConfigurationOptions {
-                relational_mode: RelationalModeConfig { enabled: true },
+                relational_mode: RelationalModeConfig {
+                    enabled: true,
+                    ..Default::default()
+                },
                 ..Default::default()
             },
             ..Default::default()
@@ -134,6 +134,63 @@ pub enum Stage {
         accumulators: BTreeMap<String, Accumulator>,
     },
 
+    /// Categorizes incoming documents into groups, called buckets, based on a specified expression
+    /// and bucket boundaries and outputs a document per each bucket. The output documents contain
+    /// an `_id` field with the inclusive lower boundary of the bucket, and a `count` field unless
+    /// `output` is specified.
+    ///
+    /// See https://www.mongodb.com/docs/manual/reference/operator/aggregation/bucket/
+    #[serde(rename = "$bucket", rename_all = "camelCase")]
+    Bucket {
+        /// An expression to group documents by.
+        group_by: bson::Bson,
+
+        /// An array of values that specify the boundaries for each bucket. Values must be in
+        /// ascending order and all of the same type.
+        boundaries: Vec<bson::Bson>,
+
+        /// Optional. A literal that specifies the `_id` of an additional bucket that contains all
+        /// documents whose `group_by` value falls outside of the boundaries.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        default: Option<bson::Bson>,
+
+        /// Optional. Fields to include in output documents in addition to `_id`, and the
+        /// accumulators that compute them. If omitted output documents have a `count` field.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        output: Option<BTreeMap<String, Accumulator>>,
+    },
+
+    /// Categorizes incoming documents into a specified number of groups, called buckets, with
+    /// automatically determined boundaries. Output documents have an `_id` field with `min` and
+    /// `max` bounds, and a `count` field unless `output` is specified.
+    ///
+    /// See https://www.mongodb.com/docs/manual/reference/operator/aggregation/bucketAuto/
+    #[serde(rename = "$bucketAuto", rename_all = "camelCase")]
+    BucketAuto {
+        /// An expression to group documents by.
+        group_by: bson::Bson,
+
+        /// The number of buckets into which input documents are grouped.
+        buckets: bson::Bson,
+
+        /// Optional. Fields to include in output documents in addition to `_id`, and the
+        /// accumulators that compute them. If omitted output documents have a `count` field.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        output: Option<BTreeMap<String, Accumulator>>,
+
+        /// Optional. A preferred number series to use to calculate boundary edges.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        granularity: Option<String>,
+    },
+
+    /// Groups incoming documents based on the value of a specified expression, then computes the
+    /// count of documents in each distinct group. Output documents have `_id` and `count` fields,
+    /// and are sorted by count in descending order.
+    ///
+    /// See https://www.mongodb.com/docs/manual/reference/operator/aggregation/sortByCount/
+    #[serde(rename = "$sortByCount")]
+    SortByCount(bson::Bson),
+
     /// Processes multiple aggregation pipelines within a single stage on the same set of input
     /// documents. Enables the creation of multi-faceted aggregations capable of characterizing
     /// data across multiple dimensions, or facets, in a single stage.
@@ -221,8 +278,122 @@ pub enum Stage {
         pipeline: Option<Pipeline>,
     },
 
+    /// Performs operations on a specified span of documents in a collection, known as a window,
+    /// and returns the results based on the chosen window operator. Output documents are the
+    /// input documents with the fields from `output` added.
+    ///
+    /// See https://www.mongodb.com/docs/manual/reference/operator/aggregation/setWindowFields/
+    #[serde(rename = "$setWindowFields", rename_all = "camelCase")]
+    SetWindowFields {
+        /// Optional. An expression to group documents by. Windows do not span partitions.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        partition_by: Option<bson::Bson>,
+
+        /// Optional. The order of documents in each partition.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sort_by: Option<SortDocument>,
+
+        /// Fields to add to output documents. Each value is a document with a single window
+        /// operator, such as `$sum` or `$rank`, and an optional `window` field that bounds the
+        /// documents that the operator is applied to.
+        output: BTreeMap<String, bson::Document>,
+    },
+
+    /// Creates new documents in a sequence of documents where certain values in a field are
+    /// missing. New documents contain only the densified field, and partition fields if given.
+    ///
+    /// See https://www.mongodb.com/docs/manual/reference/operator/aggregation/densify/
+    #[serde(rename = "$densify", rename_all = "camelCase")]
+    Densify {
+        /// The field to densify.
+        field: String,
+
+        /// Optional. Fields to group documents by. Each group is densified separately.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        partition_by_fields: Option<Vec<String>>,
+
+        /// Specifies how the data is densified, including the step between values.
+        range: bson::Document,
+    },
+
+    /// Populates null and missing field values within documents.
+    ///
+    /// See https://www.mongodb.com/docs/manual/reference/operator/aggregation/fill/
+    #[serde(rename = "$fill", rename_all = "camelCase")]
+    Fill {
+        /// Optional. An expression to group documents by. Cannot be used with
+        /// `partition_by_fields`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        partition_by: Option<bson::Bson>,
+
+        /// Optional. Fields to group documents by. Cannot be used with `partition_by`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        partition_by_fields: Option<Vec<String>>,
+
+        /// Optional. The order of documents in each partition. Required when filling with the
+        /// `linear` or `locf` methods.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sort_by: Option<SortDocument>,
+
+        /// Fields to fill, and how to fill each one. Each value is a document with either a
+        /// `value` expression or a `method` of `linear` or `locf`.
+        output: BTreeMap<String, bson::Document>,
+    },
+
+    /// Removes fields from documents. Field names may use dot notation to remove embedded fields.
+    ///
+    /// See https://www.mongodb.com/docs/manual/reference/operator/aggregation/unset/
+    #[serde(rename = "$unset", with = "string_or_array")]
+    Unset(Vec<String>),
+
+    /// Randomly selects the specified number of documents from the input documents.
+    ///
+    /// See https://www.mongodb.com/docs/manual/reference/operator/aggregation/sample/
+    #[serde(rename = "$sample")]
+    Sample {
+        /// The number of documents to select.
+        size: bson::Bson,
+    },
+
+    /// Restricts the contents of documents based on information stored in the documents
+    /// themselves. The expression must evaluate to one of the system variables `$$DESCEND`,
+    /// `$$PRUNE`, or `$$KEEP`.
+    ///
+    /// See https://www.mongodb.com/docs/manual/reference/operator/aggregation/redact/
+    #[serde(rename = "$redact")]
+    Redact(bson::Bson),
+
     /// For cases where we receive pipeline stages from an external source, such as a native query,
     /// and we don't want to attempt to parse it we store the stage BSON document unaltered.
     #[serde(untagged)]
     Other(bson::Document),
 }
+
+/// `$unset` accepts either a single field name or an array of field names.
+mod string_or_array {
+    use serde::{Deserialize, Serialize as _};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrArray {
+        String(String),
+        Array(Vec<String>),
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(match StringOrArray::deserialize(deserializer)? {
+            StringOrArray::String(field) => vec![field],
+            StringOrArray::Array(fields) => fields,
+        })
+    }
+
+    pub fn serialize<S>(fields: &[String], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        fields.serialize(serializer)
+    }
+}
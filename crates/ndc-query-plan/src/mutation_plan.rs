@@ -31,8 +31,8 @@ pub enum MutationOperation<T: ConnectorTypes> {
         arguments: BTreeMap<ndc::ArgumentName, MutationProcedureArgument<T>>,
         /// The fields to return from the result, or null to return everything
         fields: Option<plan::NestedField<T>>,
-        /// Relationships referenced by fields and expressions in this query or sub-query. Does not
-        /// include relationships in sub-queries nested under this one.
+        /// Relationships referenced by the requested result fields. Does not include relationships
+        /// in sub-queries nested under those fields, or relationships referenced by arguments.
         relationships: plan::Relationships<T>,
     },
 }
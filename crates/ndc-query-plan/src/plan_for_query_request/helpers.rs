@@ -130,3 +130,15 @@ pub fn lookup_relationship<'a>(
         .get(relationship)
         .ok_or_else(|| QueryPlanError::UnspecifiedRelation(relationship.to_owned()))
 }
+
+/// Converts the field path that a relationship path element or an exists predicate may carry into
+/// a path to the nested object that the relationship is navigated from.
+pub fn source_path_from_field_path(
+    field_path: Option<Vec<ndc::FieldName>>,
+) -> Vec<plan::SourcePathElement> {
+    field_path
+        .into_iter()
+        .flatten()
+        .map(plan::SourcePathElement::Field)
+        .collect()
+}
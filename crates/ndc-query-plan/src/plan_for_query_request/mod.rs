@@ -333,7 +333,7 @@ fn plan_for_order_by_element<T: QueryContext>(
                 root_collection_object_type,
                 object_type,
                 path,
-                vec![], // TODO: ENG-1019 propagate requested aggregate to relationship query
+                vec![column.clone()],
             )?;
 
             let object_field = collection_object_type.get(&column)?;
@@ -369,7 +369,7 @@ fn plan_for_order_by_element<T: QueryContext>(
                 root_collection_object_type,
                 object_type,
                 path,
-                vec![], // TODO: ENG-1019 propagate requested aggregate to relationship query
+                vec![column.clone()],
             )?;
 
             let object_field = collection_object_type.get(&column)?;
@@ -407,7 +407,7 @@ fn plan_for_order_by_element<T: QueryContext>(
                 root_collection_object_type,
                 object_type,
                 path,
-                vec![], // TODO: ENG-1019 propagate requested aggregate to relationship query
+                vec![],
             )?;
             plan::OrderByTarget::Aggregate {
                 path: plan_path,
@@ -1,4 +1,4 @@
-use std::iter::once;
+use std::{borrow::Cow, iter::once};
 
 use indexmap::IndexMap;
 use itertools::Itertools as _;
@@ -9,8 +9,9 @@ use crate::{self as plan, QueryContext, QueryPlanError};
 use super::{
     helpers::{
         find_nested_collection_object_type, find_nested_collection_type, get_object_field_by_path,
-        lookup_relationship,
+        lookup_relationship, source_path_from_field_path,
     },
+    plan_for_aggregate,
     plan_for_arguments::plan_arguments_from_plan_parameters,
     plan_for_relationship::plan_for_relationship_path,
     query_plan_state::QueryPlanState,
@@ -51,7 +52,12 @@ pub fn plan_for_expression<T: QueryContext>(
         }),
         ndc::Expression::UnaryComparisonOperator { column, operator } => {
             Ok(plan::Expression::UnaryComparisonOperator {
-                column: plan_for_comparison_target(plan_state, object_type, column)?,
+                column: plan_for_comparison_target(
+                    plan_state,
+                    root_collection_object_type,
+                    object_type,
+                    column,
+                )?,
                 operator,
             })
         }
@@ -80,6 +86,7 @@ pub fn plan_for_expression<T: QueryContext>(
         } => plan_for_exists(
             plan_state,
             root_collection_object_type,
+            object_type,
             in_collection,
             predicate,
         ),
@@ -94,11 +101,12 @@ fn plan_for_binary_comparison<T: QueryContext>(
     operator: ndc::ComparisonOperatorName,
     value: ndc::ComparisonValue,
 ) -> Result<plan::Expression<T>> {
-    let comparison_target = plan_for_comparison_target(plan_state, object_type, column)?;
+    let comparison_target =
+        plan_for_comparison_target(plan_state, root_collection_object_type, object_type, column)?;
     let (operator, operator_definition) = plan_state
         .context
-        .find_comparison_operator(comparison_target.target_type(), &operator)?;
-    let value_type = operator_definition.argument_type(comparison_target.target_type());
+        .find_comparison_operator(&comparison_target.target_type(), &operator)?;
+    let value_type = operator_definition.argument_type(&comparison_target.target_type());
     Ok(plan::Expression::BinaryComparisonOperator {
         operator,
         value: plan_for_comparison_value(
@@ -119,12 +127,13 @@ fn plan_for_array_comparison<T: QueryContext>(
     column: ndc::ComparisonTarget,
     comparison: ndc::ArrayComparison,
 ) -> Result<plan::Expression<T>> {
-    let comparison_target = plan_for_comparison_target(plan_state, object_type, column)?;
+    let comparison_target =
+        plan_for_comparison_target(plan_state, root_collection_object_type, object_type, column)?;
     let plan_comparison = match comparison {
         ndc::ArrayComparison::Contains { value } => {
             let array_element_type = comparison_target
                 .target_type()
-                .clone()
+                .into_owned()
                 .into_array_element_type()?;
             let value = plan_for_comparison_value(
                 plan_state,
@@ -145,6 +154,7 @@ fn plan_for_array_comparison<T: QueryContext>(
 
 fn plan_for_comparison_target<T: QueryContext>(
     plan_state: &mut QueryPlanState<'_, T>,
+    root_collection_object_type: &plan::ObjectType<T::ScalarType>,
     object_type: &plan::ObjectType<T::ScalarType>,
     target: ndc::ComparisonTarget,
 ) -> Result<plan::ComparisonTarget<T>> {
@@ -168,11 +178,30 @@ fn plan_for_comparison_target<T: QueryContext>(
                 field_type: object_field.r#type,
             })
         }
-        ndc::ComparisonTarget::Aggregate { .. } => {
-            // TODO: ENG-1457 implement query.aggregates.filter_by
-            Err(QueryPlanError::NotImplemented(
-                "filter by aggregate".to_string(),
-            ))
+        ndc::ComparisonTarget::Aggregate { path, aggregate } => {
+            if path.is_empty() {
+                return Err(QueryPlanError::NotImplemented(
+                    "filtering by an aggregate without a relationship path".into(),
+                ));
+            }
+            // Related rows need to include the aggregated column
+            let requested_columns = match &aggregate {
+                ndc::Aggregate::ColumnCount { column, .. }
+                | ndc::Aggregate::SingleColumn { column, .. } => vec![column.clone()],
+                ndc::Aggregate::StarCount {} => vec![],
+            };
+            let (plan_path, collection_object_type) = plan_for_relationship_path(
+                plan_state,
+                root_collection_object_type,
+                object_type,
+                path,
+                requested_columns,
+            )?;
+            let aggregate = plan_for_aggregate(plan_state, &collection_object_type, aggregate)?;
+            Ok(plan::ComparisonTarget::Aggregate {
+                path: plan_path,
+                aggregate,
+            })
         }
     }
 }
@@ -192,10 +221,18 @@ fn plan_for_comparison_value<T: QueryContext>(
             field_path,
             scope,
         } => {
+            // A non-zero scope index references a collection that encloses an exists expression
+            let (scope, scope_object_type) = match scope {
+                Some(index) if index > 0 => {
+                    let (scope, scope_object_type) = plan_state.resolve_scope(index)?;
+                    (Some(scope), Cow::Owned(scope_object_type))
+                }
+                _ => (None, Cow::Borrowed(object_type)),
+            };
             let (plan_path, collection_object_type) = plan_for_relationship_path(
                 plan_state,
                 root_collection_object_type,
-                object_type,
+                &scope_object_type,
                 path,
                 vec![name.clone()],
             )?;
@@ -231,16 +268,17 @@ fn plan_for_comparison_value<T: QueryContext>(
 fn plan_for_exists<T: QueryContext>(
     plan_state: &mut QueryPlanState<'_, T>,
     root_collection_object_type: &plan::ObjectType<T::ScalarType>,
+    object_type: &plan::ObjectType<T::ScalarType>,
     in_collection: ExistsInCollection,
     predicate: Option<Box<ndc::Expression>>,
 ) -> Result<plan::Expression<T>> {
-    let mut nested_state = plan_state.state_for_subquery();
+    let (mut nested_state, exists_scope) = plan_state.state_for_exists(object_type);
 
     let (in_collection, predicate) = match in_collection {
         ndc::ExistsInCollection::Related {
             relationship,
             arguments,
-            field_path: _, // TODO: ENG-1490 requires propagating this, probably through the `register_relationship` call
+            field_path,
         } => {
             let ndc_relationship =
                 lookup_relationship(plan_state.collection_relationships, &relationship)?;
@@ -259,8 +297,9 @@ fn plan_for_exists<T: QueryContext>(
                 })
                 .transpose()?;
 
-            // TODO: ENG-1457 When we implement query.aggregates.filter_by we'll need to collect aggregates
-            // here as well as fields.
+            // Aggregate comparison targets are not collected here. They reference relationships of
+            // the related collection which are registered in `nested_state` when the predicate is
+            // planned.
             let fields = predicate.as_ref().map(|p| {
                 let mut fields = IndexMap::new();
                 for comparison_target in p.query_local_comparison_targets() {
@@ -278,6 +317,7 @@ fn plan_for_exists<T: QueryContext>(
                                 column_type: field_type,
                             },
                         ),
+                        plan::ComparisonTarget::Aggregate { .. } => None,
                     };
                 }
                 fields
@@ -289,8 +329,12 @@ fn plan_for_exists<T: QueryContext>(
                 ..Default::default()
             };
 
-            let relationship_key =
-                plan_state.register_relationship(relationship, arguments, relationship_query)?;
+            let relationship_key = plan_state.register_relationship(
+                relationship,
+                source_path_from_field_path(field_path),
+                arguments,
+                relationship_query,
+            )?;
 
             let in_collection = plan::ExistsInCollection::Related {
                 relationship: relationship_key,
@@ -335,7 +379,7 @@ fn plan_for_exists<T: QueryContext>(
             arguments,
             field_path,
         } => {
-            let object_field = root_collection_object_type.get(&column_name)?;
+            let object_field = object_type.get(&column_name)?;
             let plan_arguments = plan_arguments_from_plan_parameters(
                 &mut nested_state,
                 &object_field.parameters,
@@ -343,11 +387,9 @@ fn plan_for_exists<T: QueryContext>(
             )?;
 
             let nested_collection_type = find_nested_collection_object_type(
-                root_collection_object_type.clone(),
-                &field_path
-                    .clone()
-                    .into_iter()
-                    .chain(once(column_name.clone()))
+                object_type.clone(),
+                &once(column_name.clone())
+                    .chain(field_path.clone())
                     .collect_vec(),
             )?;
 
@@ -375,7 +417,7 @@ fn plan_for_exists<T: QueryContext>(
             arguments,
             field_path,
         } => {
-            let object_field = root_collection_object_type.get(&column_name)?;
+            let object_field = object_type.get(&column_name)?;
             let plan_arguments = plan_arguments_from_plan_parameters(
                 &mut nested_state,
                 &object_field.parameters,
@@ -383,11 +425,9 @@ fn plan_for_exists<T: QueryContext>(
             )?;
 
             let nested_collection_type = find_nested_collection_type(
-                root_collection_object_type.clone(),
-                &field_path
-                    .clone()
-                    .into_iter()
-                    .chain(once(column_name.clone()))
+                object_type.clone(),
+                &once(column_name.clone())
+                    .chain(field_path.clone())
                     .collect_vec(),
             )?;
 
@@ -427,5 +467,6 @@ fn plan_for_exists<T: QueryContext>(
     Ok(plan::Expression::Exists {
         in_collection,
         predicate: predicate.map(Box::new),
+        scope: exists_scope.into_scope(),
     })
 }
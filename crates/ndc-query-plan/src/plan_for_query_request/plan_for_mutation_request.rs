@@ -36,22 +36,23 @@ fn plan_for_mutation_operation<T: QueryContext>(
             arguments,
             fields,
         } => {
-            let mut plan_state = QueryPlanState::new(context, collection_relationships);
-
             let procedure_info = context.find_procedure(&name)?;
 
+            // Arguments are planned separately from result fields so that relationships referenced
+            // by predicate arguments are not looked up when selecting result fields.
+            let mut arguments_plan_state = QueryPlanState::new(context, collection_relationships);
             let arguments = plan_for_mutation_procedure_arguments(
-                &mut plan_state,
+                &mut arguments_plan_state,
                 &procedure_info.arguments,
                 arguments,
             )?;
 
+            let mut fields_plan_state = QueryPlanState::new(context, collection_relationships);
             let fields = fields
                 .map(|nested_field| {
                     let result_type = context.ndc_to_plan_type(&procedure_info.result_type)?;
                     let plan_nested_field = type_annotated_nested_field(
-                        context,
-                        collection_relationships,
+                        &mut fields_plan_state,
                         &result_type,
                         nested_field,
                     )?;
@@ -59,7 +60,7 @@ fn plan_for_mutation_operation<T: QueryContext>(
                 })
                 .transpose()?;
 
-            let relationships = plan_state.into_relationships();
+            let relationships = fields_plan_state.into_relationships();
 
             Ok(plan::MutationOperation::Procedure {
                 name,
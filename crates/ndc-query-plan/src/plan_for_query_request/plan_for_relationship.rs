@@ -4,7 +4,7 @@ use crate::{self as plan, ObjectType, QueryContext, QueryPlanError};
 use ndc_models::{self as ndc};
 
 use super::{
-    helpers::{find_object_field, lookup_relationship},
+    helpers::{find_object_field, lookup_relationship, source_path_from_field_path},
     plan_for_expression,
     query_plan_state::QueryPlanState,
 };
@@ -67,7 +67,7 @@ fn plan_for_relationship_path_helper<T: QueryContext>(
     let is_last = tail.is_empty();
 
     let ndc::PathElement {
-        field_path: _, // TODO: ENG-1458 support nested relationships
+        field_path,
         relationship,
         arguments,
         predicate,
@@ -129,8 +129,12 @@ fn plan_for_relationship_path_helper<T: QueryContext>(
         ..Default::default()
     };
 
-    let relation_key =
-        plan_state.register_relationship(relationship, arguments, relationship_query)?;
+    let relation_key = plan_state.register_relationship(
+        relationship,
+        source_path_from_field_path(field_path),
+        arguments,
+        relationship_query,
+    )?;
 
     rest_path.push_front(relation_key);
     Ok(rest_path)
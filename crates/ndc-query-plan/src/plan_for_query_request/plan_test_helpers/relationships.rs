@@ -3,7 +3,7 @@ use std::collections::BTreeMap;
 use ndc_models::{FieldName, RelationshipType};
 use nonempty::NonEmpty;
 
-use crate::{ConnectorTypes, Field, Relationship, RelationshipArgument};
+use crate::{ConnectorTypes, Field, Relationship, RelationshipArgument, SourcePathElement};
 
 use super::QueryBuilder;
 
@@ -13,6 +13,7 @@ pub struct RelationshipBuilder<T: ConnectorTypes> {
     relationship_type: RelationshipType,
     target_collection: ndc_models::CollectionName,
     arguments: BTreeMap<ndc_models::ArgumentName, RelationshipArgument<T>>,
+    source_path: Vec<SourcePathElement>,
     query: QueryBuilder<T>,
 }
 
@@ -27,6 +28,7 @@ impl<T: ConnectorTypes> RelationshipBuilder<T> {
             relationship_type: RelationshipType::Array,
             target_collection: target.into(),
             arguments: Default::default(),
+            source_path: Default::default(),
             query: QueryBuilder::new(),
         }
     }
@@ -37,6 +39,7 @@ impl<T: ConnectorTypes> RelationshipBuilder<T> {
             relationship_type: self.relationship_type,
             target_collection: self.target_collection,
             arguments: self.arguments,
+            source_path: self.source_path,
             query: self.query.into(),
         }
     }
@@ -81,6 +84,11 @@ impl<T: ConnectorTypes> RelationshipBuilder<T> {
         self
     }
 
+    pub fn source_path(mut self, source_path: impl IntoIterator<Item = SourcePathElement>) -> Self {
+        self.source_path = source_path.into_iter().collect();
+        self
+    }
+
     pub fn query(mut self, query: QueryBuilder<T>) -> Self {
         self.query = query;
         self
@@ -52,6 +52,9 @@ pub enum QueryPlanError {
     #[error("Unknown scalar type, \"{0}\"")]
     UnknownScalarType(ndc::ScalarTypeName),
 
+    #[error("Unknown scope, {0}: there are not that many enclosing exists expressions")]
+    UnknownScope(usize),
+
     #[error("Unknown object type, \"{0}\"")]
     UnknownObjectType(String),
 
@@ -11,7 +11,8 @@ use crate::{
     plan_for_query_request::helpers::lookup_relationship,
     query_plan::{Scope, UnrelatedJoin, VariableTypes},
     vec_set::VecSet,
-    ConnectorTypes, Query, QueryContext, QueryPlanError, Relationship, Type,
+    ConnectorTypes, ObjectType, Query, QueryContext, QueryPlanError, Relationship,
+    SourcePathElement, Type,
 };
 
 use super::{
@@ -37,6 +38,28 @@ pub struct QueryPlanState<'a, T: QueryContext> {
     relationship_name_counter: Rc<Cell<i32>>,
     scope_name_counter: Rc<Cell<i32>>,
     variable_types: Rc<RefCell<VariableTypes<T::ScalarType>>>,
+
+    /// Collections that enclose `exists` expressions that we are currently inside of, innermost
+    /// last. Predicates in those expressions may reference columns of these collections.
+    enclosing_scopes: Vec<EnclosingScope<T::ScalarType>>,
+}
+
+#[derive(Clone, Debug)]
+struct EnclosingScope<S> {
+    object_type: ObjectType<S>,
+    scope: ExistsScope,
+}
+
+/// The scope that an `exists` expression introduces for the collection that encloses it. A scope
+/// name is assigned only if the predicate of the `exists` expression references the enclosing
+/// collection.
+#[derive(Clone, Debug, Default)]
+pub struct ExistsScope(Rc<RefCell<Option<Scope>>>);
+
+impl ExistsScope {
+    pub fn into_scope(self) -> Option<Scope> {
+        self.0.take()
+    }
 }
 
 impl<T: QueryContext> QueryPlanState<'_, T> {
@@ -53,6 +76,7 @@ impl<T: QueryContext> QueryPlanState<'_, T> {
             relationship_name_counter: Rc::new(Cell::new(0)),
             scope_name_counter: Rc::new(Cell::new(0)),
             variable_types: Rc::new(RefCell::new(Default::default())),
+            enclosing_scopes: Default::default(),
         }
     }
 
@@ -69,9 +93,52 @@ impl<T: QueryContext> QueryPlanState<'_, T> {
             relationship_name_counter: self.relationship_name_counter.clone(),
             scope_name_counter: self.scope_name_counter.clone(),
             variable_types: self.variable_types.clone(),
+            enclosing_scopes: Default::default(),
         }
     }
 
+    /// Get a new plan for the predicate of an `exists` expression. This is like
+    /// [Self::state_for_subquery] except that the collection enclosing the `exists` expression,
+    /// and collections enclosing any outer `exists` expressions, remain available as scopes that
+    /// the predicate can reference.
+    pub fn state_for_exists(
+        &self,
+        enclosing_object_type: &ObjectType<T::ScalarType>,
+    ) -> (QueryPlanState<'_, T>, ExistsScope) {
+        let exists_scope = ExistsScope::default();
+        let mut state = self.state_for_subquery();
+        state.enclosing_scopes = self.enclosing_scopes.clone();
+        state.enclosing_scopes.push(EnclosingScope {
+            object_type: enclosing_object_type.clone(),
+            scope: exists_scope.clone(),
+        });
+        (state, exists_scope)
+    }
+
+    /// Resolve a scope index from a comparison value to a scope name, and the object type of the
+    /// referenced collection. Index 1 refers to the collection enclosing the innermost `exists`
+    /// expression, index 2 to the collection enclosing the next `exists` expression out, and so
+    /// on. Index 0 refers to the current collection which does not need a scope name.
+    pub fn resolve_scope(&mut self, index: usize) -> Result<(Scope, ObjectType<T::ScalarType>)> {
+        let position = self
+            .enclosing_scopes
+            .len()
+            .checked_sub(index)
+            .filter(|_| index > 0)
+            .ok_or(QueryPlanError::UnknownScope(index))?;
+        let EnclosingScope { object_type, scope } = self.enclosing_scopes[position].clone();
+        let existing_name = scope.0.borrow().clone();
+        let name = match existing_name {
+            Some(name) => name,
+            None => {
+                let name = Scope::Named(self.unique_scope_name());
+                *scope.0.borrow_mut() = Some(name.clone());
+                name
+            }
+        };
+        Ok((name, object_type))
+    }
+
     pub fn new_scope(&mut self) {
         let name = self.unique_scope_name();
         self.scope = Scope::Named(name)
@@ -82,6 +149,7 @@ impl<T: QueryContext> QueryPlanState<'_, T> {
     pub fn register_relationship(
         &mut self,
         ndc_relationship_name: ndc::RelationshipName,
+        source_path: Vec<SourcePathElement>,
         arguments: BTreeMap<ndc::ArgumentName, ndc::RelationshipArgument>,
         query: Query<T>,
     ) -> Result<ndc::RelationshipName> {
@@ -117,6 +185,7 @@ impl<T: QueryContext> QueryPlanState<'_, T> {
             relationship_type: ndc_relationship.relationship_type,
             target_collection: ndc_relationship.target_collection.clone(),
             arguments,
+            source_path,
             query,
         };
 
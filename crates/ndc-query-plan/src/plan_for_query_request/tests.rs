@@ -5,513 +5,300 @@ use pretty_assertions::assert_eq;
 
 use crate::{
     self as plan,
-    plan_for_query_request::plan_test_helpers::{self, make_flat_schema, make_nested_schema},
+    plan_for_query_request::plan_test_helpers::{
+        self, make_flat_schema, make_nested_schema, TestContext,
+    },
     QueryContext, QueryPlan, Type,
 };
 
 use super::plan_for_query_request;
 
-// TODO: ENG-1487 we need named scopes to define this query in ndc-spec 0.2
-// #[test]
-// fn translates_query_request_relationships() -> Result<(), anyhow::Error> {
-//     let request = query_request()
-//         .collection("schools")
-//         .relationships([
-//             (
-//                 "school_classes",
-//                 relationship("classes", [("_id", &["school_id"])]),
-//             ),
-//             (
-//                 "class_students",
-//                 relationship("students", [("_id", &["class_id"])]),
-//             ),
-//             (
-//                 "class_department",
-//                 relationship("departments", [("department_id", &["_id"])]).object_type(),
-//             ),
-//             (
-//                 "school_directory",
-//                 relationship("directory", [("_id", &["school_id"])]).object_type(),
-//             ),
-//             (
-//                 "student_advisor",
-//                 relationship("advisors", [("advisor_id", &["_id"])]).object_type(),
-//             ),
-//             (
-//                 "existence_check",
-//                 relationship("some_collection", [("some_id", &["_id"])]),
-//             ),
-//         ])
-//         .query(
-//             query()
-//                 .fields([relation_field!("class_name" => "school_classes", query()
-//                     .fields([
-//                         relation_field!("student_name" => "class_students")
-//                     ])
-//                 )])
-//                 .order_by(vec![ndc::OrderByElement {
-//                     order_direction: OrderDirection::Asc,
-//                     target: OrderByTarget::Column {
-//                         name: "advisor_name".into(),
-//                         arguments: Default::default(),
-//                         field_path: None,
-//                         path: vec![
-//                             path_element("school_classes")
-//                                 .predicate(
-//                                     exists(
-//                                         in_related("class_department"),
-//                                         binop(
-//                                             "Equal",
-//                                             target!("_id"),
-//                                             column_value("math_department_id")
-//                                                 .path([path_element("school_directory")])
-//                                                 .scope(2)
-//                                                 .into()
-//                                             ),
-//                                         )
-//                                 )
-//                                 .into(),
-//                             path_element("class_students").into(),
-//                             path_element("student_advisor").into(),
-//                         ],
-//                     },
-//                 }])
-//                 // The `And` layer checks that we properly recurse into Expressions
-//                 .predicate(and([ndc::Expression::Exists {
-//                     in_collection: related!("existence_check"),
-//                     predicate: None,
-//                 }])),
-//         )
-//         .into();
-//
-//     let expected = QueryPlan {
-//         collection: "schools".into(),
-//         arguments: Default::default(),
-//         variables: None,
-//         variable_types: Default::default(),
-//         unrelated_collections: Default::default(),
-//         query: Query {
-//             predicate: Some(Expression::And {
-//                 expressions: vec![Expression::Exists {
-//                     in_collection: ExistsInCollection::Related {
-//                         relationship: "existence_check".into(),
-//                     },
-//                     predicate: None,
-//                 }],
-//             }),
-//             order_by: Some(OrderBy {
-//                 elements: [plan::OrderByElement {
-//                     order_direction: OrderDirection::Asc,
-//                     target: plan::OrderByTarget::Column {
-//                         name: "advisor_name".into(),
-//                         arguments: Default::default(),
-//                         field_path: Default::default(),
-//                         path: [
-//                             "school_classes_0".into(),
-//                             "class_students".into(),
-//                             "student_advisor".into(),
-//                         ]
-//                             .into(),
-//                     },
-//                 }]
-//                     .into(),
-//             }),
-//             relationships: [
-//                 // We join on the school_classes relationship twice. This one is for the `order_by`
-//                 // comparison in the top-level request query
-//                 (
-//                     "school_classes_0".into(),
-//                     Relationship {
-//                         column_mapping: [("_id".into(), vec!["school_id".into()])].into(),
-//                         relationship_type: RelationshipType::Array,
-//                         target_collection: "classes".into(),
-//                         arguments: Default::default(),
-//                         query: Query {
-//                             predicate: Some(Expression::Exists {
-//                                 in_collection: ExistsInCollection::Related {
-//                                     relationship: "school_directory".into(),
-//                                 },
-//                                 predicate: Some(Box::new(plan::Expression::BinaryComparisonOperator {
-//                                     column: plan::ComparisonTarget::Column {
-//                                         name: "_id".into(),
-//                                         arguments: Default::default(),
-//                                         field_path: None,
-//                                         field_type: plan::Type::Scalar(
-//                                             plan_test_helpers::ScalarType::Int,
-//                                         ),
-//                                     },
-//                                     operator: plan_test_helpers::ComparisonOperator::Equal,
-//                                     value: plan::ComparisonValue::Column {
-//                                         name: "math_department_id".into(),
-//                                         arguments: Default::default(),
-//                                         field_path: None,
-//                                         field_type: plan::Type::Scalar(
-//                                             plan_test_helpers::ScalarType::Int,
-//                                         ),
-//                                         path: vec!["school_directory".into()],
-//                                         scope: Default::default(),
-//                                     },
-//                                 }))
-//                             }),
-//                             relationships: [(
-//                                 "class_department".into(),
-//                                 plan::Relationship {
-//                                     target_collection: "departments".into(),
-//                                     column_mapping: [("department_id".into(), vec!["_id".into()])].into(),
-//                                     relationship_type: RelationshipType::Object,
-//                                     arguments: Default::default(),
-//                                     query: plan::Query {
-//                                         fields: Some([
-//                                             ("_id".into(), plan::Field::Column { column: "_id".into(), fields: None, column_type: plan::Type::Scalar(plan_test_helpers::ScalarType::Int) })
-//                                         ].into()),
-//                                         ..Default::default()
-//                                     },
-//                                 },
-//                             ), (
-//                                     "class_students".into(),
-//                                     plan::Relationship {
-//                                         target_collection: "students".into(),
-//                                         column_mapping: [("_id".into(), vec!["class_id".into()])].into(),
-//                                         relationship_type: RelationshipType::Array,
-//                                         arguments: Default::default(),
-//                                         query: plan::Query {
-//                                             relationships: [(
-//                                                 "student_advisor".into(),
-//                                                 plan::Relationship {
-//                                                     column_mapping: [(
-//                                                         "advisor_id".into(),
-//                                                         vec!["_id".into()],
-//                                                     )]
-//                                                         .into(),
-//                                                     relationship_type: RelationshipType::Object,
-//                                                     target_collection: "advisors".into(),
-//                                                     arguments: Default::default(),
-//                                                     query: plan::Query {
-//                                                         fields: Some(
-//                                                             [(
-//                                                                 "advisor_name".into(),
-//                                                                 plan::Field::Column {
-//                                                                     column: "advisor_name".into(),
-//                                                                     fields: None,
-//                                                                     column_type: plan::Type::Scalar(plan_test_helpers::ScalarType::String),
-//                                                                 },
-//                                                             )]
-//                                                                 .into(),
-//                                                         ),
-//                                                         ..Default::default()
-//                                                     },
-//                                                 },
-//                                             )]
-//                                                 .into(),
-//                                             ..Default::default()
-//                                         },
-//                                     },
-//                                 ),
-//                                 (
-//                                     "school_directory".into(),
-//                                     Relationship {
-//                                         target_collection: "directory".into(),
-//                                         column_mapping: [("_id".into(), vec!["school_id".into()])].into(),
-//                                         relationship_type: RelationshipType::Object,
-//                                         arguments: Default::default(),
-//                                         query: Query {
-//                                             fields: Some([
-//                                                 ("math_department_id".into(), plan::Field::Column { column: "math_department_id".into(), fields: None, column_type: plan::Type::Scalar(plan_test_helpers::ScalarType::Int) })
-//                                             ].into()),
-//                                             ..Default::default()
-//                                         },
-//                                     },
-//                                 ),
-//                             ]
-//                                 .into(),
-//                             ..Default::default()
-//                         },
-//                     },
-//                 ),
-//                 // This is the second join on school_classes - this one provides the relationship
-//                 // field for the top-level request query
-//                 (
-//                     "school_classes".into(),
-//                     Relationship {
-//                         column_mapping: [("_id".into(), vec!["school_id".into()])].into(),
-//                         relationship_type: RelationshipType::Array,
-//                         target_collection: "classes".into(),
-//                         arguments: Default::default(),
-//                         query: Query {
-//                             fields: Some(
-//                                 [(
-//                                     "student_name".into(),
-//                                     plan::Field::Relationship {
-//                                         relationship: "class_students".into(),
-//                                         aggregates: None,
-//                                         fields: None,
-//                                     },
-//                                 )]
-//                                     .into(),
-//                             ),
-//                             relationships: [(
-//                                 "class_students".into(),
-//                                 plan::Relationship {
-//                                     target_collection: "students".into(),
-//                                     column_mapping: [("_id".into(), vec!["class_id".into()])].into(),
-//                                     relationship_type: RelationshipType::Array,
-//                                     arguments: Default::default(),
-//                                     query: Query {
-//                                         scope: Some(plan::Scope::Named("scope_1".into())),
-//                                         ..Default::default()
-//                                     },
-//                                 },
-//                             )].into(),
-//                             scope: Some(plan::Scope::Named("scope_0".into())),
-//                             ..Default::default()
-//                         },
-//                     },
-//                 ),
-//                 (
-//                     "existence_check".into(),
-//                     Relationship {
-//                         column_mapping: [("some_id".into(), vec!["_id".into()])].into(),
-//                         relationship_type: RelationshipType::Array,
-//                         target_collection: "some_collection".into(),
-//                         arguments: Default::default(),
-//                         query: Query {
-//                             predicate: None,
-//                             ..Default::default()
-//                         },
-//                     },
-//                 ),
-//             ]
-//                 .into(),
-//             fields: Some(
-//                 [(
-//                     "class_name".into(),
-//                     Field::Relationship {
-//                         relationship: "school_classes".into(),
-//                         aggregates: None,
-//                         fields: Some(
-//                             [(
-//                                 "student_name".into(),
-//                                 Field::Relationship {
-//                                     relationship: "class_students".into(),
-//                                     aggregates: None,
-//                                     fields: None,
-//                                 },
-//                             )]
-//                                 .into(),
-//                         ),
-//                     },
-//                 )]
-//                     .into(),
-//             ),
-//             scope: Some(plan::Scope::Root),
-//             ..Default::default()
-//         },
-//     };
-//
-//     let context = TestContext {
-//         collections: [
-//             collection("schools"),
-//             collection("classes"),
-//             collection("students"),
-//             collection("departments"),
-//             collection("directory"),
-//             collection("advisors"),
-//             collection("some_collection"),
-//         ]
-//         .into(),
-//         object_types: [
-//             ("schools".into(), object_type([("_id", named_type("Int"))])),
-//             (
-//                 "classes".into(),
-//                 object_type([
-//                     ("_id", named_type("Int")),
-//                     ("school_id", named_type("Int")),
-//                     ("department_id", named_type("Int")),
-//                 ]),
-//             ),
-//             (
-//                 "students".into(),
-//                 object_type([
-//                     ("_id", named_type("Int")),
-//                     ("class_id", named_type("Int")),
-//                     ("advisor_id", named_type("Int")),
-//                     ("student_name", named_type("String")),
-//                 ]),
-//             ),
-//             (
-//                 "departments".into(),
-//                 object_type([("_id", named_type("Int"))]),
-//             ),
-//             (
-//                 "directory".into(),
-//                 object_type([
-//                     ("_id", named_type("Int")),
-//                     ("school_id", named_type("Int")),
-//                     ("math_department_id", named_type("Int")),
-//                 ]),
-//             ),
-//             (
-//                 "advisors".into(),
-//                 object_type([
-//                     ("_id", named_type("Int")),
-//                     ("advisor_name", named_type("String")),
-//                 ]),
-//             ),
-//             (
-//                 "some_collection".into(),
-//                 object_type([("_id", named_type("Int")), ("some_id", named_type("Int"))]),
-//             ),
-//         ]
-//         .into(),
-//         ..Default::default()
-//     };
-//
-//     let query_plan = plan_for_query_request(&context, request)?;
-//
-//     assert_eq!(query_plan, expected);
-//     Ok(())
-// }
-
-// TODO: ENG-1487 update this test to use named scopes instead of root column reference
-
-// #[test]
-// fn translates_root_column_references() -> Result<(), anyhow::Error> {
-//     let query_context = make_flat_schema();
-//     let query = query_request()
-//         .collection("authors")
-//         .query(query().fields([field!("last_name")]).predicate(exists(
-//             unrelated!("articles"),
-//             and([
-//                 binop("Equal", target!("author_id"), column_value!(root("id"))),
-//                 binop("Regex", target!("title"), value!("Functional.*")),
-//             ]),
-//         )))
-//         .into();
-//     let query_plan = plan_for_query_request(&query_context, query)?;
-//
-//     let expected = QueryPlan {
-//         collection: "authors".into(),
-//         query: plan::Query {
-//             predicate: Some(plan::Expression::Exists {
-//                 in_collection: plan::ExistsInCollection::Unrelated {
-//                     unrelated_collection: "__join_articles_0".into(),
-//                 },
-//                 predicate: Some(Box::new(plan::Expression::And {
-//                     expressions: vec![
-//                         plan::Expression::BinaryComparisonOperator {
-//                             column: plan::ComparisonTarget::Column {
-//                                 name: "author_id".into(),
-//                                 field_path: Default::default(),
-//                                 field_type: plan::Type::Scalar(plan_test_helpers::ScalarType::Int),
-//                                 path: Default::default(),
-//                             },
-//                             operator: plan_test_helpers::ComparisonOperator::Equal,
-//                             value: plan::ComparisonValue::Column {
-//                                 column: plan::ComparisonTarget::ColumnInScope {
-//                                     name: "id".into(),
-//                                     field_path: Default::default(),
-//                                     field_type: plan::Type::Scalar(
-//                                         plan_test_helpers::ScalarType::Int,
-//                                     ),
-//                                     scope: plan::Scope::Root,
-//                                 },
-//                             },
-//                         },
-//                         plan::Expression::BinaryComparisonOperator {
-//                             column: plan::ComparisonTarget::Column {
-//                                 name: "title".into(),
-//                                 field_path: Default::default(),
-//                                 field_type: plan::Type::Scalar(
-//                                     plan_test_helpers::ScalarType::String,
-//                                 ),
-//                                 path: Default::default(),
-//                             },
-//                             operator: plan_test_helpers::ComparisonOperator::Regex,
-//                             value: plan::ComparisonValue::Scalar {
-//                                 value: json!("Functional.*"),
-//                                 value_type: plan::Type::Scalar(
-//                                     plan_test_helpers::ScalarType::String,
-//                                 ),
-//                             },
-//                         },
-//                     ],
-//                 })),
-//             }),
-//             fields: Some(
-//                 [(
-//                     "last_name".into(),
-//                     plan::Field::Column {
-//                         column: "last_name".into(),
-//                         fields: None,
-//                         column_type: plan::Type::Scalar(plan_test_helpers::ScalarType::String),
-//                     },
-//                 )]
-//                 .into(),
-//             ),
-//             scope: Some(plan::Scope::Root),
-//             ..Default::default()
-//         },
-//         unrelated_collections: [(
-//             "__join_articles_0".into(),
-//             UnrelatedJoin {
-//                 target_collection: "articles".into(),
-//                 arguments: Default::default(),
-//                 query: plan::Query {
-//                     predicate: Some(plan::Expression::And {
-//                         expressions: vec![
-//                             plan::Expression::BinaryComparisonOperator {
-//                                 column: plan::ComparisonTarget::Column {
-//                                     name: "author_id".into(),
-//                                     field_type: plan::Type::Scalar(
-//                                         plan_test_helpers::ScalarType::Int,
-//                                     ),
-//                                     field_path: None,
-//                                     path: vec![],
-//                                 },
-//                                 operator: plan_test_helpers::ComparisonOperator::Equal,
-//                                 value: plan::ComparisonValue::Column {
-//                                     column: plan::ComparisonTarget::ColumnInScope {
-//                                         name: "id".into(),
-//                                         scope: plan::Scope::Root,
-//                                         field_type: plan::Type::Scalar(
-//                                             plan_test_helpers::ScalarType::Int,
-//                                         ),
-//                                         field_path: None,
-//                                     },
-//                                 },
-//                             },
-//                             plan::Expression::BinaryComparisonOperator {
-//                                 column: plan::ComparisonTarget::Column {
-//                                     name: "title".into(),
-//                                     field_type: plan::Type::Scalar(
-//                                         plan_test_helpers::ScalarType::String,
-//                                     ),
-//                                     field_path: None,
-//                                     path: vec![],
-//                                 },
-//                                 operator: plan_test_helpers::ComparisonOperator::Regex,
-//                                 value: plan::ComparisonValue::Scalar {
-//                                     value: "Functional.*".into(),
-//                                     value_type: plan::Type::Scalar(
-//                                         plan_test_helpers::ScalarType::String,
-//                                     ),
-//                                 },
-//                             },
-//                         ],
-//                     }),
-//                     ..Default::default()
-//                 },
-//             },
-//         )]
-//         .into(),
-//         arguments: Default::default(),
-//         variables: Default::default(),
-//         variable_types: Default::default(),
-//     };
-//
-//     assert_eq!(query_plan, expected);
-//     Ok(())
-// }
+#[test]
+fn translates_query_request_relationships() -> Result<(), anyhow::Error> {
+    let request = query_request()
+        .collection("schools")
+        .relationships([
+            (
+                "school_classes",
+                relationship("classes", [("_id", &["school_id"])]),
+            ),
+            (
+                "class_students",
+                relationship("students", [("_id", &["class_id"])]),
+            ),
+            (
+                "class_department",
+                relationship("departments", [("department_id", &["_id"])]).object_type(),
+            ),
+            (
+                "student_advisor",
+                relationship("advisors", [("advisor_id", &["_id"])]).object_type(),
+            ),
+            (
+                "existence_check",
+                relationship("some_collection", [("some_id", &["_id"])]),
+            ),
+        ])
+        .query(
+            query()
+                .fields([relation_field!("class_name" => "school_classes", query()
+                    .fields([
+                        relation_field!("student_name" => "class_students")
+                    ])
+                )])
+                .order_by(vec![ndc::OrderByElement {
+                    order_direction: OrderDirection::Asc,
+                    target: OrderByTarget::Column {
+                        name: "advisor_name".into(),
+                        arguments: Default::default(),
+                        field_path: None,
+                        path: vec![
+                            path_element("school_classes")
+                                .predicate(exists(
+                                    in_related("class_department"),
+                                    binop(
+                                        "Equal",
+                                        target!("_id"),
+                                        column_value("department_id").scope(1).into(),
+                                    ),
+                                ))
+                                .into(),
+                            path_element("class_students").into(),
+                            path_element("student_advisor").into(),
+                        ],
+                    },
+                }])
+                // The `And` layer checks that we properly recurse into Expressions
+                .predicate(and([ndc::Expression::Exists {
+                    in_collection: related!("existence_check"),
+                    predicate: None,
+                }])),
+        )
+        .into();
+
+    let context = TestContext {
+        collections: [
+            collection("schools"),
+            collection("classes"),
+            collection("students"),
+            collection("departments"),
+            collection("advisors"),
+            collection("some_collection"),
+        ]
+        .into(),
+        object_types: [
+            ("schools".into(), object_type([("_id", named_type("Int"))])),
+            (
+                "classes".into(),
+                object_type([
+                    ("_id", named_type("Int")),
+                    ("school_id", named_type("Int")),
+                    ("department_id", named_type("Int")),
+                ]),
+            ),
+            (
+                "students".into(),
+                object_type([
+                    ("_id", named_type("Int")),
+                    ("class_id", named_type("Int")),
+                    ("advisor_id", named_type("Int")),
+                    ("student_name", named_type("String")),
+                ]),
+            ),
+            (
+                "departments".into(),
+                object_type([("_id", named_type("Int"))]),
+            ),
+            (
+                "advisors".into(),
+                object_type([
+                    ("_id", named_type("Int")),
+                    ("advisor_name", named_type("String")),
+                ]),
+            ),
+            (
+                "some_collection".into(),
+                object_type([("_id", named_type("Int")), ("some_id", named_type("Int"))]),
+            ),
+        ]
+        .into(),
+        ..Default::default()
+    };
+
+    let query_plan = plan_for_query_request(&context, request)?;
+
+    assert_eq!(
+        query_plan.query.predicate,
+        Some(plan::Expression::And {
+            expressions: vec![plan::Expression::Exists {
+                in_collection: plan::ExistsInCollection::Related {
+                    relationship: "existence_check".into(),
+                },
+                predicate: None,
+                scope: None,
+            }],
+        })
+    );
+
+    let Some(plan::OrderBy { elements }) = &query_plan.query.order_by else {
+        panic!("expected an order by clause");
+    };
+    let plan::OrderByTarget::Column { name, path, .. } = &elements[0].target else {
+        panic!("expected to order by a column");
+    };
+    assert_eq!(name.as_str(), "advisor_name");
+    assert_eq!(
+        path.iter()
+            .skip(1)
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["class_students", "student_advisor"]
+    );
+
+    // The `school_classes` relationship is joined twice: once with the predicate for ordering, and
+    // once for the relationship field. So the ordering join gets a unique name.
+    assert_ne!(path[0].as_str(), "school_classes");
+    let order_by_classes = &query_plan.query.relationships[&path[0]];
+    assert_eq!(order_by_classes.target_collection.as_str(), "classes");
+    assert_eq!(
+        order_by_classes
+            .query
+            .relationships
+            .keys()
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["class_department", "class_students"]
+    );
+
+    // The class encloses the exists expression in the path element predicate. It gets a named
+    // scope so that the predicate can compare department rows to a column of the class.
+    let Some(plan::Expression::Exists {
+        scope: Some(scope), ..
+    }) = &order_by_classes.query.predicate
+    else {
+        panic!("expected an exists predicate with a named scope");
+    };
+    let int_type = plan::Type::Scalar(plan_test_helpers::ScalarType::Int);
+    assert_eq!(
+        order_by_classes.query.predicate,
+        Some(plan::Expression::Exists {
+            in_collection: plan::ExistsInCollection::Related {
+                relationship: "class_department".into(),
+            },
+            predicate: Some(Box::new(plan::Expression::BinaryComparisonOperator {
+                column: plan::ComparisonTarget::column("_id", int_type.clone()),
+                operator: plan_test_helpers::ComparisonOperator::Equal,
+                value: plan::ComparisonValue::Column {
+                    path: Default::default(),
+                    name: "department_id".into(),
+                    arguments: Default::default(),
+                    field_path: None,
+                    field_type: int_type,
+                    scope: Some(scope.clone()),
+                },
+            })),
+            scope: Some(scope.clone()),
+        })
+    );
+
+    let field_classes = &query_plan.query.relationships["school_classes"];
+    assert_eq!(field_classes.query.predicate, None);
+    assert!(field_classes
+        .query
+        .relationships
+        .contains_key("class_students"));
+    Ok(())
+}
+
+#[test]
+fn translates_column_references_in_named_scopes() -> Result<(), anyhow::Error> {
+    let query_context = make_flat_schema();
+    let query = query_request()
+        .collection("authors")
+        .query(query().fields([field!("last_name")]).predicate(exists(
+            unrelated!("articles"),
+            and([
+                binop(
+                    "Equal",
+                    target!("author_id"),
+                    column_value("id").scope(1).into(),
+                ),
+                binop("Regex", target!("title"), value!("Functional.*")),
+            ]),
+        )))
+        .into();
+    let query_plan = plan_for_query_request(&query_context, query)?;
+
+    let int_type = plan::Type::Scalar(plan_test_helpers::ScalarType::Int);
+    let string_type = plan::Type::Scalar(plan_test_helpers::ScalarType::String);
+    let join_predicate = plan::Expression::And {
+        expressions: vec![
+            plan::Expression::BinaryComparisonOperator {
+                column: plan::ComparisonTarget::column("author_id", int_type.clone()),
+                operator: plan_test_helpers::ComparisonOperator::Equal,
+                value: plan::ComparisonValue::Column {
+                    path: Default::default(),
+                    name: "id".into(),
+                    arguments: Default::default(),
+                    field_path: None,
+                    field_type: int_type,
+                    scope: Some(plan::Scope::Named("scope_0".into())),
+                },
+            },
+            plan::Expression::BinaryComparisonOperator {
+                column: plan::ComparisonTarget::column("title", string_type.clone()),
+                operator: plan_test_helpers::ComparisonOperator::Regex,
+                value: plan::ComparisonValue::Scalar {
+                    value: "Functional.*".into(),
+                    value_type: string_type.clone(),
+                },
+            },
+        ],
+    };
+    let expected = QueryPlan {
+        collection: "authors".into(),
+        query: plan::Query {
+            predicate: Some(plan::Expression::Exists {
+                in_collection: plan::ExistsInCollection::Unrelated {
+                    unrelated_collection: "__join_articles_0".into(),
+                },
+                predicate: Some(Box::new(join_predicate.clone())),
+                scope: Some(plan::Scope::Named("scope_0".into())),
+            }),
+            fields: Some(
+                [(
+                    "last_name".into(),
+                    plan::Field::Column {
+                        column: "last_name".into(),
+                        fields: None,
+                        column_type: string_type,
+                    },
+                )]
+                .into(),
+            ),
+            scope: Some(plan::Scope::Root),
+            ..Default::default()
+        },
+        unrelated_collections: [(
+            "__join_articles_0".into(),
+            plan::UnrelatedJoin {
+                target_collection: "articles".into(),
+                arguments: Default::default(),
+                query: plan::Query {
+                    predicate: Some(join_predicate),
+                    ..Default::default()
+                },
+            },
+        )]
+        .into(),
+        arguments: Default::default(),
+        variables: Default::default(),
+        variable_types: Default::default(),
+    };
+
+    assert_eq!(query_plan, expected);
+    Ok(())
+}
 
 #[test]
 fn translates_aggregate_selections() -> Result<(), anyhow::Error> {
@@ -636,6 +423,7 @@ fn translates_relationships_in_fields_predicates_and_orderings() -> Result<(), a
                         value_type: plan::Type::Scalar(plan_test_helpers::ScalarType::String),
                     },
                 })),
+                scope: None,
             }),
             order_by: Some(plan::OrderBy {
                 elements: vec![
@@ -723,6 +511,7 @@ fn translates_relationships_in_fields_predicates_and_orderings() -> Result<(), a
                     column_mapping: [("id".into(), NonEmpty::singleton("author_id".into()))].into(),
                     relationship_type: RelationshipType::Array,
                     arguments: Default::default(),
+                    source_path: Default::default(),
                     query: plan::Query {
                         fields: Some(
                             [
@@ -878,6 +667,72 @@ fn translates_nested_fields() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[test]
+fn registers_relationship_with_aggregated_column_for_aggregate_comparison() -> anyhow::Result<()> {
+    let query_context = make_flat_schema();
+    let query = query_request()
+        .collection("authors")
+        .query(query().fields([field!("last_name")]).predicate(binop(
+            "Equal",
+            ndc::ComparisonTarget::Aggregate {
+                path: vec![path_element("author_articles").into()],
+                aggregate: ndc::Aggregate::SingleColumn {
+                    column: "year".into(),
+                    arguments: Default::default(),
+                    field_path: None,
+                    function: "Average".into(),
+                },
+            },
+            value!(2000.0),
+        )))
+        .relationships([(
+            "author_articles",
+            relationship("articles", [("id", &["author_id"])]),
+        )])
+        .into();
+    let query_plan = plan_for_query_request(&query_context, query)?;
+
+    let result_type = plan::Type::Scalar(plan_test_helpers::ScalarType::Double).into_nullable();
+    let expected_predicate = plan::Expression::BinaryComparisonOperator {
+        column: plan::ComparisonTarget::Aggregate {
+            path: vec!["author_articles".into()],
+            aggregate: plan::Aggregate::SingleColumn {
+                column: "year".into(),
+                column_type: Type::scalar(plan_test_helpers::ScalarType::Int).into_nullable(),
+                arguments: Default::default(),
+                field_path: Default::default(),
+                function: plan_test_helpers::AggregateFunction::Average,
+                result_type: result_type.clone(),
+            },
+        },
+        operator: plan_test_helpers::ComparisonOperator::Equal,
+        value: plan::ComparisonValue::Scalar {
+            value: 2000.0.into(),
+            value_type: result_type,
+        },
+    };
+    assert_eq!(query_plan.query.predicate, Some(expected_predicate));
+
+    let relationship = &query_plan.query.relationships["author_articles"];
+    assert_eq!(
+        relationship.query.fields,
+        Some(
+            [(
+                "year".into(),
+                plan::Field::Column {
+                    column: "year".into(),
+                    column_type: plan::Type::Nullable(Box::new(plan::Type::Scalar(
+                        plan_test_helpers::ScalarType::Int,
+                    ))),
+                    fields: None,
+                },
+            )]
+            .into()
+        )
+    );
+    Ok(())
+}
+
 #[test]
 fn translates_predicate_referencing_field_of_related_collection() -> anyhow::Result<()> {
     let query_context = make_nested_schema();
@@ -912,6 +767,7 @@ fn translates_predicate_referencing_field_of_related_collection() -> anyhow::Res
                         operator: ndc_models::UnaryComparisonOperator::IsNull,
                     }),
                 })),
+                scope: None,
             }),
             fields: Some(
                 [(
@@ -944,6 +800,7 @@ fn translates_predicate_referencing_field_of_related_collection() -> anyhow::Res
                     relationship_type: RelationshipType::Array,
                     target_collection: "authors".into(),
                     arguments: Default::default(),
+                    source_path: Default::default(),
                     query: plan::Query {
                         fields: Some(
                             [(
@@ -976,3 +833,307 @@ fn translates_predicate_referencing_field_of_related_collection() -> anyhow::Res
     assert_eq!(query_plan, expected);
     Ok(())
 }
+
+#[test]
+fn translates_nested_collection_queries() -> anyhow::Result<()> {
+    let query_context = make_nested_schema();
+    let request = query_request()
+        .collection("authors")
+        .query(
+            query().fields([field!("first_articles" => "articles", nested_collection!(
+                query()
+                    .fields([field!("title")])
+                    .predicate(binop("Equal", target!("title"), value!("Hello")))
+                    .order_by([asc!("title")])
+                    .limit(2)
+            ))]),
+        )
+        .into();
+    let query_plan = plan_for_query_request(&query_context, request)?;
+
+    let title_type = plan::Type::Scalar(plan_test_helpers::ScalarType::String);
+    let expected = QueryPlan {
+        collection: "authors".into(),
+        query: plan::Query {
+            fields: Some(
+                [(
+                    "first_articles".into(),
+                    plan::Field::Column {
+                        column: "articles".into(),
+                        column_type: plan::Type::ArrayOf(Box::new(plan::Type::Object(
+                            query_context.find_object_type(&"Article".into())?,
+                        ))),
+                        fields: Some(plan::NestedField::Collection(plan::NestedCollection {
+                            query: plan::Query {
+                                fields: Some(
+                                    [(
+                                        "title".into(),
+                                        plan::Field::Column {
+                                            column: "title".into(),
+                                            fields: None,
+                                            column_type: title_type.clone(),
+                                        },
+                                    )]
+                                    .into(),
+                                ),
+                                predicate: Some(plan::Expression::BinaryComparisonOperator {
+                                    column: plan::ComparisonTarget::column(
+                                        "title",
+                                        title_type.clone(),
+                                    ),
+                                    operator: plan_test_helpers::ComparisonOperator::Equal,
+                                    value: plan::ComparisonValue::Scalar {
+                                        value: "Hello".into(),
+                                        value_type: title_type.clone(),
+                                    },
+                                }),
+                                order_by: Some(plan::OrderBy {
+                                    elements: vec![plan::OrderByElement {
+                                        order_direction: OrderDirection::Asc,
+                                        target: plan::OrderByTarget::Column {
+                                            path: Default::default(),
+                                            name: "title".into(),
+                                            arguments: Default::default(),
+                                            field_path: None,
+                                        },
+                                    }],
+                                }),
+                                limit: Some(2),
+                                ..Default::default()
+                            },
+                        })),
+                    },
+                )]
+                .into(),
+            ),
+            scope: Some(plan::Scope::Root),
+            ..Default::default()
+        },
+        arguments: Default::default(),
+        variables: Default::default(),
+        variable_types: Default::default(),
+        unrelated_collections: Default::default(),
+    };
+
+    assert_eq!(query_plan, expected);
+    Ok(())
+}
+
+#[test]
+fn translates_exists_predicate_referencing_enclosing_scope() -> anyhow::Result<()> {
+    let query_context = make_nested_schema();
+    let request = query_request()
+        .collection("authors")
+        .query(query().fields([field!("name")]).predicate(exists(
+            exists_in_nested("articles"),
+            binop(
+                "Equal",
+                target!("title"),
+                column_value("name").scope(1).into(),
+            ),
+        )))
+        .into();
+    let query_plan = plan_for_query_request(&query_context, request)?;
+
+    let string_type = plan::Type::Scalar(plan_test_helpers::ScalarType::String);
+    let expected = QueryPlan {
+        collection: "authors".into(),
+        query: plan::Query {
+            fields: Some(
+                [(
+                    "name".into(),
+                    plan::Field::Column {
+                        column: "name".into(),
+                        fields: None,
+                        column_type: string_type.clone(),
+                    },
+                )]
+                .into(),
+            ),
+            predicate: Some(plan::Expression::Exists {
+                in_collection: plan::ExistsInCollection::NestedCollection {
+                    column_name: "articles".into(),
+                    arguments: Default::default(),
+                    field_path: Default::default(),
+                },
+                predicate: Some(Box::new(plan::Expression::BinaryComparisonOperator {
+                    column: plan::ComparisonTarget::column("title", string_type.clone()),
+                    operator: plan_test_helpers::ComparisonOperator::Equal,
+                    value: plan::ComparisonValue::Column {
+                        path: Default::default(),
+                        name: "name".into(),
+                        arguments: Default::default(),
+                        field_path: None,
+                        field_type: string_type.clone(),
+                        scope: Some(plan::Scope::Named("scope_0".into())),
+                    },
+                })),
+                scope: Some(plan::Scope::Named("scope_0".into())),
+            }),
+            scope: Some(plan::Scope::Root),
+            ..Default::default()
+        },
+        arguments: Default::default(),
+        variables: Default::default(),
+        variable_types: Default::default(),
+        unrelated_collections: Default::default(),
+    };
+
+    assert_eq!(query_plan, expected);
+    Ok(())
+}
+
+#[test]
+fn translates_exists_in_nested_collection_with_field_path() -> anyhow::Result<()> {
+    let mut query_context = make_nested_schema();
+    // The nested collection is reached through the `address` column, and each of its elements
+    // has another nested collection
+    query_context.object_types.insert(
+        "Address".into(),
+        ndc_test_helpers::object_type([
+            ("country", named_type(plan_test_helpers::ScalarType::String)),
+            ("local_articles", array_of(named_type("Article"))),
+        ]),
+    );
+    query_context.object_types.insert(
+        "Article".into(),
+        ndc_test_helpers::object_type([
+            ("title", named_type(plan_test_helpers::ScalarType::String)),
+            ("editors", array_of(named_type("Author"))),
+        ]),
+    );
+
+    let request = query_request()
+        .collection("authors")
+        .query(query().fields([field!("name")]).predicate(exists(
+            exists_in_nested("address").field_path(["local_articles"]),
+            exists(
+                exists_in_nested("editors"),
+                binop("Equal", target!("name"), value!("Alice")),
+            ),
+        )))
+        .into();
+    let query_plan = plan_for_query_request(&query_context, request)?;
+
+    let string_type = plan::Type::Scalar(plan_test_helpers::ScalarType::String);
+    let expected_predicate = plan::Expression::Exists {
+        in_collection: plan::ExistsInCollection::NestedCollection {
+            column_name: "address".into(),
+            arguments: Default::default(),
+            field_path: vec!["local_articles".into()],
+        },
+        predicate: Some(Box::new(plan::Expression::Exists {
+            // `editors` is resolved against the element type of `address.local_articles`, not
+            // against the root collection type
+            in_collection: plan::ExistsInCollection::NestedCollection {
+                column_name: "editors".into(),
+                arguments: Default::default(),
+                field_path: Default::default(),
+            },
+            predicate: Some(Box::new(plan::Expression::BinaryComparisonOperator {
+                column: plan::ComparisonTarget::column("name", string_type.clone()),
+                operator: plan_test_helpers::ComparisonOperator::Equal,
+                value: plan::ComparisonValue::Scalar {
+                    value: "Alice".into(),
+                    value_type: string_type.clone(),
+                },
+            })),
+            scope: None,
+        })),
+        scope: None,
+    };
+
+    assert_eq!(query_plan.query.predicate, Some(expected_predicate));
+    Ok(())
+}
+
+#[test]
+fn fails_to_resolve_nested_collection_path_in_wrong_order() -> anyhow::Result<()> {
+    let query_context = make_nested_schema();
+    // `country` is a field of `address`, so this path is only valid as column `address` with
+    // field path `["country"]`
+    let request = query_request()
+        .collection("authors")
+        .query(query().fields([field!("name")]).predicate(exists(
+            exists_in_nested("country").field_path(["address"]),
+            binop("Equal", target!("name"), value!("Alice")),
+        )))
+        .into();
+    let result = plan_for_query_request(&query_context, request);
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn fails_to_resolve_scope_without_enclosing_exists() -> anyhow::Result<()> {
+    let query_context = make_nested_schema();
+    let request = query_request()
+        .collection("authors")
+        .query(query().fields([field!("name")]).predicate(binop(
+            "Equal",
+            target!("name"),
+            column_value("name").scope(1).into(),
+        )))
+        .into();
+    let result = plan_for_query_request(&query_context, request);
+    assert!(matches!(result, Err(plan::QueryPlanError::UnknownScope(1))));
+    Ok(())
+}
+
+#[test]
+fn records_source_paths_of_relationships_referenced_from_nested_fields() -> anyhow::Result<()> {
+    let query_context = make_nested_schema();
+    let request = query_request()
+        .collection("authors")
+        .query(query().fields([
+            field!("address" => "address", object!([
+                field!("country"),
+                relation_field!("appearances" => "country_appearances", query().fields([
+                    field!("authorId")
+                ])),
+            ])),
+            field!("articles" => "articles", array!(object!([
+                field!("title"),
+                relation_field!("appearances" => "article_appearances", query().fields([
+                    field!("authorId")
+                ])),
+            ]))),
+        ]))
+        .relationships([
+            (
+                "country_appearances",
+                relationship("appearances", [("country", &["authorId"])]),
+            ),
+            (
+                "article_appearances",
+                relationship("appearances", [("title", &["authorId"])]),
+            ),
+        ])
+        .into();
+    let query_plan = plan_for_query_request(&query_context, request)?;
+
+    let source_paths = query_plan
+        .query
+        .relationships
+        .iter()
+        .map(|(name, relationship)| (name.to_string(), relationship.source_path.clone()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        source_paths,
+        vec![
+            (
+                "article_appearances".to_string(),
+                vec![
+                    plan::SourcePathElement::Field("articles".into()),
+                    plan::SourcePathElement::ArrayElements,
+                ]
+            ),
+            (
+                "country_appearances".to_string(),
+                vec![plan::SourcePathElement::Field("address".into())]
+            ),
+        ]
+    );
+    Ok(())
+}
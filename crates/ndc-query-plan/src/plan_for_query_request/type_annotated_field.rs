@@ -1,10 +1,9 @@
-use std::collections::BTreeMap;
-
 use itertools::Itertools as _;
 use ndc_models as ndc;
 
 use crate::{
-    Field, NestedArray, NestedField, NestedObject, ObjectType, QueryContext, QueryPlanError, Type,
+    Field, NestedArray, NestedCollection, NestedField, NestedObject, ObjectType, QueryContext,
+    QueryPlanError, SourcePathElement, Type,
 };
 
 use super::{
@@ -28,6 +27,7 @@ pub fn type_annotated_field<T: QueryContext>(
         collection_object_type,
         field,
         &[],
+        &[],
     )
 }
 
@@ -37,6 +37,7 @@ fn type_annotated_field_helper<T: QueryContext>(
     collection_object_type: &ObjectType<T::ScalarType>,
     field: ndc::Field,
     path: &[&str],
+    source_path: &[SourcePathElement],
 ) -> Result<Field<T>> {
     let field = match field {
         ndc::Field::Column {
@@ -54,6 +55,10 @@ fn type_annotated_field_helper<T: QueryContext>(
                         column_type,
                         *nested_field,
                         path,
+                        &append_to_source_path(
+                            source_path,
+                            SourcePathElement::Field(column.clone()),
+                        ),
                     )
                 })
                 .transpose()?;
@@ -81,7 +86,7 @@ fn type_annotated_field_helper<T: QueryContext>(
                 &mut subquery_state,
                 &related_collection_type,
                 &related_collection_type,
-                *query,
+                query,
             )?;
             query_plan.scope = Some(subquery_state.into_scope());
 
@@ -92,8 +97,12 @@ fn type_annotated_field_helper<T: QueryContext>(
             let fields = query_plan.fields.clone();
             let groups = query_plan.groups.clone();
 
-            let relationship_key =
-                plan_state.register_relationship(relationship, arguments, query_plan)?;
+            let relationship_key = plan_state.register_relationship(
+                relationship,
+                source_path.to_vec(),
+                arguments,
+                query_plan,
+            )?;
             Field::Relationship {
                 relationship: relationship_key,
                 aggregates,
@@ -105,10 +114,10 @@ fn type_annotated_field_helper<T: QueryContext>(
     Ok(field)
 }
 
-/// Translates [ndc::NestedField] to [Field]. The latter includes type annotations.
+/// Translates [ndc::NestedField] to [Field]. The latter includes type annotations. Relationships
+/// referenced in the nested field are registered in `plan_state`.
 pub fn type_annotated_nested_field<T: QueryContext>(
-    query_context: &T,
-    collection_relationships: &BTreeMap<ndc::RelationshipName, ndc::Relationship>,
+    plan_state: &mut QueryPlanState<'_, T>,
     result_type: &Type<T::ScalarType>,
     requested_fields: ndc::NestedField,
 ) -> Result<NestedField<T>> {
@@ -118,11 +127,12 @@ pub fn type_annotated_nested_field<T: QueryContext>(
         fields: Default::default(),
     };
     type_annotated_nested_field_helper(
-        &mut QueryPlanState::new(query_context, collection_relationships),
+        plan_state,
         root_collection_object_type,
         result_type,
         requested_fields,
         &[],
+        &[],
     )
 }
 
@@ -132,6 +142,7 @@ fn type_annotated_nested_field_helper<T: QueryContext>(
     parent_type: &Type<T::ScalarType>,
     requested_fields: ndc::NestedField,
     path: &[&str],
+    source_path: &[SourcePathElement],
 ) -> Result<NestedField<T>> {
     let field = match (requested_fields, parent_type) {
         (ndc::NestedField::Object(object), Type::Object(object_type)) => {
@@ -148,6 +159,7 @@ fn type_annotated_nested_field_helper<T: QueryContext>(
                                 object_type,
                                 field.clone(),
                                 &append_to_path(path, [name.to_string().as_ref()]),
+                                source_path,
                             )?,
                         )) as Result<_>
                     })
@@ -162,13 +174,26 @@ fn type_annotated_nested_field_helper<T: QueryContext>(
                     element_type,
                     *array.fields,
                     &append_to_path(path, ["[]"]),
+                    &append_to_source_path(source_path, SourcePathElement::ArrayElements),
                 )?),
             })
         }
-        // TODO: ENG-1464
-        (ndc::NestedField::Collection(_), _) => Err(QueryPlanError::NotImplemented(
-            "query.nested_fields.nested_collections".to_string(),
-        ))?,
+        (
+            ndc::NestedField::Collection(ndc::NestedCollection { query }),
+            Type::ArrayOf(element_type),
+        ) => {
+            // Elements of the nested array act as rows of a collection. The nested query is its
+            // own enclosing query so the element type is also the root collection type for
+            // references within it.
+            let element_object_type = (**element_type).clone().into_object_type()?;
+            let query_plan = plan_for_query(
+                plan_state,
+                &element_object_type,
+                &element_object_type,
+                query,
+            )?;
+            NestedField::Collection(NestedCollection { query: query_plan })
+        }
         (nested, Type::Nullable(t)) => {
             // let path = append_to_path(path, [])
             type_annotated_nested_field_helper(
@@ -177,6 +202,7 @@ fn type_annotated_nested_field_helper<T: QueryContext>(
                 t,
                 nested,
                 path,
+                source_path,
             )?
         }
         (ndc::NestedField::Object(_), _) => Err(QueryPlanError::ExpectedObject {
@@ -185,6 +211,9 @@ fn type_annotated_nested_field_helper<T: QueryContext>(
         (ndc::NestedField::Array(_), _) => Err(QueryPlanError::ExpectedArray {
             path: path_to_owned(path),
         })?,
+        (ndc::NestedField::Collection(_), _) => Err(QueryPlanError::ExpectedArray {
+            path: path_to_owned(path),
+        })?,
     };
     Ok(field)
 }
@@ -193,6 +222,13 @@ fn append_to_path<'a>(path: &[&'a str], elems: impl IntoIterator<Item = &'a str>
     path.iter().copied().chain(elems).collect()
 }
 
+fn append_to_source_path(
+    source_path: &[SourcePathElement],
+    elem: SourcePathElement,
+) -> Vec<SourcePathElement> {
+    source_path.iter().cloned().chain([elem]).collect()
+}
+
 fn path_to_owned(path: &[&str]) -> Vec<String> {
     path.iter().map(|x| (*x).to_owned()).collect()
 }
@@ -8,7 +8,8 @@ use thiserror::Error;
 
 use crate::{
     Aggregate, ConnectorTypes, Expression, Field, GroupExpression, Grouping, NestedArray,
-    NestedField, NestedObject, Query, Relationship, RelationshipArgument, Relationships,
+    NestedCollection, NestedField, NestedObject, Query, Relationship, RelationshipArgument,
+    Relationships,
 };
 
 #[derive(Debug, Error)]
@@ -51,11 +52,16 @@ pub fn unify_relationship_references<T>(
 where
     T: ConnectorTypes,
 {
+    if a.source_path != b.source_path {
+        return Err(RelationshipUnificationError::Mismatch(vec!["source_path"]));
+    }
+
     let relationship = Relationship {
         column_mapping: a.column_mapping,
         relationship_type: a.relationship_type,
         target_collection: a.target_collection,
         arguments: unify_arguments(a.arguments, b.arguments)?,
+        source_path: a.source_path,
         query: unify_query(a.query, b.query)?,
     };
     Ok(relationship)
@@ -264,6 +270,12 @@ where
         ) => Ok(NestedField::Array(NestedArray {
             fields: Box::new(unify_nested_fields_some(*nested_a, *nested_b)?),
         })),
+        (
+            NestedField::Collection(NestedCollection { query: query_a }),
+            NestedField::Collection(NestedCollection { query: query_b }),
+        ) => Ok(NestedField::Collection(NestedCollection {
+            query: unify_query(query_a, query_b)?,
+        })),
         _ => Err(RelationshipUnificationError::Mismatch(vec!["nested field"])),
     }
 }
@@ -6,7 +6,7 @@ use ndc_models::{self as ndc, ArgumentName, FieldName};
 
 use crate::Type;
 
-use super::{Argument, ConnectorTypes};
+use super::{Aggregate, Argument, ConnectorTypes, Scope};
 
 #[derive(Derivative)]
 #[derivative(
@@ -44,6 +44,10 @@ pub enum Expression<T: ConnectorTypes> {
     Exists {
         in_collection: ExistsInCollection<T>,
         predicate: Option<Box<Expression<T>>>,
+        /// Name for the collection that encloses this expression. This is only set if the
+        /// predicate references columns of the enclosing collection using a named scope. The
+        /// connector must introduce a variable, or something similar, for such references.
+        scope: Option<Scope>,
     },
 }
 
@@ -62,8 +66,7 @@ impl<T: ConnectorTypes> Expression<T> {
     /// collections. This is used to build a plan for joining the referenced collection - we need
     /// to include fields in the join that the expression needs to access.
     //
-    // TODO: ENG-1457 When we implement query.aggregates.filter_by we'll need to collect aggregates
-    // references. That's why this function returns [ComparisonTarget] instead of [Field].
+    // Aggregate comparison targets are not included because they reference related collections.
     pub fn query_local_comparison_targets<'a>(
         &'a self,
     ) -> Box<dyn Iterator<Item = Cow<'a, ComparisonTarget<T>>> + 'a> {
@@ -80,10 +83,10 @@ impl<T: ConnectorTypes> Expression<T> {
             ),
             Expression::Not { expression } => expression.query_local_comparison_targets(),
             Expression::UnaryComparisonOperator { column, .. } => {
-                Box::new(std::iter::once(Cow::Borrowed(column)))
+                Box::new(Self::local_target(column))
             }
             Expression::BinaryComparisonOperator { column, value, .. } => Box::new(
-                std::iter::once(Cow::Borrowed(column))
+                Self::local_target(column)
                     .chain(Self::local_targets_from_comparison_value(value).map(Cow::Owned)),
             ),
             Expression::ArrayComparison { column, comparison } => {
@@ -93,12 +96,22 @@ impl<T: ConnectorTypes> Expression<T> {
                     ),
                     ArrayComparison::IsEmpty => Either::Right(std::iter::empty()),
                 };
-                Box::new(std::iter::once(Cow::Borrowed(column)).chain(value_targets))
+                Box::new(Self::local_target(column).chain(value_targets))
             }
             Expression::Exists { .. } => Box::new(iter::empty()),
         }
     }
 
+    fn local_target(
+        target: &ComparisonTarget<T>,
+    ) -> impl Iterator<Item = Cow<'_, ComparisonTarget<T>>> {
+        match target {
+            ComparisonTarget::Column { .. } => Some(Cow::Borrowed(target)),
+            ComparisonTarget::Aggregate { .. } => None,
+        }
+        .into_iter()
+    }
+
     fn local_targets_from_comparison_value(
         value: &ComparisonValue<T>,
     ) -> impl Iterator<Item = ComparisonTarget<T>> {
@@ -109,9 +122,10 @@ impl<T: ConnectorTypes> Expression<T> {
                 arguments,
                 field_path,
                 field_type,
-                ..
+                scope,
             } => {
-                if path.is_empty() {
+                // Columns in other scopes belong to collections enclosing an exists expression
+                if path.is_empty() && scope.is_none() {
                     Either::Left(iter::once(ComparisonTarget::Column {
                         name: name.clone(),
                         arguments: arguments.clone(),
@@ -168,15 +182,15 @@ pub enum ComparisonTarget<T: ConnectorTypes> {
         /// field.
         field_type: Type<T::ScalarType>,
     },
-    // TODO: ENG-1457 Add this variant to support query.aggregates.filter_by
-    // /// The comparison targets the result of aggregation.
-    // /// Only used if the 'query.aggregates.filter_by' capability is supported.
-    // Aggregate {
-    //     /// Non-empty collection of relationships to traverse
-    //     path: Vec<RelationshipName>,
-    //     /// The aggregation method to use
-    //     aggregate: Aggregate<T>,
-    // },
+    /// The comparison targets the result of aggregation.
+    /// Only used if the 'query.aggregates.filter_by' capability is supported.
+    Aggregate {
+        /// Non-empty collection of relationships to traverse. These are translated to names of
+        /// relation fields for the [crate::QueryPlan].
+        path: Vec<ndc::RelationshipName>,
+        /// The aggregation method to use
+        aggregate: Aggregate<T>,
+    },
 }
 
 impl<T: ConnectorTypes> ComparisonTarget<T> {
@@ -189,11 +203,10 @@ impl<T: ConnectorTypes> ComparisonTarget<T> {
         }
     }
 
-    pub fn target_type(&self) -> &Type<T::ScalarType> {
+    pub fn target_type(&self) -> Cow<'_, Type<T::ScalarType>> {
         match self {
-            ComparisonTarget::Column { field_type, .. } => field_type,
-            // TODO: ENG-1457
-            // ComparisonTarget::Aggregate { aggregate, .. } => aggregate.result_type,
+            ComparisonTarget::Column { field_type, .. } => Cow::Borrowed(field_type),
+            ComparisonTarget::Aggregate { aggregate, .. } => aggregate.result_type(),
         }
     }
 }
@@ -221,15 +234,12 @@ pub enum ComparisonValue<T: ConnectorTypes> {
         /// Type of the field that you get *after* follwing `field_path` to a possibly-nested
         /// field.
         field_type: Type<T::ScalarType>,
-        /// The scope in which this column exists, identified
-        /// by an top-down index into the stack of scopes.
-        /// The stack grows inside each `Expression::Exists`,
-        /// so scope 0 (the default) refers to the current collection,
-        /// and each subsequent index refers to the collection outside
-        /// its predecessor's immediately enclosing `Expression::Exists`
-        /// expression.
+        /// If the column belongs to a collection that encloses an `Expression::Exists`
+        /// expression instead of to the current collection, this is the name of the scope that
+        /// the matching `Expression::Exists` introduced for that collection. `None` refers to the
+        /// current collection.
         /// Only used if the 'query.exists.named_scopes' capability is supported.
-        scope: Option<usize>,
+        scope: Option<Scope>,
     },
     Scalar {
         value: serde_json::Value,
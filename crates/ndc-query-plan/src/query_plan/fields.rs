@@ -4,7 +4,7 @@ use ndc_models as ndc;
 
 use crate::Type;
 
-use super::{Aggregate, ConnectorTypes, Grouping};
+use super::{Aggregate, ConnectorTypes, Grouping, Query};
 
 #[derive(Derivative)]
 #[derivative(Clone(bound = ""), Debug(bound = ""), PartialEq(bound = ""))]
@@ -43,12 +43,18 @@ pub struct NestedArray<T: ConnectorTypes> {
     pub fields: Box<NestedField<T>>,
 }
 
-// TODO: ENG-1464 define NestedCollection struct
+/// Perform a query over the rows of a nested array field as if the array were a collection.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Debug(bound = ""), PartialEq(bound = ""))]
+pub struct NestedCollection<T: ConnectorTypes> {
+    pub query: Query<T>,
+}
 
 #[derive(Derivative)]
 #[derivative(Clone(bound = ""), Debug(bound = ""), PartialEq(bound = ""))]
 pub enum NestedField<T: ConnectorTypes> {
     Object(NestedObject<T>),
     Array(NestedArray<T>),
-    // TODO: ENG-1464 add `Collection(NestedCollection)` variant
+    /// Only used if the 'query.nested_fields.nested_collections' capability is supported.
+    Collection(NestedCollection<T>),
 }
@@ -127,9 +127,24 @@ pub struct Relationship<T: ConnectorTypes> {
     pub target_collection: ndc::CollectionName,
     /// Values to be provided to any collection arguments
     pub arguments: BTreeMap<ndc::ArgumentName, RelationshipArgument<T>>,
+    /// Path from the top level of a source row to the nested object that the relationship is
+    /// navigated from. Source columns in `column_mapping` are relative to that nested object. The
+    /// path is empty unless the relationship is referenced from inside a nested object or array
+    /// field, which requires the 'relationships.nested' capability.
+    pub source_path: Vec<SourcePathElement>,
     pub query: Query<T>,
 }
 
+/// One step in the path from a source row to the nested object that a relationship is navigated
+/// from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SourcePathElement {
+    /// Descend into a nested object field
+    Field(ndc::FieldName),
+    /// Descend into each element of an array field
+    ArrayElements,
+}
+
 #[derive(Derivative)]
 #[derivative(
     Clone(bound = ""),
@@ -164,7 +179,7 @@ pub struct UnrelatedJoin<T: ConnectorTypes> {
     pub query: Query<T>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Scope {
     Root,
     Named(String),
@@ -53,6 +53,15 @@ macro_rules! array {
     };
 }
 
+#[macro_export]
+macro_rules! nested_collection {
+    ($query:expr) => {
+        $crate::ndc_models::NestedField::Collection($crate::ndc_models::NestedCollection {
+            query: $query.into(),
+        })
+    };
+}
+
 #[macro_export]
 macro_rules! relation_field {
     ($name:literal => $relationship:literal) => {
@@ -196,6 +196,11 @@ impl QueryBuilder {
         self
     }
 
+    pub fn offset(mut self, n: u32) -> Self {
+        self.offset = Some(n);
+        self
+    }
+
     pub fn order_by(
         mut self,
         elements: impl IntoIterator<Item = impl Into<OrderByElement>>,
@@ -65,6 +65,7 @@ pub fn mflix_config() -> Configuration {
         functions: Default::default(),
         procedures: Default::default(),
         native_mutations: Default::default(),
+        collection_procedures: Default::default(),
         native_queries: Default::default(),
         options: Default::default(),
     }